@@ -0,0 +1,561 @@
+// lsp - `klug lsp`, a language server over stdin and stdout
+//
+// Editors send the whole document on every change, and it's analyzed again
+// from scratch, see `klug::Analysis`. Positions are in UTF-16 code units, the
+// protocol's default, and are converted to byte offsets and back.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::ops::Range;
+use klug::{Analysis, NameKind, Severity, Symbol, Token, TokenKind, Typing};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{self, Notification as _};
+use lsp_types::request::{self, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf,
+    Position, PublishDiagnosticsParams, ReferenceParams, SemanticToken, SemanticTokenType, SemanticTokens,
+    SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams,
+    SemanticTokensResult, SemanticTokensServerCapabilities, ServerCapabilities, SymbolKind, TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+// klug lsp - serves until the editor shuts it down
+pub(crate) fn serve(typing: Typing) -> io::Result<()> {
+    let (connection, threads) = Connection::stdio();
+    run(&connection, typing).map_err(io::Error::other)?;
+    // NOTE the thread writing to stdout exits once the connection is dropped
+    drop(connection);
+    threads.join()
+}
+
+fn run(connection: &Connection, typing: Typing) -> Result<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+            legend: SemanticTokensLegend {
+                token_types: TokenKind::ALL.iter().map(|kind| SemanticTokenType::new(kind.name())).collect(),
+                token_modifiers: vec![],
+            },
+            full: Some(SemanticTokensFullOptions::Bool(true)),
+            ..SemanticTokensOptions::default()
+        })),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    let mut server = Server { documents: HashMap::new(), typing };
+    for message in &connection.receiver {
+        match message {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                connection.sender.send(server.request(req).into())?;
+            }
+            Message::Notification(n) => {
+                if let Some(published) = server.notification(n)? {
+                    connection.sender.send(published.into())?;
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+struct Server {
+    documents: HashMap<Url, Document>,
+    typing: Typing,
+}
+
+impl Server {
+    // keeps the open documents up to date, and publishes their diagnostics
+    fn notification(&mut self, n: Notification) -> Result<Option<Notification>> {
+        let uri = match &n.method[..] {
+            notification::DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(n.params)?;
+                self.open(params.text_document.uri.clone(), params.text_document.text);
+                params.text_document.uri
+            }
+            notification::DidChangeTextDocument::METHOD => {
+                let mut params: DidChangeTextDocumentParams = serde_json::from_value(n.params)?;
+                // NOTE changes are whole documents, see `TextDocumentSyncKind::FULL`
+                if let Some(change) = params.content_changes.pop() {
+                    self.open(params.text_document.uri.clone(), change.text);
+                }
+                params.text_document.uri
+            }
+            notification::DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(n.params)?;
+                self.documents.remove(&params.text_document.uri);
+                params.text_document.uri
+            }
+            _ => return Ok(None),
+        };
+        let published = self.diagnostics(uri);
+        Ok(Some(Notification::new(notification::PublishDiagnostics::METHOD.to_string(), published)))
+    }
+
+    fn open(&mut self, uri: Url, text: String) {
+        let analysis = Analysis::new(&text, self.typing);
+        self.documents.insert(uri, Document { text, analysis });
+    }
+
+    // NOTE a closed document has none
+    fn diagnostics(&self, uri: Url) -> PublishDiagnosticsParams {
+        let diagnostics = match self.documents.get(&uri) {
+            Some(doc) => doc.analysis.diagnostics().iter()
+                .map(|d| lsp_types::Diagnostic {
+                    range: range(&doc.text, d.span.clone()),
+                    severity: Some(match d.severity {
+                        Severity::Error => DiagnosticSeverity::ERROR,
+                        Severity::Warning => DiagnosticSeverity::WARNING,
+                    }),
+                    source: Some("klug".to_string()),
+                    message: d.message.clone(),
+                    ..lsp_types::Diagnostic::default()
+                })
+                .collect(),
+            None => vec![],
+        };
+        PublishDiagnosticsParams { uri, diagnostics, version: None }
+    }
+
+    fn request(&self, req: Request) -> Response {
+        let result = match &req.method[..] {
+            request::HoverRequest::METHOD => self.handle::<request::HoverRequest>(req.params, Self::hover),
+            request::GotoDefinition::METHOD => self.handle::<request::GotoDefinition>(req.params, Self::definition),
+            request::References::METHOD => self.handle::<request::References>(req.params, Self::references),
+            request::DocumentSymbolRequest::METHOD => {
+                self.handle::<request::DocumentSymbolRequest>(req.params, Self::symbols)
+            }
+            request::Completion::METHOD => self.handle::<request::Completion>(req.params, Self::completions),
+            request::SemanticTokensFullRequest::METHOD => {
+                self.handle::<request::SemanticTokensFullRequest>(req.params, Self::semantic_tokens)
+            }
+            _ => {
+                let msg = format!("unknown request {:?}", req.method);
+                return Response::new_err(req.id, ErrorCode::MethodNotFound as i32, msg);
+            }
+        };
+        match result {
+            Ok(value) => Response::new_ok(req.id, value),
+            Err(e) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    fn handle<R: request::Request>(
+        &self,
+        params: serde_json::Value,
+        answer: impl FnOnce(&Self, R::Params) -> R::Result,
+    ) -> Result<serde_json::Value> {
+        let params = serde_json::from_value(params)?;
+        Ok(serde_json::to_value(answer(self, params))?)
+    }
+
+    // the document and the offset a position is at, none if it isn't open
+    fn at(&self, position: &TextDocumentPositionParams) -> Option<(&Document, usize)> {
+        let doc = self.documents.get(&position.text_document.uri)?;
+        Some((doc, offset(&doc.text, position.position)))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let (doc, offset) = self.at(&params.text_document_position_params)?;
+        let (span, text) = doc.analysis.hover(offset)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value: text }),
+            range: Some(range(&doc.text, span)),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let (doc, offset) = self.at(&position)?;
+        let span = doc.analysis.definition(offset)?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(position.text_document.uri, range(&doc.text, span))))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let (position, include_declaration) = (params.text_document_position, params.context.include_declaration);
+        let (doc, offset) = self.at(&position)?;
+        let definition = doc.analysis.definition(offset);
+        let references = doc.analysis.references(offset).into_iter()
+            .filter(|span| include_declaration || Some(span) != definition.as_ref())
+            .map(|span| Location::new(position.text_document.uri.clone(), range(&doc.text, span)))
+            .collect();
+        Some(references)
+    }
+
+    fn symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let doc = self.documents.get(&params.text_document.uri)?;
+        let symbols = doc.analysis.symbols().into_iter().map(|s| symbol(&doc.text, s)).collect();
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    fn completions(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let (doc, offset) = self.at(&params.text_document_position)?;
+        let items = doc.analysis.completions(offset).into_iter()
+            .map(|c| CompletionItem {
+                kind: Some(c.kind.map_or(CompletionItemKind::KEYWORD, completion_kind)),
+                label: c.label,
+                detail: Some(c.detail),
+                ..CompletionItem::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn semantic_tokens(&self, params: SemanticTokensParams) -> Option<SemanticTokensResult> {
+        let doc = self.documents.get(&params.text_document.uri)?;
+        let data = semantic_tokens(&doc.text, doc.analysis.tokens());
+        Some(SemanticTokensResult::Tokens(SemanticTokens { result_id: None, data }))
+    }
+}
+
+// NOTE `deprecated` is deprecated for `tags`, but has to be given
+#[allow(deprecated)]
+fn symbol(text: &str, s: Symbol) -> DocumentSymbol {
+    let children: Vec<DocumentSymbol> = s.children.into_iter().map(|c| symbol(text, c)).collect();
+    DocumentSymbol {
+        name: s.name,
+        detail: None,
+        kind: match s.kind {
+            NameKind::Function => SymbolKind::FUNCTION,
+            NameKind::Type => SymbolKind::ENUM,
+            NameKind::Constructor => SymbolKind::ENUM_MEMBER,
+            _ => SymbolKind::VARIABLE,
+        },
+        tags: None,
+        deprecated: None,
+        range: range(text, s.span),
+        selection_range: range(text, s.name_span),
+        children: Some(children).filter(|c| !c.is_empty()),
+    }
+}
+
+fn completion_kind(kind: NameKind) -> CompletionItemKind {
+    match kind {
+        NameKind::Variable | NameKind::Parameter | NameKind::Unresolved => CompletionItemKind::VARIABLE,
+        NameKind::Function | NameKind::Builtin => CompletionItemKind::FUNCTION,
+        NameKind::Constructor => CompletionItemKind::ENUM_MEMBER,
+        NameKind::Type => CompletionItemKind::ENUM,
+        NameKind::TypeParameter => CompletionItemKind::TYPE_PARAMETER,
+        NameKind::Field => CompletionItemKind::FIELD,
+    }
+}
+
+// Each token's position is relative to the one before, and as a token can't
+// span lines a string that does is split at them. Punctuation isn't sent.
+// NOTE tokens come in order, so each is found from the one before rather than
+// from the start of the document
+fn semantic_tokens(text: &str, tokens: Vec<Token>) -> Vec<SemanticToken> {
+    let mut data = Vec::new();
+    let mut last = Position::new(0, 0);
+    // how far into the text has been scanned, and where that is
+    let (mut scanned, mut at) = (0, Position::new(0, 0));
+    for token in tokens.into_iter().filter(|t| t.kind != TokenKind::Punctuation) {
+        let token_type = TokenKind::ALL.iter().position(|kind| *kind == token.kind).unwrap() as u32;
+        let mut start = token.span.start;
+        for line in text[token.span].split('\n') {
+            let here = advance(text, scanned, at, start);
+            (scanned, at) = (start, here);
+            start += line.len() + 1;
+            if line.is_empty() {
+                continue;
+            }
+            data.push(SemanticToken {
+                delta_line: here.line - last.line,
+                delta_start: match here.line == last.line {
+                    true => here.character - last.character,
+                    false => here.character,
+                },
+                length: line.encode_utf16().count() as u32,
+                token_type,
+                token_modifiers_bitset: 0,
+            });
+            last = here;
+        }
+    }
+    data
+}
+
+// NOTE a position past the end of its line is at the end of it, and past the
+// last line is at the end of the document
+fn offset(text: &str, position: Position) -> usize {
+    let start: usize = text.split_inclusive('\n').take(position.line as usize).map(str::len).sum();
+    let line = text[start..].split('\n').next().unwrap_or("");
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    start + line.len()
+}
+
+fn position(text: &str, offset: usize) -> Position {
+    advance(text, 0, Position::new(0, 0), offset)
+}
+
+// the position of `offset`, given that `from` is at `at`
+fn advance(text: &str, from: usize, at: Position, offset: usize) -> Position {
+    let between = &text[from..offset];
+    match between.rfind('\n') {
+        Some(i) => Position::new(
+            at.line + between.matches('\n').count() as u32,
+            between[i + 1..].encode_utf16().count() as u32,
+        ),
+        None => Position::new(at.line, at.character + between.encode_utf16().count() as u32),
+    }
+}
+
+fn range(text: &str, span: Range<usize>) -> lsp_types::Range {
+    lsp_types::Range::new(position(text, span.start), position(text, span.end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::{self, JoinHandle};
+    use lsp_server::RequestId;
+    use lsp_types::{
+        notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Exit, Initialized, PublishDiagnostics},
+        request::{
+            Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Initialize, References,
+            SemanticTokensFullRequest, Shutdown,
+        },
+        InitializeParams, InitializedParams, ReferenceContext, TextDocumentContentChangeEvent, TextDocumentIdentifier,
+        TextDocumentItem, VersionedTextDocumentIdentifier,
+    };
+
+    // An editor talking to a server on another thread
+    struct Client {
+        connection: Connection,
+        server: JoinHandle<Result<()>>,
+        id: i32,
+    }
+
+    impl Client {
+        fn new(typing: Typing) -> Self {
+            let (connection, server) = Connection::memory();
+            let server = thread::spawn(move || run(&server, typing));
+            let mut client = Client { connection, server, id: 0 };
+            client.request::<Initialize>(InitializeParams::default());
+            client.notify::<Initialized>(InitializedParams {});
+            client
+        }
+
+        fn request<R: request::Request>(&mut self, params: R::Params) -> R::Result {
+            self.id += 1;
+            let id = RequestId::from(self.id);
+            self.connection.sender.send(Request::new(id.clone(), R::METHOD.to_string(), params).into()).unwrap();
+            match self.connection.receiver.recv().unwrap() {
+                Message::Response(Response { id: got, result: Some(result), error: None }) if got == id => {
+                    serde_json::from_value(result).unwrap()
+                }
+                msg => panic!("expected a response to {}, got {:?}", R::METHOD, msg),
+            }
+        }
+
+        fn notify<N: notification::Notification>(&self, params: N::Params) {
+            self.connection.sender.send(Notification::new(N::METHOD.to_string(), params).into()).unwrap();
+        }
+
+        // what's published after a document is opened, changed or closed
+        fn published(&self) -> Vec<(lsp_types::Range, String)> {
+            match self.connection.receiver.recv().unwrap() {
+                Message::Notification(n) if n.method == PublishDiagnostics::METHOD => {
+                    let params: PublishDiagnosticsParams = serde_json::from_value(n.params).unwrap();
+                    params.diagnostics.into_iter().map(|d| (d.range, d.message)).collect()
+                }
+                msg => panic!("expected diagnostics, got {:?}", msg),
+            }
+        }
+
+        fn open(&self, text: &str) -> Vec<(lsp_types::Range, String)> {
+            let document = TextDocumentItem::new(uri(), "klug".to_string(), 0, text.to_string());
+            self.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams { text_document: document });
+            self.published()
+        }
+
+        fn shutdown(mut self) {
+            self.request::<Shutdown>(());
+            self.notify::<Exit>(());
+            self.server.join().unwrap().unwrap();
+        }
+    }
+
+    fn uri() -> Url {
+        Url::parse("file:///test.kl").unwrap()
+    }
+
+    fn at(line: u32, character: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri()), Position::new(line, character))
+    }
+
+    fn span(line: u32, start: u32, end: u32) -> lsp_types::Range {
+        lsp_types::Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        let text = "let s = \"é🙂\"\nlet t = s";
+        let t = text.rfind('t').unwrap();
+        assert_eq!(position(text, text.find('"').unwrap() + 1), Position::new(0, 9));
+        assert_eq!(position(text, text.rfind('"').unwrap()), Position::new(0, 12));
+        assert_eq!(position(text, t), Position::new(1, 4));
+        let quote = text.find('"').unwrap() + 1;
+        assert_eq!(advance(text, quote, Position::new(0, 9), text.rfind('"').unwrap()), Position::new(0, 12));
+        assert_eq!(advance(text, quote, Position::new(0, 9), t), Position::new(1, 4));
+        assert_eq!(offset(text, Position::new(0, 12)), text.rfind('"').unwrap());
+        assert_eq!(offset(text, Position::new(1, 4)), t);
+        assert_eq!(offset(text, Position::new(0, 100)), text.find('\n').unwrap());
+        assert_eq!(offset(text, Position::new(5, 0)), text.len());
+    }
+
+    #[test]
+    fn diagnostics_follow_the_document() {
+        let client = Client::new(Typing::Inferred);
+        let published = client.open("let = 1\nlet x = 1 + true\n");
+        assert_eq!(published.len(), 2);
+        assert_eq!(published[0].0, span(0, 0, 7));
+        assert!(published[0].1.starts_with("error: "), "{}", published[0].1);
+        assert_eq!(published[1].0, span(1, 0, 16));
+
+        let change = TextDocumentContentChangeEvent { range: None, range_length: None, text: "let x = 1\n".to_string() };
+        client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri(), 1),
+            content_changes: vec![change],
+        });
+        assert_eq!(client.published(), vec![]);
+
+        let text_document = TextDocumentIdentifier::new(uri());
+        client.notify::<DidCloseTextDocument>(DidCloseTextDocumentParams { text_document });
+        assert_eq!(client.published(), vec![]);
+        client.shutdown();
+    }
+
+    #[test]
+    fn names_are_answered_by_position() {
+        let mut client = Client::new(Typing::Inferred);
+        let source = "fn double(n: Int) -> Int { n * 2 }\nlet x = double(1)\nlet y = double(x)\n";
+        assert_eq!(client.open(source), vec![]);
+
+        let hover = client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: at(1, 4),
+            work_done_progress_params: Default::default(),
+        });
+        let hover = hover.expect("a hover over x");
+        assert_eq!(hover.range, Some(span(1, 4, 5)));
+        assert_eq!(
+            hover.contents,
+            HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "```klug\nlet x\n```\n\ntype `Int`".to_string(),
+            })
+        );
+
+        let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: at(2, 16),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        assert_eq!(definition, Some(GotoDefinitionResponse::Scalar(Location::new(uri(), span(1, 4, 5)))));
+
+        let mut references = |include_declaration| {
+            let references = client.request::<References>(ReferenceParams {
+                text_document_position: at(0, 5),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: ReferenceContext { include_declaration },
+            });
+            references.unwrap().into_iter().map(|l| l.range).collect::<Vec<_>>()
+        };
+        assert_eq!(references(true), vec![span(0, 3, 9), span(1, 8, 14), span(2, 8, 14)]);
+        assert_eq!(references(false), vec![span(1, 8, 14), span(2, 8, 14)]);
+
+        let symbols = client.request::<DocumentSymbolRequest>(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let symbols = match symbols {
+            Some(DocumentSymbolResponse::Nested(symbols)) => symbols,
+            symbols => panic!("expected nested symbols, got {:?}", symbols),
+        };
+        let symbols: Vec<_> = symbols.iter().map(|s| (&s.name[..], s.kind, s.range, s.selection_range)).collect();
+        assert_eq!(symbols, vec![
+            ("double", SymbolKind::FUNCTION, span(0, 0, 34), span(0, 3, 9)),
+            ("x", SymbolKind::VARIABLE, span(1, 0, 17), span(1, 4, 5)),
+            ("y", SymbolKind::VARIABLE, span(2, 0, 17), span(2, 4, 5)),
+        ]);
+
+        let completions = client.request::<Completion>(CompletionParams {
+            text_document_position: at(0, 28),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        });
+        let completions = match completions {
+            Some(CompletionResponse::Array(items)) => items,
+            completions => panic!("expected completions, got {:?}", completions),
+        };
+        let kind = |label: &str| completions.iter().find(|c| c.label == label).and_then(|c| c.kind);
+        assert_eq!(kind("n"), Some(CompletionItemKind::VARIABLE));
+        assert_eq!(kind("double"), Some(CompletionItemKind::FUNCTION));
+        assert_eq!(kind("match"), Some(CompletionItemKind::KEYWORD));
+        assert_eq!(kind("x"), None);
+        client.shutdown();
+    }
+
+    #[test]
+    fn semantic_tokens_are_relative_and_on_one_line() {
+        let mut client = Client::new(Typing::Off);
+        assert_eq!(client.open("let s = 'a\nbc' # é\nfn f(x) { s }"), vec![]);
+        let tokens = client.request::<SemanticTokensFullRequest>(SemanticTokensParams {
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            text_document: TextDocumentIdentifier::new(uri()),
+        });
+        let tokens = match tokens {
+            Some(SemanticTokensResult::Tokens(tokens)) => tokens.data,
+            tokens => panic!("expected tokens, got {:?}", tokens),
+        };
+        let index = |kind| TokenKind::ALL.iter().position(|k| *k == kind).unwrap() as u32;
+        let token = |delta_line, delta_start, length, kind| SemanticToken {
+            delta_line,
+            delta_start,
+            length,
+            token_type: index(kind),
+            token_modifiers_bitset: 0,
+        };
+        assert_eq!(tokens, vec![
+            token(0, 0, 3, TokenKind::Keyword),
+            token(0, 4, 1, TokenKind::Variable),
+            token(0, 2, 1, TokenKind::Operator),
+            token(0, 2, 2, TokenKind::String),
+            token(1, 0, 3, TokenKind::String),
+            token(0, 4, 3, TokenKind::Comment),
+            token(1, 0, 2, TokenKind::Keyword),
+            token(0, 3, 1, TokenKind::Function),
+            token(0, 2, 1, TokenKind::Parameter),
+            token(0, 5, 1, TokenKind::Variable),
+        ]);
+        client.shutdown();
+    }
+}
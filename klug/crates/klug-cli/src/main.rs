@@ -1,7 +1,220 @@
-use klug;
+mod lsp;
+
+use std::env;
+use std::fs;
 use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use klug::{DivByZero, Engine, FloatEq, Limits, Numerics, Typing};
+
+const USAGE: &str = "usage: klug [--typecheck | --gradual] [--float-eq=exact|ulps:N|epsilon:E] [--float-div=error|inf] [--max-nesting=N] [--max-depth=N] [--engine=tree|vm] [check FILE... | build FILE [-o OUT] | run FILE | fmt [--check] FILE... | highlight [--html] FILE... | lsp]";
+
+// NOTE klug recurses as deeply as the interpreter does, so everything runs on a
+// thread with a bigger stack than the main thread's, enough for the default limits
+const STACK_SIZE: usize = 256 << 20;
 
 fn main() -> io::Result<()> {
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(klug)?
+        .join()
+        .unwrap_or_else(|_| process::exit(101))
+}
+
+fn klug() -> io::Result<()> {
+    let (flags, args): (Vec<String>, Vec<String>) = env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let mut typing = Typing::Off;
+    let mut numerics = Numerics::default();
+    let mut limits = Limits::default();
+    // NOTE only given for source, a compiled program always runs on the vm
+    let mut engine = None;
+    let mut check_only = false;
+    let mut html = false;
+    for flag in &flags {
+        match &flag[..] {
+            "--typecheck" => typing = Typing::Inferred,
+            "--gradual" => typing = Typing::Gradual,
+            "--float-div=error" => numerics.div_by_zero = DivByZero::Error,
+            "--float-div=inf" => numerics.div_by_zero = DivByZero::Infinity,
+            "--engine=tree" => engine = Some(Engine::Tree),
+            "--engine=vm" => engine = Some(Engine::Vm),
+            "--check" => check_only = true,
+            "--html" => html = true,
+            _ if flag.starts_with("--float-eq=") => match float_eq(&flag["--float-eq=".len()..]) {
+                Some(eq) => numerics.float_eq = eq,
+                None => {
+                    eprintln!("invalid float equality {:?}, {}", flag, USAGE);
+                    process::exit(2);
+                }
+            },
+            _ if flag.starts_with("--max-nesting=") => limits.nesting = limit(flag, "--max-nesting="),
+            _ if flag.starts_with("--max-depth=") => limits.depth = limit(flag, "--max-depth="),
+            _ => {
+                eprintln!("unknown flag {:?}, {}", flag, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+    match args.first().map(|s| &s[..]) {
+        Some("check") => check(&args[1..], typing),
+        Some("build") => build(&args[1..], typing),
+        Some("run") => run(&args[1..], engine, session(typing, numerics, limits, engine.unwrap_or_default())),
+        Some("fmt") => fmt(&args[1..], check_only),
+        Some("highlight") => highlight(&args[1..], html),
+        Some("lsp") => lsp::serve(typing),
+        Some(cmd) => {
+            eprintln!("unknown command {:?}, {}", cmd, USAGE);
+            process::exit(2);
+        }
+        None => repl(session(typing, numerics, limits, engine.unwrap_or_default())),
+    }
+}
+
+// e.g. `exact`, `ulps:4` or `epsilon:0.001`
+fn float_eq(s: &str) -> Option<FloatEq> {
+    match s.split_once(':') {
+        None if s == "exact" => Some(FloatEq::Exact),
+        Some(("ulps", n)) => n.parse().ok().filter(|n| *n >= 0).map(FloatEq::Ulps),
+        Some(("epsilon", e)) => e.parse().ok().filter(|e| *e >= 0.0).map(FloatEq::Epsilon),
+        _ => None,
+    }
+}
+
+// e.g. `--max-depth=5000`
+fn limit(flag: &str, prefix: &str) -> usize {
+    match flag[prefix.len()..].parse() {
+        Ok(n) => n,
+        Err(_) => {
+            eprintln!("invalid limit {:?}, {}", flag, USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+// klug check FILE... - exits non-zero if any file has diagnostics
+fn check(files: &[String], typing: Typing) -> io::Result<()> {
+    let mut failed = false;
+    for file in files {
+        let input = fs::read_to_string(file)?;
+        for diagnostic in klug::check(&input, typing) {
+            println!("{}: {}", file, diagnostic);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+// klug build FILE [-o OUT] - compiles FILE to OUT, by default FILE with a `.klc` extension
+fn build(args: &[String], typing: Typing) -> io::Result<()> {
+    let (file, out) = match args {
+        [file] => (file, PathBuf::from(file).with_extension("klc")),
+        [file, o, out] if o == "-o" => (file, PathBuf::from(out)),
+        _ => {
+            eprintln!("expected a file and an optional `-o OUT`, {}", USAGE);
+            process::exit(2);
+        }
+    };
+    match klug::build(&fs::read_to_string(file)?, typing) {
+        Ok(klc) => fs::write(out, klc),
+        Err(errors) => {
+            for error in errors {
+                println!("{}: {}", file, error);
+            }
+            process::exit(1);
+        }
+    }
+}
+
+// klug run FILE - runs a program made by `klug build`, or else FILE as source,
+// and exits non-zero if it fails
+fn run(args: &[String], engine: Option<Engine>, mut session: klug::Session) -> io::Result<()> {
+    let file = match args {
+        [file] => file,
+        _ => {
+            eprintln!("expected a file, {}", USAGE);
+            process::exit(2);
+        }
+    };
+    let bytes = fs::read(file)?;
+    let ret = match klug::is_compiled(&bytes) {
+        true if engine == Some(Engine::Tree) => {
+            eprintln!("{}: a compiled program only runs on the vm, {}", file, USAGE);
+            process::exit(2);
+        }
+        true => session.run_compiled(&bytes).unwrap_or_else(|e| {
+            eprintln!("{}: {}", file, e);
+            process::exit(1);
+        }),
+        false => match String::from_utf8(bytes) {
+            Ok(input) => session.run(&input),
+            Err(_) => {
+                eprintln!("{}: neither source nor a compiled program", file);
+                process::exit(1);
+            }
+        },
+    };
+    if !ret.is_empty() {
+        println!("{}", ret);
+    }
+    if session.failed() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+// klug fmt [--check] FILE... - formats each FILE in place, or with `--check`
+// lists the files that aren't formatted and exits non-zero if there are any
+fn fmt(files: &[String], check_only: bool) -> io::Result<()> {
+    let mut failed = false;
+    for file in files {
+        let input = fs::read_to_string(file)?;
+        match klug::format(&input) {
+            Ok(formatted) if formatted == input => (),
+            Ok(_) if check_only => {
+                println!("{}: not formatted", file);
+                failed = true;
+            }
+            Ok(formatted) => fs::write(file, formatted)?,
+            Err(errors) => {
+                for error in errors {
+                    println!("{}: {}", file, error);
+                }
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+// klug highlight [--html] FILE... - prints each FILE colored for a terminal, or as HTML
+fn highlight(files: &[String], html: bool) -> io::Result<()> {
+    for file in files {
+        let input = fs::read_to_string(file)?;
+        match html {
+            true => print!("{}", klug::highlight_html(&input)),
+            false => print!("{}", klug::highlight_ansi(&input)),
+        }
+    }
+    Ok(())
+}
+
+fn session(typing: Typing, numerics: Numerics, limits: Limits, engine: Engine) -> klug::Session {
+    let mut session = klug::Session::default();
+    session.set_typing(typing);
+    session.set_numerics(numerics);
+    session.set_limits(limits);
+    session.set_engine(engine);
+    session
+}
+
+fn repl(mut session: klug::Session) -> io::Result<()> {
     let stdin = io::stdin();
     let mut stdout = io::stdout();
 
@@ -12,11 +225,16 @@ fn main() -> io::Result<()> {
 //        write!(stdout, "klug \u{1F449} ")?;
         stdout.flush()?;
 
-        stdin.read_line(&mut input)?;
+        if stdin.read_line(&mut input)? == 0 {
+            return Ok(());
+        }
 
         // let parse = Parser::new(&input).parse();
-        let ret = klug::run(&input);
-        println!("{}", ret);
+        let ret = session.run(&input);
+        // NOTE declarations and unit results print nothing
+        if !ret.is_empty() {
+            println!("{}", ret);
+        }
 
         input.clear();
     }
@@ -0,0 +1,513 @@
+// check - static analysis of `match` expressions
+//
+// Exhaustiveness and redundancy are both answered by the usefulness
+// algorithm from Maranget's "Warnings for pattern matching": an arm is
+// unreachable if it isn't useful w.r.t. the arms above it, and a match is
+// exhaustive if a wildcard isn't useful w.r.t. all of its (unguarded) arms.
+
+use std::collections::HashMap;
+use std::fmt;
+use num_bigint::BigInt;
+use crate::parser::{
+    decl::Decl,
+    stmt::Stmt,
+    contract::{Clause, Contract},
+    literal::Literal,
+    pattern::Pattern,
+    expr::{Expr, Arm},
+    Span,
+};
+
+// NOTE only this many missing patterns are listed in a warning
+const MAX_WITNESSES: usize = 3;
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Warning {
+    pub(crate) msg: String,
+    // the match for a non-exhaustive one, otherwise the arm
+    pub(crate) span: Span,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "warning: {}", self.msg)
+    }
+}
+
+// Remembers the enums declared so far, a REPL session keeps one around
+#[derive(Debug, Default)]
+pub(crate) struct Checker {
+    // constructor name -> (enum name, arity)
+    ctors: HashMap<String, (String, usize)>,
+    // enum name -> constructor names in declaration order
+    enums: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    Variant(String),
+    Bool(bool),
+    Int(i64),
+    Big(BigInt),
+    // NOTE floats are compared by their bits so Ctor can be Eq
+    Float(u64),
+    Str(String),
+    Tuple(usize),
+    // lists are matched as if they were `enum List { Nil, Cons(head, tail) }`
+    Nil,
+    Cons,
+}
+
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+impl Checker {
+    pub(crate) fn check_decls(&mut self, decls: &[Decl]) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for decl in decls {
+            self.check_decl(decl, &mut warnings);
+        }
+        warnings
+    }
+
+    fn check_decl(&mut self, decl: &Decl, warnings: &mut Vec<Warning>) {
+        match decl {
+            Decl::Enum(name, _, variants) => {
+                let names = variants.iter().map(|v| v.name.clone()).collect();
+                for variant in variants {
+                    self.ctors.insert(variant.name.clone(), (name.clone(), variant.fields.len()));
+                }
+                self.enums.insert(name.clone(), names);
+            }
+            Decl::Let(_, _, Some(expr), _) => self.check_expr(expr, warnings),
+            Decl::Fn(f) => {
+                for clause in f.requires.iter().chain(&f.ensures) {
+                    self.check_clause(clause, warnings);
+                }
+                self.check_expr(&f.body, warnings)
+            }
+            Decl::Stmt(bx_stmt) => match &**bx_stmt {
+                Stmt::Expr(expr) => self.check_expr(expr, warnings),
+            },
+            Decl::Let(_, _, None, _) | Decl::Error(_) => (),
+        }
+    }
+
+    fn check_clause(&mut self, clause: &Clause, warnings: &mut Vec<Warning>) {
+        match clause {
+            Clause::Holds(cond) => self.check_expr(cond, warnings),
+            Clause::Satisfies(_, contract) => self.check_contract(contract, warnings),
+        }
+    }
+
+    fn check_contract(&mut self, contract: &Contract, warnings: &mut Vec<Warning>) {
+        match contract {
+            Contract::Flat(pred) => self.check_expr(pred, warnings),
+            Contract::Fn(params, ret) => {
+                for param in params {
+                    self.check_contract(param, warnings);
+                }
+                self.check_contract(ret, warnings);
+            }
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr, warnings: &mut Vec<Warning>) {
+        match expr {
+            Expr::Unary(_, bdy) | Expr::Grouping(bdy) => self.check_expr(bdy, warnings),
+            Expr::Binary(lhs, _, rhs) => {
+                self.check_expr(lhs, warnings);
+                self.check_expr(rhs, warnings);
+            }
+            Expr::Literal(_) => (),
+            Expr::Call(callee, args, _) => {
+                self.check_expr(callee, warnings);
+                args.iter().for_each(|arg| self.check_expr(arg, warnings));
+            }
+            Expr::Block(decls, expr) => {
+                decls.iter().for_each(|decl| self.check_decl(decl, warnings));
+                self.check_expr(expr, warnings);
+            }
+            Expr::Lambda(f) => self.check_expr(&f.body, warnings),
+            Expr::Try(bdy) | Expr::Throw(bdy) => self.check_expr(bdy, warnings),
+            Expr::TryCatch(bdy, catch, finally) => {
+                self.check_expr(bdy, warnings);
+                if let Some((_, handler)) = catch {
+                    self.check_expr(handler, warnings);
+                }
+                if let Some(finally) = finally {
+                    self.check_expr(finally, warnings);
+                }
+            }
+            Expr::Tuple(items) => items.iter().for_each(|item| self.check_expr(item, warnings)),
+            Expr::List(items, rest) => {
+                items.iter().for_each(|item| self.check_expr(item, warnings));
+                if let Some(rest) = rest {
+                    self.check_expr(rest, warnings);
+                }
+            }
+            Expr::Match(scrut, arms, span) => {
+                self.check_expr(scrut, warnings);
+                for arm in arms {
+                    if let Some(guard) = &arm.guard {
+                        self.check_expr(guard, warnings);
+                    }
+                    self.check_expr(&arm.body, warnings);
+                }
+                self.check_match(scrut, arms, span, warnings);
+            }
+        }
+    }
+
+    fn check_match(&self, scrut: &Expr, arms: &[Arm], span: &Span, warnings: &mut Vec<Warning>) {
+        let mut rows: Vec<Vec<Pat>> = Vec::new();
+        for arm in arms {
+            let pat = match self.lower(&arm.pattern) {
+                Ok(pat) => pat,
+                Err(msg) => {
+                    warnings.push(Warning { msg, span: arm.span.clone() });
+                    continue;
+                }
+            };
+            if !self.is_useful(&rows, std::slice::from_ref(&pat)) {
+                let msg = format!("unreachable match arm `{}`", arm.stringify());
+                warnings.push(Warning { msg, span: arm.span.clone() });
+            }
+            // NOTE a guard may fail, so guarded arms don't cover anything
+            if arm.guard.is_none() {
+                rows.push(vec![pat]);
+            }
+        }
+
+        let missing = self.missing(&rows, 1);
+        if !missing.is_empty() {
+            let pats: Vec<String> = missing.iter()
+                .take(MAX_WITNESSES)
+                .map(|w| self.stringify(&w[0]))
+                .collect();
+            let etc = if missing.len() > MAX_WITNESSES { " and more" } else { "" };
+            warnings.push(Warning {
+                msg: format!("non-exhaustive match on `{}`: `{}`{} not covered", scrut, pats.join("`, `"), etc),
+                span: span.clone(),
+            });
+        }
+    }
+
+    fn lower(&self, pat: &Pattern) -> Result<Pat, String> {
+        match pat {
+            Pattern::Wildcard | Pattern::Binding(_) => Ok(Pat::Wild),
+            Pattern::Literal(Literal::TRUE) => Ok(Pat::Ctor(Ctor::Bool(true), vec![])),
+            Pattern::Literal(Literal::FALSE) => Ok(Pat::Ctor(Ctor::Bool(false), vec![])),
+            Pattern::Literal(Literal::INT(n)) => Ok(Pat::Ctor(Ctor::Int(*n), vec![])),
+            Pattern::Literal(Literal::BIG(n)) => Ok(Pat::Ctor(Ctor::Big(n.clone()), vec![])),
+            Pattern::Literal(Literal::FLOAT(n)) => Ok(Pat::Ctor(Ctor::Float(n.to_bits()), vec![])),
+            Pattern::Literal(Literal::STRING(s)) => Ok(Pat::Ctor(Ctor::Str(s.clone()), vec![])),
+            Pattern::Literal(Literal::IDENT(_)) => unreachable!(),
+            Pattern::Constructor(name, args) => match self.ctors.get(name) {
+                None => Err(format!("unknown constructor `{}` in pattern `{}`", name, pat)),
+                Some((_, arity)) if *arity != args.len() => Err(format!(
+                    "constructor `{}` has {} field(s) but pattern `{}` has {}",
+                    name, arity, pat, args.len()
+                )),
+                Some(_) => {
+                    let args = args.iter().map(|a| self.lower(a)).collect::<Result<_, _>>()?;
+                    Ok(Pat::Ctor(Ctor::Variant(name.clone()), args))
+                }
+            },
+            Pattern::Tuple(items) => {
+                let items = items.iter().map(|i| self.lower(i)).collect::<Result<Vec<_>, _>>()?;
+                Ok(Pat::Ctor(Ctor::Tuple(items.len()), items))
+            }
+            Pattern::List(items, rest) => {
+                let mut list = match rest {
+                    Some(rest) => self.lower(rest)?,
+                    None => Pat::Ctor(Ctor::Nil, vec![]),
+                };
+                for item in items.iter().rev() {
+                    list = Pat::Ctor(Ctor::Cons, vec![self.lower(item)?, list]);
+                }
+                Ok(list)
+            }
+        }
+    }
+
+    fn arity(&self, ctor: &Ctor) -> usize {
+        match ctor {
+            Ctor::Variant(name) => self.ctors[name].1,
+            Ctor::Tuple(arity) => *arity,
+            Ctor::Cons => 2,
+            _ => 0,
+        }
+    }
+
+    // The constructors heading the first column, along with every
+    // constructor of their type when that type has finitely many
+    fn signature(&self, rows: &[Vec<Pat>]) -> (Vec<Ctor>, Option<Vec<Ctor>>) {
+        let mut heads: Vec<Ctor> = Vec::new();
+        for row in rows {
+            if let Pat::Ctor(ctor, _) = &row[0] {
+                if !heads.contains(ctor) {
+                    heads.push(ctor.clone());
+                }
+            }
+        }
+        let all = match heads.first() {
+            Some(Ctor::Bool(_)) => Some(vec![Ctor::Bool(true), Ctor::Bool(false)]),
+            Some(Ctor::Tuple(arity)) => Some(vec![Ctor::Tuple(*arity)]),
+            Some(Ctor::Nil) | Some(Ctor::Cons) => Some(vec![Ctor::Nil, Ctor::Cons]),
+            Some(Ctor::Variant(name)) => {
+                let (enm, _) = &self.ctors[name];
+                Some(self.enums[enm].iter().cloned().map(Ctor::Variant).collect())
+            }
+            // numbers and strings can never be covered by literals alone
+            _ => None,
+        };
+        (heads, all)
+    }
+
+    // Is there a value matched by `v` but by none of the rows?
+    fn is_useful(&self, rows: &[Vec<Pat>], v: &[Pat]) -> bool {
+        let (head, rest) = match v.split_first() {
+            None => return rows.is_empty(),
+            Some(split) => split,
+        };
+        match head {
+            Pat::Ctor(ctor, args) => {
+                let v: Vec<Pat> = args.iter().chain(rest).cloned().collect();
+                self.is_useful(&self.specialize(rows, ctor), &v)
+            }
+            Pat::Wild => match self.signature(rows) {
+                (heads, Some(all)) if all.iter().all(|c| heads.contains(c)) => all.iter().any(|ctor| {
+                    let v: Vec<Pat> = vec![Pat::Wild; self.arity(ctor)].into_iter().chain(rest.iter().cloned()).collect();
+                    self.is_useful(&self.specialize(rows, ctor), &v)
+                }),
+                _ => self.is_useful(&default(rows), rest),
+            },
+        }
+    }
+
+    // Every (up to wildcards) vector of `width` patterns matched by none of the rows
+    fn missing(&self, rows: &[Vec<Pat>], width: usize) -> Vec<Vec<Pat>> {
+        if width == 0 {
+            return if rows.is_empty() { vec![vec![]] } else { vec![] };
+        }
+        match self.signature(rows) {
+            (heads, Some(all)) if all.iter().all(|c| heads.contains(c)) => {
+                let mut witnesses = Vec::new();
+                for ctor in all {
+                    let arity = self.arity(&ctor);
+                    for mut w in self.missing(&self.specialize(rows, &ctor), arity + width - 1) {
+                        let rest = w.split_off(arity);
+                        let mut witness = vec![Pat::Ctor(ctor.clone(), w)];
+                        witness.extend(rest);
+                        witnesses.push(witness);
+                    }
+                }
+                witnesses
+            }
+            (heads, all) => {
+                let rest = self.missing(&default(rows), width - 1);
+                if rest.is_empty() {
+                    return rest;
+                }
+                // name the uncovered constructors when we can, o.t. any value is missing
+                let heads: Vec<Pat> = match all {
+                    Some(all) if !heads.is_empty() => all.into_iter()
+                        .filter(|c| !heads.contains(c))
+                        .map(|c| Pat::Ctor(c.clone(), vec![Pat::Wild; self.arity(&c)]))
+                        .collect(),
+                    _ => vec![Pat::Wild],
+                };
+                let mut witnesses = Vec::new();
+                for head in heads {
+                    for w in &rest {
+                        let mut witness = vec![head.clone()];
+                        witness.extend(w.iter().cloned());
+                        witnesses.push(witness);
+                    }
+                }
+                witnesses
+            }
+        }
+    }
+
+    // The rows whose first pattern matches `ctor`, with that pattern replaced by its fields
+    fn specialize(&self, rows: &[Vec<Pat>], ctor: &Ctor) -> Vec<Vec<Pat>> {
+        rows.iter().filter_map(|row| {
+            let fields = match &row[0] {
+                Pat::Ctor(c, args) if c == ctor => args.clone(),
+                Pat::Ctor(_, _) => return None,
+                Pat::Wild => vec![Pat::Wild; self.arity(ctor)],
+            };
+            Some(fields.into_iter().chain(row[1..].iter().cloned()).collect())
+        }).collect()
+    }
+
+    fn stringify(&self, pat: &Pat) -> String {
+        match pat {
+            Pat::Wild => "_".to_string(),
+            Pat::Ctor(Ctor::Variant(name), args) if args.is_empty() => name.clone(),
+            Pat::Ctor(Ctor::Variant(name), args) => {
+                let args: Vec<String> = args.iter().map(|a| self.stringify(a)).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            Pat::Ctor(Ctor::Bool(b), _) => b.to_string(),
+            Pat::Ctor(Ctor::Int(n), _) => n.to_string(),
+            Pat::Ctor(Ctor::Big(n), _) => n.to_string(),
+            Pat::Ctor(Ctor::Float(bits), _) => format!("{:?}", f64::from_bits(*bits)),
+            Pat::Ctor(Ctor::Str(s), _) => format!("'{}'", s),
+            Pat::Ctor(Ctor::Tuple(_), items) => {
+                let items: Vec<String> = items.iter().map(|i| self.stringify(i)).collect();
+                format!("({})", items.join(", "))
+            }
+            Pat::Ctor(Ctor::Nil, _) | Pat::Ctor(Ctor::Cons, _) => {
+                // NOTE flatten the conses back into `[a, b, ..rest]`
+                let mut items = Vec::new();
+                let mut list = pat;
+                while let Pat::Ctor(Ctor::Cons, args) = list {
+                    items.push(self.stringify(&args[0]));
+                    list = &args[1];
+                }
+                if let Pat::Wild = list {
+                    items.push("..".to_string() + &self.stringify(list));
+                }
+                format!("[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+// The rows whose first pattern is a wildcard, without that pattern
+fn default(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter(|row| matches!(row[0], Pat::Wild))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const SHAPES: &str = "enum Shape { Circle(r), Rect(w, h), Empty }\n";
+
+    fn check(input: &str) -> Vec<String> {
+        let parse = Parser::new(input).parse();
+        Checker::default()
+            .check_decls(&parse.declarations)
+            .into_iter()
+            .map(|w| w.msg)
+            .collect()
+    }
+
+    #[test]
+    fn exhaustive_enum_match() {
+        assert!(check(&format!("{}match s {{ Circle(r) => r, Rect(w, h) => w, Empty => 0 }}", SHAPES)).is_empty());
+    }
+
+    #[test]
+    fn missing_constructor() {
+        assert_eq!(
+            check(&format!("{}match s {{ Circle(r) => r }}", SHAPES)),
+            vec!["non-exhaustive match on `s`: `Rect(_, _)`, `Empty` not covered"]);
+    }
+
+    #[test]
+    fn missing_nested_constructor() {
+        let input = "enum Tree { Leaf, Node(l, v, r) }\n\
+                     match t { Leaf => 0, Node(Leaf, v, _) => v }";
+        assert_eq!(
+            check(input),
+            vec!["non-exhaustive match on `t`: `Node(Node(_, _, _), _, _)` not covered"]);
+    }
+
+    #[test]
+    fn missing_bool() {
+        assert_eq!(
+            check("match b { true => 1 }"),
+            vec!["non-exhaustive match on `b`: `false` not covered"]);
+    }
+
+    #[test]
+    fn numbers_need_a_catch_all() {
+        assert_eq!(
+            check("match n { 0 => 1, 1 => 1 }"),
+            vec!["non-exhaustive match on `n`: `_` not covered"]);
+        assert!(check("match n { 0 => 1, n => n }").is_empty());
+    }
+
+    #[test]
+    fn guarded_arms_do_not_cover() {
+        assert_eq!(
+            check("match b { true => 1, false if c => 0 }"),
+            vec!["non-exhaustive match on `b`: `false` not covered"]);
+    }
+
+    #[test]
+    fn missing_list_patterns() {
+        assert_eq!(
+            check("match xs { [] => 0 }"),
+            vec!["non-exhaustive match on `xs`: `[_, .._]` not covered"]);
+        assert_eq!(
+            check("match xs { [] => 0, [x] => 1 }"),
+            vec!["non-exhaustive match on `xs`: `[_, _, .._]` not covered"]);
+        assert!(check("match xs { [] => 0, [x, ..rest] => 1 }").is_empty());
+    }
+
+    #[test]
+    fn tuple_patterns() {
+        assert_eq!(
+            check("match p { (true, _) => 0, (_, true) => 1 }"),
+            vec!["non-exhaustive match on `p`: `(false, false)` not covered"]);
+        assert_eq!(
+            check("match p { (a, b) => 0, (true, b) => 1 }"),
+            vec!["unreachable match arm `(true, b) => 1`"]);
+    }
+
+    #[test]
+    fn unreachable_after_wildcard() {
+        assert_eq!(
+            check(&format!("{}match s {{ _ => 0, Empty => 1 }}", SHAPES)),
+            vec!["unreachable match arm `Empty => 1`"]);
+    }
+
+    #[test]
+    fn unreachable_when_covered_by_earlier_arms() {
+        assert_eq!(
+            check("match b { true => 1, false => 0, x => 2 }"),
+            vec!["unreachable match arm `x => 2`"]);
+    }
+
+    #[test]
+    fn nested_matches_are_checked() {
+        assert_eq!(
+            check(&format!("{}let x = 1 + match s {{ Circle(r) => match r {{ 1 => 1 }}, _ => 0 }}", SHAPES)),
+            vec!["non-exhaustive match on `r`: `_` not covered"]);
+    }
+
+    #[test]
+    fn constructor_arity_mismatch() {
+        assert_eq!(
+            check(&format!("{}match s {{ Circle(a, b) => a, _ => 0 }}", SHAPES)),
+            vec!["constructor `Circle` has 1 field(s) but pattern `Circle(a, b)` has 2"]);
+    }
+
+    #[test]
+    fn unknown_constructor() {
+        assert_eq!(
+            check("match s { Square(x) => x, _ => 0 }"),
+            vec!["unknown constructor `Square` in pattern `Square(x)`"]);
+    }
+
+    #[test]
+    fn enums_are_remembered() {
+        let mut checker = Checker::default();
+        checker.check_decls(&Parser::new(SHAPES).parse().declarations);
+        let warnings = checker.check_decls(&Parser::new("match s { Empty => 0 }").parse().declarations);
+        assert_eq!(warnings.len(), 1);
+    }
+}
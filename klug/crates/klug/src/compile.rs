@@ -0,0 +1,697 @@
+// compile - lowers the syntax tree to bytecode for the `vm`
+//
+// Every function becomes a `Proto` with its own code, constants and local
+// slots. Names are resolved here rather than looked up while running: a
+// function's parameters and everything its body binds are slots, the names
+// it uses from enclosing functions are copied into its closure when that's
+// made, and only the top level looks names up by name. Adjacent functions
+// are compiled as a group sharing its captures, so they can call each other.
+
+pub(crate) mod klc;
+
+use std::collections::HashSet;
+use std::rc::Rc;
+use crate::interp::{env::Env, value::{Ctor, Value}, ErrorKind};
+use crate::parser::{
+    Span,
+    stmt::Stmt,
+    literal::Literal,
+    decl::{self, Decl, Function},
+    pattern::Pattern,
+    contract::{Clause, Contract},
+    ty::Type,
+    expr::{Expr, Arm, op::{InfixOp, PrefixOp}},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Op {
+    // push `consts[i]`
+    Const(usize),
+    Unit,
+    Local(usize),
+    // pop into a slot
+    SetLocal(usize),
+    Capture(usize),
+    // a closure of the i'th function in the running function's group
+    Sibling(usize),
+    // look up `names[i]` at the top level, it's unbound if it's neither declared nor a builtin
+    Global(usize),
+    // a name no enclosing code declares, so it can only be a builtin
+    Builtin(usize),
+    // pop and declare `names[i]` at the top level
+    Define(usize),
+    Pop,
+    Unary(PrefixOp),
+    Binary(InfixOp),
+    Jump(usize),
+    // call with the callee and N arguments on the stack, `sites[i]` is the call
+    Call(usize, usize),
+    // a call in tail position, made in place of the running function
+    TailCall(usize, usize),
+    Return,
+    // pop N captures and push a closure of each function in `groups[i]`
+    Closure(usize, usize),
+    Tuple(usize),
+    List(usize),
+    // pop a list and append it to the list under it
+    Spread,
+    // match the top of the stack against `patterns[i]` and bind its slots, or jump
+    Match(usize, usize),
+    // pop a match guard and jump if it's false
+    Guard(usize),
+    // fail on the top of the stack, no arm matched it
+    NoMatch,
+    Question,
+    Throw,
+    // until the matching `PopHandler`, a failure jumps to the handler
+    Catch(usize),
+    // a finally block, run for any failure
+    Finally(usize),
+    // `?` returning from the function jumps to its `ensures` clauses
+    Returned(usize),
+    PopHandler,
+    // the end of a finally block run for a failure, which carries on
+    Reraise,
+    // pop a condition of a `requires` clause, or an `ensures` one if true, `texts[i]` is how it's written
+    Require(usize, bool),
+    // pop the predicates of `contracts[i]` and the value under them, and push it monitored
+    Monitor(usize, bool),
+    // fail with `errors[i]`
+    Fail(usize),
+    // cast the top of the stack to `casts[i]`, a `let`'s annotation
+    Cast(usize),
+}
+
+// A compiled function, or the top level of a program
+#[derive(Debug, Default)]
+pub(crate) struct Proto {
+    // empty for anonymous functions and the top level
+    pub(crate) name: String,
+    pub(crate) span: Span,
+    // the annotations the arguments are cast to
+    pub(crate) params: Vec<Option<Type>>,
+    pub(crate) generics: Vec<String>,
+    pub(crate) ret: Option<Type>,
+    // the parameters come first
+    pub(crate) slots: usize,
+    pub(crate) code: Vec<Op>,
+    pub(crate) consts: Vec<Value>,
+    pub(crate) names: Vec<String>,
+    // NOTE shared by every closure made of a group
+    pub(crate) groups: Vec<Rc<[Proto]>>,
+    // a pattern with the slots of its bindings, from left to right
+    pub(crate) patterns: Vec<(Pattern, Vec<usize>)>,
+    // the calls, kept to place failures and blame the caller
+    pub(crate) sites: Vec<Rc<Expr>>,
+    pub(crate) texts: Vec<String>,
+    pub(crate) contracts: Vec<Shape>,
+    pub(crate) errors: Vec<(ErrorKind, String)>,
+    pub(crate) casts: Vec<Annotation>,
+}
+
+// An annotated `let`, with the type parameters in scope where it's declared
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Annotation {
+    pub(crate) name: String,
+    pub(crate) ty: Type,
+    pub(crate) generics: Vec<String>,
+    pub(crate) span: Span,
+}
+
+// A contract without its predicates, those are evaluated onto the stack in order
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Shape {
+    Flat(String),
+    Fn(Vec<Shape>, Box<Shape>),
+}
+
+impl Shape {
+    // how many predicates there are
+    pub(crate) fn flats(&self) -> usize {
+        match self {
+            Self::Flat(_) => 1,
+            Self::Fn(params, ret) => params.iter().map(|p| p.flats()).sum::<usize>() + ret.flats(),
+        }
+    }
+}
+
+// compiles the top level of a program, `env` has what earlier inputs declared
+pub(crate) fn compile(decls: &[Decl], env: &Env) -> Proto {
+    let mut compiler = Compiler { scopes: vec![Scope::new(vec![], vec![], false)], env, defined: HashSet::new() };
+    compiler.top_decls(decls);
+    compiler.scopes.pop().unwrap().proto
+}
+
+// A function being compiled
+struct Scope {
+    proto: Proto,
+    // the names bound so far with their slots, innermost last
+    locals: Vec<(String, usize)>,
+    // the names of the functions in its group
+    siblings: Vec<String>,
+    // the names it copies from enclosing functions, shared by its group
+    captures: Vec<String>,
+    // whether a call in tail position can replace it
+    // NOTE not when its result is checked, that's only known after the body
+    tail: bool,
+}
+
+impl Scope {
+    fn new(siblings: Vec<String>, captures: Vec<String>, tail: bool) -> Self {
+        Self { proto: Proto::default(), locals: Vec::new(), siblings, captures, tail }
+    }
+}
+
+enum Var {
+    Local(usize),
+    Capture(usize),
+    Sibling(usize),
+    // declared at the top level
+    Global,
+    Unknown,
+}
+
+struct Compiler<'a> {
+    // the enclosing functions, the top level first
+    scopes: Vec<Scope>,
+    env: &'a Env,
+    // the names declared so far at the top level
+    defined: HashSet<String>,
+}
+
+impl Compiler<'_> {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn proto(&mut self) -> &mut Proto {
+        &mut self.scope().proto
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let code = &mut self.proto().code;
+        code.push(op);
+        code.len() - 1
+    }
+
+    // points the jump at `at` to the next op
+    fn patch(&mut self, at: usize) {
+        let target = self.proto().code.len();
+        match &mut self.proto().code[at] {
+            Op::Jump(t) | Op::Match(_, t) | Op::Guard(t) | Op::Catch(t) | Op::Finally(t) | Op::Returned(t) => *t = target,
+            op => unreachable!("{:?} doesn't jump", op),
+        }
+    }
+
+    fn constant(&mut self, val: Value) {
+        let consts = &mut self.proto().consts;
+        consts.push(val);
+        let i = consts.len() - 1;
+        self.emit(Op::Const(i));
+    }
+
+    fn name(&mut self, name: &str) -> usize {
+        let names = &mut self.proto().names;
+        match names.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => {
+                names.push(name.to_string());
+                names.len() - 1
+            }
+        }
+    }
+
+    fn fail(&mut self, kind: ErrorKind, msg: String) {
+        let errors = &mut self.proto().errors;
+        errors.push((kind, msg));
+        let i = errors.len() - 1;
+        self.emit(Op::Fail(i));
+    }
+
+    // a new slot for `name`, visible until the locals are truncated
+    fn bind(&mut self, name: &str) -> usize {
+        let scope = self.scope();
+        let slot = scope.proto.slots;
+        scope.proto.slots += 1;
+        scope.locals.push((name.to_string(), slot));
+        slot
+    }
+
+    fn locals(&mut self) -> usize {
+        self.scope().locals.len()
+    }
+
+    fn truncate(&mut self, locals: usize) {
+        self.scope().locals.truncate(locals);
+    }
+
+    // NOTE a name an enclosing function binds is captured by every function in between
+    fn resolve(&mut self, depth: usize, name: &str) -> Var {
+        let scope = &self.scopes[depth];
+        if let Some((_, slot)) = scope.locals.iter().rev().find(|(n, _)| n == name) {
+            return Var::Local(*slot);
+        }
+        if let Some(i) = scope.siblings.iter().position(|n| n == name) {
+            return Var::Sibling(i);
+        }
+        if let Some(i) = scope.captures.iter().position(|n| n == name) {
+            return Var::Capture(i);
+        }
+        if depth == 0 {
+            return match self.defined.contains(name) || self.env.lookup(name).is_some() {
+                true => Var::Global,
+                false => Var::Unknown,
+            };
+        }
+        match self.resolve(depth - 1, name) {
+            Var::Unknown => Var::Unknown,
+            _ => {
+                let captures = &mut self.scopes[depth].captures;
+                captures.push(name.to_string());
+                Var::Capture(captures.len() - 1)
+            }
+        }
+    }
+
+    fn load(&mut self, name: &str) {
+        let depth = self.scopes.len() - 1;
+        let op = match self.resolve(depth, name) {
+            Var::Local(slot) => Op::Local(slot),
+            Var::Capture(i) => Op::Capture(i),
+            Var::Sibling(i) => Op::Sibling(i),
+            Var::Global => Op::Global(self.name(name)),
+            Var::Unknown if depth == 0 => Op::Global(self.name(name)),
+            Var::Unknown => Op::Builtin(self.name(name)),
+        };
+        self.emit(op);
+    }
+
+    // NOTE the top level leaves the value of the last declaration on the stack,
+    // the same as `exec_decls`
+    fn top_decls(&mut self, decls: &[Decl]) {
+        self.emit(Op::Unit);
+        let mut rest = decls;
+        while let Some(decl) = rest.first() {
+            self.emit(Op::Pop);
+            let fns = decl::leading_functions(rest);
+            if !fns.is_empty() {
+                self.group(&fns);
+                // NOTE the first of functions with the same name is the one that's visible
+                for f in fns.iter().rev() {
+                    self.define(&f.name);
+                }
+                self.emit(Op::Unit);
+                rest = &rest[fns.len()..];
+                continue;
+            }
+            match decl {
+                Decl::Let(name, ty, Some(expr), span) => {
+                    self.expr(expr, false);
+                    self.annotation(name, ty, span);
+                    self.define(name);
+                }
+                Decl::Enum(name, generics, variants) => {
+                    for variant in variants {
+                        self.constant(Ctor::new(name, generics, variant).value());
+                        self.define(&variant.name);
+                    }
+                }
+                Decl::Stmt(bx_stmt) => self.stmt(bx_stmt),
+                decl => self.decl(decl),
+            }
+            if !matches!(decl, Decl::Stmt(_)) {
+                self.emit(Op::Unit);
+            }
+            rest = &rest[1..];
+        }
+        self.emit(Op::Return);
+    }
+
+    fn define(&mut self, name: &str) {
+        let i = self.name(name);
+        self.emit(Op::Define(i));
+        self.defined.insert(name.to_string());
+    }
+
+    // declarations in a block, which bind slots and leave nothing on the stack
+    fn decls(&mut self, decls: &[Decl]) {
+        let mut rest = decls;
+        while let Some(decl) = rest.first() {
+            let fns = decl::leading_functions(rest);
+            if !fns.is_empty() {
+                self.group(&fns);
+                for f in fns.iter().rev() {
+                    let slot = self.bind(&f.name);
+                    self.emit(Op::SetLocal(slot));
+                }
+                rest = &rest[fns.len()..];
+                continue;
+            }
+            match decl {
+                Decl::Let(name, ty, Some(expr), span) => {
+                    self.expr(expr, false);
+                    self.annotation(name, ty, span);
+                    let slot = self.bind(name);
+                    self.emit(Op::SetLocal(slot));
+                }
+                Decl::Enum(name, generics, variants) => {
+                    for variant in variants {
+                        self.constant(Ctor::new(name, generics, variant).value());
+                        let slot = self.bind(&variant.name);
+                        self.emit(Op::SetLocal(slot));
+                    }
+                }
+                Decl::Stmt(bx_stmt) => {
+                    self.stmt(bx_stmt);
+                    self.emit(Op::Pop);
+                }
+                decl => self.decl(decl),
+            }
+            rest = &rest[1..];
+        }
+    }
+
+    // NOTE the enclosing functions' type parameters are known here, the vm only
+    // knows the running function's
+    fn annotation(&mut self, name: &str, ty: &Option<Type>, span: &Span) {
+        let Some(ty) = ty else { return };
+        let generics = self.scopes.iter().rev().flat_map(|s| s.proto.generics.iter().cloned()).collect();
+        let casts = &mut self.proto().casts;
+        casts.push(Annotation { name: name.to_string(), ty: ty.clone(), generics, span: span.clone() });
+        let i = casts.len() - 1;
+        self.emit(Op::Cast(i));
+    }
+
+    // the declarations that can only fail
+    fn decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Let(ident, _, None, _) => self.fail(ErrorKind::Name, format!("{} must be initialized", ident)),
+            Decl::Error(msg) => self.fail(ErrorKind::Syntax, msg.clone()),
+            decl => unreachable!("{:?} is compiled with the other declarations", decl),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(bx_expr) => self.expr(bx_expr, false),
+        }
+    }
+
+    // functions declared together, pushes a closure of each
+    fn group(&mut self, fns: &[&Rc<Function>]) {
+        let siblings: Vec<String> = fns.iter().map(|f| f.name.clone()).collect();
+        let mut captures = Vec::new();
+        let mut protos = Vec::new();
+        for f in fns {
+            let tail = f.ensures.is_empty() && f.ret.is_none();
+            self.scopes.push(Scope::new(siblings.clone(), captures, tail));
+            self.function(f);
+            let scope = self.scopes.pop().unwrap();
+            captures = scope.captures;
+            protos.push(scope.proto);
+        }
+        for name in &captures {
+            self.load(name);
+        }
+        let groups = &mut self.proto().groups;
+        groups.push(Rc::from(protos));
+        let i = groups.len() - 1;
+        self.emit(Op::Closure(i, captures.len()));
+    }
+
+    fn function(&mut self, f: &Function) {
+        let proto = self.proto();
+        proto.name = f.name.clone();
+        proto.span = f.span.clone();
+        proto.params = f.params.iter().map(|param| param.ty.clone()).collect();
+        proto.generics = f.generics.clone();
+        proto.ret = f.ret.clone();
+        for param in &f.params {
+            self.bind(&param.name);
+        }
+        self.clauses(&f.requires, false);
+        if f.ensures.is_empty() {
+            let tail = self.scope().tail;
+            self.expr(&f.body, tail);
+            self.emit(Op::Return);
+            return;
+        }
+        let returned = self.emit(Op::Returned(0));
+        self.expr(&f.body, false);
+        self.emit(Op::PopHandler);
+        self.patch(returned);
+        let result = self.bind("result");
+        self.emit(Op::SetLocal(result));
+        self.clauses(&f.ensures, true);
+        self.load("result");
+        self.emit(Op::Return);
+    }
+
+    // NOTE a value given a contract is rebound to its monitored version
+    fn clauses(&mut self, clauses: &[Clause], ensures: bool) {
+        for clause in clauses {
+            match clause {
+                Clause::Holds(cond) => {
+                    self.expr(cond, false);
+                    let texts = &mut self.proto().texts;
+                    texts.push(cond.to_string());
+                    let i = texts.len() - 1;
+                    self.emit(Op::Require(i, ensures));
+                }
+                Clause::Satisfies(name, contract) => {
+                    self.load(name);
+                    let shape = self.contract(contract);
+                    let contracts = &mut self.proto().contracts;
+                    contracts.push(shape);
+                    let i = contracts.len() - 1;
+                    self.emit(Op::Monitor(i, ensures));
+                    let slot = self.bind(name);
+                    self.emit(Op::SetLocal(slot));
+                }
+            }
+        }
+    }
+
+    fn contract(&mut self, contract: &Contract) -> Shape {
+        match contract {
+            Contract::Flat(pred) => {
+                self.expr(pred, false);
+                Shape::Flat(pred.to_string())
+            }
+            Contract::Fn(params, ret) => {
+                let params = params.iter().map(|p| self.contract(p)).collect();
+                Shape::Fn(params, Box::new(self.contract(ret)))
+            }
+        }
+    }
+
+    // NOTE `tail` is whether the expression's value is the function's result,
+    // the same positions `interp_tail` looks through
+    fn expr(&mut self, expr: &Expr, tail: bool) {
+        match expr {
+            Expr::Literal(Literal::IDENT(name)) => self.load(name),
+            Expr::Literal(Literal::INT(num)) => self.constant(Value::Int(*num)),
+            Expr::Literal(Literal::BIG(num)) => self.constant(Value::BigInt(num.clone())),
+            Expr::Literal(Literal::FLOAT(num)) => self.constant(Value::Float(*num)),
+            Expr::Literal(Literal::STRING(s)) => self.constant(Value::Str(s.clone())),
+            Expr::Literal(Literal::TRUE) => self.constant(Value::Bool(true)),
+            Expr::Literal(Literal::FALSE) => self.constant(Value::Bool(false)),
+
+            Expr::Unary(prefop, box_bdy) => {
+                self.expr(box_bdy, false);
+                self.emit(Op::Unary(*prefop));
+            }
+            Expr::Binary(box_lhs, infop, box_rhs) => {
+                self.expr(box_lhs, false);
+                self.expr(box_rhs, false);
+                self.emit(Op::Binary(*infop));
+            }
+            Expr::Grouping(box_bdy) => self.expr(box_bdy, tail),
+
+            Expr::Call(box_callee, args, _) => {
+                self.expr(box_callee, false);
+                for arg in args {
+                    self.expr(arg, false);
+                }
+                let sites = &mut self.proto().sites;
+                sites.push(Rc::new(expr.clone()));
+                let site = sites.len() - 1;
+                match tail && self.scope().tail {
+                    true => self.emit(Op::TailCall(args.len(), site)),
+                    false => self.emit(Op::Call(args.len(), site)),
+                };
+            }
+
+            Expr::Match(box_scrut, arms, _) => self.arms(box_scrut, arms, tail),
+
+            Expr::Block(decls, box_expr) => {
+                let locals = self.locals();
+                self.decls(decls);
+                self.expr(box_expr, tail);
+                self.truncate(locals);
+            }
+
+            Expr::Lambda(f) => self.group(&[f]),
+
+            Expr::Try(bdy) => {
+                self.expr(bdy, false);
+                self.emit(Op::Question);
+            }
+            Expr::Throw(bdy) => {
+                self.expr(bdy, false);
+                self.emit(Op::Throw);
+            }
+            Expr::TryCatch(bdy, catch, finally) => self.try_catch(bdy, catch, finally),
+
+            Expr::Tuple(items) if items.is_empty() => {
+                self.emit(Op::Unit);
+            }
+            Expr::Tuple(items) => {
+                for item in items {
+                    self.expr(item, false);
+                }
+                self.emit(Op::Tuple(items.len()));
+            }
+            Expr::List(items, rest) => {
+                for item in items {
+                    self.expr(item, false);
+                }
+                self.emit(Op::List(items.len()));
+                if let Some(rest) = rest {
+                    self.expr(rest, false);
+                    self.emit(Op::Spread);
+                }
+            }
+        }
+    }
+
+    // NOTE the scrutinee stays on the stack until an arm is chosen
+    fn arms(&mut self, scrut: &Expr, arms: &[Arm], tail: bool) {
+        self.expr(scrut, false);
+        let mut ends = Vec::new();
+        for arm in arms {
+            let locals = self.locals();
+            let mut names = Vec::new();
+            bindings(&arm.pattern, &mut names);
+            let slots = names.iter().map(|name| self.bind(name)).collect();
+            let patterns = &mut self.proto().patterns;
+            patterns.push((arm.pattern.clone(), slots));
+            let i = patterns.len() - 1;
+            let next = self.emit(Op::Match(i, 0));
+            let guard = arm.guard.as_ref().map(|guard| {
+                self.expr(guard, false);
+                self.emit(Op::Guard(0))
+            });
+            self.emit(Op::Pop);
+            self.expr(&arm.body, tail);
+            ends.push(self.emit(Op::Jump(0)));
+            self.patch(next);
+            if let Some(guard) = guard {
+                self.patch(guard);
+            }
+            self.truncate(locals);
+        }
+        self.emit(Op::NoMatch);
+        for end in ends {
+            self.patch(end);
+        }
+    }
+
+    // NOTE the finally block is compiled twice, for when the try ends and for
+    // when it fails
+    fn try_catch(&mut self, bdy: &Expr, catch: &Option<(String, Box<Expr>)>, finally: &Option<Box<Expr>>) {
+        let fin = finally.as_ref().map(|_| self.emit(Op::Finally(0)));
+        let handler = catch.as_ref().map(|_| self.emit(Op::Catch(0)));
+        self.expr(bdy, false);
+        if let (Some((name, handler_expr)), Some(handler)) = (catch, handler) {
+            self.emit(Op::PopHandler);
+            let done = self.emit(Op::Jump(0));
+            self.patch(handler);
+            let locals = self.locals();
+            let slot = self.bind(name);
+            self.emit(Op::SetLocal(slot));
+            self.expr(handler_expr, false);
+            self.truncate(locals);
+            self.patch(done);
+        }
+        if let (Some(finally), Some(fin)) = (finally, fin) {
+            self.emit(Op::PopHandler);
+            self.expr(finally, false);
+            self.emit(Op::Pop);
+            let end = self.emit(Op::Jump(0));
+            self.patch(fin);
+            self.expr(finally, false);
+            self.emit(Op::Pop);
+            self.emit(Op::Reraise);
+            self.patch(end);
+        }
+    }
+}
+
+// NOTE in the order `bind_pattern` binds them
+fn bindings(pat: &Pattern, names: &mut Vec<String>) {
+    match pat {
+        Pattern::Binding(name) => names.push(name.clone()),
+        Pattern::Constructor(_, pats) | Pattern::Tuple(pats) | Pattern::List(pats, None) => {
+            pats.iter().for_each(|pat| bindings(pat, names));
+        }
+        Pattern::List(pats, Some(rest)) => {
+            pats.iter().for_each(|pat| bindings(pat, names));
+            bindings(rest, names);
+        }
+        Pattern::Wildcard | Pattern::Literal(_) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn compile(input: &str) -> Proto {
+        super::compile(&Parser::new(input).parse().declarations, &Env::default())
+    }
+
+    #[test]
+    fn parameters_and_lets_are_slots() {
+        let proto = compile("fn f(a, b) { let c = a + b\n c }");
+        let f = &proto.groups[0][0];
+        assert_eq!(f.slots, 3);
+        assert_eq!(f.code, vec![
+            Op::Local(0), Op::Local(1), Op::Binary(InfixOp::Add), Op::SetLocal(2), Op::Local(2), Op::Return,
+        ]);
+    }
+
+    #[test]
+    fn enclosing_names_are_captured() {
+        let proto = compile("let x = 1\nfn f(y) { fn(z) x + y + z }");
+        let f = &proto.groups[0][0];
+        let lambda = &f.groups[0][0];
+        // f captures x for the lambda, which captures both
+        assert_eq!(f.code[..3], [Op::Capture(0), Op::Local(0), Op::Closure(0, 2)]);
+        assert_eq!(lambda.code[..3], [Op::Capture(0), Op::Capture(1), Op::Binary(InfixOp::Add)]);
+        assert!(proto.code.contains(&Op::Global(0)));
+    }
+
+    #[test]
+    fn adjacent_functions_are_siblings() {
+        let proto = compile("fn even(n) { match n { 0 => true, _ => odd(n - 1) } }\nfn odd(n) { match n { 0 => false, _ => even(n - 1) } }");
+        let group = &proto.groups[0];
+        assert_eq!(group.len(), 2);
+        assert!(group[0].code.contains(&Op::Sibling(1)));
+        assert!(group[1].code.contains(&Op::Sibling(0)));
+        assert!(group[0].code.contains(&Op::TailCall(1, 0)));
+    }
+
+    #[test]
+    fn checked_results_have_no_tail_calls() {
+        let proto = compile("fn f(n) -> Int { f(n) }");
+        assert!(proto.groups[0][0].code.contains(&Op::Call(1, 0)));
+    }
+
+    #[test]
+    fn undeclared_names_are_builtins() {
+        let proto = compile("fn f(x) { float(x) }");
+        assert_eq!(proto.groups[0][0].code[0], Op::Builtin(0));
+    }
+}
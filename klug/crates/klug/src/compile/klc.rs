@@ -0,0 +1,930 @@
+// klc - the file format of compiled programs
+//
+// A header, then a payload of a constant pool, a table of every function and
+// a line table. The header is
+//
+//     "\x7fKLC", version: u16, reserved: u16, payload length: u32, CRC-32 of the payload: u32
+//
+// with little endian numbers, and numbers in the payload are LEB128. Strings
+// and constants are indices into the pool, and functions refer to the groups
+// they make by indices into the table, which lists a function after every
+// function it makes, so the top level is last. The line table is where each
+// line of the source starts, so failures can be placed without the source.
+
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::rc::Rc;
+use num_bigint::BigInt;
+use super::{Annotation, Op, Proto, Shape};
+use crate::interp::{value::{Ctor, Value}, ErrorKind};
+use crate::parser::{
+    Span,
+    MAX_NESTING,
+    literal::Literal,
+    pattern::Pattern,
+    ty::Type,
+    expr::{Expr, op::{InfixOp, PrefixOp}},
+};
+
+// NOTE bumped whenever the format or the meaning of an `Op` changes
+pub(crate) const VERSION: u16 = 2;
+const MAGIC: &[u8; 4] = b"\x7fKLC";
+const HEADER: usize = 16;
+
+// A compiled program read back from a file
+pub(crate) struct Program {
+    pub(crate) main: Proto,
+    // where each line of the source starts, and how long it was
+    lines: Vec<usize>,
+    len: usize,
+}
+
+impl Program {
+    // the 1-based line:column of a byte offset in the source
+    // NOTE the column counts bytes, without the source it can't count characters
+    pub(crate) fn position(&self, offset: usize) -> Option<String> {
+        if offset > self.len {
+            return None;
+        }
+        let line = self.lines.iter().take_while(|start| **start <= offset).count();
+        Some(format!("{}:{}", line, offset - self.lines[line - 1] + 1))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum LoadError {
+    NotCompiled,
+    // built for another version of the format
+    Version(u16),
+    Corrupt(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotCompiled => write!(f, "not a compiled klug program"),
+            Self::Version(v) => write!(f, "compiled for bytecode version {} but this is version {}, rebuild it", v, VERSION),
+            Self::Corrupt(why) => write!(f, "corrupted compiled program, {}", why),
+        }
+    }
+}
+
+pub(crate) fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub(crate) fn write(main: &Proto, source: &str) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.proto(main);
+    let mut payload = Vec::new();
+    usize(&mut payload, w.consts);
+    payload.extend(w.pool);
+    usize(&mut payload, w.fns);
+    payload.extend(w.table);
+    let mut lines = vec![0];
+    lines.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+    usize(&mut payload, source.len());
+    usize(&mut payload, lines.len());
+    for start in lines {
+        usize(&mut payload, start);
+    }
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+    bytes.extend(0u16.to_le_bytes());
+    bytes.extend((payload.len() as u32).to_le_bytes());
+    bytes.extend(crc32(&payload).to_le_bytes());
+    bytes.extend(payload);
+    bytes
+}
+
+pub(crate) fn read(bytes: &[u8]) -> Result<Program, LoadError> {
+    if !is_compiled(bytes) {
+        return Err(LoadError::NotCompiled);
+    }
+    if bytes.len() < HEADER {
+        return Err(LoadError::Corrupt("the header is cut off".to_string()));
+    }
+    let word = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(LoadError::Version(version));
+    }
+    let payload = &bytes[HEADER..];
+    if payload.len() != word(8) as usize {
+        return Err(LoadError::Corrupt(format!("expected {} bytes after the header but there are {}", word(8), payload.len())));
+    }
+    if crc32(payload) != word(12) {
+        return Err(LoadError::Corrupt("the checksum doesn't match".to_string()));
+    }
+
+    let mut r = Reader { bytes: payload, at: 0, pool: Vec::new(), fns: Vec::new(), depth: 0 };
+    for _ in 0..r.usize()? {
+        let val = r.constant()?;
+        r.pool.push(val);
+    }
+    for _ in 0..r.usize()? {
+        let proto = r.proto()?;
+        r.fns.push(Some(proto));
+    }
+    let main = match r.fns.pop() {
+        Some(Some(main)) => main,
+        _ => return Err(r.corrupt("there are no functions")),
+    };
+    let len = r.usize()?;
+    let lines = (0..r.usize()?).map(|_| r.usize()).collect::<Result<Vec<_>, _>>()?;
+    if r.at != payload.len() {
+        return Err(r.corrupt("there's more after the line table"));
+    }
+    if lines.first() != Some(&0) || lines.windows(2).any(|w| w[0] >= w[1]) || lines.iter().any(|start| *start > len) {
+        return Err(r.corrupt("the line table is out of order"));
+    }
+    validate_main(&main).map_err(|why| LoadError::Corrupt(why.to_string()))?;
+    Ok(Program { main, lines, len })
+}
+
+// NOTE only a function is called, so only its code can blame a call site
+fn validate_main(main: &Proto) -> Result<(), &'static str> {
+    let blames = |op: &Op| matches!(op, Op::Require(..) | Op::Monitor(..));
+    if !main.params.is_empty() || main.ret.is_some() || main.code.iter().any(blames) {
+        return Err("the top level has a contract");
+    }
+    validate(main, 0, 1)
+}
+
+// NOTE everything the `vm` indexes is in bounds, and its stack is as big as
+// the slots that are set, but what's on the stack is checked as it runs
+fn validate(proto: &Proto, captures: usize, siblings: usize) -> Result<(), &'static str> {
+    let code = proto.code.len();
+    let ok = |i: usize, len: usize, what| if i < len { Ok(()) } else { Err(what) };
+    // NOTE the compiler only jumps forwards, and a loop would run forever as fuel only counts calls
+    let jump = |at: usize, t: usize| match t > at {
+        true => ok(t, code, "a jump is out of range"),
+        false => Err("a jump goes backwards"),
+    };
+    if proto.code.last() != Some(&Op::Return) {
+        return Err("a function doesn't end with a return");
+    }
+    let set = proto.code.iter().filter(|op| matches!(op, Op::SetLocal(_))).count();
+    let bound = proto.patterns.iter().map(|(_, slots)| slots.len()).sum::<usize>();
+    if proto.slots > proto.params.len() + set + bound {
+        return Err("there are more slots than are ever set");
+    }
+    for (at, op) in proto.code.iter().enumerate() {
+        match *op {
+            Op::Const(i) => ok(i, proto.consts.len(), "a constant is missing")?,
+            Op::Local(slot) | Op::SetLocal(slot) => ok(slot, proto.slots, "a slot is out of range")?,
+            Op::Capture(i) => ok(i, captures, "a capture is out of range")?,
+            Op::Sibling(i) => ok(i, siblings, "a sibling is out of range")?,
+            Op::Global(i) | Op::Builtin(i) | Op::Define(i) => ok(i, proto.names.len(), "a name is missing")?,
+            Op::Jump(t) | Op::Guard(t) | Op::Catch(t) | Op::Finally(t) | Op::Returned(t) => jump(at, t)?,
+            Op::Match(i, t) => {
+                ok(i, proto.patterns.len(), "a pattern is missing")?;
+                jump(at, t)?;
+                if proto.patterns[i].1.iter().any(|slot| *slot >= proto.slots) {
+                    return Err("a slot is out of range");
+                }
+            }
+            Op::Call(_, i) | Op::TailCall(_, i) => ok(i, proto.sites.len(), "a call is missing")?,
+            Op::Closure(i, n) => {
+                ok(i, proto.groups.len(), "a function is missing")?;
+                let group = &proto.groups[i];
+                for f in group.iter() {
+                    validate(f, n, group.len())?;
+                }
+            }
+            Op::Require(i, _) => ok(i, proto.texts.len(), "a contract is missing")?,
+            Op::Monitor(i, _) => ok(i, proto.contracts.len(), "a contract is missing")?,
+            Op::Fail(i) => ok(i, proto.errors.len(), "an error is missing")?,
+            Op::Cast(i) => ok(i, proto.casts.len(), "an annotation is missing")?,
+            Op::Unit | Op::Pop | Op::Unary(_) | Op::Binary(_) | Op::Return | Op::Tuple(_) | Op::List(_) | Op::Spread
+                | Op::NoMatch | Op::Question | Op::Throw | Op::PopHandler | Op::Reraise => (),
+        }
+    }
+    Ok(())
+}
+
+const INFIX_OPS: [InfixOp; 18] = [
+    InfixOp::Add, InfixOp::Sub, InfixOp::Mul, InfixOp::Div, InfixOp::IntDiv, InfixOp::Mod, InfixOp::Pow,
+    InfixOp::BitAnd, InfixOp::BitOr, InfixOp::BitXor, InfixOp::Shl, InfixOp::Shr,
+    InfixOp::Eq, InfixOp::NotEq, InfixOp::Lt, InfixOp::LtEq, InfixOp::Gt, InfixOp::GtEq,
+];
+const PREFIX_OPS: [PrefixOp; 3] = [PrefixOp::Neg, PrefixOp::Not, PrefixOp::BitNot];
+const ERROR_KINDS: [ErrorKind; 9] = [
+    ErrorKind::Syntax, ErrorKind::Name, ErrorKind::Type, ErrorKind::Arity, ErrorKind::Arith,
+    ErrorKind::Match, ErrorKind::Contract, ErrorKind::Throw, ErrorKind::Stack,
+];
+
+fn usize(buf: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+// NOTE zigzag encoded, so small negative numbers are small too
+fn i64(buf: &mut Vec<u8>, n: i64) {
+    usize(buf, ((n << 1) ^ (n >> 63)) as u64 as usize);
+}
+
+fn bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    usize(buf, bytes.len());
+    buf.extend(bytes);
+}
+
+#[derive(Default)]
+struct Writer {
+    pool: Vec<u8>,
+    consts: usize,
+    // NOTE by encoding, so that e.g. `0.0` and `-0.0` stay apart
+    index: HashMap<Vec<u8>, usize>,
+    table: Vec<u8>,
+    fns: usize,
+}
+
+impl Writer {
+    fn constant(&mut self, val: &Value) -> usize {
+        let mut buf = Vec::new();
+        match val {
+            Value::Int(n) => {
+                buf.push(0);
+                i64(&mut buf, *n);
+            }
+            Value::BigInt(n) => {
+                buf.push(1);
+                bytes(&mut buf, &n.to_signed_bytes_le());
+            }
+            Value::Float(n) => {
+                buf.push(2);
+                buf.extend(n.to_bits().to_le_bytes());
+            }
+            Value::Str(s) => {
+                buf.push(3);
+                bytes(&mut buf, s.as_bytes());
+            }
+            Value::Bool(b) => buf.extend([4, *b as u8]),
+            Value::Variant(ctor, args) if args.is_empty() => {
+                buf.push(5);
+                self.ctor(&mut buf, ctor);
+            }
+            Value::Constructor(ctor) => {
+                buf.push(6);
+                self.ctor(&mut buf, ctor);
+            }
+            val => unreachable!("{} isn't a constant", val),
+        }
+        if let Some(i) = self.index.get(&buf) {
+            return *i;
+        }
+        self.pool.extend(&buf);
+        self.index.insert(buf, self.consts);
+        self.consts += 1;
+        self.consts - 1
+    }
+
+    fn str(&mut self, buf: &mut Vec<u8>, s: &str) {
+        let i = self.constant(&Value::Str(s.to_string()));
+        usize(buf, i);
+    }
+
+    fn ctor(&mut self, buf: &mut Vec<u8>, ctor: &Ctor) {
+        self.str(buf, &ctor.name);
+        self.str(buf, &ctor.ty);
+        usize(buf, ctor.generics.len());
+        ctor.generics.iter().for_each(|name| self.str(buf, name));
+        usize(buf, ctor.fields.len());
+        ctor.fields.iter().for_each(|ty| self.optional_ty(buf, ty.as_ref()));
+    }
+
+    // writes a function after the functions it makes, returning its index in the table
+    fn proto(&mut self, proto: &Proto) -> usize {
+        let groups: Vec<Vec<usize>> = proto.groups.iter()
+            .map(|group| group.iter().map(|f| self.proto(f)).collect())
+            .collect();
+        let mut buf = Vec::new();
+        self.str(&mut buf, &proto.name);
+        span(&mut buf, &proto.span);
+        usize(&mut buf, proto.params.len());
+        for param in &proto.params {
+            self.optional_ty(&mut buf, param.as_ref());
+        }
+        usize(&mut buf, proto.generics.len());
+        for generic in &proto.generics {
+            self.str(&mut buf, generic);
+        }
+        self.optional_ty(&mut buf, proto.ret.as_ref());
+        usize(&mut buf, proto.slots);
+        usize(&mut buf, proto.code.len());
+        for op in &proto.code {
+            self.op(&mut buf, op);
+        }
+        usize(&mut buf, proto.consts.len());
+        for val in &proto.consts {
+            let i = self.constant(val);
+            usize(&mut buf, i);
+        }
+        usize(&mut buf, proto.names.len());
+        for name in &proto.names {
+            self.str(&mut buf, name);
+        }
+        usize(&mut buf, groups.len());
+        for group in groups {
+            usize(&mut buf, group.len());
+            group.into_iter().for_each(|f| usize(&mut buf, f));
+        }
+        usize(&mut buf, proto.patterns.len());
+        for (pat, slots) in &proto.patterns {
+            self.pattern(&mut buf, pat);
+            usize(&mut buf, slots.len());
+            slots.iter().for_each(|slot| usize(&mut buf, *slot));
+        }
+        usize(&mut buf, proto.sites.len());
+        for site in &proto.sites {
+            self.site(&mut buf, site);
+        }
+        usize(&mut buf, proto.texts.len());
+        for text in &proto.texts {
+            self.str(&mut buf, text);
+        }
+        usize(&mut buf, proto.contracts.len());
+        for shape in &proto.contracts {
+            self.shape(&mut buf, shape);
+        }
+        usize(&mut buf, proto.errors.len());
+        for (kind, msg) in &proto.errors {
+            buf.push(ERROR_KINDS.iter().position(|k| k == kind).unwrap() as u8);
+            self.str(&mut buf, msg);
+        }
+        usize(&mut buf, proto.casts.len());
+        for cast in &proto.casts {
+            self.str(&mut buf, &cast.name);
+            self.ty(&mut buf, &cast.ty);
+            usize(&mut buf, cast.generics.len());
+            for generic in &cast.generics {
+                self.str(&mut buf, generic);
+            }
+            span(&mut buf, &cast.span);
+        }
+        self.table.extend(buf);
+        self.fns += 1;
+        self.fns - 1
+    }
+
+    fn op(&mut self, buf: &mut Vec<u8>, op: &Op) {
+        let (code, args): (u8, &[usize]) = match op {
+            Op::Const(i) => (0, &[*i]),
+            Op::Unit => (1, &[]),
+            Op::Local(i) => (2, &[*i]),
+            Op::SetLocal(i) => (3, &[*i]),
+            Op::Capture(i) => (4, &[*i]),
+            Op::Sibling(i) => (5, &[*i]),
+            Op::Global(i) => (6, &[*i]),
+            Op::Builtin(i) => (7, &[*i]),
+            Op::Define(i) => (8, &[*i]),
+            Op::Pop => (9, &[]),
+            Op::Unary(op) => {
+                buf.extend([10, PREFIX_OPS.iter().position(|o| o == op).unwrap() as u8]);
+                return;
+            }
+            Op::Binary(op) => {
+                buf.extend([11, INFIX_OPS.iter().position(|o| o == op).unwrap() as u8]);
+                return;
+            }
+            Op::Jump(t) => (12, &[*t]),
+            Op::Call(argc, i) => (13, &[*argc, *i]),
+            Op::TailCall(argc, i) => (14, &[*argc, *i]),
+            Op::Return => (15, &[]),
+            Op::Closure(i, n) => (16, &[*i, *n]),
+            Op::Tuple(n) => (17, &[*n]),
+            Op::List(n) => (18, &[*n]),
+            Op::Spread => (19, &[]),
+            Op::Match(i, t) => (20, &[*i, *t]),
+            Op::Guard(t) => (21, &[*t]),
+            Op::NoMatch => (22, &[]),
+            Op::Question => (23, &[]),
+            Op::Throw => (24, &[]),
+            Op::Catch(t) => (25, &[*t]),
+            Op::Finally(t) => (26, &[*t]),
+            Op::Returned(t) => (27, &[*t]),
+            Op::PopHandler => (28, &[]),
+            Op::Reraise => (29, &[]),
+            Op::Require(i, ensures) => (30, &[*i, *ensures as usize]),
+            Op::Monitor(i, ensures) => (31, &[*i, *ensures as usize]),
+            Op::Fail(i) => (32, &[*i]),
+            Op::Cast(i) => (33, &[*i]),
+        };
+        buf.push(code);
+        args.iter().for_each(|arg| usize(buf, *arg));
+    }
+
+    fn ty(&mut self, buf: &mut Vec<u8>, ty: &Type) {
+        match ty {
+            Type::Named(name, args) => {
+                buf.push(0);
+                self.str(buf, name);
+                self.tys(buf, args);
+            }
+            Type::Fn(params, ret) => {
+                buf.push(1);
+                self.tys(buf, params);
+                self.ty(buf, ret);
+            }
+            Type::Tuple(items) => {
+                buf.push(2);
+                self.tys(buf, items);
+            }
+        }
+    }
+
+    fn tys(&mut self, buf: &mut Vec<u8>, tys: &[Type]) {
+        usize(buf, tys.len());
+        tys.iter().for_each(|ty| self.ty(buf, ty));
+    }
+
+    fn optional_ty(&mut self, buf: &mut Vec<u8>, ty: Option<&Type>) {
+        match ty {
+            Some(ty) => {
+                buf.push(1);
+                self.ty(buf, ty);
+            }
+            None => buf.push(0),
+        }
+    }
+
+    fn pattern(&mut self, buf: &mut Vec<u8>, pat: &Pattern) {
+        match pat {
+            Pattern::Wildcard => buf.push(0),
+            Pattern::Binding(name) => {
+                buf.push(1);
+                self.str(buf, name);
+            }
+            Pattern::Literal(lit) => {
+                buf.push(2);
+                let val = match lit {
+                    Literal::INT(n) => Value::Int(*n),
+                    Literal::BIG(n) => Value::BigInt(n.clone()),
+                    Literal::FLOAT(n) => Value::Float(*n),
+                    Literal::STRING(s) => Value::Str(s.clone()),
+                    Literal::TRUE => Value::Bool(true),
+                    Literal::FALSE => Value::Bool(false),
+                    Literal::IDENT(name) => unreachable!("{} isn't a literal pattern", name),
+                };
+                let i = self.constant(&val);
+                usize(buf, i);
+            }
+            Pattern::Constructor(name, pats) => {
+                buf.push(3);
+                self.str(buf, name);
+                self.patterns(buf, pats);
+            }
+            Pattern::Tuple(pats) => {
+                buf.push(4);
+                self.patterns(buf, pats);
+            }
+            Pattern::List(pats, rest) => {
+                buf.push(5);
+                self.patterns(buf, pats);
+                match rest {
+                    Some(rest) => {
+                        buf.push(1);
+                        self.pattern(buf, rest);
+                    }
+                    None => buf.push(0),
+                }
+            }
+        }
+    }
+
+    fn patterns(&mut self, buf: &mut Vec<u8>, pats: &[Pattern]) {
+        usize(buf, pats.len());
+        pats.iter().for_each(|pat| self.pattern(buf, pat));
+    }
+
+    // NOTE only how a call is written and where are needed, to blame and place it
+    fn site(&mut self, buf: &mut Vec<u8>, site: &Expr) {
+        match site {
+            Expr::Call(callee, args, sp) => {
+                self.str(buf, &callee.to_string());
+                usize(buf, args.len());
+                args.iter().for_each(|arg| self.str(buf, &arg.to_string()));
+                span(buf, sp);
+            }
+            site => unreachable!("{} isn't a call", site),
+        }
+    }
+
+    fn shape(&mut self, buf: &mut Vec<u8>, shape: &Shape) {
+        match shape {
+            Shape::Flat(label) => {
+                buf.push(0);
+                self.str(buf, label);
+            }
+            Shape::Fn(params, ret) => {
+                buf.push(1);
+                usize(buf, params.len());
+                params.iter().for_each(|p| self.shape(buf, p));
+                self.shape(buf, ret);
+            }
+        }
+    }
+}
+
+fn span(buf: &mut Vec<u8>, span: &Span) {
+    usize(buf, span.start);
+    usize(buf, span.end);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    pool: Vec<Value>,
+    // NOTE taken when a group uses them, a function is only in one group
+    fns: Vec<Option<Proto>>,
+    // how deeply types, patterns and contracts are nested, like the parser's limit
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn corrupt(&self, why: &str) -> LoadError {
+        LoadError::Corrupt(format!("{} at byte {}", why, HEADER + self.at))
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        let b = *self.bytes.get(self.at).ok_or_else(|| self.corrupt("it ends too soon"))?;
+        self.at += 1;
+        Ok(b)
+    }
+
+    fn usize(&mut self) -> Result<usize, LoadError> {
+        let mut n: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let b = self.u8()?;
+            n |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return usize::try_from(n).map_err(|_| self.corrupt("a number is too big"));
+            }
+        }
+        Err(self.corrupt("a number is too long"))
+    }
+
+    fn i64(&mut self) -> Result<i64, LoadError> {
+        let n = self.usize()? as u64;
+        Ok(((n >> 1) as i64) ^ -((n & 1) as i64))
+    }
+
+    fn bool(&mut self) -> Result<bool, LoadError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.corrupt("a flag isn't 0 or 1")),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<&[u8], LoadError> {
+        let len = self.usize()?;
+        let end = self.at.checked_add(len).filter(|end| *end <= self.bytes.len()).ok_or_else(|| self.corrupt("it ends too soon"))?;
+        let bytes = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let bytes = self.bytes()?.to_vec();
+        String::from_utf8(bytes).map_err(|_| self.corrupt("a string isn't UTF-8"))
+    }
+
+    fn constant(&mut self) -> Result<Value, LoadError> {
+        Ok(match self.u8()? {
+            0 => Value::Int(self.i64()?),
+            1 => Value::BigInt(BigInt::from_signed_bytes_le(self.bytes()?)),
+            2 => {
+                let bytes = self.bytes.get(self.at..self.at + 8).ok_or_else(|| self.corrupt("it ends too soon"))?;
+                self.at += 8;
+                Value::Float(f64::from_bits(u64::from_le_bytes(bytes.try_into().unwrap())))
+            }
+            3 => Value::Str(self.string()?),
+            4 => Value::Bool(self.bool()?),
+            5 => Value::Variant(self.ctor()?, vec![]),
+            6 => Value::Constructor(self.ctor()?),
+            _ => return Err(self.corrupt("unknown constant")),
+        })
+    }
+
+    fn ctor(&mut self) -> Result<Rc<Ctor>, LoadError> {
+        Ok(Rc::new(Ctor {
+            name: self.str()?,
+            ty: self.str()?,
+            generics: self.many(|r| r.str())?,
+            fields: self.many(|r| r.optional_ty())?,
+        }))
+    }
+
+    fn pooled(&mut self) -> Result<Value, LoadError> {
+        let i = self.usize()?;
+        self.pool.get(i).cloned().ok_or_else(|| self.corrupt("a constant is missing"))
+    }
+
+    fn str(&mut self) -> Result<String, LoadError> {
+        match self.pooled()? {
+            Value::Str(s) => Ok(s),
+            _ => Err(self.corrupt("a string isn't a string")),
+        }
+    }
+
+    fn many<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, LoadError>) -> Result<Vec<T>, LoadError> {
+        let len = self.usize()?;
+        // NOTE every item takes at least a byte, so a huge length fails here rather than allocating
+        if len > self.bytes.len() - self.at {
+            return Err(self.corrupt("a length is too long"));
+        }
+        (0..len).map(|_| item(self)).collect()
+    }
+
+    fn nested<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T, LoadError>) -> Result<T, LoadError> {
+        if self.depth >= MAX_NESTING {
+            return Err(self.corrupt("too deeply nested"));
+        }
+        self.depth += 1;
+        let ret = read(self);
+        self.depth -= 1;
+        ret
+    }
+
+    fn proto(&mut self) -> Result<Proto, LoadError> {
+        let name = self.str()?;
+        let span = self.span()?;
+        let params = self.many(|r| r.optional_ty())?;
+        let generics = self.many(|r| r.str())?;
+        let ret = self.optional_ty()?;
+        let slots = self.usize()?;
+        let code = self.many(|r| r.op())?;
+        let consts = self.many(|r| r.pooled())?;
+        let names = self.many(|r| r.str())?;
+        let groups = self.many(|r| {
+            let protos = r.many(|r| {
+                let i = r.usize()?;
+                r.fns.get_mut(i).and_then(Option::take).ok_or_else(|| r.corrupt("a function is missing"))
+            })?;
+            Ok(Rc::from(protos))
+        })?;
+        let patterns = self.many(|r| Ok((r.pattern()?, r.many(|r| r.usize())?)))?;
+        let sites = self.many(|r| r.site().map(Rc::new))?;
+        let texts = self.many(|r| r.str())?;
+        let contracts = self.many(|r| r.shape())?;
+        let errors = self.many(|r| {
+            let kind = *ERROR_KINDS.get(r.u8()? as usize).ok_or_else(|| r.corrupt("unknown error"))?;
+            Ok((kind, r.str()?))
+        })?;
+        let casts = self.many(|r| {
+            Ok(Annotation { name: r.str()?, ty: r.ty()?, generics: r.many(|r| r.str())?, span: r.span()? })
+        })?;
+        Ok(Proto { name, span, params, generics, ret, slots, code, consts, names, groups, patterns, sites, texts, contracts, errors, casts })
+    }
+
+    fn span(&mut self) -> Result<Span, LoadError> {
+        Ok(self.usize()?..self.usize()?)
+    }
+
+    fn op(&mut self) -> Result<Op, LoadError> {
+        Ok(match self.u8()? {
+            0 => Op::Const(self.usize()?),
+            1 => Op::Unit,
+            2 => Op::Local(self.usize()?),
+            3 => Op::SetLocal(self.usize()?),
+            4 => Op::Capture(self.usize()?),
+            5 => Op::Sibling(self.usize()?),
+            6 => Op::Global(self.usize()?),
+            7 => Op::Builtin(self.usize()?),
+            8 => Op::Define(self.usize()?),
+            9 => Op::Pop,
+            10 => Op::Unary(*PREFIX_OPS.get(self.u8()? as usize).ok_or_else(|| self.corrupt("unknown operator"))?),
+            11 => Op::Binary(*INFIX_OPS.get(self.u8()? as usize).ok_or_else(|| self.corrupt("unknown operator"))?),
+            12 => Op::Jump(self.usize()?),
+            13 => Op::Call(self.usize()?, self.usize()?),
+            14 => Op::TailCall(self.usize()?, self.usize()?),
+            15 => Op::Return,
+            16 => Op::Closure(self.usize()?, self.usize()?),
+            17 => Op::Tuple(self.usize()?),
+            18 => Op::List(self.usize()?),
+            19 => Op::Spread,
+            20 => Op::Match(self.usize()?, self.usize()?),
+            21 => Op::Guard(self.usize()?),
+            22 => Op::NoMatch,
+            23 => Op::Question,
+            24 => Op::Throw,
+            25 => Op::Catch(self.usize()?),
+            26 => Op::Finally(self.usize()?),
+            27 => Op::Returned(self.usize()?),
+            28 => Op::PopHandler,
+            29 => Op::Reraise,
+            30 => Op::Require(self.usize()?, self.bool()?),
+            31 => Op::Monitor(self.usize()?, self.bool()?),
+            32 => Op::Fail(self.usize()?),
+            33 => Op::Cast(self.usize()?),
+            _ => return Err(self.corrupt("unknown op")),
+        })
+    }
+
+    fn ty(&mut self) -> Result<Type, LoadError> {
+        self.nested(|r| match r.u8()? {
+            0 => Ok(Type::Named(r.str()?, r.many(|r| r.ty())?)),
+            1 => Ok(Type::Fn(r.many(|r| r.ty())?, Box::new(r.ty()?))),
+            2 => Ok(Type::Tuple(r.many(|r| r.ty())?)),
+            _ => Err(r.corrupt("unknown type")),
+        })
+    }
+
+    fn optional_ty(&mut self) -> Result<Option<Type>, LoadError> {
+        match self.bool()? {
+            true => self.ty().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, LoadError> {
+        self.nested(|r| match r.u8()? {
+            0 => Ok(Pattern::Wildcard),
+            1 => Ok(Pattern::Binding(r.str()?)),
+            2 => Ok(Pattern::Literal(match r.pooled()? {
+                Value::Int(n) => Literal::INT(n),
+                Value::BigInt(n) => Literal::BIG(n),
+                Value::Float(n) => Literal::FLOAT(n),
+                Value::Str(s) => Literal::STRING(s),
+                Value::Bool(true) => Literal::TRUE,
+                Value::Bool(false) => Literal::FALSE,
+                _ => return Err(r.corrupt("unknown literal")),
+            })),
+            3 => Ok(Pattern::Constructor(r.str()?, r.many(|r| r.pattern())?)),
+            4 => Ok(Pattern::Tuple(r.many(|r| r.pattern())?)),
+            5 => {
+                let pats = r.many(|r| r.pattern())?;
+                let rest = match r.bool()? {
+                    true => Some(Box::new(r.pattern()?)),
+                    false => None,
+                };
+                Ok(Pattern::List(pats, rest))
+            }
+            _ => Err(r.corrupt("unknown pattern")),
+        })
+    }
+
+    // NOTE a call is rebuilt from how it's written, it prints the same and has the same span
+    fn site(&mut self) -> Result<Expr, LoadError> {
+        let callee = Expr::Literal(Literal::IDENT(self.str()?));
+        let args = self.many(|r| Ok(Expr::Literal(Literal::IDENT(r.str()?))))?;
+        Ok(Expr::Call(Box::new(callee), args, self.span()?))
+    }
+
+    fn shape(&mut self) -> Result<Shape, LoadError> {
+        self.nested(|r| match r.u8()? {
+            0 => Ok(Shape::Flat(r.str()?)),
+            1 => Ok(Shape::Fn(r.many(|r| r.shape())?, Box::new(r.shape()?))),
+            _ => Err(r.corrupt("unknown contract")),
+        })
+    }
+}
+
+// CRC-32 as in zip and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::env::Env;
+    use crate::parser::Parser;
+
+    const PROGRAM: &str = "enum Shape { Circle(r: Float), Rect(w, h) }\n\
+                           fn area(s) -> Float requires s != Rect(0, 0) { match s { Circle(r) => 3.0 * r * r, Rect(w, h) => float(w * h) } }\n\
+                           fn total(shapes: List<Shape>) { match shapes { [] => 0.0, [s, ..rest] => area(s) + total(rest) } }\n\
+                           total([Circle(-0.0), Rect(2, 3), Rect(99999999999999999999, 0)])";
+
+    fn build(input: &str) -> (Proto, Vec<u8>) {
+        let main = super::super::compile(&Parser::new(input).parse().declarations, &Env::default());
+        let bytes = write(&main, input);
+        (main, bytes)
+    }
+
+    // NOTE sites are rebuilt, so they're compared by how they print
+    fn assert_same(p1: &Proto, p2: &Proto) {
+        assert_eq!((&p1.name, &p1.span, &p1.params, &p1.ret, p1.slots), (&p2.name, &p2.span, &p2.params, &p2.ret, p2.slots));
+        assert_eq!((&p1.code, &p1.names, &p1.patterns, &p1.texts, &p1.contracts, &p1.errors, &p1.casts),
+                   (&p2.code, &p2.names, &p2.patterns, &p2.texts, &p2.contracts, &p2.errors, &p2.casts));
+        assert_eq!(format!("{:?}", p1.consts), format!("{:?}", p2.consts));
+        let sites = |p: &Proto| p.sites.iter().map(|s| match &**s {
+            Expr::Call(_, _, span) => (s.to_string(), span.clone()),
+            _ => unreachable!(),
+        }).collect::<Vec<_>>();
+        assert_eq!(sites(p1), sites(p2));
+        assert_eq!(p1.groups.len(), p2.groups.len());
+        for (g1, g2) in p1.groups.iter().zip(&p2.groups) {
+            assert_eq!(g1.len(), g2.len());
+            g1.iter().zip(g2.iter()).for_each(|(f1, f2)| assert_same(f1, f2));
+        }
+    }
+
+    #[test]
+    fn programs_round_trip() {
+        let (main, bytes) = build(PROGRAM);
+        assert_same(&read(&bytes).unwrap().main, &main);
+    }
+
+    #[test]
+    fn positions_come_from_the_line_table() {
+        let (_, bytes) = build("let x = 1\n\nfn f() { x }");
+        let program = read(&bytes).unwrap();
+        assert_eq!(program.position(0), Some("1:1".to_string()));
+        assert_eq!(program.position(11), Some("3:1".to_string()));
+        assert_eq!(program.position(20), Some("3:10".to_string()));
+        assert_eq!(program.position(100), None);
+    }
+
+    #[test]
+    fn version_mismatches_are_errors() {
+        let (_, mut bytes) = build(PROGRAM);
+        bytes[4] = 99;
+        assert_eq!(read(&bytes).err(), Some(LoadError::Version(99)));
+    }
+
+    #[test]
+    fn corrupted_files_are_errors() {
+        let (_, bytes) = build(PROGRAM);
+        assert_eq!(read(b"fn f() { 1 }").err(), Some(LoadError::NotCompiled));
+        assert!(matches!(read(&bytes[..10]), Err(LoadError::Corrupt(_))));
+        assert!(matches!(read(&bytes[..bytes.len() - 1]), Err(LoadError::Corrupt(_))));
+        // every flipped bit is caught by the checksum
+        for i in HEADER..bytes.len() {
+            let mut bytes = bytes.clone();
+            bytes[i] ^= 1 << (i % 8);
+            assert_eq!(read(&bytes).err(), Some(LoadError::Corrupt("the checksum doesn't match".to_string())));
+        }
+    }
+
+    #[test]
+    fn checksummed_nonsense_is_an_error() {
+        let mut payload = vec![0, 1, 2];
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(0u16.to_le_bytes());
+        bytes.extend((payload.len() as u32).to_le_bytes());
+        bytes.extend(crc32(&payload).to_le_bytes());
+        bytes.append(&mut payload);
+        assert!(matches!(read(&bytes), Err(LoadError::Corrupt(_))));
+    }
+
+    // NOTE as if it had been damaged on purpose, with its checksum made to match
+    fn checksummed(mut bytes: Vec<u8>) -> Vec<u8> {
+        let crc = crc32(&bytes[HEADER..]);
+        bytes[12..16].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    fn run(bytes: &[u8]) -> Result<String, String> {
+        let mut session = crate::Session::default();
+        session.set_limits(crate::Limits { fuel: Some(10_000), ..crate::Limits::default() });
+        session.run_compiled(bytes)
+    }
+
+    #[test]
+    fn checksummed_damage_fails_to_load_or_run() {
+        let (mut main, _) = build("1 + 2");
+        main.code = vec![Op::Binary(InfixOp::Add), Op::Return];
+        assert_eq!(run(&write(&main, "1 + 2")), Ok("MSG: corrupt program: the stack underflows".to_string()));
+        main.code = vec![Op::Const(0), Op::Require(0, false), Op::Return];
+        main.texts = vec!["1".to_string()];
+        assert_eq!(read(&write(&main, "1 + 2")).err(), Some(LoadError::Corrupt("the top level has a contract".to_string())));
+        main.slots = usize::MAX;
+        assert!(matches!(read(&write(&main, "1 + 2")), Err(LoadError::Corrupt(_))));
+        let (mut main, _) = build("1 + 2");
+        main.code = vec![Op::Jump(0), Op::Return];
+        assert_eq!(read(&write(&main, "1 + 2")).err(), Some(LoadError::Corrupt("a jump goes backwards".to_string())));
+
+        let (_, bytes) = build(PROGRAM);
+        for at in HEADER..bytes.len() {
+            for byte in [0, 1, 2, 3, 0x7f, 0x80, 0xff] {
+                let mut bytes = bytes.clone();
+                bytes[at] = byte;
+                let bytes = checksummed(bytes);
+                if read(&bytes).is_ok() {
+                    let ran = std::panic::catch_unwind(|| run(&bytes));
+                    assert!(ran.is_ok(), "byte {} set to {} panics", at, byte);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
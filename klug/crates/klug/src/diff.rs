@@ -0,0 +1,318 @@
+// diff - differential testing of the engines against the tree walker
+//
+// A program is run from a fresh session by `Engine::Tree` and then by every
+// other engine, which must give the same value, or fail with the same kind of
+// error, message and span. A program that diverges is shrunk by removing its
+// tokens while it still diverges, so what's reported is as small as it gets.
+// Every program in `corpus/` is checked, and new engines go in `ENGINES`.
+
+use std::fmt;
+use std::thread;
+use crate::{vm, Engine, Session};
+use crate::interp::{exec_decls, set_fuel, set_max_depth, value::Value, ErrorKind, MAX_DEPTH};
+use crate::lexer::Lexer;
+use crate::parser::{Parser, Span};
+
+// every engine but the reference one
+const ENGINES: &[Engine] = &[Engine::Vm];
+
+// NOTE enough for every program in the corpus
+const FUEL: u64 = 1_000_000;
+
+// NOTE every program runs on a thread of its own with a stack this big, like
+// the CLI's, so the tree walker reaches `MAX_DEPTH` in a debug build
+const STACK_SIZE: usize = 256 << 20;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Outcome {
+    Value(String),
+    Error(ErrorKind, String, Option<Span>),
+    // NOTE not a result, the engines use their fuel at different rates
+    OutOfFuel,
+    // nor is this, the tree walker counts nested expressions against `MAX_DEPTH`
+    // and the `Vm` counts calls, so they don't stop at the same place
+    TooDeep,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Value(val) => write!(f, "`{}`", val),
+            Self::Error(kind, msg, Some(span)) => write!(f, "a {} error `{}` at {:?}", kind.name(), msg, span),
+            Self::Error(kind, msg, None) => write!(f, "a {} error `{}`", kind.name(), msg),
+            Self::OutOfFuel => write!(f, "nothing, it ran out of fuel"),
+            Self::TooDeep => write!(f, "nothing, it went too deep"),
+        }
+    }
+}
+
+// A program that an engine runs differently from the tree walker
+#[derive(Debug)]
+pub(crate) struct Divergence {
+    pub(crate) program: String,
+    pub(crate) engine: Engine,
+    pub(crate) expected: Outcome,
+    pub(crate) found: Outcome,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} gives {} but the tree walker gives {} for\n{}", self.engine, self.found, self.expected, self.program)
+    }
+}
+
+pub(crate) fn outcome(input: &str, engine: Engine) -> Outcome {
+    run(input, engine, FUEL).0
+}
+
+// the outcome of a program, and how much fuel it used
+fn run(input: &str, engine: Engine, fuel: u64) -> (Outcome, u64) {
+    let input = input.to_string();
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let parse = Parser::new(&input).parse();
+            let mut env = Session::default().env;
+            set_fuel(Some(fuel));
+            set_max_depth(MAX_DEPTH);
+            let ret = match engine {
+                Engine::Tree => exec_decls(&parse.declarations, &mut env),
+                Engine::Vm => vm::run(&parse.declarations, &mut env),
+            };
+            let used = fuel - set_fuel(None).unwrap();
+            let outcome = match ret {
+                Ok(Value::Unit) => Outcome::Value(String::new()),
+                Ok(val) => Outcome::Value(val.to_string()),
+                Err(e) if e.kind == ErrorKind::Stack && e.msg == "out of fuel" => Outcome::OutOfFuel,
+                Err(e) if e.kind == ErrorKind::Stack && e.msg == "stack overflow" => Outcome::TooDeep,
+                Err(e) => Outcome::Error(e.kind, e.msg, e.span),
+            };
+            (outcome, used)
+        })
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+// the first engine to run a program differently, if one does
+pub(crate) fn diverges(input: &str) -> Option<Divergence> {
+    diverges_with(input, FUEL)
+}
+
+fn diverges_with(input: &str, fuel: u64) -> Option<Divergence> {
+    let inconclusive = |outcome: &Outcome| matches!(outcome, Outcome::OutOfFuel | Outcome::TooDeep);
+    let (expected, _) = run(input, Engine::Tree, fuel);
+    if inconclusive(&expected) {
+        return None;
+    }
+    let (engine, found) = ENGINES.iter()
+        .map(|engine| (*engine, run(input, *engine, fuel).0))
+        .find(|(_, found)| !inconclusive(found) && *found != expected)?;
+    Some(Divergence { program: input.to_string(), engine, expected, found })
+}
+
+// the smallest program that diverges like a program does, if it does
+// NOTE removing tokens can make a program that never ends, so each one only
+// gets a few times the fuel it took to run the program being shrunk
+pub(crate) fn minimal_divergence(input: &str) -> Option<Divergence> {
+    let engine = diverges(input)?.engine;
+    let fuel = ENGINES.iter().chain([Engine::Tree].iter())
+        .map(|engine| run(input, *engine, FUEL).1)
+        .max()
+        .unwrap() * 4 + 100;
+    let program = shrink(input, |p| diverges_with(p, fuel).map(|d| d.engine) == Some(engine));
+    diverges_with(&program, fuel)
+}
+
+// removes tokens from a program for as long as `keep` still holds for it,
+// first in big runs, then by lines, a few at a time and brackets with what they
+// close, e.g. the `f(` and `)` around an argument, until none can be removed
+// NOTE what's left is written with the spacing it had, and a space where
+// tokens were removed, so it's still the same tokens when it's lexed again
+pub(crate) fn shrink(input: &str, keep: impl Fn(&str) -> bool) -> String {
+    let tokens: Vec<(usize, &str)> = Lexer::new(input)
+        .map(|(_, txt)| (txt.as_ptr() as usize - input.as_ptr() as usize, txt))
+        .collect();
+    let write = |kept: &[usize]| {
+        let mut program = String::new();
+        for (i, t) in kept.iter().enumerate() {
+            let (start, txt) = tokens[*t];
+            let prev_end = match t {
+                0 => 0,
+                _ => tokens[t - 1].0 + tokens[t - 1].1.len(),
+            };
+            if i > 0 && (start > prev_end || kept[i - 1] + 1 != *t) && txt != "\n" && !program.ends_with('\n') {
+                program.push(' ');
+            }
+            program.push_str(txt);
+        }
+        program
+    };
+    // removes the tokens at some positions in `kept` if `keep` still holds without them
+    let remove = |kept: &mut Vec<usize>, positions: &[usize]| {
+        let candidate: Vec<usize> = kept.iter().enumerate()
+            .filter(|(i, _)| !positions.contains(i))
+            .map(|(_, t)| *t)
+            .collect();
+        let removed = keep(&write(&candidate));
+        if removed {
+            *kept = candidate;
+        }
+        removed
+    };
+    let brackets = |kept: &[usize]| {
+        let mut pairs = Vec::new();
+        let mut open = Vec::new();
+        for (i, t) in kept.iter().enumerate() {
+            match tokens[*t].1 {
+                "(" | "[" | "{" => open.push(i),
+                ")" | "]" | "}" => pairs.extend(open.pop().map(|o| (o, i))),
+                _ => (),
+            }
+        }
+        pairs
+    };
+
+    // removes runs of tokens, at every position or only at multiples of `run`
+    let runs = |kept: &mut Vec<usize>, run: usize, every: bool| {
+        let mut removed = false;
+        let mut start = 0;
+        while start < kept.len() {
+            let positions: Vec<usize> = (start..(start + run).min(kept.len())).collect();
+            if remove(kept, &positions) {
+                removed = true;
+            } else {
+                start += if every { 1 } else { run };
+            }
+        }
+        removed
+    };
+
+    let mut kept: Vec<usize> = (0..tokens.len()).collect();
+    let mut run = kept.len() / 2;
+    while run > 4 {
+        runs(&mut kept, run, false);
+        run /= 2;
+    }
+    loop {
+        let mut removed = false;
+        // whole lines, which usually are whole declarations
+        let mut start = 0;
+        while start < kept.len() {
+            let end = (start..kept.len()).find(|i| tokens[kept[*i]].1 == "\n").map_or(kept.len(), |i| i + 1);
+            match remove(&mut kept, &(start..end).collect::<Vec<_>>()) {
+                true => removed = true,
+                false => start = end,
+            }
+        }
+        for run in (1..=4).rev() {
+            removed |= runs(&mut kept, run, true);
+        }
+        while brackets(&kept).into_iter().any(|(o, c)| {
+            if remove(&mut kept, &[o, c]) {
+                return true;
+            }
+            // NOTE with the callee, and those of the calls directly inside it, e.g. `f(g(x))` to `x`
+            let pairs = brackets(&kept);
+            let mut positions = vec![];
+            let (mut o, mut c) = (o, c);
+            while o > 0 {
+                positions.extend([o - 1, o, c]);
+                if remove(&mut kept, &positions) {
+                    return true;
+                }
+                match pairs.iter().find(|pair| **pair == (o + 2, c - 1)) {
+                    Some(inner) => (o, c) = *inner,
+                    None => break,
+                }
+            }
+            false
+        }) {
+            removed = true;
+        }
+        if !removed {
+            break;
+        }
+    }
+    write(&kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn the_corpus_runs_the_same_on_every_engine() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+        let mut programs = 0;
+        for entry in fs::read_dir(corpus).unwrap() {
+            let path = entry.unwrap().path();
+            let input = fs::read_to_string(&path).unwrap();
+            if let Some(divergence) = minimal_divergence(&input) {
+                panic!("{} diverges: {}", path.display(), divergence);
+            }
+            assert_ne!(outcome(&input, Engine::Tree), Outcome::OutOfFuel, "{} ran out of fuel", path.display());
+            programs += 1;
+        }
+        assert!(programs > 0);
+    }
+
+    #[test]
+    fn outcomes_are_values_and_errors() {
+        assert_eq!(outcome("fn f(x) { x * 2 }\nf(21)", Engine::Vm), Outcome::Value("42".to_string()));
+        assert_eq!(outcome("fn f(x) { x // 0 }\n\nf(1)", Engine::Tree), Outcome::Error(ErrorKind::Arith, "division by zero".to_string(), Some(20..24)));
+        assert_eq!(outcome("let x = 1", Engine::Tree), Outcome::Value(String::new()));
+    }
+
+    // NOTE the engines returned from a function differently, so it doesn't parse
+    #[test]
+    fn contract_clauses_never_return_on_any_engine() {
+        let input = "fn f(x) requires int(x)? > 0 { 1 }\ntry { f('z') } catch e { e }";
+        assert!(matches!(outcome(input, Engine::Tree), Outcome::Error(ErrorKind::Syntax, _, _)));
+        assert!(diverges(input).is_none());
+    }
+
+    #[test]
+    fn programs_that_never_end_are_inconclusive() {
+        assert_eq!(outcome("fn f(n) { f(n) }\nf(1)", Engine::Tree), Outcome::OutOfFuel);
+        assert_eq!(outcome("fn f(n) { f(n) }\nf(1)", Engine::Vm), Outcome::OutOfFuel);
+        assert!(diverges("fn f(n) { f(n) }\nf(1)").is_none());
+        // a program that ends has enough
+        assert_eq!(outcome("fn f(n) { match n { 0 => 0, _ => f(n - 1) } }\nf(10)", Engine::Tree), Outcome::Value("0".to_string()));
+    }
+
+    #[test]
+    fn programs_too_deep_for_an_engine_are_inconclusive() {
+        let input = "fn f(n) { match n { 0 => 0, _ => 1 + f(n - 1) } }\nf(2500)";
+        assert_eq!(outcome(input, Engine::Tree), Outcome::TooDeep);
+        assert_eq!(outcome(input, Engine::Vm), Outcome::Value("2500".to_string()));
+        assert!(diverges(input).is_none());
+    }
+
+    #[test]
+    fn shrinking_removes_every_token_it_can() {
+        let input = "enum Shape { Circle(r), Rect(w, h) }\nlet a = 3\nlet b = 0\n\
+                     fn area(s) { match s { Circle(r) => 3 * r * r, Rect(w, h) => w * h } }\n\
+                     (area(Rect(a, 2)), area(Circle(1)) + a // b, a + b)";
+        let fails = |p: &str| matches!(outcome(p, Engine::Tree), Outcome::Error(ErrorKind::Arith, ..));
+        let program = shrink(input, fails);
+        assert!(fails(&program));
+        assert_eq!(program, "let b = 0\n1 // b");
+        // removing any one token more and it doesn't fail
+        let tokens: Vec<&str> = Lexer::new(&program).map(|(_, txt)| txt).collect();
+        for i in 0..tokens.len() {
+            let mut fewer = tokens.clone();
+            fewer.remove(i);
+            assert!(!fails(&fewer.join(" ")), "{:?} still fails", fewer.join(" "));
+        }
+    }
+
+    #[test]
+    fn shrinking_keeps_joined_tokens_joined() {
+        // `>>` is two tokens, and a shift only if they're next to each other
+        let program = shrink("let x = 1\n8 >> 2", |p| p.contains('8') && outcome(p, Engine::Tree) == Outcome::Value("2".to_string()));
+        assert_eq!(program, "8 >> 2");
+    }
+}
@@ -0,0 +1,335 @@
+// fmt - formats source code
+//
+// A program is printed by `pretty`, so however it was laid out it comes out
+// the same, and then its comments are put back. The parser never sees them,
+// instead each comment goes with the code around it: one after code on the
+// same line goes at the end of the line that code is printed on, and one on
+// a line of its own goes on its own line before the code that followed it.
+// A blank line between declarations, or between match arms, is kept the same
+// way, though never more than one.
+
+use std::collections::HashSet;
+use crate::lexer::{Lexer, SyntaxKind};
+use crate::parser::{decl::Decl, Span};
+use crate::pretty::{self, doc};
+
+const INDENT: &str = "  ";
+
+// What's kept of the lines before a token
+#[derive(Debug, Clone, PartialEq)]
+enum Trivia {
+    Comment(String),
+    Blank,
+}
+
+// The tokens the parser sees, commas aside, and what surrounds them
+#[derive(Debug, Default)]
+struct Tokens {
+    kinds: Vec<SyntaxKind>,
+    spans: Vec<Span>,
+    // the comments and blank lines before each token, and before the end
+    leading: Vec<Vec<Trivia>>,
+    // the comments at the end of the line of each token
+    trailing: Vec<Vec<String>>,
+    // where the lines end, outside of strings
+    newlines: Vec<usize>,
+}
+
+// NOTE `decls` are what `input` parses to, without any errors
+pub(crate) fn format(input: &str, decls: &[Decl]) -> String {
+    let source = tokens(input);
+    let mut doc = pretty::document(decls);
+    loop {
+        let (printed, unbroken) = doc::layout(&doc);
+        let output = tokens(&printed);
+        let at = align(&source.kinds, &output.kinds);
+        // NOTE a comment that would be moved off the line it's on instead
+        // breaks the group that put the code around it on one line
+        let groups: HashSet<usize> = output.joined(&source, &at)
+            .into_iter()
+            .flat_map(|gap| unbroken.iter().filter(move |(offset, _)| gap.contains(offset)))
+            .map(|(_, group)| *group)
+            .collect();
+        if groups.is_empty() {
+            return restore(&source, &output, &at, &printed);
+        }
+        doc::force(&mut doc, &groups);
+    }
+}
+
+// `printed` with the comments and blank lines of the source put back
+fn restore(source: &Tokens, output: &Tokens, at: &[usize], printed: &str) -> String {
+    let mut inserts: Vec<(usize, String)> = Vec::new();
+    for (i, leading) in source.leading.iter().enumerate() {
+        if leading.is_empty() {
+            continue;
+        }
+        match at.get(i) {
+            Some(j) if *j < output.kinds.len() => inserts.push(output.before(*j, leading, printed)),
+            // NOTE what follows the last token goes at the end
+            _ => inserts.push((printed.len(), lines(leading, ""))),
+        }
+    }
+    for (i, trailing) in source.trailing.iter().enumerate() {
+        if !trailing.is_empty() {
+            let end = output.line_end(output.spans[printed_as(source, output, at, i)].end, printed);
+            inserts.push((end, format!(" {}", trailing.join(" "))));
+        }
+    }
+    // NOTE the sort is stable, so what goes in the same place stays in source order
+    inserts.sort_by_key(|(offset, _)| *offset);
+    let mut out = String::with_capacity(printed.len());
+    let mut last = 0;
+    for (offset, text) in inserts {
+        out.push_str(&printed[last..offset]);
+        out.push_str(&text);
+        last = offset;
+    }
+    out.push_str(&printed[last..]);
+    out
+}
+
+// NOTE a token that isn't printed, e.g. the parentheses around a type, leaves
+// its comments to the token before where it would have been
+fn printed_as(source: &Tokens, output: &Tokens, at: &[usize], i: usize) -> usize {
+    match at[i] {
+        j if j < output.kinds.len() && output.kinds[j] == source.kinds[i] => j,
+        j => j.saturating_sub(1),
+    }
+}
+
+fn tokens(input: &str) -> Tokens {
+    let mut tokens = Tokens::default();
+    let mut pending = Vec::new();
+    // the newlines since the last token or comment, two make a blank line
+    let mut newlines = 0;
+    let mut on_line = false;
+    for (kind, text) in Lexer::with_comments(input) {
+        // NOTE every token is a slice of the source, so its offset is recoverable from the pointers
+        let start = text.as_ptr() as usize - input.as_ptr() as usize;
+        match kind {
+            SyntaxKind::Newline => {
+                tokens.newlines.push(start);
+                newlines += 1;
+                on_line = false;
+            }
+            SyntaxKind::Comment if on_line && !tokens.kinds.is_empty() => {
+                tokens.trailing.last_mut().unwrap().push(text.trim_end().to_string());
+            }
+            SyntaxKind::Comment => {
+                if newlines >= 2 && !(tokens.kinds.is_empty() && pending.is_empty()) {
+                    pending.push(Trivia::Blank);
+                }
+                pending.push(Trivia::Comment(text.trim_end().to_string()));
+                newlines = 0;
+            }
+            SyntaxKind::Comma => {
+                newlines = 0;
+                on_line = true;
+            }
+            _ => {
+                if newlines >= 2 && !(tokens.kinds.is_empty() && pending.is_empty()) {
+                    pending.push(Trivia::Blank);
+                }
+                tokens.leading.push(std::mem::take(&mut pending));
+                tokens.trailing.push(Vec::new());
+                tokens.kinds.push(kind);
+                tokens.spans.push(start..start + text.len());
+                newlines = 0;
+                on_line = true;
+            }
+        }
+    }
+    // NOTE a blank line is only pushed before something, so none at the end are kept
+    tokens.leading.push(pending);
+    tokens
+}
+
+// for each token of the source, the token of the output it's printed as,
+// or the one after where it would have been if it isn't printed
+fn align(source: &[SyntaxKind], output: &[SyntaxKind]) -> Vec<usize> {
+    // NOTE parentheses around a type or a contract, and empty generics or
+    // fields, are the only tokens that aren't printed
+    let optional = |kind: SyntaxKind| matches!(kind,
+        SyntaxKind::LParen | SyntaxKind::RParen | SyntaxKind::LessThan | SyntaxKind::GreaterThan);
+    let mut at = Vec::with_capacity(source.len());
+    let mut j = 0;
+    for kind in source {
+        let printed = |j: usize| j < output.len() && (output[j] == *kind || !optional(*kind));
+        while j < output.len() && output[j] != *kind && optional(output[j]) && !optional(*kind) {
+            j += 1;
+        }
+        at.push(j);
+        if printed(j) {
+            j += 1;
+        }
+    }
+    at
+}
+
+impl Tokens {
+    // the gaps between the printed tokens that a comment needs a line break in,
+    // one after a token with a comment at the end of its line, or one before
+    // a token with a comment on the line above
+    fn joined(&self, source: &Tokens, at: &[usize]) -> Vec<Span> {
+        let mut gaps = Vec::new();
+        for i in 0..source.kinds.len() {
+            let comment = |trivia: &[Trivia]| trivia.iter().any(|t| matches!(t, Trivia::Comment(_)));
+            if !source.trailing[i].is_empty() {
+                let j = printed_as(source, self, at, i);
+                if j + 1 < self.kinds.len() {
+                    gaps.push(self.spans[j].end..self.spans[j + 1].start);
+                }
+            }
+            if comment(&source.leading[i]) && at[i] > 0 && at[i] < self.kinds.len() {
+                gaps.push(self.spans[at[i] - 1].end..self.spans[at[i]].start);
+            }
+        }
+        // NOTE a gap that already has a line break is fine
+        gaps.retain(|gap| !self.newlines.iter().any(|n| gap.contains(n)));
+        gaps
+    }
+
+    // where the comments and blank lines before token `j` go, and how they're written
+    fn before(&self, j: usize, trivia: &[Trivia], printed: &str) -> (usize, String) {
+        let start = self.line_start(self.spans[j].start);
+        let first = self.spans.partition_point(|span| span.start < start);
+        let mut indent = printed[start..].chars().take_while(|c| *c == ' ').collect::<String>();
+        if matches!(self.kinds[first], SyntaxKind::RBrace | SyntaxKind::RParen | SyntaxKind::RBracket) {
+            indent.push_str(INDENT);
+        }
+        // NOTE blank lines only stay between lines that were kept apart, not
+        // at the start or the end of a block or a list
+        let opened = first > 0 && matches!(self.kinds[first - 1], SyntaxKind::LBrace | SyntaxKind::LParen | SyntaxKind::LBracket);
+        let closing = matches!(self.kinds[first], SyntaxKind::RBrace | SyntaxKind::RParen | SyntaxKind::RBracket);
+        let mut trivia = trivia.to_vec();
+        if first != j {
+            trivia.retain(|t| *t != Trivia::Blank);
+        }
+        if opened && trivia.first() == Some(&Trivia::Blank) {
+            trivia.remove(0);
+        }
+        if closing && trivia.last() == Some(&Trivia::Blank) {
+            trivia.pop();
+        }
+        (start, lines(&trivia, &indent))
+    }
+
+    fn line_start(&self, offset: usize) -> usize {
+        match self.newlines.partition_point(|n| *n < offset) {
+            0 => 0,
+            i => self.newlines[i - 1] + 1,
+        }
+    }
+
+    fn line_end(&self, offset: usize, printed: &str) -> usize {
+        let i = self.newlines.partition_point(|n| *n < offset);
+        self.newlines.get(i).copied().unwrap_or(printed.len())
+    }
+}
+
+// each comment on its own line, and blank lines as empty ones
+fn lines(trivia: &[Trivia], indent: &str) -> String {
+    trivia.iter().map(|t| match t {
+        Trivia::Comment(text) => format!("{}{}\n", indent, text),
+        Trivia::Blank => "\n".to_string(),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+    use crate::parser::Parser;
+    use super::*;
+
+    fn check(input: &str, expected: &str) {
+        let formatted = crate::format(input).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(crate::format(&formatted).unwrap(), formatted, "formatting {:?} again changed it", formatted);
+    }
+
+    // NOTE the printer is faithful, so programs that print the same parsed the same
+    fn same_program(a: &str, b: &str) -> bool {
+        pretty::program(&Parser::new(a).parse().declarations) == pretty::program(&Parser::new(b).parse().declarations)
+    }
+
+    #[test]
+    fn spaces_operators_and_indents_blocks() {
+        check("let x=1+2*  3\nfn f(x){\nlet y=-x\n      y}", "let x = 1 + 2 * 3\nfn f(x) {\n  let y = -x\n  y\n}\n");
+        check("fn f(x) {\n  x\n}\nmatch  f(1) {\n1=>true\n_=>false }", "fn f(x) { x }\nmatch f(1) { 1 => true, _ => false }\n");
+    }
+
+    #[test]
+    fn wraps_long_expressions() {
+        let item = "x".repeat(50);
+        check(
+            &format!("let xs = [{}, {}, ..ys]", item, item),
+            &format!("let xs = [\n  {},\n  {},\n  ..ys\n]\n", item, item));
+        check(
+            &format!("let n = f({}) + g({}) + h(1)", item, item),
+            &format!("let n = f({}) +\n  g({}) +\n  h(1)\n", item, item));
+        check(
+            &format!("fn f(x) {{ match x {{ 1 => {}, _ => {} }} }}", item, item),
+            &format!("fn f(x) {{\n  match x {{\n    1 => {}\n    _ => {}\n  }}\n}}\n", item, item));
+    }
+
+    #[test]
+    fn keeps_comments() {
+        check("# double it\nfn f(x) { x * 2 } # cheap\n# the end", "# double it\nfn f(x) { x * 2 } # cheap\n# the end\n");
+        check("fn f(x) {\n    # first\n  let y = x\n  y # then\n  # done\n}", "fn f(x) {\n  # first\n  let y = x\n  y # then\n  # done\n}\n");
+        check("let s = '# not a comment'", "let s = '# not a comment'\n");
+    }
+
+    #[test]
+    fn breaks_lines_to_keep_comments_in_place() {
+        check("let xs = [1, # one\n  2]", "let xs = [\n  1, # one\n  2,\n]\n");
+        check("let n = 1 + # one\n  2", "let n = 1 + # one\n  2\n");
+        check("match x {\n  # small\n  0 => 1, _ => 2 }", "match x {\n  # small\n  0 => 1\n  _ => 2\n}\n");
+    }
+
+    #[test]
+    fn comments_stay_at_every_try_clause() {
+        let input = "let x = try { 1 } # t\ncatch e { 2 } # c\nfinally { 3 } # f\nx";
+        check(input, "let x = try { 1 } # t\ncatch e { 2 } # c\nfinally { 3 } # f\nx\n");
+        let input = "let y = try { # t\n  # body\n  1 # one\n} catch e { # c\n  # handler\n  2\n  # after\n} # caught\nfinally { # f\n  3 } # done";
+        let expected = "let y = try { # t\n  # body\n  1 # one\n} catch e { # c\n  # handler\n  2\n  # after\n} # caught\nfinally { # f\n  3\n} # done\n";
+        check(input, expected);
+        assert!(same_program(input, expected));
+        check("try { f() } catch e { 0 } # c", "try { f() } catch e { 0 } # c\n");
+    }
+
+    #[test]
+    fn keeps_single_blank_lines() {
+        check("\n\nlet x = 1\n\n\n\nlet y = 2\n\n", "let x = 1\n\nlet y = 2\n");
+        check("# about x\n\nlet x = 1\n# about y\n\n\nlet y = 2", "# about x\n\nlet x = 1\n# about y\n\nlet y = 2\n");
+        check("fn f(x) {\n\n  let y = x\n\n  y\n\n}", "fn f(x) {\n  let y = x\n\n  y\n}\n");
+    }
+
+    #[test]
+    fn comments_on_tokens_that_arent_printed_stay() {
+        check("enum Shape { Circle(), # round\n  Empty }", "enum Shape {\n  Circle, # round\n  Empty\n}\n");
+    }
+
+    #[test]
+    fn programs_with_syntax_errors_arent_formatted() {
+        assert_eq!(crate::format("let = 1"), Err(vec!["error: Expected Ident but got \"=\"".to_string()]));
+    }
+
+    #[test]
+    fn floats_too_large_to_write_back_arent_formatted() {
+        let input = format!("let x = 1{}.0", "0".repeat(400));
+        assert_eq!(crate::format(&input), Err(vec![format!("error: float literal 1{}.0 is too large", "0".repeat(400))]));
+    }
+
+    #[test]
+    fn the_corpus_formats_to_the_same_programs() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+        for entry in fs::read_dir(corpus).unwrap() {
+            let path = entry.unwrap().path();
+            let input = fs::read_to_string(&path).unwrap();
+            let formatted = crate::format(&input).unwrap();
+            assert!(same_program(&input, &formatted), "formatting {} changed it", path.display());
+            assert_eq!(crate::format(&formatted).unwrap(), formatted, "formatting {} isn't idempotent", path.display());
+        }
+    }
+}
@@ -0,0 +1,18 @@
+// fuzzing - entry points for the fuzz targets in `fuzz/`
+//
+// The lexer and parser aren't part of the public API, so the fuzz targets go
+// through these instead. They only exist with the `fuzzing` feature, and
+// return something small so the work they do can't be optimized away.
+
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+// how many tokens `input` lexes to
+pub fn lex(input: &str) -> usize {
+    Lexer::new(input).count()
+}
+
+// how many declarations `input` parses to, including the ones with errors
+pub fn parse(input: &str) -> usize {
+    Parser::new(input).parse().declarations.len()
+}
@@ -0,0 +1,981 @@
+// gen - random programs for property tests
+//
+// Programs are generated top down from the grammar in `parser/`, each
+// expression from the type it should have, so a typed program type checks.
+// An untyped one now and then gives an expression some other type, declares
+// enums and uses `?` outside of functions, so it also fails the ways programs
+// can. Trees are shaped the way the parser shapes them, with a `Grouping`
+// wherever precedence calls for one, so one printed and parsed back is the
+// same tree. A seed always gives the same program.
+// NOTE functions only recurse by counting down, a random recursive function
+// would soon square numbers too big to ever finish multiplying
+
+use std::rc::Rc;
+use num_bigint::BigInt;
+use crate::parser::{
+    contract::{Clause, Contract},
+    decl::{Decl, Field, Function, Param, Variant},
+    expr::{op::{InfixOp, PrefixOp}, Arm, Expr},
+    literal::Literal,
+    pattern::Pattern,
+    stmt::Stmt,
+    ty::Type,
+};
+
+// splitmix64
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // a number in 0..n
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    // true `percent` times in a hundred
+    pub(crate) fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+
+    pub(crate) fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+// The types expressions are generated for
+#[derive(Debug, Clone, PartialEq)]
+enum Ty {
+    Int,
+    Float,
+    Bool,
+    Str,
+    List(Box<Ty>),
+    Pair(Box<Ty>, Box<Ty>),
+    Option(Box<Ty>),
+    Fn(Vec<Ty>, Box<Ty>),
+}
+
+impl Ty {
+    fn annotation(&self) -> Type {
+        let named = |name: &str, args: Vec<Type>| Type::Named(name.to_string(), args);
+        match self {
+            Self::Int => named("Int", vec![]),
+            Self::Float => named("Float", vec![]),
+            Self::Bool => named("Bool", vec![]),
+            Self::Str => named("Str", vec![]),
+            Self::List(item) => named("List", vec![item.annotation()]),
+            Self::Pair(a, b) => Type::Tuple(vec![a.annotation(), b.annotation()]),
+            Self::Option(item) => named("Option", vec![item.annotation()]),
+            Self::Fn(params, ret) => Type::Fn(params.iter().map(Self::annotation).collect(), Box::new(ret.annotation())),
+        }
+    }
+}
+
+// a program for property tests, one that type checks if it's `typed`
+pub(crate) fn program(seed: u64, typed: bool) -> Vec<Decl> {
+    let mut gen = Gen { rng: Rng::new(seed), typed, scope: vec![], ctors: vec![], returns: vec![], names: 0, depth: 0 };
+    gen.program()
+}
+
+struct Gen {
+    rng: Rng,
+    typed: bool,
+    // the variables in scope, innermost last
+    scope: Vec<(String, Ty)>,
+    // the constructors of the enums declared so far, with how many fields they have
+    ctors: Vec<(String, usize)>,
+    // what the functions being generated return, innermost last, none in a
+    // contract clause, which `?` can't return from
+    returns: Vec<Option<Ty>>,
+    names: usize,
+    // how many more levels expressions can nest
+    depth: usize,
+}
+
+// NOTE a seed is more readable if its programs are small
+const MAX_DEPTH: usize = 5;
+
+impl Gen {
+    fn program(&mut self) -> Vec<Decl> {
+        let mut decls: Vec<Decl> = (0..self.rng.below(5)).map(|_| self.decl()).collect();
+        let ty = self.ty(2);
+        self.depth = 1 + self.rng.below(MAX_DEPTH);
+        decls.push(stmt(self.expr(&ty)));
+        decls
+    }
+
+    fn name(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("{}{}", prefix, self.names)
+    }
+
+    fn ty(&mut self, depth: usize) -> Ty {
+        let n = match depth {
+            0 => 4,
+            _ => 8,
+        };
+        let inner = |gen: &mut Self| Box::new(gen.ty(depth - 1));
+        match self.rng.below(n) {
+            0 => Ty::Int,
+            1 => Ty::Float,
+            2 => Ty::Bool,
+            3 => Ty::Str,
+            4 => Ty::List(inner(self)),
+            5 => Ty::Pair(inner(self), inner(self)),
+            6 => Ty::Option(inner(self)),
+            _ => {
+                let params = (0..self.rng.below(3)).map(|_| self.ty(depth - 1)).collect();
+                Ty::Fn(params, inner(self))
+            }
+        }
+    }
+
+    // NOTE each top level declaration is as deep as it gets, what's inside one shares it
+    fn decl(&mut self) -> Decl {
+        self.depth = 1 + self.rng.below(MAX_DEPTH);
+        match self.rng.below(10) {
+            0..=3 => self.let_decl(),
+            4 | 5 => self.fn_decl(),
+            6 => self.countdown(),
+            7 if !self.typed => self.enum_decl(),
+            _ => {
+                let ty = self.ty(1);
+                stmt(self.expr(&ty))
+            }
+        }
+    }
+
+    fn let_decl(&mut self) -> Decl {
+        let ty = self.ty(2);
+        let name = self.name("v");
+        if !self.typed && self.rng.chance(5) {
+            return Decl::Let(name, None, None, 0..0);
+        }
+        let value = self.expr(&ty);
+        let annotation = match self.rng.chance(30) {
+            true => Some(ty.annotation()),
+            false => None,
+        };
+        self.scope.push((name.clone(), ty));
+        Decl::Let(name, annotation, Some(value), 0..0)
+    }
+
+    // NOTE a typed function is annotated, so it's as general as it's meant to be
+    fn fn_decl(&mut self) -> Decl {
+        let name = self.name("f");
+        let params: Vec<(String, Ty)> = (0..self.rng.below(3)).map(|_| (self.name("x"), self.ty(1))).collect();
+        let ret = match self.typed && self.rng.chance(30) {
+            true => Ty::Option(Box::new(self.ty(1))),
+            false => self.ty(2),
+        };
+        let scope = self.scope.len();
+        self.scope.extend(params.iter().cloned());
+        let mut requires = vec![];
+        if self.rng.chance(20) {
+            requires.push(self.clause(&params));
+        }
+        self.returns.push(Some(ret.clone()));
+        let body = self.block(&ret);
+        self.returns.pop();
+        let mut ensures = vec![];
+        if self.rng.chance(20) {
+            self.scope.push(("result".to_string(), ret.clone()));
+            ensures.push(self.clause(&[("result".to_string(), ret.clone())]));
+        }
+        self.scope.truncate(scope);
+        let annotate = self.typed || self.rng.chance(30);
+        let f = Function {
+            name: name.clone(),
+            generics: vec![],
+            params: params.iter().map(|(name, ty)| Param { name: name.clone(), ty: annotate.then(|| ty.annotation()) }).collect(),
+            ret: annotate.then(|| ret.annotation()),
+            requires,
+            ensures,
+            body,
+            span: 0..0,
+        };
+        self.scope.push((name, Ty::Fn(params.into_iter().map(|(_, ty)| ty).collect(), Box::new(ret))));
+        Decl::Fn(Rc::new(f))
+    }
+
+    // a `requires` or `ensures` clause about some of the variables in scope
+    fn clause(&mut self, vars: &[(String, Ty)]) -> Clause {
+        self.returns.push(None);
+        let clause = match vars {
+            [(name, ty), ..] if !self.typed && self.rng.chance(50) => {
+                let contract = match ty {
+                    Ty::Fn(params, _) => {
+                        let params = params.iter().map(|_| self.contract()).collect();
+                        Contract::Fn(params, Box::new(self.contract()))
+                    }
+                    _ => self.contract(),
+                };
+                Clause::Satisfies(name.clone(), contract)
+            }
+            _ => Clause::Holds(self.expr(&Ty::Bool)),
+        };
+        self.returns.pop();
+        clause
+    }
+
+    // NOTE a flat contract starting with '(' would be read as a list of contracts
+    fn contract(&mut self) -> Contract {
+        let x = self.name("x");
+        let op = *self.rng.pick(&[InfixOp::Eq, InfixOp::NotEq]);
+        let pred = Expr::Binary(Box::new(ident(&x)), op, Box::new(ident(&x)));
+        Contract::Flat(lambda(vec![(x, None)], None, pred))
+    }
+
+    // fn f(n: Int, x: A) -> B { match n <= 0 { true => ..., false => f(n - 1, x) } }
+    fn countdown(&mut self) -> Decl {
+        let name = self.name("f");
+        let (n, x) = (self.name("n"), self.name("x"));
+        let (param, ret) = (self.ty(1), self.ty(1));
+        let scope = self.scope.len();
+        self.scope.push((n.clone(), Ty::Int));
+        self.scope.push((x.clone(), param.clone()));
+        self.returns.push(Some(ret.clone()));
+        let done = self.expr(&ret);
+        self.returns.pop();
+        self.scope.truncate(scope);
+        let next = Expr::Binary(Box::new(ident(&n)), InfixOp::Sub, Box::new(int(1)));
+        let recurse = call(ident(&name), vec![next, ident(&x)]);
+        let recurse = match ret == Ty::Int && self.rng.chance(50) {
+            true => binary(int(1), InfixOp::Add, recurse),
+            false => recurse,
+        };
+        let test = Expr::Binary(Box::new(ident(&n)), InfixOp::LtEq, Box::new(int(0)));
+        let body = Expr::Match(Box::new(test), vec![
+            arm(Pattern::Literal(Literal::TRUE), done),
+            arm(Pattern::Literal(Literal::FALSE), recurse),
+        ], 0..0);
+        let f = Function {
+            name: name.clone(),
+            generics: vec![],
+            params: vec![
+                Param { name: n, ty: Some(Ty::Int.annotation()) },
+                Param { name: x, ty: Some(param.annotation()) },
+            ],
+            ret: Some(ret.annotation()),
+            requires: vec![],
+            ensures: vec![],
+            body: Expr::Block(vec![], Box::new(body)),
+            span: 0..0,
+        };
+        self.scope.push((name, Ty::Fn(vec![Ty::Int, param], Box::new(ret))));
+        Decl::Fn(Rc::new(f))
+    }
+
+    fn enum_decl(&mut self) -> Decl {
+        let name = self.name("E");
+        let variants: Vec<Variant> = (0..1 + self.rng.below(3)).map(|_| {
+            let fields = (0..self.rng.below(3)).map(|_| match self.rng.chance(50) {
+                true => Field { name: Some(self.name("x")), ty: None },
+                false => Field { name: None, ty: Some(self.ty(1).annotation()) },
+            }).collect();
+            Variant { name: self.name("C"), fields }
+        }).collect();
+        self.ctors.extend(variants.iter().map(|v| (v.name.clone(), v.fields.len())));
+        Decl::Enum(name, vec![], variants)
+    }
+
+    // { decl ... expr }
+    fn block(&mut self, ty: &Ty) -> Expr {
+        let scope = self.scope.len();
+        let mut decls = vec![];
+        for _ in 0..self.rng.below(3) {
+            decls.push(match self.rng.below(6) {
+                0..=3 => self.let_decl(),
+                4 => self.fn_decl(),
+                _ => {
+                    let ty = self.ty(1);
+                    stmt(self.expr(&ty))
+                }
+            });
+        }
+        let last = self.expr(ty);
+        self.scope.truncate(scope);
+        Expr::Block(decls, Box::new(last))
+    }
+
+    fn expr(&mut self, ty: &Ty) -> Expr {
+        if self.depth == 0 {
+            return self.leaf(ty);
+        }
+        self.depth -= 1;
+        let ty = match !self.typed && self.rng.chance(5) {
+            true => self.ty(1),
+            false => ty.clone(),
+        };
+        let e = match self.rng.below(3) {
+            0 => self.common(&ty),
+            _ => self.specific(&ty),
+        };
+        self.depth += 1;
+        match self.rng.chance(3) {
+            true => Expr::Grouping(Box::new(e)),
+            false => e,
+        }
+    }
+
+    // a variable or a literal
+    fn leaf(&mut self, ty: &Ty) -> Expr {
+        let vars: Vec<String> = self.visible().into_iter().filter(|(_, t)| t == ty).map(|(name, _)| name).collect();
+        if !vars.is_empty() && self.rng.chance(60) {
+            return ident(&self.rng.pick(&vars).clone());
+        }
+        match ty {
+            Ty::Int => match self.rng.below(10) {
+                0 => int(i64::MAX),
+                1 => Expr::Literal(Literal::BIG(BigInt::from(i64::MAX) * 1000)),
+                2 => int(-*self.rng.pick(&[1, 7, i64::MAX])),
+                _ => int(*self.rng.pick(&[0, 1, 2, 3, 7, 42, 100])),
+            },
+            Ty::Float => Expr::Literal(Literal::FLOAT(*self.rng.pick(&[0.0, 0.1, 0.5, 1.0, 2.25, 1e16, 1e-7]))),
+            Ty::Bool => Expr::Literal(self.rng.pick(&[Literal::TRUE, Literal::FALSE]).clone()),
+            Ty::Str => Expr::Literal(Literal::STRING(self.rng.pick(&["", "a", "klug", "a b"]).to_string())),
+            Ty::List(item) => match self.rng.chance(50) {
+                true => Expr::List(vec![], None),
+                false => Expr::List(vec![self.leaf(item)], None),
+            },
+            Ty::Pair(a, b) => Expr::Tuple(vec![self.leaf(a), self.leaf(b)]),
+            Ty::Option(item) => match self.rng.chance(30) {
+                true => ident("None"),
+                false => call(ident("Some"), vec![self.leaf(item)]),
+            },
+            Ty::Fn(params, ret) => self.lambda(params, ret),
+        }
+    }
+
+    // fn(x: A, ...) body
+    fn lambda(&mut self, params: &[Ty], ret: &Ty) -> Expr {
+        let params: Vec<(String, Ty)> = params.iter().map(|ty| (self.name("x"), ty.clone())).collect();
+        let scope = self.scope.len();
+        self.scope.extend(params.iter().cloned());
+        self.returns.push(Some(ret.clone()));
+        let body = self.expr(ret);
+        self.returns.pop();
+        self.scope.truncate(scope);
+        // NOTE typed lambdas are annotated so `?` is never used on an unknown type
+        let annotate = self.typed || self.rng.chance(20);
+        let params = params.into_iter().map(|(name, ty)| (name, annotate.then(|| ty.annotation()))).collect();
+        let ret = match self.rng.chance(20) {
+            true => Some(ret.annotation()),
+            false => None,
+        };
+        lambda(params, ret, body)
+    }
+
+    // the expressions any type can come from
+    fn common(&mut self, ty: &Ty) -> Expr {
+        match self.rng.below(12) {
+            0 => self.block(ty),
+            1 => {
+                let test = self.expr(&Ty::Bool);
+                let (yes, no) = (self.expr(ty), self.expr(ty));
+                Expr::Match(Box::new(test), vec![
+                    arm(Pattern::Literal(Literal::TRUE), yes),
+                    arm(Pattern::Literal(Literal::FALSE), no),
+                ], 0..0)
+            }
+            2 => self.literal_match(ty),
+            3 => {
+                let fns: Vec<(String, Vec<Ty>)> = self.visible().into_iter()
+                    .filter_map(|(name, t)| match t {
+                        Ty::Fn(params, ret) if *ret == *ty => Some((name, params)),
+                        _ => None,
+                    })
+                    .collect();
+                if fns.is_empty() {
+                    return self.specific(ty);
+                }
+                let (name, params) = self.rng.pick(&fns).clone();
+                let args = params.iter().map(|p| self.expr(p)).collect();
+                call(ident(&name), args)
+            }
+            4 => {
+                let params: Vec<Ty> = (0..self.rng.below(3)).map(|_| self.ty(1)).collect();
+                let f = self.lambda(&params, ty);
+                let args = params.iter().map(|p| self.expr(p)).collect();
+                call(f, args)
+            }
+            5 => {
+                let item = self.ty(1);
+                let scrut = self.expr(&Ty::Option(Box::new(item.clone())));
+                let x = self.name("x");
+                let some = self.scoped(&[(x.clone(), item)], |gen| gen.expr(ty));
+                let none = self.expr(ty);
+                let mut arms = vec![
+                    arm(Pattern::Constructor("Some".to_string(), vec![Pattern::Binding(x)]), some),
+                    arm(Pattern::Constructor("None".to_string(), vec![]), none),
+                ];
+                if self.rng.chance(50) {
+                    arms.reverse();
+                }
+                Expr::Match(Box::new(scrut), arms, 0..0)
+            }
+            6 => {
+                let item = self.ty(1);
+                let list = Ty::List(Box::new(item.clone()));
+                let scrut = self.expr(&list);
+                let empty = self.expr(ty);
+                let (x, rest) = (self.name("x"), self.name("xs"));
+                let cons = self.scoped(&[(x.clone(), item), (rest.clone(), list)], |gen| gen.expr(ty));
+                Expr::Match(Box::new(scrut), vec![
+                    arm(Pattern::List(vec![], None), empty),
+                    arm(Pattern::List(vec![Pattern::Binding(x)], Some(Box::new(Pattern::Binding(rest)))), cons),
+                ], 0..0)
+            }
+            7 => {
+                let (a, b) = (self.ty(1), self.ty(1));
+                let scrut = self.expr(&Ty::Pair(Box::new(a.clone()), Box::new(b.clone())));
+                let (x, y) = (self.name("x"), self.name("y"));
+                let body = self.scoped(&[(x.clone(), a), (y.clone(), b)], |gen| gen.expr(ty));
+                let pattern = Pattern::Tuple(vec![Pattern::Binding(x), Pattern::Binding(y)]);
+                Expr::Match(Box::new(scrut), vec![arm(pattern, body)], 0..0)
+            }
+            8 => {
+                let body = self.block(ty);
+                let catch = match self.rng.chance(80) {
+                    true => Some((self.name("e"), Box::new(self.block(ty)))),
+                    false => None,
+                };
+                let finally = match catch.is_none() || self.rng.chance(30) {
+                    true => {
+                        let any = self.ty(1);
+                        Some(Box::new(self.block(&any)))
+                    }
+                    false => None,
+                };
+                Expr::TryCatch(Box::new(body), catch, finally)
+            }
+            // NOTE what's thrown has an unknown type, and unknown numbers are Ints
+            9 if self.rng.chance(30) && !(self.typed && *ty == Ty::Float) => {
+                let error = call(ident("Error"), vec![
+                    Expr::Literal(Literal::STRING("Gen".to_string())),
+                    Expr::Literal(Literal::STRING("thrown".to_string())),
+                    ident("None"),
+                ]);
+                Expr::Throw(Box::new(error))
+            }
+            10 if self.returns.last() != Some(&None) && (!self.typed || matches!(self.returns.last(), Some(Some(Ty::Option(_))))) => {
+                let option = self.expr(&Ty::Option(Box::new(ty.clone())));
+                Expr::Try(Box::new(postfix(option)))
+            }
+            11 if !self.typed => self.untyped(ty),
+            _ => self.specific(ty),
+        }
+    }
+
+    // a match on literals of some type, with a last arm for the rest
+    fn literal_match(&mut self, ty: &Ty) -> Expr {
+        let (scrut_ty, literals): (Ty, Vec<Literal>) = match self.rng.below(3) {
+            0 => (Ty::Int, [0, 1, -3, 42].iter().map(|n| Literal::INT(*n)).collect()),
+            1 => (Ty::Float, [0.5, -2.25, 1e16].iter().map(|n| Literal::FLOAT(*n)).collect()),
+            _ => (Ty::Str, ["", "a", "klug"].iter().map(|s| Literal::STRING(s.to_string())).collect()),
+        };
+        let scrut = self.expr(&scrut_ty);
+        let mut arms = vec![];
+        for lit in literals {
+            if !self.rng.chance(60) {
+                continue;
+            }
+            let guard = match self.rng.chance(20) {
+                true => Some(self.expr(&Ty::Bool)),
+                false => None,
+            };
+            let body = self.expr(ty);
+            arms.push(Arm { pattern: Pattern::Literal(lit), guard, body, span: 0..0 });
+        }
+        let rest = match self.rng.chance(50) {
+            true => {
+                let x = self.name("x");
+                let body = self.scoped(&[(x.clone(), scrut_ty)], |gen| gen.expr(ty));
+                arm(Pattern::Binding(x), body)
+            }
+            false => arm(Pattern::Wildcard, self.expr(ty)),
+        };
+        arms.push(rest);
+        Expr::Match(Box::new(scrut), arms, 0..0)
+    }
+
+    // the expressions only some types come from
+    fn specific(&mut self, ty: &Ty) -> Expr {
+        match ty {
+            Ty::Int => match self.rng.below(6) {
+                0 => {
+                    let op = *self.rng.pick(&[InfixOp::Add, InfixOp::Sub, InfixOp::Mul]);
+                    self.binary(&Ty::Int, op, &Ty::Int)
+                }
+                1 => {
+                    let op = *self.rng.pick(&[InfixOp::IntDiv, InfixOp::Mod, InfixOp::BitAnd, InfixOp::BitOr, InfixOp::BitXor]);
+                    self.binary(&Ty::Int, op, &Ty::Int)
+                }
+                // NOTE only small powers and shifts, big ones never finish
+                2 => {
+                    let op = *self.rng.pick(&[InfixOp::Pow, InfixOp::Shl, InfixOp::Shr]);
+                    let lhs = self.expr(&Ty::Int);
+                    binary(lhs, op, int(self.rng.below(5) as i64))
+                }
+                3 => {
+                    let op = *self.rng.pick(&[PrefixOp::Neg, PrefixOp::BitNot]);
+                    let operand = self.expr(&Ty::Int);
+                    unary(op, operand)
+                }
+                4 => {
+                    let x = self.expr(&Ty::Float);
+                    call(ident("int"), vec![x])
+                }
+                _ => self.leaf(ty),
+            },
+            Ty::Float => match self.rng.below(6) {
+                0 => {
+                    let op = *self.rng.pick(&[InfixOp::Add, InfixOp::Sub, InfixOp::Mul]);
+                    self.binary(&Ty::Float, op, &Ty::Float)
+                }
+                // NOTE dividing values of unknown types gives a Rat, e.g. thrown ones
+                // or what's in a `None`, so at least one is a literal
+                5 => {
+                    let lhs = Expr::Literal(Literal::FLOAT(*self.rng.pick(&[0.5, 1.0, 2.25])));
+                    let rhs = self.expr(&Ty::Float);
+                    binary(lhs, InfixOp::Div, rhs)
+                }
+                1 => {
+                    let n = self.expr(&Ty::Int);
+                    call(ident("float"), vec![n])
+                }
+                2 => {
+                    let operand = self.expr(&Ty::Float);
+                    unary(PrefixOp::Neg, operand)
+                }
+                3 => {
+                    let lhs = self.expr(&Ty::Float);
+                    binary(lhs, InfixOp::Pow, Expr::Literal(Literal::FLOAT(*self.rng.pick(&[0.5, 2.0, 3.0]))))
+                }
+                _ => self.leaf(ty),
+            },
+            Ty::Bool => match self.rng.below(5) {
+                0 => {
+                    let op = *self.rng.pick(&[InfixOp::Lt, InfixOp::LtEq, InfixOp::Gt, InfixOp::GtEq]);
+                    let operands = self.rng.pick(&[Ty::Int, Ty::Float, Ty::Str]).clone();
+                    self.binary(&operands, op, &operands)
+                }
+                1 => {
+                    let op = *self.rng.pick(&[InfixOp::Eq, InfixOp::NotEq]);
+                    let operands = loop {
+                        match self.ty(1) {
+                            Ty::Fn(..) => continue,
+                            ty => break ty,
+                        }
+                    };
+                    self.binary(&operands, op, &operands)
+                }
+                2 => {
+                    let operand = self.expr(&Ty::Bool);
+                    unary(PrefixOp::Not, operand)
+                }
+                3 => {
+                    let x = self.expr(&Ty::Float);
+                    call(ident("is_nan"), vec![x])
+                }
+                _ => self.leaf(ty),
+            },
+            Ty::Str => self.leaf(ty),
+            Ty::List(item) => {
+                let items = (0..self.rng.below(4)).map(|_| self.expr(item)).collect();
+                let rest = match self.rng.chance(30) {
+                    true => Some(Box::new(self.expr(ty))),
+                    false => None,
+                };
+                Expr::List(items, rest)
+            }
+            Ty::Pair(a, b) => Expr::Tuple(vec![self.expr(a), self.expr(b)]),
+            Ty::Option(item) => match self.rng.below(3) {
+                0 => ident("None"),
+                1 => {
+                    let x = self.expr(item);
+                    call(ident("Some"), vec![x])
+                }
+                _ => {
+                    let list = self.expr(&Ty::List(item.clone()));
+                    call(ident("head"), vec![list])
+                }
+            },
+            Ty::Fn(params, ret) => self.lambda(params, ret),
+        }
+    }
+
+    // what only an untyped program has
+    fn untyped(&mut self, ty: &Ty) -> Expr {
+        match self.rng.below(5) {
+            0 if !self.ctors.is_empty() => {
+                let (name, arity) = self.rng.pick(&self.ctors).clone();
+                let args = (0..arity).map(|_| {
+                    let ty = self.ty(1);
+                    self.expr(&ty)
+                }).collect();
+                match arity {
+                    0 => ident(&name),
+                    _ => call(ident(&name), args),
+                }
+            }
+            1 if !self.ctors.is_empty() => {
+                let any = self.ty(1);
+                let scrut = self.expr(&any);
+                let mut arms = vec![];
+                for (name, arity) in self.ctors.clone() {
+                    if !self.rng.chance(50) {
+                        continue;
+                    }
+                    let fields = (0..arity).map(|_| match self.rng.chance(50) {
+                        true => Pattern::Wildcard,
+                        false => Pattern::Binding(self.name("x")),
+                    }).collect();
+                    arms.push(arm(Pattern::Constructor(name, fields), self.expr(ty)));
+                }
+                arms.push(arm(Pattern::Wildcard, self.expr(ty)));
+                Expr::Match(Box::new(scrut), arms, 0..0)
+            }
+            2 => {
+                let item = self.expr(ty);
+                Expr::Tuple(vec![item])
+            }
+            3 => Expr::Tuple(vec![]),
+            _ => self.binary(&Ty::Int, InfixOp::Div, &Ty::Int),
+        }
+    }
+
+    fn binary(&mut self, lhs: &Ty, op: InfixOp, rhs: &Ty) -> Expr {
+        let lhs = self.expr(lhs);
+        let rhs = self.expr(rhs);
+        binary(lhs, op, rhs)
+    }
+
+    // the variables in scope that aren't shadowed, e.g. the `result` of an outer function
+    fn visible(&self) -> Vec<(String, Ty)> {
+        let mut vars: Vec<(String, Ty)> = vec![];
+        for (name, ty) in self.scope.iter().rev() {
+            if !vars.iter().any(|(n, _)| n == name) {
+                vars.push((name.clone(), ty.clone()));
+            }
+        }
+        vars
+    }
+
+    // an expression with some more variables in scope
+    fn scoped(&mut self, vars: &[(String, Ty)], expr: impl FnOnce(&mut Self) -> Expr) -> Expr {
+        let scope = self.scope.len();
+        self.scope.extend(vars.iter().cloned());
+        let e = expr(self);
+        self.scope.truncate(scope);
+        e
+    }
+}
+
+fn stmt(e: Expr) -> Decl {
+    Decl::Stmt(Box::new(Stmt::Expr(Box::new(e))))
+}
+
+fn ident(name: &str) -> Expr {
+    Expr::Literal(Literal::IDENT(name.to_string()))
+}
+
+// NOTE negative numbers are negated literals
+fn int(n: i64) -> Expr {
+    match n {
+        0.. => Expr::Literal(Literal::INT(n)),
+        _ => Expr::Unary(PrefixOp::Neg, Box::new(Expr::Literal(Literal::INT(-n)))),
+    }
+}
+
+fn arm(pattern: Pattern, body: Expr) -> Arm {
+    Arm { pattern, guard: None, body, span: 0..0 }
+}
+
+fn lambda(params: Vec<(String, Option<Type>)>, ret: Option<Type>, body: Expr) -> Expr {
+    Expr::Lambda(Rc::new(Function {
+        name: String::new(),
+        generics: vec![],
+        params: params.into_iter().map(|(name, ty)| Param { name, ty }).collect(),
+        ret,
+        requires: vec![],
+        ensures: vec![],
+        body,
+        span: 0..0,
+    }))
+}
+
+fn call(callee: Expr, args: Vec<Expr>) -> Expr {
+    Expr::Call(Box::new(postfix(callee)), args, 0..0)
+}
+
+// whether an expression never needs parentheses around it, it can't be split
+// by an operator next to it, e.g. a literal, a call or anything in brackets
+fn is_atom(e: &Expr) -> bool {
+    matches!(e, Expr::Literal(_) | Expr::Call(..) | Expr::Grouping(_) | Expr::Tuple(_) | Expr::List(..)
+        | Expr::Try(_) | Expr::Block(..) | Expr::Match(..) | Expr::TryCatch(..))
+}
+
+// NOTE lambdas and what's thrown extend as far as they can, so they're always grouped
+fn grouped(e: Expr, keep: bool) -> Expr {
+    match keep && !matches!(e, Expr::Lambda(_) | Expr::Throw(_)) {
+        true => e,
+        false => Expr::Grouping(Box::new(e)),
+    }
+}
+
+// what's called or has a `?`, they bind tighter than anything
+fn postfix(e: Expr) -> Expr {
+    let keep = is_atom(&e);
+    grouped(e, keep)
+}
+
+fn unary(op: PrefixOp, operand: Expr) -> Expr {
+    let keep = match &operand {
+        // NOTE `-2 ** 2` is `-(2 ** 2)`
+        Expr::Binary(_, InfixOp::Pow, _) | Expr::Unary(..) => true,
+        e => is_atom(e),
+    };
+    Expr::Unary(op, Box::new(grouped(operand, keep)))
+}
+
+fn binary(lhs: Expr, op: InfixOp, rhs: Expr) -> Expr {
+    let (left, right) = op.binding_power();
+    let keep = match &lhs {
+        Expr::Binary(_, inner, _) => left < inner.binding_power().1,
+        Expr::Unary(..) => op != InfixOp::Pow,
+        e => is_atom(e),
+    };
+    let lhs = grouped(lhs, keep);
+    let keep = match &rhs {
+        Expr::Binary(_, inner, _) => inner.binding_power().0 >= right,
+        Expr::Unary(..) => true,
+        e => is_atom(e),
+    };
+    let rhs = grouped(rhs, keep);
+    Expr::Binary(Box::new(lhs), op, Box::new(rhs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use crate::ide::resolve;
+    use crate::lexer::{Lexer, SyntaxKind};
+    use crate::parser::Parser;
+    use crate::{pretty, Engine, Limits, Session, Typing};
+
+    const SEEDS: u64 = 500;
+
+    // the same tree with every span empty, they're all the generator knows
+    fn spanless(decls: &[Decl]) -> Vec<Decl> {
+        erased(decls, false)
+    }
+
+    // and without any `Grouping`, parentheses are only what precedence calls for
+    fn ungrouped(decls: &[Decl]) -> Vec<Decl> {
+        erased(decls, true)
+    }
+
+    fn erased(decls: &[Decl], ungroup: bool) -> Vec<Decl> {
+        decls.iter().cloned().map(|mut d| {
+            erase_decl(&mut d, ungroup);
+            d
+        }).collect()
+    }
+
+    fn erase_decl(d: &mut Decl, ungroup: bool) {
+        match d {
+            Decl::Let(_, _, e, span) => {
+                *span = 0..0;
+                if let Some(e) = e {
+                    erase(e, ungroup);
+                }
+            }
+            Decl::Fn(f) => erase_fn(f, ungroup),
+            Decl::Stmt(stmt) => match &mut **stmt {
+                Stmt::Expr(e) => erase(e, ungroup),
+            },
+            _ => (),
+        }
+    }
+
+    fn erase_fn(f: &mut Rc<Function>, ungroup: bool) {
+        let f = Rc::make_mut(f);
+        f.span = 0..0;
+        for clause in f.requires.iter_mut().chain(f.ensures.iter_mut()) {
+            match clause {
+                Clause::Holds(e) => erase(e, ungroup),
+                Clause::Satisfies(_, contract) => erase_contract(contract, ungroup),
+            }
+        }
+        erase(&mut f.body, ungroup);
+    }
+
+    fn erase_contract(c: &mut Contract, ungroup: bool) {
+        match c {
+            Contract::Flat(e) => erase(e, ungroup),
+            Contract::Fn(params, ret) => {
+                params.iter_mut().for_each(|c| erase_contract(c, ungroup));
+                erase_contract(ret, ungroup);
+            }
+        }
+    }
+
+    fn erase(e: &mut Expr, ungroup: bool) {
+        while let (true, Expr::Grouping(inner)) = (ungroup, &*e) {
+            *e = (**inner).clone();
+        }
+        match e {
+            Expr::Call(callee, args, span) => {
+                *span = 0..0;
+                erase(callee, ungroup);
+                args.iter_mut().for_each(|e| erase(e, ungroup));
+            }
+            Expr::Lambda(f) => erase_fn(f, ungroup),
+            Expr::Unary(_, e) | Expr::Grouping(e) | Expr::Try(e) | Expr::Throw(e) => erase(e, ungroup),
+            Expr::Binary(lhs, _, rhs) => {
+                erase(lhs, ungroup);
+                erase(rhs, ungroup);
+            }
+            Expr::Match(scrut, arms, span) => {
+                *span = 0..0;
+                erase(scrut, ungroup);
+                for arm in arms {
+                    arm.span = 0..0;
+                    arm.guard.iter_mut().for_each(|e| erase(e, ungroup));
+                    erase(&mut arm.body, ungroup);
+                }
+            }
+            Expr::Block(decls, last) => {
+                decls.iter_mut().for_each(|d| erase_decl(d, ungroup));
+                erase(last, ungroup);
+            }
+            Expr::Tuple(items) => items.iter_mut().for_each(|e| erase(e, ungroup)),
+            Expr::List(items, rest) => {
+                items.iter_mut().for_each(|e| erase(e, ungroup));
+                rest.iter_mut().for_each(|e| erase(e, ungroup));
+            }
+            Expr::TryCatch(body, catch, finally) => {
+                erase(body, ungroup);
+                catch.iter_mut().for_each(|(_, e)| erase(e, ungroup));
+                finally.iter_mut().for_each(|e| erase(e, ungroup));
+            }
+            Expr::Literal(_) => (),
+        }
+    }
+
+    #[test]
+    fn printed_programs_parse_back_the_same() {
+        for seed in 0..SEEDS {
+            for typed in [true, false] {
+                let decls = program(seed, typed);
+                let source = pretty::program(&decls);
+                let parsed = Parser::new(&source).parse().declarations;
+                assert_eq!(spanless(&parsed), decls, "seed {} printed as\n{}", seed, source);
+                assert_eq!(pretty::program(&parsed), source);
+            }
+        }
+    }
+
+    #[test]
+    fn programs_without_groupings_print_with_the_parens_they_need() {
+        for seed in 0..SEEDS {
+            for typed in [true, false] {
+                let decls = ungrouped(&program(seed, typed));
+                let source = pretty::program(&decls);
+                let parsed = Parser::new(&source).parse().declarations;
+                assert_eq!(ungrouped(&parsed), decls, "seed {} printed as\n{}", seed, source);
+            }
+        }
+    }
+
+    // NOTE comments and blank lines go where the parser doesn't care about
+    // newlines, at the ends of lines and on lines of their own
+    fn commented(source: &str, rng: &mut Rng) -> (String, Vec<String>) {
+        let mut out = String::new();
+        let mut comments = Vec::new();
+        for line in source.lines() {
+            if rng.chance(10) {
+                out.push('\n');
+            }
+            if rng.chance(20) {
+                comments.push(format!("# above {}.", comments.len()));
+                out.push_str(&format!("{}\n", comments[comments.len() - 1]));
+            }
+            out.push_str(line);
+            if rng.chance(20) {
+                comments.push(format!("# after {}.", comments.len()));
+                out.push_str(&format!(" {}", comments[comments.len() - 1]));
+            }
+            out.push('\n');
+        }
+        (out, comments)
+    }
+
+    #[test]
+    fn formatting_keeps_programs_and_comments() {
+        let printed = |source: &str| pretty::program(&Parser::new(source).parse().declarations);
+        for seed in 0..SEEDS {
+            let (source, comments) = commented(&pretty::program(&program(seed, false)), &mut Rng::new(seed));
+            let formatted = crate::format(&source).unwrap();
+            assert_eq!(printed(&formatted), printed(&source), "seed {} formatted\n{}\nas\n{}", seed, source, formatted);
+            for comment in &comments {
+                assert!(formatted.contains(comment), "seed {} lost {:?} formatting\n{}\nas\n{}", seed, comment, source, formatted);
+            }
+            assert_eq!(crate::format(&formatted).unwrap(), formatted, "seed {} formatted\n{}", seed, source);
+        }
+    }
+
+    #[test]
+    fn typed_programs_type_check() {
+        for seed in 0..SEEDS {
+            let source = pretty::program(&program(seed, true));
+            assert_eq!(crate::check(&source, Typing::Inferred), Vec::<String>::new(), "seed {}\n{}", seed, source);
+        }
+    }
+
+    #[test]
+    fn every_identifier_resolves() {
+        for seed in 0..SEEDS {
+            for typed in [true, false] {
+                let source = pretty::program(&program(seed, typed));
+                let parse = Parser::new(&source).parse();
+                let resolution = resolve::resolve(&source, &parse);
+                let idents = Lexer::new(&source).filter(|(kind, _)| *kind == SyntaxKind::Ident).count();
+                assert_eq!(resolution.names.len(), idents, "seed {}\n{}", seed, source);
+                for name in &resolution.names {
+                    assert!(name.declared.is_some(), "seed {} {:?} is unresolved in\n{}", seed, &source[name.span.clone()], source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn programs_never_panic() {
+        let limits = Limits { nesting: 200, depth: 100, fuel: Some(10_000) };
+        for seed in 0..SEEDS {
+            for typed in [true, false] {
+                let source = pretty::program(&program(seed, typed));
+                for engine in [Engine::Tree, Engine::Vm] {
+                    let mut session = Session::default();
+                    session.set_limits(limits);
+                    session.set_engine(engine);
+                    let ran = panic::catch_unwind(AssertUnwindSafe(|| session.run(&source)));
+                    assert!(ran.is_ok(), "seed {} panics on {:?} running\n{}", seed, engine, source);
+                }
+            }
+        }
+    }
+}
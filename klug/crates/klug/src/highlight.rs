@@ -0,0 +1,217 @@
+// highlight - what each token of a program is, for coloring it
+//
+// Tokens come from the lexer, comments included, and a name is classified by
+// what it resolves to, see `ide::resolve`, so a parameter looks the same
+// wherever it's used and a name that's declared nowhere stands out. The
+// renderers copy everything between tokens as it's written.
+
+use std::ops::Range;
+use crate::ide::resolve::{NameKind, Resolution};
+use crate::lexer::{Lexer, SyntaxKind};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    // including `true` and `false`
+    Keyword,
+    // bound by `let`, a pattern or a `catch`, or a named field
+    Variable,
+    // including the builtins
+    Function,
+    Parameter,
+    // including type parameters
+    Type,
+    Constructor,
+    String,
+    Number,
+    Operator,
+    // brackets, separators and anything the lexer doesn't know
+    Punctuation,
+    Comment,
+    // a name that isn't declared anywhere
+    Unresolved,
+}
+
+impl TokenKind {
+    pub const ALL: [Self; 12] = [
+        Self::Keyword, Self::Variable, Self::Function, Self::Parameter, Self::Type, Self::Constructor,
+        Self::String, Self::Number, Self::Operator, Self::Punctuation, Self::Comment, Self::Unresolved,
+    ];
+
+    // NOTE the LSP's names for semantic token types where it has one
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Keyword => "keyword",
+            Self::Variable => "variable",
+            Self::Function => "function",
+            Self::Parameter => "parameter",
+            Self::Type => "type",
+            Self::Constructor => "enumMember",
+            Self::String => "string",
+            Self::Number => "number",
+            Self::Operator => "operator",
+            Self::Punctuation => "punctuation",
+            Self::Comment => "comment",
+            Self::Unresolved => "unresolvedReference",
+        }
+    }
+
+    // the SGR parameters it's shown with in a terminal, none for plain text
+    fn ansi(&self) -> Option<&'static str> {
+        match self {
+            Self::Keyword => Some("35"),
+            Self::Function => Some("34"),
+            Self::Parameter => Some("3"),
+            Self::Type | Self::Constructor => Some("33"),
+            Self::String => Some("32"),
+            Self::Number => Some("36"),
+            Self::Comment => Some("90"),
+            Self::Unresolved => Some("31;4"),
+            Self::Variable | Self::Operator | Self::Punctuation => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub span: Range<usize>,
+    pub kind: TokenKind,
+}
+
+// every token but whitespace, in order
+pub(crate) fn classify(source: &str, resolution: &Resolution) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (kind, text) in Lexer::with_comments(source) {
+        let start = text.as_ptr() as usize - source.as_ptr() as usize;
+        let span = start..start + text.len();
+        let kind = match kind {
+            SyntaxKind::Whitespace | SyntaxKind::Newline => continue,
+            SyntaxKind::Comment => TokenKind::Comment,
+            SyntaxKind::FnKw | SyntaxKind::LetKw | SyntaxKind::TrueKw | SyntaxKind::FalseKw | SyntaxKind::EnumKw
+            | SyntaxKind::MatchKw | SyntaxKind::IfKw | SyntaxKind::RequiresKw | SyntaxKind::EnsuresKw
+            | SyntaxKind::ThrowKw | SyntaxKind::TryKw | SyntaxKind::CatchKw | SyntaxKind::FinallyKw => TokenKind::Keyword,
+            SyntaxKind::Ident => name(resolution, &span),
+            SyntaxKind::Float | SyntaxKind::Int => TokenKind::Number,
+            SyntaxKind::StringKw => TokenKind::String,
+            SyntaxKind::LessThanEq | SyntaxKind::GreaterThanEq | SyntaxKind::LessThan | SyntaxKind::GreaterThan
+            | SyntaxKind::NotEqual | SyntaxKind::Equal | SyntaxKind::BitAnd | SyntaxKind::BitOr | SyntaxKind::Caret
+            | SyntaxKind::Tilde | SyntaxKind::FatArrow | SyntaxKind::Arrow | SyntaxKind::Bang | SyntaxKind::Question
+            | SyntaxKind::Plus | SyntaxKind::Minus | SyntaxKind::Star | SyntaxKind::StarStar | SyntaxKind::Slash
+            | SyntaxKind::SlashSlash | SyntaxKind::Percent | SyntaxKind::Equals | SyntaxKind::DotDot => TokenKind::Operator,
+            SyntaxKind::Colon | SyntaxKind::Comma | SyntaxKind::Underscore | SyntaxKind::LBrace | SyntaxKind::RBrace
+            | SyntaxKind::LParen | SyntaxKind::RParen | SyntaxKind::LBracket | SyntaxKind::RBracket
+            | SyntaxKind::Root | SyntaxKind::Error => TokenKind::Punctuation,
+        };
+        tokens.push(Token { span, kind });
+    }
+    tokens
+}
+
+// NOTE a name in a declaration that doesn't parse isn't resolved, but that
+// doesn't mean it's declared nowhere
+fn name(resolution: &Resolution, span: &Range<usize>) -> TokenKind {
+    let name = match resolution.names.binary_search_by_key(&span.start, |n| n.span.start) {
+        Ok(i) => &resolution.names[i],
+        Err(_) => return TokenKind::Variable,
+    };
+    let declared = match name.declared {
+        Some(d) => &resolution.declared[d],
+        None => return TokenKind::Unresolved,
+    };
+    match declared.kind {
+        NameKind::Variable | NameKind::Field => TokenKind::Variable,
+        NameKind::Function | NameKind::Builtin => TokenKind::Function,
+        NameKind::Parameter => TokenKind::Parameter,
+        NameKind::Constructor => TokenKind::Constructor,
+        NameKind::Type | NameKind::TypeParameter => TokenKind::Type,
+        NameKind::Unresolved => TokenKind::Unresolved,
+    }
+}
+
+// e.g. `<span class="kl-keyword">let</span>`, in a `pre` of class `klug`
+pub(crate) fn html(source: &str, tokens: &[Token]) -> String {
+    let body = render(source, tokens, escape, |kind, text| match kind {
+        TokenKind::Punctuation => text.to_string(),
+        _ => format!("<span class=\"kl-{}\">{}</span>", kind.name(), text),
+    });
+    format!("<pre class=\"klug\">{}</pre>\n", body)
+}
+
+pub(crate) fn ansi(source: &str, tokens: &[Token]) -> String {
+    render(source, tokens, str::to_string, |kind, text| match kind.ansi() {
+        Some(sgr) => format!("\x1b[{}m{}\x1b[0m", sgr, text),
+        None => text.to_string(),
+    })
+}
+
+fn render(
+    source: &str,
+    tokens: &[Token],
+    escape: impl Fn(&str) -> String,
+    style: impl Fn(TokenKind, &str) -> String,
+) -> String {
+    let mut out = String::new();
+    let mut at = 0;
+    for token in tokens {
+        out.push_str(&escape(&source[at..token.span.start]));
+        out.push_str(&style(token.kind, &escape(&source[token.span.clone()])));
+        at = token.span.end;
+    }
+    out.push_str(&escape(&source[at..]));
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ide::resolve;
+    use crate::parser::Parser;
+
+    fn tokens(source: &str) -> Vec<(&str, TokenKind)> {
+        let resolution = resolve::resolve(source, &Parser::new(source).parse());
+        classify(source, &resolution).into_iter().map(|t| (&source[t.span], t.kind)).collect()
+    }
+
+    #[test]
+    fn names_are_classified_by_their_declarations() {
+        use TokenKind::*;
+        let source = "fn f(x: Int) -> Option<Int> { Some(float(x) + y) } # f\nlet s = 'a' == 'b'";
+        assert_eq!(tokens(source), vec![
+            ("fn", Keyword), ("f", Function), ("(", Punctuation), ("x", Parameter), (":", Punctuation),
+            ("Int", Type), (")", Punctuation), ("->", Operator), ("Option", Type), ("<", Operator), ("Int", Type),
+            (">", Operator), ("{", Punctuation), ("Some", Constructor), ("(", Punctuation), ("float", Function),
+            ("(", Punctuation), ("x", Parameter), (")", Punctuation), ("+", Operator), ("y", Unresolved),
+            (")", Punctuation), ("}", Punctuation), ("# f", Comment),
+            ("let", Keyword), ("s", Variable), ("=", Operator), ("'a'", String), ("==", Operator), ("'b'", String),
+        ]);
+    }
+
+    #[test]
+    fn names_that_dont_parse_are_variables() {
+        let source = "let = x\nlet y = 1.5";
+        assert_eq!(tokens(source)[2], ("x", TokenKind::Variable));
+        assert_eq!(tokens(source)[4], ("y", TokenKind::Variable));
+        assert_eq!(tokens(source)[6], ("1.5", TokenKind::Number));
+    }
+
+    #[test]
+    fn renderers_keep_the_source_between_tokens() {
+        let source = "let x = 1 < 2 # &\n\nx";
+        let resolution = resolve::resolve(source, &Parser::new(source).parse());
+        let tokens = classify(source, &resolution);
+        assert_eq!(
+            html(source, &tokens),
+            "<pre class=\"klug\"><span class=\"kl-keyword\">let</span> <span class=\"kl-variable\">x</span> \
+             <span class=\"kl-operator\">=</span> <span class=\"kl-number\">1</span> \
+             <span class=\"kl-operator\">&lt;</span> <span class=\"kl-number\">2</span> \
+             <span class=\"kl-comment\"># &amp;</span>\n\n<span class=\"kl-variable\">x</span></pre>\n"
+        );
+        assert_eq!(
+            ansi(source, &tokens),
+            "\x1b[35mlet\x1b[0m x = \x1b[36m1\x1b[0m < \x1b[36m2\x1b[0m \x1b[90m# &\x1b[0m\n\nx"
+        );
+    }
+}
@@ -0,0 +1,357 @@
+// ide - what an editor asks about a program, see `klug lsp`
+//
+// Everything is answered from one parse of the source, with its names
+// resolved, see `resolve`. Offsets and spans are byte offsets into the source,
+// a name is found by an offset anywhere in it, including just after its end.
+// NOTE the prelude and the builtins have no spans, they aren't in the source
+
+pub(crate) mod resolve;
+
+use std::ops::Range;
+use crate::check::Checker;
+use crate::parser::{decl::{self, Decl}, expr::Expr, literal::Literal, Parse, Parser};
+use crate::typeck::TypeChecker;
+use crate::highlight::{self, Token};
+use crate::{prelude, Typing};
+use resolve::{Declared, Resolution};
+
+pub use resolve::NameKind;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub span: Range<usize>,
+    pub severity: Severity,
+    // what `klug check` prints, without the position
+    pub message: String,
+}
+
+// A top level declaration, or a constructor of one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: NameKind,
+    // the whole declaration, and just its name
+    pub span: Range<usize>,
+    pub name_span: Range<usize>,
+    pub children: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    // none for a keyword
+    pub kind: Option<NameKind>,
+    pub detail: String,
+}
+
+const KEYWORDS: [&str; 13] = [
+    "fn", "let", "true", "false", "enum", "match", "if", "requires", "ensures", "throw", "try", "catch", "finally",
+];
+
+pub struct Analysis {
+    source: String,
+    parse: Parse,
+    resolution: Resolution,
+    diagnostics: Vec<Diagnostic>,
+    typing: Typing,
+}
+
+impl Analysis {
+    pub fn new(input: &str, typing: Typing) -> Self {
+        let parse = Parser::new(input).parse();
+        let resolution = resolve::resolve(input, &parse);
+        let diagnostics = diagnostics(&parse, typing);
+        Self { source: input.to_string(), parse, resolution, diagnostics, typing }
+    }
+
+    // syntax errors, match warnings, and type errors unless typing is off,
+    // each spanning the declaration it's in
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    // how the name at `offset` is declared, and its type if it's declared at the
+    // top level and type checks, with the span of the name
+    pub fn hover(&self, offset: usize) -> Option<(Range<usize>, String)> {
+        let name = self.resolution.name_at(offset)?;
+        let declared = &self.resolution.declared[name.declared?];
+        let mut text = format!("```klug\n{}\n```", declared.detail);
+        if let Some(parent) = declared.parent {
+            text.push_str(&format!("\nin `{}`", self.resolution.declared[parent].detail));
+        }
+        if let Some(ty) = self.type_of(declared) {
+            text.push_str(&format!("\n\ntype `{}`", ty));
+        }
+        Some((name.span.clone(), text))
+    }
+
+    // where the name at `offset` is declared
+    pub fn definition(&self, offset: usize) -> Option<Range<usize>> {
+        let name = self.resolution.name_at(offset)?;
+        self.resolution.declared[name.declared?].span.clone()
+    }
+
+    // every use of what the name at `offset` refers to, including where it's declared
+    pub fn references(&self, offset: usize) -> Vec<Range<usize>> {
+        let declared = match self.resolution.name_at(offset).and_then(|n| n.declared) {
+            Some(d) => d,
+            None => return vec![],
+        };
+        self.resolution.names.iter()
+            .filter(|n| n.declared == Some(declared))
+            .map(|n| n.span.clone())
+            .collect()
+    }
+
+    // the top level declarations, with the constructors of enums inside them
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let declared = &self.resolution.declared;
+        let symbol = |d: usize, span: Range<usize>, children| {
+            let name_span = declared[d].span.clone()?;
+            Some(Symbol { name: declared[d].name.clone(), kind: declared[d].kind, span, name_span, children })
+        };
+        (0..declared.len())
+            .filter(|d| declared[*d].global && declared[*d].top.is_some())
+            .filter_map(|d| {
+                let children = (0..declared.len())
+                    .filter(|c| declared[*c].parent == Some(d) && declared[*c].kind == NameKind::Constructor)
+                    .filter_map(|c| symbol(c, declared[c].span.clone()?, vec![]))
+                    .collect();
+                symbol(d, self.parse.spans[declared[d].top?].clone(), children)
+            })
+            .collect()
+    }
+
+    // the keywords, and the names in scope at `offset`, innermost first
+    pub fn completions(&self, offset: usize) -> Vec<Completion> {
+        let resolution = resolve::resolve_at(&self.source, &self.parse, Some(offset));
+        let mut completions: Vec<Completion> = resolution.visible.iter()
+            .map(|d| {
+                let declared = &resolution.declared[*d];
+                Completion { label: declared.name.clone(), kind: Some(declared.kind), detail: declared.detail.clone() }
+            })
+            .collect();
+        completions.extend(KEYWORDS.iter().map(|kw| Completion {
+            label: kw.to_string(),
+            kind: None,
+            detail: "keyword".to_string(),
+        }));
+        completions
+    }
+
+    // every token but whitespace, see `highlight`
+    pub fn tokens(&self) -> Vec<Token> {
+        highlight::classify(&self.source, &self.resolution)
+    }
+
+    // NOTE checks the declarations up to the one it's in, and the functions declared with it
+    fn type_of(&self, declared: &Declared) -> Option<String> {
+        let top = declared.top?;
+        if !declared.global && declared.kind != NameKind::Constructor {
+            return None;
+        }
+        let decls = &self.parse.declarations;
+        let end = top + decl::leading_functions(&decls[top..]).len().max(1);
+        let prelude = prelude::parse();
+        let mut type_checker = TypeChecker::default();
+        type_checker.check_decls(&prelude.declarations, &prelude.spans);
+        type_checker.set_gradual(self.typing == Typing::Gradual);
+        let errors = type_checker.check_decls(&decls[..end], &self.parse.spans[..end]);
+        // NOTE an error is placed at the innermost call, function or let inside a declaration
+        let inside = |span: &Range<usize>| self.parse.spans[top..end].iter().any(|d| span.start >= d.start && span.end <= d.end);
+        if errors.iter().any(|e| inside(&e.span)) {
+            return None;
+        }
+        let name = Expr::Literal(Literal::IDENT(declared.name.clone()));
+        type_checker.type_of(&name).ok().map(|ty| ty.to_string())
+    }
+}
+
+fn diagnostics(parse: &Parse, typing: Typing) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let prelude = prelude::parse();
+    let mut checker = Checker::default();
+    checker.check_decls(&prelude.declarations);
+    for (decl, span) in parse.declarations.iter().zip(&parse.spans) {
+        if let Decl::Error(msg) = decl {
+            let message = format!("error: {}", msg);
+            diagnostics.push(Diagnostic { span: span.clone(), severity: Severity::Error, message });
+        }
+        for warning in checker.check_decls(std::slice::from_ref(decl)) {
+            let message = warning.to_string();
+            diagnostics.push(Diagnostic { span: warning.span, severity: Severity::Warning, message });
+        }
+    }
+    if typing != Typing::Off {
+        let mut type_checker = TypeChecker::default();
+        type_checker.check_decls(&prelude.declarations, &prelude.spans);
+        type_checker.set_gradual(typing == Typing::Gradual);
+        for error in type_checker.check_decls(&parse.declarations, &parse.spans) {
+            let message = error.to_string();
+            diagnostics.push(Diagnostic { span: error.span, severity: Severity::Error, message });
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the offset of the `n`th `needle` in `source` that isn't part of a longer word, counting from 0
+    fn at(source: &str, needle: &str, n: usize) -> usize {
+        let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        source.match_indices(needle)
+            .map(|(i, _)| i)
+            .filter(|i| !word(source[..*i].chars().next_back()) && !word(source[i + needle.len()..].chars().next()))
+            .nth(n)
+            .unwrap()
+    }
+
+    fn span(source: &str, needle: &str, n: usize) -> Range<usize> {
+        let start = at(source, needle, n);
+        start..start + needle.len()
+    }
+
+    #[test]
+    fn names_refer_to_the_innermost_declaration() {
+        let source = "let x = 1\nfn f(x) { x + y }\nlet y = x";
+        let analysis = Analysis::new(source, Typing::Off);
+        assert_eq!(analysis.definition(at(source, "x", 2)), Some(span(source, "x", 1)));
+        assert_eq!(analysis.definition(at(source, "x", 3)), Some(span(source, "x", 0)));
+        // NOTE `y` is declared after `f`
+        assert_eq!(analysis.definition(at(source, "y", 0)), None);
+    }
+
+    #[test]
+    fn functions_declared_together_can_call_each_other() {
+        let source = "fn even(n) { n == 0 | odd(n - 1) }\nfn odd(n) { n != 0 & even(n - 1) }\nodd(3)";
+        let analysis = Analysis::new(source, Typing::Off);
+        assert_eq!(analysis.definition(at(source, "odd", 0)), Some(span(source, "odd", 1)));
+        assert_eq!(analysis.references(at(source, "odd", 2)), vec![
+            span(source, "odd", 0), span(source, "odd", 1), span(source, "odd", 2),
+        ]);
+        assert_eq!(analysis.references(at(source, "n", 1)), vec![
+            span(source, "n", 0), span(source, "n", 1), span(source, "n", 2),
+        ]);
+    }
+
+    #[test]
+    fn patterns_catches_and_results_are_scoped() {
+        let source = "fn f(o) ensures result > 0 { match o { Some(v) if v > 0 => v, _ => try { g() } catch e { e } } }";
+        let analysis = Analysis::new(source, Typing::Off);
+        assert_eq!(analysis.definition(at(source, "v", 1)), Some(span(source, "v", 0)));
+        assert_eq!(analysis.definition(at(source, "v", 2)), Some(span(source, "v", 0)));
+        assert_eq!(analysis.definition(at(source, "e", 1)), Some(span(source, "e", 0)));
+        // NOTE `Some` is declared by the prelude, `g` isn't declared at all
+        assert_eq!(analysis.hover(at(source, "Some", 0)).unwrap().1, "```klug\nSome(T)\n```\nin `enum Option<T>`");
+        assert_eq!(analysis.definition(at(source, "Some", 0)), None);
+        assert_eq!(analysis.hover(at(source, "g", 0)), None);
+        assert_eq!(analysis.hover(at(source, "result", 0)).unwrap().1, "```klug\nresult\n```\nin `fn f(o) ensures result > 0`");
+    }
+
+    #[test]
+    fn types_have_a_namespace_of_their_own() {
+        let source = "enum Tree<T> { Leaf, Node(left: Tree<T>, T, right: Tree<T>) }\nfn Tree(t: Tree<Int>) -> Int { 0 }";
+        let analysis = Analysis::new(source, Typing::Off);
+        assert_eq!(analysis.definition(at(source, "Tree", 1)), Some(span(source, "Tree", 0)));
+        assert_eq!(analysis.definition(at(source, "T", 2)), Some(span(source, "T", 0)));
+        assert_eq!(analysis.definition(at(source, "Tree", 3)), Some(span(source, "Tree", 3)));
+        assert_eq!(analysis.definition(at(source, "Tree", 4)), Some(span(source, "Tree", 0)));
+        assert_eq!(analysis.hover(at(source, "Int", 0)).unwrap().1, "```klug\nbuiltin type Int\n```");
+    }
+
+    #[test]
+    fn hovers_show_declarations_and_their_types() {
+        let source = "fn double(x: Int) -> Int { x * 2 }\nlet four = double(2)\nenum Shape { Circle(r), Rect(w, h) }";
+        let analysis = Analysis::new(source, Typing::Off);
+        let (span, text) = analysis.hover(at(source, "double", 1) + 2).unwrap();
+        assert_eq!(span, self::span(source, "double", 1));
+        assert_eq!(text, "```klug\nfn double(x: Int) -> Int\n```\n\ntype `Int -> Int`");
+        assert_eq!(analysis.hover(at(source, "four", 0)).unwrap().1, "```klug\nlet four\n```\n\ntype `Int`");
+        assert_eq!(
+            analysis.hover(at(source, "Rect", 0)).unwrap().1,
+            "```klug\nRect(w, h)\n```\nin `enum Shape`\n\ntype `('a, 'b) -> Shape`");
+        assert_eq!(analysis.hover(at(source, "x", 1)).unwrap().1, "```klug\nx: Int\n```\nin `fn double(x: Int) -> Int`");
+    }
+
+    #[test]
+    fn ill_typed_declarations_have_no_type() {
+        let source = "let x = 1 + 'one'\nlet y = 2";
+        let analysis = Analysis::new(source, Typing::Off);
+        assert_eq!(analysis.hover(at(source, "x", 0)).unwrap().1, "```klug\nlet x\n```");
+        assert_eq!(analysis.hover(at(source, "y", 0)).unwrap().1, "```klug\nlet y\n```\n\ntype `Int`");
+        let source = "fn f(n: Int) -> Int { n }\nlet x = f(1 + 'one')";
+        let analysis = Analysis::new(source, Typing::Inferred);
+        assert_eq!(analysis.hover(at(source, "x", 0)).unwrap().1, "```klug\nlet x\n```");
+    }
+
+    #[test]
+    fn symbols_are_the_top_level_declarations() {
+        let source = "enum Shape { Circle(r), Rect(w, h) }\nfn area(s) { 0 }\nlet x = { let y = 1\n y }\nx";
+        let analysis = Analysis::new(source, Typing::Off);
+        let symbol = |name: &str, kind, span, children| Symbol {
+            name: name.to_string(),
+            kind,
+            span,
+            name_span: self::span(source, name, 0),
+            children,
+        };
+        assert_eq!(analysis.symbols(), vec![
+            symbol("Shape", NameKind::Type, 0..36, vec![
+                symbol("Circle", NameKind::Constructor, span(source, "Circle", 0), vec![]),
+                symbol("Rect", NameKind::Constructor, span(source, "Rect", 0), vec![]),
+            ]),
+            symbol("area", NameKind::Function, 37..53, vec![]),
+            symbol("x", NameKind::Variable, 54..78, vec![]),
+        ]);
+    }
+
+    #[test]
+    fn completions_are_whats_in_scope_and_keywords() {
+        let source = "let a = 1\nfn f(b) { let c = 2\n c + b }\nlet d = a\n";
+        let analysis = Analysis::new(source, Typing::Off);
+        let labels = |offset| -> Vec<String> {
+            analysis.completions(offset).into_iter().map(|c| c.label).collect()
+        };
+        let inside = labels(at(source, "c", 1));
+        assert_eq!(inside[..4], ["c", "b", "f", "a"]);
+        assert!(inside.contains(&"head".to_string()) && inside.contains(&"None".to_string()));
+        assert!(inside.contains(&"match".to_string()) && !inside.contains(&"d".to_string()));
+        assert_eq!(labels(source.len())[..3], ["d", "f", "a"]);
+        // NOTE with a syntax error, what's declared before it
+        let source = "let a = 1\nlet b = (a +\nlet c = 2";
+        let analysis = Analysis::new(source, Typing::Off);
+        assert_eq!(analysis.completions(at(source, "+", 0) + 1)[0].label, "a");
+    }
+
+    #[test]
+    fn diagnostics_span_their_declarations() {
+        let source = "let = 1\nlet x = match 1 { 1 => 2 }\nlet y = 1 + 'one'";
+        let messages = |typing| -> Vec<(Range<usize>, Severity)> {
+            Analysis::new(source, typing).diagnostics().iter().map(|d| (d.span.clone(), d.severity)).collect()
+        };
+        assert_eq!(messages(Typing::Off), vec![(0..7, Severity::Error), (16..34, Severity::Warning)]);
+        assert_eq!(messages(Typing::Inferred), vec![
+            (0..7, Severity::Error), (16..34, Severity::Warning), (35..52, Severity::Error),
+        ]);
+        let analysis = Analysis::new(source, Typing::Off);
+        assert_eq!(analysis.diagnostics()[0].message, "error: Expected Ident but got \"=\"");
+        assert!(analysis.diagnostics()[1].message.starts_with("warning: non-exhaustive match"));
+    }
+
+    #[test]
+    fn warnings_span_their_match_or_arm() {
+        let source = "fn f(b) {\n  match b {\n    true => 1\n    _ => 2\n    false => 3\n  }\n}\nlet x = match 1 { 1 => 2 }";
+        let spans: Vec<Range<usize>> = Analysis::new(source, Typing::Off).diagnostics().iter().map(|d| d.span.clone()).collect();
+        let arm = at(source, "false", 0);
+        let m = at(source, "match", 1);
+        assert_eq!(spans, vec![arm..arm + "false => 3".len(), m..source.len()]);
+    }
+}
@@ -0,0 +1,499 @@
+// resolve - where each name in a program is declared
+//
+// The syntax tree doesn't keep where its names are, so the identifiers a
+// declaration was parsed from are matched up with its names, which are walked
+// in the order they're written. Scopes are the interpreter's: a declaration is
+// visible after it's made, adjacent functions can call each other, and the
+// builtins are looked up after everything else. Types are a namespace of their
+// own, the enums declared so far, type parameters and the builtin types.
+
+use std::rc::Rc;
+use crate::interp::builtin::Builtin;
+use crate::lexer::{Lexer, SyntaxKind};
+use crate::parser::{
+    contract::{Clause, Contract},
+    decl::{self, Decl, Function},
+    expr::{Arm, Expr},
+    literal::Literal,
+    pattern::Pattern,
+    stmt::Stmt,
+    ty::Type,
+    Parse, Span,
+};
+use crate::prelude;
+
+// What a name is declared as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NameKind {
+    // bound by `let`, a pattern or a `catch`
+    Variable,
+    Function,
+    Parameter,
+    Constructor,
+    // an enum or a builtin type
+    Type,
+    TypeParameter,
+    // a named field of a constructor
+    Field,
+    Builtin,
+    // isn't declared anywhere
+    Unresolved,
+}
+
+// Something declared, in the program or before it, e.g. by the prelude
+#[derive(Debug, Clone)]
+pub(crate) struct Declared {
+    pub(crate) name: String,
+    pub(crate) kind: NameKind,
+    // how it's declared, e.g. `fn f(x) -> Int` or `let x: Int`
+    pub(crate) detail: String,
+    // where its name is, none if it's not declared in the program
+    pub(crate) span: Option<Span>,
+    // the top level declaration it's in
+    pub(crate) top: Option<usize>,
+    // whether it's declared at the top level rather than inside something
+    pub(crate) global: bool,
+    // e.g. the enum of a constructor, or the function of a parameter
+    pub(crate) parent: Option<usize>,
+}
+
+// An identifier in the program
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Name {
+    pub(crate) span: Span,
+    // what it refers to, none if it's unresolved
+    pub(crate) declared: Option<usize>,
+    // whether this is where it's declared
+    pub(crate) is_decl: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Resolution {
+    pub(crate) declared: Vec<Declared>,
+    // in the order they're written
+    pub(crate) names: Vec<Name>,
+    // what's in scope at the offset asked for, see `resolve_at`
+    pub(crate) visible: Vec<usize>,
+}
+
+impl Resolution {
+    // the name at `offset`, including where it ends so a cursor after it counts
+    pub(crate) fn name_at(&self, offset: usize) -> Option<&Name> {
+        self.names.iter().find(|n| n.span.start <= offset && offset <= n.span.end)
+    }
+}
+
+pub(crate) fn resolve(source: &str, parse: &Parse) -> Resolution {
+    resolve_at(source, parse, None)
+}
+
+// NOTE with an offset, also remembers what's in scope there for completion
+pub(crate) fn resolve_at(source: &str, parse: &Parse, at: Option<usize>) -> Resolution {
+    let idents = Lexer::new(source)
+        .filter(|(kind, _)| *kind == SyntaxKind::Ident)
+        .map(|(_, txt)| {
+            let start = txt.as_ptr() as usize - source.as_ptr() as usize;
+            start..start + txt.len()
+        })
+        .collect();
+    let mut resolver = Resolver {
+        source,
+        idents,
+        next: 0,
+        end: 0,
+        top: None,
+        at,
+        visible: None,
+        resolution: Resolution::default(),
+        scope: Vec::new(),
+        types: Vec::new(),
+        depth: 0,
+        parents: Vec::new(),
+    };
+    resolver.prelude();
+    resolver.program(parse);
+    let visible = resolver.visible.take().unwrap_or_else(|| resolver.in_scope());
+    Resolution { visible, ..resolver.resolution }
+}
+
+// NOTE the builtin types aren't declared anywhere, see `TypeChecker::annotation`
+const BUILTIN_TYPES: [&str; 7] = ["Int", "Rat", "Float", "Str", "Bool", "Dyn", "List"];
+
+struct Resolver<'a> {
+    source: &'a str,
+    // the spans of the identifiers in the source
+    idents: Vec<Span>,
+    // the first identifier that hasn't been matched to a name
+    next: usize,
+    // where the top level declaration being walked ends
+    end: usize,
+    top: Option<usize>,
+    at: Option<usize>,
+    visible: Option<Vec<usize>>,
+    resolution: Resolution,
+    // NOTE innermost last, scopes are popped by truncating
+    scope: Vec<(String, usize)>,
+    types: Vec<(String, usize)>,
+    // how many scopes are open, declarations at depth 0 are global
+    depth: usize,
+    // what's being declared inside, innermost last
+    parents: Vec<usize>,
+}
+
+impl Resolver<'_> {
+    // NOTE builtins are looked up last, so they're the outermost scope
+    fn prelude(&mut self) {
+        for name in BUILTIN_TYPES {
+            let d = self.add(name, NameKind::Type, format!("builtin type {}", name), None);
+            self.types.push((name.to_string(), d));
+        }
+        for builtin in Builtin::ALL {
+            let name = builtin.name();
+            let d = self.add(name, NameKind::Builtin, format!("builtin {}", name), None);
+            self.scope.push((name.to_string(), d));
+        }
+        // NOTE the prelude's identifiers aren't in the source, so they're never matched
+        let prelude = prelude::parse();
+        self.end = 0;
+        self.decls(&prelude.declarations);
+    }
+
+    fn program(&mut self, parse: &Parse) {
+        let mut i = 0;
+        while i < parse.declarations.len() {
+            let decls = &parse.declarations[i..];
+            let len = decl::leading_functions(decls).len().max(1);
+            let (start, end) = (parse.spans[i].start, parse.spans[i + len - 1].end);
+            if self.at.is_some_and(|at| at <= start) {
+                self.remember_scope();
+            }
+            self.next = self.idents.partition_point(|span| span.start < start);
+            self.end = end;
+            self.top = Some(i);
+            self.decls(&decls[..len]);
+            if self.at.is_some_and(|at| at <= end) {
+                self.remember_scope();
+            }
+            i += len;
+        }
+    }
+
+    // NOTE only the first time, the innermost scope around the offset
+    fn remember_scope(&mut self) {
+        if self.visible.is_none() {
+            self.visible = Some(self.in_scope());
+        }
+    }
+
+    // what can be referred to from here, the innermost of each name
+    fn in_scope(&self) -> Vec<usize> {
+        let mut visible: Vec<usize> = Vec::new();
+        for (name, d) in self.scope.iter().rev() {
+            if !visible.iter().any(|v| self.resolution.declared[*v].name == *name) {
+                visible.push(*d);
+            }
+        }
+        visible
+    }
+
+    fn add(&mut self, name: &str, kind: NameKind, detail: String, span: Option<Span>) -> usize {
+        self.resolution.declared.push(Declared {
+            name: name.to_string(),
+            kind,
+            detail,
+            span,
+            top: self.top,
+            global: self.is_global(),
+            parent: self.parents.last().copied(),
+        });
+        self.resolution.declared.len() - 1
+    }
+
+    fn is_global(&self) -> bool {
+        self.depth == 0 && self.parents.is_empty()
+    }
+
+    // the next identifier spelled `name`, if it's in the declaration being walked
+    fn take(&mut self, name: &str) -> Option<Span> {
+        let found = self.idents[self.next..].iter()
+            .take_while(|span| span.end <= self.end)
+            .position(|span| &self.source[span.clone()] == name)?;
+        let span = self.idents[self.next + found].clone();
+        if self.at.is_some_and(|at| at <= span.end) {
+            self.remember_scope();
+        }
+        self.next += found + 1;
+        Some(span)
+    }
+
+    // declares `name` where it's written next, without bringing it into scope
+    fn declare(&mut self, name: &str, kind: NameKind, detail: String) -> usize {
+        let span = self.take(name);
+        let d = self.add(name, kind, detail, span.clone());
+        if let Some(span) = span {
+            self.resolution.names.push(Name { span, declared: Some(d), is_decl: true });
+        }
+        d
+    }
+
+    // a name declared ahead of where it's written, see `functions`
+    fn locate(&mut self, d: usize) {
+        let name = self.resolution.declared[d].name.clone();
+        if let Some(span) = self.take(&name) {
+            self.resolution.declared[d].span = Some(span.clone());
+            self.resolution.names.push(Name { span, declared: Some(d), is_decl: true });
+        }
+    }
+
+    fn refer(&mut self, name: &str, types: bool) {
+        let scope = if types { &self.types } else { &self.scope };
+        let declared = scope.iter().rev().find(|(n, _)| n == name).map(|(_, d)| *d);
+        if let Some(span) = self.take(name) {
+            self.resolution.names.push(Name { span, declared, is_decl: false });
+        }
+    }
+
+    fn bind(&mut self, d: usize) {
+        let name = self.resolution.declared[d].name.clone();
+        self.scope.push((name, d));
+    }
+
+    fn scoped(&mut self, walk: impl FnOnce(&mut Self)) {
+        let (scope, types) = (self.scope.len(), self.types.len());
+        self.depth += 1;
+        walk(self);
+        self.depth -= 1;
+        self.scope.truncate(scope);
+        self.types.truncate(types);
+    }
+
+    fn inside(&mut self, parent: usize, walk: impl FnOnce(&mut Self)) {
+        self.parents.push(parent);
+        walk(self);
+        self.parents.pop();
+    }
+
+    fn decls(&mut self, decls: &[Decl]) {
+        let mut rest = decls;
+        while let Some(decl) = rest.first() {
+            let fns = decl::leading_functions(rest);
+            if fns.is_empty() {
+                self.decl(decl);
+                rest = &rest[1..];
+            } else {
+                self.functions(&fns);
+                rest = &rest[fns.len()..];
+            }
+        }
+    }
+
+    // NOTE functions declared together are all in scope in each of them, and
+    // at the top level each is a declaration of its own
+    fn functions(&mut self, fns: &[&Rc<Function>]) {
+        let (top, global) = (self.top, self.is_global());
+        let tops: Vec<Option<usize>> = (0..fns.len())
+            .map(|i| match global {
+                true => top.map(|top| top + i),
+                false => top,
+            })
+            .collect();
+        let declared: Vec<usize> = fns.iter().zip(&tops)
+            .map(|(f, top)| {
+                self.top = *top;
+                self.add(&f.name, NameKind::Function, f.signature(), None)
+            })
+            .collect();
+        declared.iter().for_each(|d| self.bind(*d));
+        for ((f, d), top) in fns.iter().zip(declared).zip(tops) {
+            self.top = top;
+            self.locate(d);
+            self.inside(d, |r| r.function(f));
+        }
+        self.top = top;
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Let(name, ty, expr, _) => {
+                let detail = match ty {
+                    Some(ty) => format!("let {}: {}", name, ty),
+                    None => format!("let {}", name),
+                };
+                let d = self.declare(name, NameKind::Variable, detail);
+                self.inside(d, |r| {
+                    ty.iter().for_each(|ty| r.ty(ty));
+                    expr.iter().for_each(|expr| r.expr(expr));
+                });
+                self.bind(d);
+            }
+            Decl::Fn(f) => self.functions(&[f]),
+            Decl::Enum(name, generics, variants) => {
+                let detail = match generics.is_empty() {
+                    true => format!("enum {}", name),
+                    false => format!("enum {}<{}>", name, generics.join(", ")),
+                };
+                let d = self.declare(name, NameKind::Type, detail);
+                // NOTE variants can refer to the enum itself
+                self.types.push((name.clone(), d));
+                let mut ctors = Vec::new();
+                self.inside(d, |r| r.scoped(|r| {
+                    r.type_params(generics);
+                    for variant in variants {
+                        let ctor = r.declare(&variant.name, NameKind::Constructor, variant.stringify());
+                        r.inside(ctor, |r| {
+                            for field in &variant.fields {
+                                if let Some(name) = &field.name {
+                                    let detail = match &field.ty {
+                                        Some(ty) => format!("{}: {}", name, ty),
+                                        None => name.clone(),
+                                    };
+                                    r.declare(name, NameKind::Field, detail);
+                                }
+                                field.ty.iter().for_each(|ty| r.ty(ty));
+                            }
+                        });
+                        ctors.push(ctor);
+                    }
+                }));
+                ctors.into_iter().for_each(|ctor| self.bind(ctor));
+            }
+            Decl::Stmt(stmt) => match &**stmt {
+                Stmt::Expr(expr) => self.expr(expr),
+            },
+            Decl::Error(_) => (),
+        }
+    }
+
+    fn type_params(&mut self, generics: &[String]) {
+        for name in generics {
+            let d = self.declare(name, NameKind::TypeParameter, name.clone());
+            self.types.push((name.clone(), d));
+        }
+    }
+
+    // NOTE `result` is bound in the `ensures` clauses
+    fn function(&mut self, f: &Function) {
+        self.scoped(|r| {
+            r.type_params(&f.generics);
+            for param in &f.params {
+                let detail = match &param.ty {
+                    Some(ty) => format!("{}: {}", param.name, ty),
+                    None => param.name.clone(),
+                };
+                let d = r.declare(&param.name, NameKind::Parameter, detail);
+                param.ty.iter().for_each(|ty| r.ty(ty));
+                r.bind(d);
+            }
+            f.ret.iter().for_each(|ty| r.ty(ty));
+            f.requires.iter().for_each(|c| r.clause(c));
+            r.scoped(|r| {
+                if !f.ensures.is_empty() {
+                    let d = r.add("result", NameKind::Variable, "result".to_string(), None);
+                    r.bind(d);
+                }
+                f.ensures.iter().for_each(|c| r.clause(c));
+            });
+            r.expr(&f.body);
+        });
+    }
+
+    fn clause(&mut self, clause: &Clause) {
+        match clause {
+            Clause::Holds(expr) => self.expr(expr),
+            Clause::Satisfies(name, contract) => {
+                self.refer(name, false);
+                self.contract(contract);
+            }
+        }
+    }
+
+    fn contract(&mut self, contract: &Contract) {
+        match contract {
+            Contract::Flat(expr) => self.expr(expr),
+            Contract::Fn(params, ret) => {
+                params.iter().for_each(|c| self.contract(c));
+                self.contract(ret);
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        match ty {
+            Type::Named(name, args) => {
+                self.refer(name, true);
+                args.iter().for_each(|ty| self.ty(ty));
+            }
+            Type::Fn(params, ret) => {
+                params.iter().for_each(|ty| self.ty(ty));
+                self.ty(ret);
+            }
+            Type::Tuple(items) => items.iter().for_each(|ty| self.ty(ty)),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(Literal::IDENT(name)) => self.refer(name, false),
+            Expr::Literal(_) => (),
+            Expr::Unary(_, e) | Expr::Grouping(e) | Expr::Try(e) | Expr::Throw(e) => self.expr(e),
+            Expr::Binary(lhs, _, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Call(callee, args, _) => {
+                self.expr(callee);
+                args.iter().for_each(|e| self.expr(e));
+            }
+            Expr::Match(scrut, arms, _) => {
+                self.expr(scrut);
+                for Arm { pattern, guard, body, .. } in arms {
+                    self.scoped(|r| {
+                        r.pattern(pattern);
+                        guard.iter().for_each(|e| r.expr(e));
+                        r.expr(body);
+                    });
+                }
+            }
+            Expr::Block(decls, last) => self.scoped(|r| {
+                r.decls(decls);
+                r.expr(last);
+            }),
+            Expr::Lambda(f) => self.function(f),
+            Expr::Tuple(items) => items.iter().for_each(|e| self.expr(e)),
+            Expr::List(items, rest) => {
+                items.iter().for_each(|e| self.expr(e));
+                rest.iter().for_each(|e| self.expr(e));
+            }
+            Expr::TryCatch(body, catch, finally) => {
+                self.expr(body);
+                if let Some((name, handler)) = catch {
+                    self.scoped(|r| {
+                        let d = r.declare(name, NameKind::Variable, format!("catch {}", name));
+                        r.bind(d);
+                        r.expr(handler);
+                    });
+                }
+                finally.iter().for_each(|e| self.expr(e));
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(name) => {
+                let d = self.declare(name, NameKind::Variable, name.clone());
+                self.bind(d);
+            }
+            Pattern::Constructor(name, args) => {
+                self.refer(name, false);
+                args.iter().for_each(|p| self.pattern(p));
+            }
+            Pattern::Tuple(items) => items.iter().for_each(|p| self.pattern(p)),
+            Pattern::List(items, rest) => {
+                items.iter().for_each(|p| self.pattern(p));
+                rest.iter().for_each(|p| self.pattern(p));
+            }
+            Pattern::Wildcard | Pattern::Literal(_) => (),
+        }
+    }
+}
@@ -1,21 +1,105 @@
 pub(crate) mod value;
+pub(crate) mod env;
+pub(crate) mod cast;
+pub(crate) mod contract;
+pub(crate) mod builtin;
+pub(crate) mod num;
 
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::fmt;
-use num_traits::Float;
-use float_cmp::approx_eq;
-use value::Value;
+use std::rc::Rc;
+use value::{Ctor, Value};
+use env::Env;
+use builtin::Builtin;
+use cast::{cast, Blame, Party};
+use crate::{prelude, vm};
 use crate::parser::{
+    Span,
     stmt::Stmt,
     literal::Literal,
-    decl::Decl,
+    decl::{self, Decl, Function},
+    pattern::Pattern,
     expr::{
     Expr,
+    Arm,
     op::{InfixOp, PrefixOp}
 }};
 
 #[derive(Debug)]
 pub(crate) struct RuntimeError {
-    msg: String,
+    pub(crate) msg: String,
+    pub(crate) kind: ErrorKind,
+    // where the blamed party of a failed cast is
+    pub(crate) span: Option<Span>,
+    // NOTE set for a non-local exit rather than a failure, it travels up
+    // like an error until it's caught, and `msg` says why it wasn't
+    pub(crate) unwind: Option<Unwind>,
+}
+
+#[derive(Debug)]
+// NOTE the values are boxed to keep errors small
+pub(crate) enum Unwind {
+    // `?` returning a `None` or `Err` from the enclosing function
+    Return(Box<Value>),
+    // `throw`, caught by the nearest enclosing `catch`
+    Throw(Box<Value>),
+}
+
+// what went wrong, a caught error is an `Error(kind, message, span)` value
+// with the kind's name, e.g. `Error('arith', 'division by zero', None)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ErrorKind {
+    Syntax,
+    // an unbound or uninitialized name
+    Name,
+    // a value of the wrong type, e.g. `1 + 'a'`
+    Type,
+    Arity,
+    // e.g. dividing by zero
+    Arith,
+    Match,
+    // a failed cast or contract
+    Contract,
+    // a value thrown, or returned by `?`, that nothing caught
+    Throw,
+    // evaluation nested too deeply, see `MAX_DEPTH`, or ran out of fuel
+    Stack,
+}
+
+impl ErrorKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Syntax => "syntax",
+            Self::Name => "name",
+            Self::Type => "type",
+            Self::Arity => "arity",
+            Self::Arith => "arith",
+            Self::Match => "match",
+            Self::Contract => "contract",
+            Self::Throw => "throw",
+            Self::Stack => "stack",
+        }
+    }
+}
+
+impl RuntimeError {
+    // what a `catch` is given, a thrown value as is and a failure as an `Error`
+    pub(crate) fn into_value(self) -> Value {
+        match self.unwind {
+            Some(Unwind::Throw(val)) | Some(Unwind::Return(val)) => *val,
+            None => {
+                let span = match self.span {
+                    Some(span) => Value::Variant(prelude::ctor("Some"), vec![Value::Tuple(vec![
+                        Value::Int(span.start as i64),
+                        Value::Int(span.end as i64),
+                    ])]),
+                    None => Value::Variant(prelude::ctor("None"), vec![]),
+                };
+                Value::Variant(prelude::ctor("Error"), vec![Value::Str(self.kind.name().to_string()), Value::Str(self.msg), span])
+            }
+        }
+    }
 }
 
 impl fmt::Display for RuntimeError {
@@ -24,78 +108,554 @@ impl fmt::Display for RuntimeError {
     }    
 }
 
+// NOTE deeper evaluation would overflow the Rust stack, it's counted in nested
+// expressions, so it limits calls that aren't tail calls too. This much fits
+// in an 8MB stack in a release build, a debug build needs several times more
+pub const MAX_DEPTH: usize = 5000;
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static MAX: Cell<usize> = const { Cell::new(MAX_DEPTH) };
+    static FUEL: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+// the maximum depth for evaluation on this thread
+pub(crate) fn set_max_depth(max: usize) {
+    MAX.with(|m| m.set(max));
+}
+
+// how many more times evaluation may descend on this thread, or no limit,
+// returning what was left of it
+// NOTE the engines descend at different times, so they run out at different times
+pub(crate) fn set_fuel(fuel: Option<u64>) -> Option<u64> {
+    FUEL.with(|f| f.replace(fuel))
+}
+
+// one level deeper, failing past the maximum depth
+// NOTE shared with the `vm`, where a level is a call
+pub(crate) fn descend() -> Result<(), RuntimeError> {
+    match FUEL.with(Cell::get) {
+        Some(0) => return Err(RuntimeError {
+            msg: "out of fuel".to_string(),
+            kind: ErrorKind::Stack,
+            span: None,
+            unwind: None,
+        }),
+        Some(fuel) => FUEL.with(|f| f.set(Some(fuel - 1))),
+        None => (),
+    }
+    let depth = DEPTH.with(Cell::get);
+    if depth >= MAX.with(Cell::get) {
+        return Err(RuntimeError {
+            msg: "stack overflow".to_string(),
+            kind: ErrorKind::Stack,
+            span: None,
+            unwind: None,
+        });
+    }
+    DEPTH.with(|d| d.set(depth + 1));
+    Ok(())
+}
+
+pub(crate) fn ascend() {
+    DEPTH.with(|d| d.set(d.get() - 1));
+}
+
+pub(crate) fn interp_expr(expr: &Expr, env: &Env) -> Result<Value, RuntimeError> {
+    descend()?;
+    let ret = eval(expr, env);
+    ascend();
+    ret
+}
+
 // interp - I'm going for a fully functional style here :)
-pub(crate) fn interp_expr(expr: Expr) -> Result<Value, RuntimeError> {
+fn eval(expr: &Expr, env: &Env) -> Result<Value, RuntimeError> {
     match expr {
         // terminals
-        Expr::Literal(Literal::NUMBER(num)) => Ok(Value::Number(num)),
-        Expr::Literal(Literal::IDENT(_)) => todo!(),
-        Expr::Literal(Literal::STRING(s)) => Ok(Value::Str(s)),
+        Expr::Literal(Literal::INT(num)) => Ok(Value::Int(*num)),
+        Expr::Literal(Literal::BIG(num)) => Ok(Value::BigInt(num.clone())),
+        Expr::Literal(Literal::FLOAT(num)) => Ok(Value::Float(*num)),
+        Expr::Literal(Literal::IDENT(name)) => env.lookup(name)
+            .or_else(|| Builtin::from_name(name).map(Value::Builtin))
+            .ok_or_else(|| RuntimeError {
+                msg: format!("unbound identifier {}", name),
+                kind: ErrorKind::Name,
+                span: None,
+                unwind: None,
+            }),
+        Expr::Literal(Literal::STRING(s)) => Ok(Value::Str(s.clone())),
         Expr::Literal(Literal::TRUE) => Ok(Value::Bool(true)),
         Expr::Literal(Literal::FALSE) => Ok(Value::Bool(false)),
 
-        Expr::Unary(prefop, box_bdy) => {
-            let val = interp_expr(*box_bdy)?;
-            match (prefop, val) {
-                (PrefixOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
-                (PrefixOp::Neg, Value::Number(n)) => Ok(Value::Number(-n)),
-                _ => todo!(),
+        Expr::Unary(prefop, box_bdy) => unary(prefop, interp_expr(box_bdy, env)?),
+
+        Expr::Binary(box_lhs, infop, box_rhs) => {
+            let vlhs = interp_expr(box_lhs, env)?;
+            let vrhs = interp_expr(box_rhs, env)?;
+            binary(infop, vlhs, vrhs)
+        }
+
+        Expr::Grouping(box_bdy) => interp_expr(box_bdy, env),
+
+        Expr::Call(box_callee, args, _) => {
+            let callee = interp_expr(box_callee, env)?;
+            let args = interp_exprs(args, env)?;
+            call(callee, args, expr).map_err(|e| at_call(e, expr))
+        }
+
+        Expr::Match(box_scrut, arms, _) => {
+            let val = interp_expr(box_scrut, env)?;
+            let (arm, arm_env) = select_arm(&val, arms, env)?;
+            interp_expr(&arm.body, &arm_env)
+        }
+
+        Expr::Block(decls, box_expr) => {
+            let mut env = env.clone();
+            exec_decls(decls, &mut env)?;
+            interp_expr(box_expr, &env)
+        }
+
+        Expr::Lambda(f) => Ok(Value::Closure(f.clone(), env.clone())),
+
+        Expr::Try(bdy) => question(interp_expr(bdy, env)?),
+
+        Expr::Throw(bdy) => Err(throw(interp_expr(bdy, env)?)),
+
+        // NOTE `?` returning from the function isn't a failure, it passes through
+        // the catch, but the finally block runs however the try ends
+        Expr::TryCatch(bdy, catch, finally) => {
+            let ret = match (interp_expr(bdy, env), catch) {
+                (Err(e), Some((name, handler))) if !matches!(e.unwind, Some(Unwind::Return(_))) => {
+                    interp_expr(handler, &env.extend(name.clone(), e.into_value()))
+                }
+                (ret, _) => ret,
+            };
+            if let Some(finally) = finally {
+                interp_expr(finally, env)?;
             }
+            ret
         }
 
-        Expr::Binary(box_lhs, infop, box_rhs) => {
-            let vlhs = interp_expr(*box_lhs)?;
-            let vrhs = interp_expr(*box_rhs)?;
-            match (vlhs, vrhs) {
-                (Value::Number(n1), Value::Number(n2)) => num_calc(n1, n2, infop),
-                (Value::Str(_), Value::Str(_)) => todo!(),
-                (Value::Bool(_), Value::Bool(_)) => todo!(),
-                _ => todo!(),
+        // NOTE the empty tuple is unit
+        Expr::Tuple(items) if items.is_empty() => Ok(Value::Unit),
+        Expr::Tuple(items) => Ok(Value::Tuple(interp_exprs(items, env)?)),
+
+        Expr::List(items, rest) => {
+            let mut vals = interp_exprs(items, env)?;
+            if let Some(rest) = rest {
+                spread(&mut vals, interp_expr(rest, env)?)?;
             }
+            Ok(Value::List(vals))
+        }
+    }
+}
+
+// NOTE the operators, `?` and `throw` are shared with the `vm`
+pub(crate) fn unary(op: &PrefixOp, val: Value) -> Result<Value, RuntimeError> {
+    match (op, val) {
+        (PrefixOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (PrefixOp::Neg, n) if n.is_number() => Ok(num::neg(n)),
+        (PrefixOp::BitNot, n) => num::bit_not(n),
+        (op, v) => Err(RuntimeError {
+            msg: format!("can't apply {} to {}", op.stringify(), v),
+            kind: ErrorKind::Type,
+            span: None,
+            unwind: None,
+        }),
+    }
+}
+
+pub(crate) fn binary(op: &InfixOp, vlhs: Value, vrhs: Value) -> Result<Value, RuntimeError> {
+    match (vlhs, vrhs) {
+        (v1, v2) if *op == InfixOp::Eq => Ok(Value::Bool(v1.equals(&v2, num::numerics().float_eq))),
+        (v1, v2) if *op == InfixOp::NotEq => Ok(Value::Bool(!v1.equals(&v2, num::numerics().float_eq))),
+        (v1, v2) if v1.is_number() && v2.is_number() => num::num_calc(v1, v2, op),
+        (Value::Str(s1), Value::Str(s2)) => str_calc(&s1, &s2, op),
+        (v1, v2) => Err(RuntimeError {
+            msg: format!("can't apply {} to {} and {}", op.stringify(), v1, v2),
+            kind: ErrorKind::Type,
+            span: None,
+            unwind: None,
+        }),
+    }
+}
+
+// NOTE these are the prelude's variants, by name
+pub(crate) fn question(val: Value) -> Result<Value, RuntimeError> {
+    match val {
+        Value::Variant(ctor, mut args) if (ctor.name == "Some" || ctor.name == "Ok") && args.len() == 1 => Ok(args.remove(0)),
+        Value::Variant(ctor, args) if ctor.name == "None" || ctor.name == "Err" => {
+            let val = Value::Variant(ctor, args);
+            Err(RuntimeError {
+                msg: format!("`?` returned {} outside of a function", val),
+                kind: ErrorKind::Throw,
+                span: None,
+                unwind: Some(Unwind::Return(Box::new(val))),
+            })
         }
+        v => Err(RuntimeError {
+            msg: format!("`?` needs an Option or a Result, got {}", v),
+            kind: ErrorKind::Type,
+            span: None,
+            unwind: None,
+        }),
+    }
+}
+
+pub(crate) fn throw(val: Value) -> RuntimeError {
+    RuntimeError {
+        msg: format!("uncaught exception {}", val),
+        kind: ErrorKind::Throw,
+        span: None,
+        unwind: Some(Unwind::Throw(Box::new(val))),
+    }
+}
+
+// the `..rest` of a list
+pub(crate) fn spread(vals: &mut Vec<Value>, rest: Value) -> Result<(), RuntimeError> {
+    match rest {
+        Value::List(rest) => {
+            vals.extend(rest);
+            Ok(())
+        }
+        v => Err(RuntimeError { msg: format!("expected a list after '..' but got {}", v), kind: ErrorKind::Type, span: None, unwind: None }),
+    }
+}
 
-        Expr::Grouping(box_bdy) => interp_expr(*box_bdy),
+fn interp_exprs(exprs: &[Expr], env: &Env) -> Result<Vec<Value>, RuntimeError> {
+    exprs.iter().map(|expr| interp_expr(expr, env)).collect()
+}
 
-        _ => unreachable!(), // I'll handle errors later
+// NOTE `site` is the call expression, it's blamed for arguments that fail a cast
+pub(crate) fn call(callee: Value, args: Vec<Value>, site: &Expr) -> Result<Value, RuntimeError> {
+    match callee {
+        Value::Constructor(ctor) if ctor.fields.len() == args.len() => Ok(Value::Variant(ctor, args)),
+        Value::Constructor(ctor) => Err(RuntimeError {
+            msg: format!("{} expects {} argument(s) but was given {}", ctor.name, ctor.fields.len(), args.len()),
+            kind: ErrorKind::Arity,
+            span: None,
+            unwind: None,
+        }),
+        Value::Closure(f, cenv) => apply(f, cenv, args, site),
+        Value::Compiled(f) => vm::call(&f, args, site),
+        Value::Builtin(builtin) => builtin.apply(args),
+        Value::Guarded(guard) => {
+            guard.check_arity(args.len())?;
+            let args = guard.params.iter()
+                .zip(args)
+                .map(|(spec, arg)| guard.check(arg, spec, &guard.blame.flip(), site))
+                .collect::<Result<_, _>>()?;
+            let ret = call(guard.value.clone(), args, site)?;
+            guard.check(ret, &guard.ret, &guard.blame, site)
+        }
+        v => Err(RuntimeError { msg: format!("{} is not callable", v), kind: ErrorKind::Type, span: None, unwind: None }),
     }
 }
 
-// TODO statements don't (currently) have a return value. 
-pub(crate) fn interp_stmt(stmt: Stmt) -> Result<Value, RuntimeError> {
+// NOTE a failure without a span of its own is placed at the innermost call it escapes
+pub(crate) fn at_call(e: RuntimeError, site: &Expr) -> RuntimeError {
+    match (e, site) {
+        (e @ RuntimeError { span: None, unwind: None, .. }, Expr::Call(_, _, span)) => RuntimeError { span: Some(span.clone()), ..e },
+        (e, _) => e,
+    }
+}
+
+// What a function body evaluates to, a call in tail position is left for
+// `apply` to make so that tail calls don't use any more of the Rust stack
+enum Tail<'a> {
+    Value(Value),
+    Call(Rc<Function>, Env, Vec<Value>, &'a Expr),
+}
+
+// interp_expr, except a call to a closure in tail position isn't made
+fn interp_tail<'a>(expr: &'a Expr, env: &Env) -> Result<Tail<'a>, RuntimeError> {
+    match expr {
+        Expr::Call(box_callee, args, _) => {
+            let callee = interp_expr(box_callee, env)?;
+            let args = interp_exprs(args, env)?;
+            match callee {
+                Value::Closure(f, cenv) => Ok(Tail::Call(f, cenv, args, expr)),
+                callee => call(callee, args, expr).map(Tail::Value).map_err(|e| at_call(e, expr)),
+            }
+        }
+        Expr::Grouping(box_bdy) => interp_tail(box_bdy, env),
+        Expr::Match(box_scrut, arms, _) => {
+            let val = interp_expr(box_scrut, env)?;
+            let (arm, arm_env) = select_arm(&val, arms, env)?;
+            interp_tail(&arm.body, &arm_env)
+        }
+        Expr::Block(decls, box_expr) => {
+            let mut env = env.clone();
+            exec_decls(decls, &mut env)?;
+            interp_tail(box_expr, &env)
+        }
+        expr => interp_expr(expr, env).map(Tail::Value),
+    }
+}
+
+// NOTE loops rather than recursing for tail calls, including calls to other functions
+fn apply(f: Rc<Function>, cenv: Env, args: Vec<Value>, site: &Expr) -> Result<Value, RuntimeError> {
+    let mut next = (f, cenv, args, Cow::Borrowed(site));
+    loop {
+        let (f, cenv, args, site) = next;
+        let tail = match site {
+            // NOTE the caller places failures of the first call
+            Cow::Borrowed(_) => enter(&f, cenv, args, &site),
+            Cow::Owned(_) => enter(&f, cenv, args, &site).map_err(|e| at_call(e, &site)),
+        };
+        match tail? {
+            Tail::Value(val) => return Ok(val),
+            Tail::Call(g, genv, args, site) => next = (g, genv, args, Cow::Owned(site.clone())),
+        }
+    }
+}
+
+// what a function is called in an error, lambdas have no name
+pub(crate) fn function_name(name: &str) -> &str {
+    match name {
+        "" => "anonymous function",
+        name => name,
+    }
+}
+
+// runs a single call, up to a call in tail position
+fn enter<'a>(f: &'a Rc<Function>, cenv: Env, args: Vec<Value>, site: &Expr) -> Result<Tail<'a>, RuntimeError> {
+    if f.params.len() != args.len() {
+        return Err(RuntimeError {
+            msg: format!("{} expects {} argument(s) but was given {}", function_name(&f.name), f.params.len(), args.len()),
+            kind: ErrorKind::Arity,
+            span: None,
+            unwind: None,
+        });
+    }
+    let mut env = match f.generics.is_empty() {
+        true => cenv,
+        false => cenv.extend_generics(f.generics.clone()),
+    };
+    // NOTE the blame is only built for annotations and contracts
+    let blame = || Blame { positive: Party::call(site), negative: Party::function(&f.name, &f.span) };
+    for (param, arg) in f.params.iter().zip(args) {
+        let arg = match &param.ty {
+            Some(ty) => cast(arg, ty, &f.generics, &blame())?,
+            None => arg,
+        };
+        env = env.extend(param.name.clone(), arg);
+    }
+    if !f.requires.is_empty() {
+        env = contract::check_clauses(&f.requires, env, &blame(), site)?;
+    }
+    // NOTE a checked result is only known after the body, so then the body's calls aren't in tail position
+    if f.ensures.is_empty() && f.ret.is_none() {
+        return match interp_tail(&f.body, &env) {
+            Err(RuntimeError { unwind: Some(Unwind::Return(val)), .. }) => Ok(Tail::Value(*val)),
+            tail => tail,
+        };
+    }
+    let mut ret = match interp_expr(&f.body, &env) {
+        Err(RuntimeError { unwind: Some(Unwind::Return(val)), .. }) => *val,
+        ret => ret?,
+    };
+    if !f.ensures.is_empty() {
+        let env = env.extend("result".to_string(), ret);
+        let env = contract::check_clauses(&f.ensures, env, &blame().flip(), site)?;
+        ret = env.lookup("result").unwrap();
+    }
+    match &f.ret {
+        Some(ty) => cast(ret, ty, &f.generics, &blame().flip()).map(Tail::Value),
+        None => Ok(Tail::Value(ret)),
+    }
+}
+
+// the first arm that matches `val`, with the environment its body runs in
+fn select_arm<'a>(val: &Value, arms: &'a [Arm], env: &Env) -> Result<(&'a Arm, Env), RuntimeError> {
+    for arm in arms {
+        let arm_env = match match_pattern(&arm.pattern, val, env) {
+            Some(arm_env) => arm_env,
+            None => continue,
+        };
+        let guarded = match &arm.guard {
+            None => true,
+            Some(guard) => match interp_expr(guard, &arm_env)? {
+                Value::Bool(b) => b,
+                v => return Err(RuntimeError {
+                    msg: format!("match guard must produce a boolean, got {}", v),
+                    kind: ErrorKind::Type,
+                    span: None,
+                    unwind: None,
+                }),
+            },
+        };
+        if guarded {
+            return Ok((arm, arm_env));
+        }
+    }
+    Err(RuntimeError { msg: format!("no match arm matches the value {}", val), kind: ErrorKind::Match, span: None, unwind: None })
+}
+
+// returns the environment extended with the pattern's bindings if `val` matches
+fn match_pattern(pat: &Pattern, val: &Value, env: &Env) -> Option<Env> {
+    let mut env = env.clone();
+    let matched = bind_pattern(pat, val, &mut |name, val| env = env.extend(name.to_string(), val));
+    matched.then_some(env)
+}
+
+// whether `val` matches, `bind` is given the pattern's bindings from left to right
+// NOTE the `vm` binds them to slots rather than names
+pub(crate) fn bind_pattern(pat: &Pattern, val: &Value, bind: &mut impl FnMut(&str, Value)) -> bool {
+    match (pat, val) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Binding(name), _) => {
+            bind(name, val.clone());
+            true
+        }
+        (Pattern::Literal(Literal::INT(n1)), Value::Int(n2)) => n1 == n2,
+        (Pattern::Literal(Literal::BIG(n1)), Value::BigInt(n2)) => n1 == n2,
+        (Pattern::Literal(Literal::FLOAT(n1)), Value::Float(n2)) => n1 == n2,
+        (Pattern::Literal(Literal::STRING(s1)), Value::Str(s2)) => s1 == s2,
+        (Pattern::Literal(Literal::TRUE), Value::Bool(b)) => *b,
+        (Pattern::Literal(Literal::FALSE), Value::Bool(b)) => !*b,
+        (Pattern::Constructor(name, pats), Value::Variant(ctor, args))
+            if *name == ctor.name && pats.len() == args.len() => {
+            bind_patterns(pats, args, bind)
+        }
+        (Pattern::Tuple(pats), Value::Unit) => pats.is_empty(),
+        (Pattern::Tuple(pats), Value::Tuple(items)) if pats.len() == items.len() => {
+            bind_patterns(pats, items, bind)
+        }
+        (Pattern::List(pats, None), Value::List(items)) if pats.len() == items.len() => {
+            bind_patterns(pats, items, bind)
+        }
+        (Pattern::List(pats, Some(rest)), Value::List(items)) if pats.len() <= items.len() => {
+            bind_patterns(pats, &items[..pats.len()], bind)
+                && bind_pattern(rest, &Value::List(items[pats.len()..].to_vec()), bind)
+        }
+        _ => false,
+    }
+}
+
+fn bind_patterns(pats: &[Pattern], vals: &[Value], bind: &mut impl FnMut(&str, Value)) -> bool {
+    pats.iter().zip(vals).all(|(pat, val)| bind_pattern(pat, val, bind))
+}
+
+// runs declarations in order, the result is the value of the last one if
+// it's an expression statement and `Unit` otherwise
+pub(crate) fn exec_decls(decls: &[Decl], env: &mut Env) -> Result<Value, RuntimeError> {
+    let mut last = Value::Unit;
+    let mut rest = decls;
+    while let Some(decl) = rest.first() {
+        let fns = decl::leading_functions(rest);
+        if !fns.is_empty() {
+            *env = env.extend_functions(fns.iter().map(|f| (*f).clone()).collect());
+            last = Value::Unit;
+            rest = &rest[fns.len()..];
+            continue;
+        }
+        last = match decl {
+            Decl::Stmt(bx_stmt) => eval_stmt(bx_stmt, env)?,
+            decl => {
+                exec_decl(decl, env)?;
+                Value::Unit
+            }
+        };
+        rest = &rest[1..];
+    }
+    Ok(last)
+}
+
+pub(crate) fn eval_stmt(stmt: &Stmt, env: &Env) -> Result<Value, RuntimeError> {
     match stmt {
-        Stmt::Expr(bx_expr) => interp_expr(*expr),
-        _ => todo!(),
+        Stmt::Expr(bx_expr) => interp_expr(bx_expr, env),
     }
 }
 
-// TODO declarations really shouldn't return a Value :)
-pub(crate) fn interp_decl(decl: Decl) -> Result<Value, RuntimeError> {
+// NOTE declarations only extend the environment, an expression statement
+// is run for its effects and its value is dropped
+pub(crate) fn exec_decl(decl: &Decl, env: &mut Env) -> Result<(), RuntimeError> {
     match decl {
-        Decl::Let(ident, opt_expr) => todo!(),
-        Decl::Stmt(bx_stmt) => todo!(),
-        _ => todo!(),
+        Decl::Let(ident, ty, Some(expr), span) => {
+            let mut val = interp_expr(expr, env)?;
+            // NOTE the binding both provides the value and is the one using it
+            if let Some(ty) = ty {
+                let party = Party::binding(ident, span);
+                val = cast(val, ty, &env.generics(), &Blame { positive: party.clone(), negative: party })?;
+            }
+            *env = env.extend(ident.clone(), val);
+            Ok(())
+        }
+        Decl::Let(ident, _, None, _) => Err(RuntimeError {
+            msg: format!("{} must be initialized", ident),
+            kind: ErrorKind::Name,
+            span: None,
+            unwind: None,
+        }),
+        Decl::Fn(f) => {
+            *env = env.extend_functions(vec![f.clone()]);
+            Ok(())
+        }
+        Decl::Enum(name, generics, variants) => {
+            for variant in variants {
+                *env = env.extend(variant.name.clone(), Ctor::new(name, generics, variant).value());
+            }
+            Ok(())
+        }
+        Decl::Stmt(bx_stmt) => eval_stmt(bx_stmt, env).map(|_| ()),
+        Decl::Error(msg) => Err(RuntimeError { msg: msg.clone(), kind: ErrorKind::Syntax, span: None, unwind: None }),
     }
 }
 
-//fn num_calc<T: Float>(n1: T, n2: T, op: InfixOp) -> Value {
-fn num_calc(n1: f64, n2: f64, op: InfixOp) -> Result<Value, RuntimeError>{
-    let num = match op {
-       InfixOp::Add => n1 + n2, 
-       InfixOp::Mul => n1 * n2, 
-       InfixOp::Sub => n1 - n2, 
-       InfixOp::Div if approx_eq!(f64, n2, 0.0) => panic!("division by 0"),
-       InfixOp::Div => n1 / n2,
-    };
-    Ok(Value::Number(num))
+fn str_calc(s1: &str, s2: &str, op: &InfixOp) -> Result<Value, RuntimeError> {
+    match op {
+        InfixOp::Lt => Ok(Value::Bool(s1 < s2)),
+        InfixOp::LtEq => Ok(Value::Bool(s1 <= s2)),
+        InfixOp::Gt => Ok(Value::Bool(s1 > s2)),
+        InfixOp::GtEq => Ok(Value::Bool(s1 >= s2)),
+        op => Err(RuntimeError {
+            msg: format!("can't apply {} to '{}' and '{}'", op.stringify(), s1, s2),
+            kind: ErrorKind::Type,
+            span: None,
+            unwind: None,
+        }),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::Parser;
+
+    // NOTE shadows the glob import, most tests don't need an environment
+    fn interp_expr(expr: Expr) -> Result<Value, RuntimeError> {
+        let ret = super::interp_expr(&expr, &Env::default());
+        let decls = vec![Decl::Stmt(Box::new(Stmt::Expr(Box::new(expr))))];
+        assert_eq!(outcome(&vm::run(&decls, &mut Env::default())), outcome(&ret), "the engines disagree on {}", decls[0].stringify());
+        ret
+    }
+
+    // NOTE like the REPL, a unit result is no result, and every program is
+    // run by the `vm` too, which has to agree
+    fn run(input: &str) -> Result<Option<Value>, RuntimeError> {
+        let decls = Parser::new(input).parse().declarations;
+        let ret = exec_decls(&decls, &mut Env::default());
+        assert_eq!(outcome(&vm::run(&decls, &mut Env::default())), outcome(&ret), "the engines disagree on {:?}", input);
+        match ret? {
+            Value::Unit => Ok(None),
+            val => Ok(Some(val)),
+        }
+    }
+
+    // what the engines are compared on, functions only by how they print
+    fn outcome(ret: &Result<Value, RuntimeError>) -> Result<String, (String, ErrorKind, Option<Span>)> {
+        match ret {
+            Ok(val) => Ok(val.to_string()),
+            Err(e) => Err((e.msg.clone(), e.kind, e.span.clone())),
+        }
+    }
+
+    const SHAPES: &str = "enum Shape { Circle(r), Rect(w, h), Empty }\n";
 
     #[test]
     fn interp_num() {
-        assert_eq!(interp_expr(Expr::Literal(Literal::NUMBER(5.0))).unwrap(), Value::Number(5.0));
+        assert_eq!(interp_expr(Expr::Literal(Literal::INT(5))).unwrap(), Value::Int(5));
     }
 
     #[test]
@@ -108,10 +668,10 @@ mod tests {
     #[test]
     fn simple_binary() {
         assert_eq!(
-            interp_expr(Expr::Binary(Box::new(Expr::Literal(Literal::NUMBER(1.0))), 
+            interp_expr(Expr::Binary(Box::new(Expr::Literal(Literal::INT(1))), 
                            InfixOp::Add, 
-                           Box::new(Expr::Literal(Literal::NUMBER(2.0))))).unwrap(), 
-            Value::Number(3.0));  
+                           Box::new(Expr::Literal(Literal::INT(2))))).unwrap(), 
+            Value::Int(3));  
     }
 
     #[test]
@@ -119,25 +679,25 @@ mod tests {
         assert_eq!(
             interp_expr(Expr::Binary(
                   Box::new(Expr::Binary(
-                      Box::new(Expr::Literal(Literal::NUMBER(1.0))),
+                      Box::new(Expr::Literal(Literal::INT(1))),
                       InfixOp::Add, 
-                      Box::new(Expr::Literal(Literal::NUMBER(2.0))))),
+                      Box::new(Expr::Literal(Literal::INT(2))))),
                   InfixOp::Sub, 
-                  Box::new(Expr::Literal(Literal::NUMBER(4.0))))).unwrap(), 
-            Value::Number(-1.0));
+                  Box::new(Expr::Literal(Literal::INT(4))))).unwrap(), 
+            Value::Int(-1));
     }
 
     #[test]
     fn simple_precedence1() {
         assert_eq!(
             interp_expr(Expr::Binary(
-                  Box::new(Expr::Literal(Literal::NUMBER(1.0))),
+                  Box::new(Expr::Literal(Literal::INT(1))),
                   InfixOp::Add, 
                   Box::new(Expr::Binary(
-                      Box::new(Expr::Literal(Literal::NUMBER(2.0))),
+                      Box::new(Expr::Literal(Literal::INT(2))),
                       InfixOp::Mul, 
-                      Box::new(Expr::Literal(Literal::NUMBER(4.0))))))).unwrap(), 
-            Value::Number(9.0));
+                      Box::new(Expr::Literal(Literal::INT(4))))))).unwrap(), 
+            Value::Int(9));
     }
 
     #[test]
@@ -145,12 +705,12 @@ mod tests {
         assert_eq!(
             interp_expr(Expr::Binary(
                 Box::new(Expr::Binary(
-                    Box::new(Expr::Literal(Literal::NUMBER(1.0))),
+                    Box::new(Expr::Literal(Literal::INT(1))),
                     InfixOp::Mul,
-                    Box::new(Expr::Literal(Literal::NUMBER(2.0))))),
+                    Box::new(Expr::Literal(Literal::INT(2))))),
                 InfixOp::Sub,
-                Box::new(Expr::Literal(Literal::NUMBER(4.0))))).unwrap(), 
-            Value::Number(-2.0));
+                Box::new(Expr::Literal(Literal::INT(4))))).unwrap(), 
+            Value::Int(-2));
     }
 
     // rough ...
@@ -159,26 +719,26 @@ mod tests {
         assert_eq!(
             interp_expr(Expr::Binary(
                   Box::new(Expr::Binary(
-                          Box::new(Expr::Literal(Literal::NUMBER(1.0))),
+                          Box::new(Expr::Literal(Literal::INT(1))),
                           InfixOp::Mul,
                           Box::new(Expr::Grouping(
                                   Box::new(Expr::Binary(
-                                          Box::new(Expr::Literal(Literal::NUMBER(2.0))),
+                                          Box::new(Expr::Literal(Literal::INT(2))),
                                           InfixOp::Add,
-                                          Box::new(Expr::Literal(Literal::NUMBER(2.0))))))))),
+                                          Box::new(Expr::Literal(Literal::INT(2))))))))),
                   InfixOp::Div,
-                  Box::new(Expr::Literal(Literal::NUMBER(4.0))))).unwrap(), 
-            Value::Number(1.0));
+                  Box::new(Expr::Literal(Literal::INT(4))))).unwrap(), 
+            Value::Int(1));
     }
 
     #[test]
     fn unary_expr_minus() {
         assert_eq!(
             interp_expr(Expr::Binary(
-                  Box::new(Expr::Unary(PrefixOp::Neg, Box::new(Expr::Literal(Literal::NUMBER(10.0))))),
+                  Box::new(Expr::Unary(PrefixOp::Neg, Box::new(Expr::Literal(Literal::INT(10))))),
                   InfixOp::Add,
-                  Box::new(Expr::Literal(Literal::NUMBER(20.0))))).unwrap(), 
-            Value::Number(10.0));
+                  Box::new(Expr::Literal(Literal::INT(20))))).unwrap(), 
+            Value::Int(10));
     }
     #[test]
     fn unary_expr_bang() {
@@ -186,4 +746,384 @@ mod tests {
               interp_expr(Expr::Unary(PrefixOp::Not, Box::new(Expr::Literal(Literal::TRUE)))).unwrap(),
               Value::Bool(false));
     }
+
+    #[test]
+    fn construct_variant() {
+        assert_eq!(
+            run(&format!("{}Rect(1, 2)", SHAPES)).unwrap().map(|v| v.to_string()),
+            Some("Rect(1, 2)".to_string()));
+    }
+
+    #[test]
+    fn constructor_arity_mismatch() {
+        assert!(run(&format!("{}Rect(1)", SHAPES)).is_err());
+    }
+
+    #[test]
+    fn match_constructor_binding() {
+        assert_eq!(
+            run(&format!("{}match Rect(3, 4) {{ Circle(r) => r, Rect(w, h) => w * h, Empty => 0 }}", SHAPES)).unwrap(),
+            Some(Value::Int(12)));
+    }
+
+    #[test]
+    fn match_nullary_constructor() {
+        assert_eq!(
+            run(&format!("{}match Empty {{ Circle(_) => 1, _ => 2 }}", SHAPES)).unwrap(),
+            Some(Value::Int(2)));
+    }
+
+    #[test]
+    fn match_literal() {
+        assert_eq!(
+            run("match 2 { 1 => 'one', 2 => 'two', _ => 'many' }").unwrap(),
+            Some(Value::Str("two".to_string())));
+    }
+
+    #[test]
+    fn match_nested() {
+        let input = "enum Tree { Leaf, Node(l, v, r) }\n\
+                     match Node(Leaf, 5, Node(Leaf, 6, Leaf)) {\n\
+                       Node(Leaf, v, Leaf) => v\n\
+                       Node(_, _, Node(_, v, _)) => v\n\
+                       _ => 0\n\
+                     }";
+        assert_eq!(run(input).unwrap(), Some(Value::Int(6)));
+    }
+
+    #[test]
+    fn match_guard_falls_through() {
+        assert_eq!(
+            run(&format!("{}match Circle(5) {{ Circle(r) if r > 10 => 'big', Circle(r) => 'small', _ => 'other' }}", SHAPES)).unwrap(),
+            Some(Value::Str("small".to_string())));
+    }
+
+    #[test]
+    fn match_bindings_are_scoped_to_arm() {
+        let input = format!("{}let r = 1\nmatch Circle(5) {{ Circle(r) => r }}\nr", SHAPES);
+        assert_eq!(run(&input).unwrap(), Some(Value::Int(1)));
+    }
+
+    #[test]
+    fn match_no_arm_matches() {
+        let err = run(&format!("{}match Empty {{ Circle(r) => r }}", SHAPES)).unwrap_err();
+        assert_eq!(err.msg, "no match arm matches the value Empty");
+    }
+
+    #[test]
+    fn call_function() {
+        assert_eq!(
+            run("fn add(a, b) { a + b }\nadd(1, 2)").unwrap(),
+            Some(Value::Int(3)));
+    }
+
+    #[test]
+    fn recursive_function() {
+        let input = "fn fact(n: Int) -> Int {\n\
+                       match n { 0 => 1, _ => n * fact(n - 1) }\n\
+                     }\n\
+                     fact(5)";
+        assert_eq!(run(input).unwrap(), Some(Value::Int(120)));
+    }
+
+    #[test]
+    fn closure_captures_defining_env() {
+        let input = "let x = 1\nfn f() { x }\nlet x = 2\nf()";
+        assert_eq!(run(input).unwrap(), Some(Value::Int(1)));
+    }
+
+    #[test]
+    fn function_arity_mismatch() {
+        assert!(run("fn f(a) { a }\nf(1, 2)").is_err());
+    }
+
+    #[test]
+    fn block_scopes_its_declarations() {
+        assert_eq!(
+            run("let x = 1\nlet y = { let x = 10\n x + 1 }\nx + y").unwrap(),
+            Some(Value::Int(12)));
+    }
+
+    #[test]
+    fn lambda() {
+        assert_eq!(
+            run("let add = fn(a, b) a + b\nadd(1, 2)").unwrap(),
+            Some(Value::Int(3)));
+    }
+
+    #[test]
+    fn tuples() {
+        assert_eq!(
+            run("match (1, 'a') { (n, s) => n }").unwrap(),
+            Some(Value::Int(1)));
+    }
+
+    #[test]
+    fn list_spread() {
+        assert_eq!(
+            run("let xs = [2, 3]\n[1, ..xs]").unwrap(),
+            Some(Value::List(vec![Value::Int(1), Value::Int(2), Value::Int(3)])));
+    }
+
+    #[test]
+    fn list_patterns() {
+        let input = "fn map(f, xs) { match xs { [] => [], [x, ..rest] => [f(x), ..map(f, rest)] } }\n\
+                     map(fn(x) x * 2, [1, 2, 3])";
+        assert_eq!(
+            run(input).unwrap(),
+            Some(Value::List(vec![Value::Int(2), Value::Int(4), Value::Int(6)])));
+    }
+
+    #[test]
+    fn fixed_length_list_pattern() {
+        assert_eq!(
+            run("match [1, 2] { [a] => a, [a, b] => b, _ => 0 }").unwrap(),
+            Some(Value::Int(2)));
+    }
+
+    #[test]
+    fn generic_declarations() {
+        let input = "enum Option<T> { Some(T), None }\n\
+                     fn unwrap_or<T>(o: Option<T>, d: T) -> T { match o { Some(v) => v, None => d } }\n\
+                     (unwrap_or(Some(1), 2), unwrap_or(None, 'none'))";
+        assert_eq!(
+            run(input).unwrap(),
+            Some(Value::Tuple(vec![Value::Int(1), Value::Str("none".to_string())])));
+    }
+
+    #[test]
+    fn requires_blames_the_caller() {
+        let input = "fn f(x) requires x > 0 { x }\nf(0)";
+        assert_eq!(
+            run(input).unwrap_err().to_string(),
+            "MSG: contract violation: `x > 0` does not hold, blame the call `f(0)`");
+    }
+
+    #[test]
+    fn ensures_blames_the_function() {
+        let input = "fn double(x) ensures result < 100 { x * 2 }\n(double(10), double(60))";
+        assert_eq!(
+            run(input).unwrap_err().to_string(),
+            "MSG: contract violation: `result < 100` does not hold, blame `double`");
+        assert_eq!(run("fn double(x) ensures result < 100 { x * 2 }\ndouble(10)").unwrap(), Some(Value::Int(20)));
+    }
+
+    #[test]
+    fn flat_contracts() {
+        let input = "fn pos(x) { x > 0 }\nfn f(x) requires x: pos ensures result: fn(r) r != 3 { x - 1 }\n";
+        assert_eq!(run(&format!("{}f(2)", input)).unwrap(), Some(Value::Int(1)));
+        assert_eq!(
+            run(&format!("{}f(-1)", input)).unwrap_err().to_string(),
+            "MSG: contract violation: -1 does not satisfy `pos`, blame the call `f(-1)`");
+        assert_eq!(
+            run(&format!("{}f(4)", input)).unwrap_err().to_string(),
+            "MSG: contract violation: 3 does not satisfy `fn(r) r != 3`, blame `f`");
+    }
+
+    #[test]
+    fn higher_order_contracts() {
+        let input = "fn pos(x) { x > 0 }\nfn apply(g, x) requires g: pos -> pos { g(x) }\n";
+        assert_eq!(run(&format!("{}apply(fn(n) n + 1, 1)", input)).unwrap(), Some(Value::Int(2)));
+        // the function passed in breaks its promise
+        assert_eq!(
+            run(&format!("{}apply(fn(n) n - 5, 1)", input)).unwrap_err().to_string(),
+            "MSG: contract violation: -4 does not satisfy `pos`, blame the call `apply(fn(n) n - 5, 1)`");
+        // apply calls it with an argument the contract rules out
+        assert_eq!(
+            run(&format!("{}apply(fn(n) n, 0)", input)).unwrap_err().to_string(),
+            "MSG: contract violation: 0 does not satisfy `pos`, blame `apply`");
+        assert_eq!(
+            run(&format!("{}apply(1, 1)", input)).unwrap_err().to_string(),
+            "MSG: contract violation: 1 does not satisfy `(pos) -> pos`, blame the call `apply(1, 1)`");
+    }
+
+    #[test]
+    fn contracts_must_be_predicates() {
+        assert_eq!(
+            run("fn f(x) requires x { x }\nf(1)").unwrap_err().to_string(),
+            "MSG: contract `x` must produce a boolean, got 1");
+    }
+
+    #[test]
+    fn int_overflow_promotes_to_bigints() {
+        assert_eq!(run("9223372036854775807 + 1").unwrap().unwrap().to_string(), "9223372036854775808");
+        assert_eq!(run("let min = -9223372036854775807 - 1\nmin // -1").unwrap().unwrap().to_string(), "9223372036854775808");
+        assert_eq!(run("-9223372036854775808").unwrap(), Some(Value::Int(i64::MIN)));
+        let input = "fn fact(n) { match n { 0 => 1, _ => n * fact(n - 1) } }\nfact(25)";
+        assert_eq!(run(input).unwrap().unwrap().to_string(), "15511210043330985984000000");
+    }
+
+    #[test]
+    fn int_division_is_exact() {
+        assert_eq!(run("1 / 3").unwrap().unwrap().to_string(), "1/3");
+        assert_eq!(run("1 / 3 + 2 / 3").unwrap(), Some(Value::Int(1)));
+        assert_eq!(run("1 / 3 < 1 / 2").unwrap(), Some(Value::Bool(true)));
+        assert_eq!(run("match 4 / 2 { 2 => true, _ => false }").unwrap(), Some(Value::Bool(true)));
+        assert_eq!(run("1 / 0").unwrap_err().to_string(), "MSG: division by zero");
+    }
+
+    #[test]
+    fn int_division_rounds_down() {
+        assert_eq!(run("7 // 2").unwrap(), Some(Value::Int(3)));
+        assert_eq!(run("-7 // 2").unwrap(), Some(Value::Int(-4)));
+        assert_eq!(run("-7 % 2").unwrap(), Some(Value::Int(1)));
+        assert_eq!(run("7 % -2").unwrap(), Some(Value::Int(-1)));
+        assert_eq!(run("1 % 0").unwrap_err().to_string(), "MSG: division by zero");
+    }
+
+    #[test]
+    fn ints_and_floats_dont_mix() {
+        assert_eq!(run("1.5 * 2.0").unwrap(), Some(Value::Float(3.0)));
+        assert_eq!(run("7.0 / 2.0").unwrap(), Some(Value::Float(3.5)));
+        assert_eq!(run("1.0 / 0.0").unwrap_err().to_string(), "MSG: division by zero");
+        assert_eq!(
+            run("1 + 1.5").unwrap_err().to_string(),
+            "MSG: can't apply + to 1 and 1.5, convert one with `int` or `float`");
+        assert_eq!(run("float(1) + 1.5").unwrap(), Some(Value::Float(2.5)));
+        assert_eq!(run("int(-2.7)").unwrap(), Some(Value::Int(-2)));
+        assert_eq!(run("float(1 / 4)").unwrap(), Some(Value::Float(0.25)));
+        assert_eq!(run("int(-7 / 2)").unwrap(), Some(Value::Int(-3)));
+        assert_eq!(run("int(1000000000000000000000000000000.0)").unwrap().unwrap().to_string(), "1000000000000000019884624838656");
+    }
+
+    #[test]
+    fn declarations_shadow_builtins() {
+        assert_eq!(run("let float = 3\nfloat").unwrap(), Some(Value::Int(3)));
+    }
+
+    #[test]
+    fn exponentiation() {
+        assert_eq!(run("2 ** 10").unwrap(), Some(Value::Int(1024)));
+        assert_eq!(run("2 ** 3 ** 2").unwrap(), Some(Value::Int(512)));
+        assert_eq!(run("-2 ** 2").unwrap(), Some(Value::Int(-4)));
+        assert_eq!(run("2 ** 100").unwrap().unwrap().to_string(), "1267650600228229401496703205376");
+        assert_eq!(run("(1 / 2) ** -2").unwrap(), Some(Value::Int(4)));
+        assert_eq!(run("2.0 ** 0.5").unwrap(), Some(Value::Float(2f64.powf(0.5))));
+        assert_eq!(run("2 ** -1").unwrap_err().to_string(), "MSG: negative exponent of an Int in 2 ** -1");
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        assert_eq!(run("12 & 10").unwrap(), Some(Value::Int(8)));
+        assert_eq!(run("12 | 10").unwrap(), Some(Value::Int(14)));
+        assert_eq!(run("12 ^ 10").unwrap(), Some(Value::Int(6)));
+        assert_eq!(run("~5").unwrap(), Some(Value::Int(-6)));
+        assert_eq!(run("1 << 4").unwrap(), Some(Value::Int(16)));
+        assert_eq!(run("-16 >> 2").unwrap(), Some(Value::Int(-4)));
+        assert_eq!(run("-1 >> 100").unwrap(), Some(Value::Int(-1)));
+        assert_eq!(run("1 << 64").unwrap().unwrap().to_string(), "18446744073709551616");
+        assert_eq!(run("(1 << 64) >> 63").unwrap(), Some(Value::Int(2)));
+        assert_eq!(run("1 << -1").unwrap_err().to_string(), "MSG: invalid shift amount in 1 << -1");
+        assert_eq!(run("1.0 & 2.0").unwrap_err().to_string(), "MSG: & needs Ints but was given 1.0 and 2.0");
+    }
+
+    #[test]
+    fn ieee_special_values() {
+        assert_eq!(run("float(10 ** 400)").unwrap(), Some(Value::Float(f64::INFINITY)));
+        assert_eq!(run("float(-(10 ** 400))").unwrap(), Some(Value::Float(f64::NEG_INFINITY)));
+        assert_eq!(run("int(float(10 ** 400))").unwrap_err().to_string(), "MSG: inf doesn't fit in an Int");
+        assert_eq!(run("approx_eq(1.0, 2.0, -1)").unwrap_err().to_string(), "MSG: approx_eq needs a margin of ulps or an epsilon, got -1");
+        assert_eq!(run("1.0 / 0.000000000000000000001").unwrap(), Some(Value::Float(1.0 / 0.000000000000000000001)));
+    }
+
+    #[test]
+    fn declarations_have_no_value() {
+        let decls = Parser::new("1 + 1\nlet x = 2").parse().declarations;
+        assert_eq!(exec_decls(&decls, &mut Env::default()).unwrap(), Value::Unit);
+        assert_eq!(run("fn f() { () }\nf()").unwrap(), None);
+        assert_eq!(run("fn f(x: ()) -> () { x }\nf(())").unwrap(), None);
+        assert_eq!(run("fn f(x: ()) { x }\nf(1)").unwrap_err().to_string(), "MSG: cast failed: 1 is not (), blame the call `f(1)`");
+    }
+
+    #[test]
+    fn catch_thrown_values() {
+        assert_eq!(run("try { throw 1 } catch e { e + 1 }").unwrap(), Some(Value::Int(2)));
+        assert_eq!(run("try { 1 } catch e { 2 }").unwrap(), Some(Value::Int(1)));
+        assert_eq!(run("fn f(x) { throw x }\ntry { f(3) * 2 } catch e { e }").unwrap(), Some(Value::Int(3)));
+        // a handler can rethrow
+        let err = run("try { throw 1 } catch e { throw e + 1 }").unwrap_err();
+        assert_eq!(err.to_string(), "MSG: uncaught exception 2");
+    }
+
+    #[test]
+    fn catch_runtime_errors_as_values() {
+        let caught = |input: &str| run(&format!("try {{ {} }} catch e {{ e }}", input)).unwrap().unwrap().to_string();
+        assert_eq!(caught("1 // 0"), "Error(arith, division by zero, None)");
+        assert_eq!(caught("1 + 'a'"), "Error(type, can't apply + to 1 and a, None)");
+        assert_eq!(caught("-true"), "Error(type, can't apply - to true, None)");
+        assert_eq!(caught("nope"), "Error(name, unbound identifier nope, None)");
+        assert_eq!(caught("match 1 { 2 => 2 }"), "Error(match, no match arm matches the value 1, None)");
+        // NOTE failures inside a call are placed at the call
+        assert_eq!(caught("(fn(x) x // 0)(1)"), "Error(arith, division by zero, Some((6, 23)))");
+    }
+
+    #[test]
+    fn finally_always_runs() {
+        // NOTE there's no mutation yet, so a failing finally block shows it ran
+        let err = run("try { 1 } finally { 1 // 0 }").unwrap_err();
+        assert_eq!(err.to_string(), "MSG: division by zero");
+        let err = run("try { throw 1 } finally { nope }").unwrap_err();
+        assert_eq!(err.to_string(), "MSG: unbound identifier nope");
+        assert_eq!(run("try { throw 1 } catch e { e } finally { 2 }").unwrap(), Some(Value::Int(1)));
+        assert_eq!(run("try { throw 1 } finally { 2 }").unwrap_err().to_string(), "MSG: uncaught exception 1");
+    }
+
+    #[test]
+    fn question_mark_passes_through_catch() {
+        let input = "enum Option<T> { Some(T), None }\nfn f(x) { try { Some(x?) } catch e { Some(0) } }\nf(None)";
+        assert_eq!(run(input).unwrap(), Some(Value::Variant(prelude::ctor("None"), vec![])));
+    }
+
+    // NOTE on a thread with a small stack, so a call that isn't eliminated overflows it
+    fn run_small_stack(input: &'static str) -> Result<String, String> {
+        std::thread::Builder::new()
+            .stack_size(1 << 20)
+            .spawn(move || run(input).map(|val| val.unwrap().to_string()).map_err(|e| e.to_string()))
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_stack() {
+        let input = "fn count(n, acc) { match n { 0 => acc, _ => count(n - 1, acc + 1) } }\ncount(1000000, 0)";
+        assert_eq!(run_small_stack(input), Ok("1000000".to_string()));
+        // through blocks and groupings too
+        let input = "fn count(n) { match n { 0 => 'done', _ => { let m = n - 1\n (count(m)) } } }\ncount(100000)";
+        assert_eq!(run_small_stack(input), Ok("done".to_string()));
+    }
+
+    #[test]
+    fn mutual_tail_calls_run_in_constant_stack() {
+        let input = "fn even(n) { match n { 0 => true, _ => odd(n - 1) } }\n\
+                     fn odd(n) { match n { 0 => false, _ => even(n - 1) } }\n\
+                     (even(1000000), even(7))";
+        assert_eq!(run_small_stack(input), Ok("(true, false)".to_string()));
+    }
+
+    #[test]
+    fn failures_in_tail_calls_are_placed_at_the_call() {
+        let input = "fn f(n) { match n { 0 => 1 // n, _ => g(n - 1) } }\nfn g(n) { f(n) }\ng(3)";
+        let err = run(input).unwrap_err();
+        assert_eq!(err.to_string(), "MSG: division by zero");
+        assert_eq!(err.span, Some(61..65));
+    }
+
+    #[test]
+    fn block_functions_call_each_other() {
+        let input = "{ fn ping(n) { match n { 0 => 'ping', _ => pong(n - 1) } }\n fn pong(n) { match n { 0 => 'pong', _ => ping(n - 1) } }\n ping(3) }";
+        assert_eq!(run(input).unwrap(), Some(Value::Str("pong".to_string())));
+    }
+
+    #[test]
+    fn deep_recursion_is_a_stack_error() {
+        set_max_depth(100);
+        let input = "fn f(n) { match n { 0 => 0, _ => 1 + f(n - 1) } }\n";
+        assert_eq!(run(&format!("{}f(3)", input)).unwrap(), Some(Value::Int(3)));
+        let err = run(&format!("{}f(100000)", input)).unwrap_err();
+        assert_eq!((err.kind, &err.msg[..]), (ErrorKind::Stack, "stack overflow"));
+        // and the depth is back to zero after the failure
+        assert_eq!(run(&format!("{}f(3)", input)).unwrap(), Some(Value::Int(3)));
+        set_max_depth(MAX_DEPTH);
+    }
 }
@@ -0,0 +1,153 @@
+// builtin - functions every program can use without declaring them
+//
+// NOTE builtins are looked up after the environment, so a declaration with
+// the same name shadows them
+
+use std::fmt;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive};
+use super::{ErrorKind, RuntimeError, num::{self, FloatEq}, value::Value};
+use crate::prelude;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Builtin {
+    // any number to the nearest Float
+    Float,
+    // any number to an Int, rounding towards zero
+    Int,
+    // `approx_eq(a, b, margin)`, an Int margin is in ulps and a Float one is an epsilon
+    ApproxEq,
+    IsNan,
+    IsFinite,
+    // `parse_int('12')` is `Ok(12)`, and an `Err` with a message for anything but an Int
+    ParseInt,
+    ParseFloat,
+    // the first item of a list, or `None` if it's empty
+    Head,
+}
+
+impl Builtin {
+    pub(crate) const ALL: [Self; 8] = [
+        Self::Float, Self::Int, Self::ApproxEq, Self::IsNan, Self::IsFinite, Self::ParseInt, Self::ParseFloat, Self::Head,
+    ];
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "float" => Some(Self::Float),
+            "int" => Some(Self::Int),
+            "approx_eq" => Some(Self::ApproxEq),
+            "is_nan" => Some(Self::IsNan),
+            "is_finite" => Some(Self::IsFinite),
+            "parse_int" => Some(Self::ParseInt),
+            "parse_float" => Some(Self::ParseFloat),
+            "head" => Some(Self::Head),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Float => "float",
+            Self::Int => "int",
+            Self::ApproxEq => "approx_eq",
+            Self::IsNan => "is_nan",
+            Self::IsFinite => "is_finite",
+            Self::ParseInt => "parse_int",
+            Self::ParseFloat => "parse_float",
+            Self::Head => "head",
+        }
+    }
+
+    pub(crate) fn arity(&self) -> usize {
+        match self {
+            Self::Float | Self::Int | Self::IsNan | Self::IsFinite => 1,
+            Self::ParseInt | Self::ParseFloat | Self::Head => 1,
+            Self::ApproxEq => 3,
+        }
+    }
+
+    pub(super) fn apply(&self, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        if args.len() != self.arity() {
+            return Err(RuntimeError {
+                msg: format!("{} expects {} argument(s) but was given {}", self.name(), self.arity(), args.len()),
+                kind: ErrorKind::Arity,
+                span: None,
+                unwind: None,
+            });
+        }
+        match (self, &args[..]) {
+            (Self::Float, [Value::Float(n)]) => Ok(Value::Float(*n)),
+            (Self::Float, [v @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_))]) => {
+                // NOTE the nearest float, or an infinity for huge numbers
+                let r = num::to_ratio(v);
+                let inf = if r.is_negative() { f64::NEG_INFINITY } else { f64::INFINITY };
+                Ok(Value::Float(r.to_f64().unwrap_or(inf)))
+            }
+            (Self::Int, [Value::Float(n)]) => BigInt::from_f64(n.trunc()).map(num::big).ok_or_else(|| RuntimeError {
+                msg: format!("{:?} doesn't fit in an Int", n),
+                kind: ErrorKind::Arith,
+                span: None,
+                unwind: None,
+            }),
+            (Self::Int, [v @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_))]) => {
+                Ok(num::big(num::to_ratio(v).trunc().to_integer()))
+            }
+            (Self::ApproxEq, [Value::Float(n1), Value::Float(n2), margin]) => {
+                let eq = match margin {
+                    Value::Int(ulps) if *ulps >= 0 => FloatEq::Ulps(*ulps),
+                    Value::Float(epsilon) if *epsilon >= 0.0 => FloatEq::Epsilon(*epsilon),
+                    v => return Err(RuntimeError {
+                        msg: format!("approx_eq needs a margin of ulps or an epsilon, got {}", v),
+                        kind: ErrorKind::Type,
+                        span: None,
+                        unwind: None,
+                    }),
+                };
+                Ok(Value::Bool(num::float_eq(*n1, *n2, eq)))
+            }
+            (Self::IsNan, [Value::Float(n)]) => Ok(Value::Bool(n.is_nan())),
+            (Self::IsFinite, [Value::Float(n)]) => Ok(Value::Bool(n.is_finite())),
+            (Self::ParseInt, [Value::Str(s)]) => Ok(match s.trim().parse::<BigInt>() {
+                Ok(n) => variant("Ok", num::big(n)),
+                Err(_) => variant("Err", Value::Str(format!("'{}' isn't an Int", s))),
+            }),
+            (Self::ParseFloat, [Value::Str(s)]) => Ok(match s.trim().parse::<f64>() {
+                Ok(n) => variant("Ok", Value::Float(n)),
+                Err(_) => variant("Err", Value::Str(format!("'{}' isn't a Float", s))),
+            }),
+            (Self::Head, [Value::List(items)]) => Ok(match items.first() {
+                Some(item) => variant("Some", item.clone()),
+                None => Value::Variant(prelude::ctor("None"), vec![]),
+            }),
+            (Self::ParseInt, [v]) | (Self::ParseFloat, [v]) | (Self::Head, [v]) => Err(RuntimeError {
+                msg: format!("{} can't be given {}", self.name(), v),
+                kind: ErrorKind::Type,
+                span: None,
+                unwind: None,
+            }),
+            (Self::Float, [v]) | (Self::Int, [v]) => Err(RuntimeError {
+                msg: format!("{} can't convert {}", self.name(), v),
+                kind: ErrorKind::Type,
+                span: None,
+                unwind: None,
+            }),
+            (_, args) => Err(RuntimeError {
+                msg: format!("{} needs Floats but was given {}", self.name(), args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")),
+                kind: ErrorKind::Type,
+                span: None,
+                unwind: None,
+            }),
+        }
+    }
+}
+
+// a prelude variant holding one value, e.g. `Some(1)`
+fn variant(name: &str, value: Value) -> Value {
+    Value::Variant(prelude::ctor(name), vec![value])
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -0,0 +1,172 @@
+// cast - checks values against type annotations at runtime
+//
+// Annotated functions cast their arguments and results, so a dynamically
+// typed value that doesn't fit is caught where it enters typed code. A
+// function can't be checked up front, instead it's wrapped in a guard that
+// casts what flows in and out of each call. Contracts reuse the same guards.
+
+use std::rc::Rc;
+use super::{ErrorKind, RuntimeError, value::Value, contract::{self, Ctc}};
+use crate::parser::{Span, expr::Expr, ty::Type};
+
+// Someone a failed cast can be blamed on, a call, a function or an annotated binding
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Party {
+    label: String,
+    span: Span,
+}
+
+impl Party {
+    pub(crate) fn call(site: &Expr) -> Self {
+        match site {
+            Expr::Call(_, _, span) => Self { label: format!("the call `{}`", site), span: span.clone() },
+            _ => unreachable!(),
+        }
+    }
+
+    pub(crate) fn function(name: &str, span: &Span) -> Self {
+        let label = match name {
+            "" => "the anonymous function".to_string(),
+            name => format!("`{}`", name),
+        };
+        Self { label, span: span.clone() }
+    }
+
+    pub(crate) fn binding(name: &str, span: &Span) -> Self {
+        Self { label: format!("the binding `{}`", name), span: span.clone() }
+    }
+
+    pub(crate) fn blame(&self, msg: String) -> RuntimeError {
+        RuntimeError { msg: format!("{}, blame {}", msg, self.label), kind: ErrorKind::Contract, span: Some(self.span.clone()), unwind: None }
+    }
+}
+
+// NOTE the positive party provides the value and is blamed when it doesn't fit
+// the type, the negative party is blamed when it misuses a guarded function
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Blame {
+    pub(crate) positive: Party,
+    pub(crate) negative: Party,
+}
+
+impl Blame {
+    // arguments flow into a function, the opposite way to its result
+    pub(crate) fn flip(&self) -> Self {
+        Self { positive: self.negative.clone(), negative: self.positive.clone() }
+    }
+}
+
+// What a guard checks the values flowing through it against
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Spec {
+    Type(Type),
+    Contract(Ctc),
+}
+
+// A function value that has been cast to a function type, or given an arrow contract
+#[derive(Debug, PartialEq)]
+pub(crate) struct Guard {
+    pub(crate) value: Value,
+    pub(crate) params: Vec<Spec>,
+    pub(crate) ret: Spec,
+    // the type parameters in scope where the cast happened
+    pub(crate) generics: Vec<String>,
+    pub(crate) blame: Blame,
+}
+
+impl Guard {
+    pub(crate) fn check_arity(&self, args: usize) -> Result<(), RuntimeError> {
+        if self.params.len() == args {
+            return Ok(());
+        }
+        Err(self.blame.negative.blame(format!("{} argument(s) given to a guarded function expecting {}", args, self.params.len())))
+    }
+
+    // NOTE `site` is the call of the guarded function
+    pub(crate) fn check(&self, val: Value, spec: &Spec, blame: &Blame, site: &Expr) -> Result<Value, RuntimeError> {
+        match spec {
+            Spec::Type(ty) => cast(val, ty, &self.generics, blame),
+            Spec::Contract(ctc) => contract::monitor(val, ctc, blame, site),
+        }
+    }
+}
+
+// NOTE type parameters and `Dyn` accept anything, and an enum type accepts
+// its own variants with their typed fields cast like the items of a tuple
+pub(crate) fn cast(val: Value, ty: &Type, generics: &[String], blame: &Blame) -> Result<Value, RuntimeError> {
+    let fits = match (ty, &val) {
+        (Type::Named(name, _), _) if name == "Dyn" || generics.contains(name) => true,
+        (Type::Named(name, args), Value::List(items)) if name == "List" && args.len() == 1 => {
+            let items = items.iter()
+                .map(|item| cast(item.clone(), &args[0], generics, blame))
+                .collect::<Result<_, _>>()?;
+            return Ok(Value::List(items));
+        }
+        // NOTE ints are rationals too, `1/2 + 1/2` is the Int 1
+        (Type::Named(name, _), Value::Int(_) | Value::BigInt(_)) => name == "Int" || name == "Rat",
+        (Type::Named(name, _), Value::Rational(_)) => name == "Rat",
+        (Type::Named(name, _), Value::Float(_)) => name == "Float",
+        (Type::Named(name, _), Value::Str(_)) => name == "Str",
+        (Type::Named(name, _), Value::Bool(_)) => name == "Bool",
+        (Type::Named(name, args), Value::Variant(ctor, fields)) if *name == ctor.ty => {
+            // NOTE without its type arguments, e.g. `Option`, the enum's type parameters accept anything
+            let (params, generics) = match args.len() == ctor.generics.len() {
+                true => (&ctor.generics[..], generics.to_vec()),
+                false => (&[][..], [generics, &ctor.generics].concat()),
+            };
+            let fields = fields.iter()
+                .zip(&ctor.fields)
+                .map(|(field, ty)| match ty {
+                    Some(ty) => cast(field.clone(), &substitute(ty, params, args), &generics, blame),
+                    None => Ok(field.clone()),
+                })
+                .collect::<Result<_, _>>()?;
+            return Ok(Value::Variant(ctor.clone(), fields));
+        }
+        (Type::Tuple(tys), Value::Unit) => tys.is_empty(),
+        (Type::Tuple(tys), Value::Tuple(items)) if tys.len() == items.len() => {
+            let items = items.iter()
+                .zip(tys)
+                .map(|(item, ty)| cast(item.clone(), ty, generics, blame))
+                .collect::<Result<_, _>>()?;
+            return Ok(Value::Tuple(items));
+        }
+        (Type::Fn(params, ret), _) if arity(&val) == Some(params.len()) => {
+            return Ok(Value::Guarded(Rc::new(Guard {
+                value: val,
+                params: params.iter().cloned().map(Spec::Type).collect(),
+                ret: Spec::Type((**ret).clone()),
+                generics: generics.to_vec(),
+                blame: blame.clone(),
+            })));
+        }
+        _ => false,
+    };
+    match fits {
+        true => Ok(val),
+        false => Err(blame.positive.blame(format!("cast failed: {} is not {}", val, ty))),
+    }
+}
+
+// `ty` with each of `params` replaced by its argument
+fn substitute(ty: &Type, params: &[String], args: &[Type]) -> Type {
+    match ty {
+        Type::Named(name, tys) => match params.iter().position(|param| param == name) {
+            Some(i) => args[i].clone(),
+            None => Type::Named(name.clone(), tys.iter().map(|ty| substitute(ty, params, args)).collect()),
+        },
+        Type::Fn(tys, ret) => Type::Fn(tys.iter().map(|ty| substitute(ty, params, args)).collect(), Box::new(substitute(ret, params, args))),
+        Type::Tuple(tys) => Type::Tuple(tys.iter().map(|ty| substitute(ty, params, args)).collect()),
+    }
+}
+
+pub(super) fn arity(val: &Value) -> Option<usize> {
+    match val {
+        Value::Closure(f, _) => Some(f.params.len()),
+        Value::Compiled(f) => Some(f.arity()),
+        Value::Constructor(ctor) => Some(ctor.fields.len()),
+        Value::Guarded(guard) => Some(guard.params.len()),
+        Value::Builtin(builtin) => Some(builtin.arity()),
+        _ => None,
+    }
+}
@@ -0,0 +1,92 @@
+// contract - checks `requires` and `ensures` clauses at runtime
+//
+// A flat contract is a predicate that's called on the value. An arrow
+// contract can't be checked up front, so the function is wrapped in a guard
+// that checks the arguments and result of each call, the same as a cast.
+
+use std::fmt;
+use std::rc::Rc;
+use super::{call, interp_expr, ErrorKind, RuntimeError, value::Value, env::Env};
+use super::cast::{arity, Blame, Guard, Spec};
+use crate::parser::{contract::{Clause, Contract}, expr::Expr};
+
+// A contract with its predicates evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Ctc {
+    // the predicate and how it was written
+    Flat(Value, String),
+    Fn(Vec<Ctc>, Box<Ctc>),
+}
+
+impl fmt::Display for Ctc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Flat(_, label) => write!(f, "{}", label),
+            Self::Fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "({}) -> {}", params.join(", "), ret)
+            }
+        }
+    }
+}
+
+fn eval(contract: &Contract, env: &Env) -> Result<Ctc, RuntimeError> {
+    match contract {
+        Contract::Flat(pred) => Ok(Ctc::Flat(interp_expr(pred, env)?, pred.to_string())),
+        Contract::Fn(params, ret) => {
+            let params = params.iter().map(|p| eval(p, env)).collect::<Result<_, _>>()?;
+            Ok(Ctc::Fn(params, Box::new(eval(ret, env)?)))
+        }
+    }
+}
+
+// NOTE a value given an arrow contract is rebound to its guarded version, so
+// the rest of the function only sees the guarded one
+pub(super) fn check_clauses(clauses: &[Clause], env: Env, blame: &Blame, site: &Expr) -> Result<Env, RuntimeError> {
+    clauses.iter().try_fold(env, |env, clause| match clause {
+        Clause::Holds(cond) => match interp_expr(cond, &env)? {
+            Value::Bool(true) => Ok(env),
+            Value::Bool(false) => Err(blame.positive.blame(format!("contract violation: `{}` does not hold", cond))),
+            v => Err(RuntimeError {
+                msg: format!("contract `{}` must produce a boolean, got {}", cond, v),
+                kind: ErrorKind::Type,
+                span: None,
+                unwind: None,
+            }),
+        },
+        Clause::Satisfies(name, contract) => {
+            let val = env.lookup(name).ok_or_else(|| RuntimeError {
+                msg: format!("unbound identifier {}", name),
+                kind: ErrorKind::Name,
+                span: None,
+                unwind: None,
+            })?;
+            let ctc = eval(contract, &env)?;
+            let val = monitor(val, &ctc, blame, site)?;
+            Ok(env.extend(name.clone(), val))
+        }
+    })
+}
+
+pub(crate) fn monitor(val: Value, ctc: &Ctc, blame: &Blame, site: &Expr) -> Result<Value, RuntimeError> {
+    match ctc {
+        Ctc::Flat(pred, label) => match call(pred.clone(), vec![val.clone()], site)? {
+            Value::Bool(true) => Ok(val),
+            Value::Bool(false) => Err(blame.positive.blame(format!("contract violation: {} does not satisfy `{}`", val, label))),
+            v => Err(RuntimeError {
+                msg: format!("contract `{}` must produce a boolean, got {}", label, v),
+                kind: ErrorKind::Type,
+                span: None,
+                unwind: None,
+            }),
+        },
+        Ctc::Fn(params, ret) if arity(&val) == Some(params.len()) => Ok(Value::Guarded(Rc::new(Guard {
+            value: val,
+            params: params.iter().cloned().map(Spec::Contract).collect(),
+            ret: Spec::Contract((**ret).clone()),
+            generics: vec![],
+            blame: blame.clone(),
+        }))),
+        Ctc::Fn(_, _) => Err(blame.positive.blame(format!("contract violation: {} does not satisfy `{}`", val, ctc))),
+    }
+}
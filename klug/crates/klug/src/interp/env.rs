@@ -0,0 +1,131 @@
+use std::rc::Rc;
+use super::value::Value;
+use crate::parser::decl::Function;
+
+// NOTE environments are persistent, extending one never changes the original
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Env(Option<Rc<Binding>>);
+
+// NOTE environments are only equal if they are the same environment
+impl PartialEq for Env {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(b1), Some(b2)) => Rc::ptr_eq(b1, b2),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Binding {
+    Value { name: String, value: Value, rest: Env },
+    // functions declared together, the closures looked up here are made in
+    // the environment that starts with them so they can call each other
+    Functions { fns: Vec<Rc<Function>>, rest: Env },
+    // the type parameters of the function being run, annotations inside it
+    // can mention them
+    Generics { names: Vec<String>, rest: Env },
+}
+
+impl Env {
+    pub(crate) fn extend(&self, name: String, value: Value) -> Self {
+        Self(Some(Rc::new(Binding::Value { name, value, rest: self.clone() })))
+    }
+
+    pub(crate) fn extend_functions(&self, fns: Vec<Rc<Function>>) -> Self {
+        Self(Some(Rc::new(Binding::Functions { fns, rest: self.clone() })))
+    }
+
+    pub(crate) fn extend_generics(&self, names: Vec<String>) -> Self {
+        Self(Some(Rc::new(Binding::Generics { names, rest: self.clone() })))
+    }
+
+    // every type parameter in scope, including those of enclosing functions
+    pub(crate) fn generics(&self) -> Vec<String> {
+        let mut generics = Vec::new();
+        let mut env = self;
+        while let Some(binding) = &env.0 {
+            if let Binding::Generics { names, .. } = &**binding {
+                generics.extend(names.iter().cloned());
+            }
+            env = binding.rest();
+        }
+        generics
+    }
+
+    pub(crate) fn lookup(&self, name: &str) -> Option<Value> {
+        let mut env = self;
+        while let Some(binding) = &env.0 {
+            match &**binding {
+                Binding::Value { name: n, value, .. } if n == name => return Some(value.clone()),
+                Binding::Functions { fns, .. } => {
+                    if let Some(f) = fns.iter().find(|f| f.name == name) {
+                        return Some(Value::Closure(f.clone(), env.clone()));
+                    }
+                }
+                _ => (),
+            }
+            env = binding.rest();
+        }
+        None
+    }
+}
+
+impl Binding {
+    fn rest(&self) -> &Env {
+        match self {
+            Self::Value { rest, .. } | Self::Functions { rest, .. } | Self::Generics { rest, .. } => rest,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Parser, decl::Decl};
+
+    #[test]
+    fn lookup_shadowed() {
+        let env = Env::default()
+            .extend("x".to_string(), Value::Int(1))
+            .extend("x".to_string(), Value::Int(2));
+        assert_eq!(env.lookup("x"), Some(Value::Int(2)));
+    }
+
+    #[test]
+    fn extend_is_persistent() {
+        let outer = Env::default().extend("x".to_string(), Value::Int(1));
+        let _inner = outer.extend("y".to_string(), Value::Int(2));
+        assert_eq!(outer.lookup("y"), None);
+    }
+
+    #[test]
+    fn functions_see_each_other() {
+        let fns = Parser::new("fn f() { g() }\nfn g() { f() }").parse().declarations.into_iter()
+            .map(|decl| match decl {
+                Decl::Fn(f) => f,
+                decl => panic!("expected a function, got {:?}", decl),
+            })
+            .collect();
+        let env = Env::default().extend("x".to_string(), Value::Int(1)).extend_functions(fns);
+        match env.lookup("g") {
+            Some(Value::Closure(g, genv)) => {
+                assert_eq!(g.name, "g");
+                assert_eq!(genv, env);
+                assert!(matches!(genv.lookup("f"), Some(Value::Closure(f, _)) if f.name == "f"));
+            }
+            v => panic!("expected a closure, got {:?}", v),
+        }
+        assert_eq!(env.lookup("x"), Some(Value::Int(1)));
+    }
+
+    #[test]
+    fn generics_are_lexical() {
+        let outer = Env::default().extend_generics(vec!["T".to_string()]);
+        let inner = outer.extend("x".to_string(), Value::Int(1)).extend_generics(vec!["U".to_string()]);
+        assert_eq!(inner.generics(), vec!["U".to_string(), "T".to_string()]);
+        assert_eq!(outer.generics(), vec!["T".to_string()]);
+        assert_eq!(inner.lookup("x"), Some(Value::Int(1)));
+    }
+}
@@ -0,0 +1,307 @@
+// num - the numeric tower
+//
+// Exact numbers are Ints, which are promoted to BigInts when an operation
+// overflows, and Rationals, which dividing Ints produces. Results are always
+// normalized to the smallest representation, so a BigInt never fits in an
+// i64 and a Rational never has a denominator of 1. Floats are inexact and
+// never mixed with exact numbers, `float` and `int` convert between them.
+//
+// Floats follow IEEE 754: `==` is exact by default, so `0.1 + 0.2 != 0.3`,
+// NaN isn't equal to anything, itself included, and `-0.0 == 0.0`. An engine
+// can opt into tolerant `==` with `Numerics`, the `approx_eq` builtin always
+// compares with the margin it's given. Float literal patterns always match
+// exactly. Dividing a float by zero is an error unless the engine asks for
+// the IEEE result, an infinity or NaN.
+
+use std::cell::Cell;
+use float_cmp::approx_eq;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use super::{ErrorKind, RuntimeError, value::Value};
+use crate::parser::expr::op::InfixOp;
+
+// How `==` compares floats
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FloatEq {
+    #[default]
+    Exact,
+    // equal if at most this many representable floats apart
+    Ulps(i64),
+    // equal if at most this far apart
+    Epsilon(f64),
+}
+
+// What dividing a float by zero produces
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DivByZero {
+    #[default]
+    Error,
+    // the IEEE result, e.g. `1.0 / 0.0` is inf and `0.0 / 0.0` is NaN
+    Infinity,
+}
+
+// The numeric settings of an engine
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Numerics {
+    pub float_eq: FloatEq,
+    pub div_by_zero: DivByZero,
+}
+
+// NOTE the interpreter is a set of pure functions over expressions, so the
+// settings of the engine that's running live here rather than in every call
+thread_local! {
+    static NUMERICS: Cell<Numerics> = Cell::new(Numerics::default());
+}
+
+pub(crate) fn set_numerics(numerics: Numerics) {
+    NUMERICS.with(|n| n.set(numerics));
+}
+
+pub(crate) fn numerics() -> Numerics {
+    NUMERICS.with(|n| n.get())
+}
+
+// NOTE NaN is never equal and infinities are only equal to themselves,
+// however close they are in ulps to the largest finite floats
+pub(crate) fn float_eq(n1: f64, n2: f64, eq: FloatEq) -> bool {
+    if n1.is_nan() || n2.is_nan() {
+        return false;
+    }
+    if n1.is_infinite() || n2.is_infinite() {
+        return n1 == n2;
+    }
+    match eq {
+        FloatEq::Exact => n1 == n2,
+        FloatEq::Ulps(ulps) => approx_eq!(f64, n1, n2, ulps = ulps),
+        FloatEq::Epsilon(epsilon) => approx_eq!(f64, n1, n2, epsilon = epsilon),
+    }
+}
+
+pub(super) fn num_calc(v1: Value, v2: Value, op: &InfixOp) -> Result<Value, RuntimeError> {
+    match (v1, v2) {
+        (Value::Int(n1), Value::Int(n2)) => match int_calc(n1, n2, op) {
+            Some(val) => Ok(val),
+            None => exact_calc(BigRational::from(BigInt::from(n1)), BigRational::from(BigInt::from(n2)), op),
+        },
+        (Value::Float(n1), Value::Float(n2)) => float_calc(n1, n2, op),
+        (v1 @ Value::Float(_), v2) | (v1, v2 @ Value::Float(_)) => Err(RuntimeError {
+            msg: format!("can't apply {} to {} and {}, convert one with `int` or `float`", op.stringify(), v1, v2),
+            kind: ErrorKind::Type,
+            span: None,
+            unwind: None,
+        }),
+        (v1, v2) => exact_calc(to_ratio(&v1), to_ratio(&v2), op),
+    }
+}
+
+pub(super) fn neg(val: Value) -> Value {
+    match val {
+        Value::Int(n) => n.checked_neg().map_or_else(|| big(-BigInt::from(n)), Value::Int),
+        Value::BigInt(n) => big(-n),
+        Value::Rational(r) => ratio(-r),
+        Value::Float(n) => Value::Float(-n),
+        _ => unreachable!(),
+    }
+}
+
+pub(super) fn bit_not(val: Value) -> Result<Value, RuntimeError> {
+    match val {
+        Value::Int(n) => Ok(Value::Int(!n)),
+        Value::BigInt(n) => Ok(big(!n)),
+        v => Err(RuntimeError {
+            msg: format!("~ needs an Int but was given {}", v),
+            kind: ErrorKind::Type,
+            span: None,
+            unwind: None,
+        }),
+    }
+}
+
+pub(crate) fn big(n: BigInt) -> Value {
+    match n.to_i64() {
+        Some(n) => Value::Int(n),
+        None => Value::BigInt(n),
+    }
+}
+
+pub(super) fn ratio(r: BigRational) -> Value {
+    match r.is_integer() {
+        true => big(r.to_integer()),
+        false => Value::Rational(r),
+    }
+}
+
+// NOTE only called with exact numbers
+pub(super) fn to_ratio(val: &Value) -> BigRational {
+    match val {
+        Value::Int(n) => BigRational::from(BigInt::from(*n)),
+        Value::BigInt(n) => BigRational::from(n.clone()),
+        Value::Rational(r) => r.clone(),
+        _ => unreachable!(),
+    }
+}
+
+// the fast path for small ints, `None` if the result doesn't fit in an i64
+fn int_calc(n1: i64, n2: i64, op: &InfixOp) -> Option<Value> {
+    let num = match op {
+        InfixOp::Add => n1.checked_add(n2),
+        InfixOp::Sub => n1.checked_sub(n2),
+        InfixOp::Mul => n1.checked_mul(n2),
+        // NOTE checked_rem fails on the same divisions as `//` and `%`
+        InfixOp::IntDiv => n1.checked_rem(n2).map(|_| Integer::div_floor(&n1, &n2)),
+        InfixOp::Mod => n1.checked_rem(n2).map(|_| Integer::mod_floor(&n1, &n2)),
+        InfixOp::Pow => n2.to_u32().and_then(|n2| n1.checked_pow(n2)),
+        InfixOp::BitAnd => Some(n1 & n2),
+        InfixOp::BitOr => Some(n1 | n2),
+        InfixOp::BitXor => Some(n1 ^ n2),
+        // NOTE shifting right by 63 or more leaves only the sign
+        InfixOp::Shr if n2 >= 0 => Some(n1 >> n2.min(63)),
+        InfixOp::Shl if (0..64).contains(&n2) => Some(n1 << n2).filter(|n| n >> n2 == n1),
+        InfixOp::Div | InfixOp::Shl | InfixOp::Shr => None,
+        InfixOp::Lt => return Some(Value::Bool(n1 < n2)),
+        InfixOp::LtEq => return Some(Value::Bool(n1 <= n2)),
+        InfixOp::Gt => return Some(Value::Bool(n1 > n2)),
+        InfixOp::GtEq => return Some(Value::Bool(n1 >= n2)),
+        InfixOp::Eq | InfixOp::NotEq => unreachable!(),
+    };
+    num.map(Value::Int)
+}
+
+// NOTE `//` rounds towards negative infinity, so `%` has the sign of the divisor,
+// and the bitwise operators treat ints as infinitely sign extended two's complement
+fn exact_calc(r1: BigRational, r2: BigRational, op: &InfixOp) -> Result<Value, RuntimeError> {
+    if *op == InfixOp::Pow {
+        return pow(r1, r2);
+    }
+    let division = matches!(op, InfixOp::Div | InfixOp::IntDiv | InfixOp::Mod);
+    if division && r2.is_zero() {
+        return Err(RuntimeError {
+            msg: "division by zero".to_string(),
+            kind: ErrorKind::Arith,
+            span: None,
+            unwind: None,
+        });
+    }
+    let int_only = !matches!(op, InfixOp::Add | InfixOp::Sub | InfixOp::Mul | InfixOp::Div
+        | InfixOp::Lt | InfixOp::LtEq | InfixOp::Gt | InfixOp::GtEq);
+    if int_only && !(r1.is_integer() && r2.is_integer()) {
+        return Err(RuntimeError {
+            msg: format!("{} needs Ints but was given {} and {}", op.stringify(), r1, r2),
+            kind: ErrorKind::Type,
+            span: None,
+            unwind: None,
+        });
+    }
+    Ok(match op {
+        InfixOp::Add => ratio(r1 + r2),
+        InfixOp::Sub => ratio(r1 - r2),
+        InfixOp::Mul => ratio(r1 * r2),
+        InfixOp::Div => ratio(r1 / r2),
+        InfixOp::IntDiv => big(r1.to_integer().div_floor(&r2.to_integer())),
+        InfixOp::Mod => big(r1.to_integer().mod_floor(&r2.to_integer())),
+        InfixOp::BitAnd => big(r1.to_integer() & r2.to_integer()),
+        InfixOp::BitOr => big(r1.to_integer() | r2.to_integer()),
+        InfixOp::BitXor => big(r1.to_integer() ^ r2.to_integer()),
+        InfixOp::Shl => big(r1.to_integer() << shift(&r1, op, &r2)?),
+        InfixOp::Shr => big(r1.to_integer() >> shift(&r1, op, &r2)?),
+        InfixOp::Pow => unreachable!(),
+        InfixOp::Lt => Value::Bool(r1 < r2),
+        InfixOp::LtEq => Value::Bool(r1 <= r2),
+        InfixOp::Gt => Value::Bool(r1 > r2),
+        InfixOp::GtEq => Value::Bool(r1 >= r2),
+        InfixOp::Eq | InfixOp::NotEq => unreachable!(),
+    })
+}
+
+// NOTE an Int to a negative power isn't an Int, only a Rational can be inverted
+fn pow(base: BigRational, exp: BigRational) -> Result<Value, RuntimeError> {
+    let invalid = |why: &str| RuntimeError {
+        msg: format!("{} in {} ** {}", why, base, exp),
+        kind: ErrorKind::Arith,
+        span: None,
+        unwind: None,
+    };
+    if !exp.is_integer() {
+        return Err(invalid("the exponent must be an Int"));
+    }
+    if base.is_integer() && exp.is_negative() {
+        return Err(invalid("negative exponent of an Int"));
+    }
+    let n = exp.to_integer().to_i32().ok_or_else(|| invalid("the exponent is too large"))?;
+    Ok(ratio(base.pow(n)))
+}
+
+fn shift(r1: &BigRational, op: &InfixOp, r2: &BigRational) -> Result<usize, RuntimeError> {
+    r2.to_integer().to_u32().map(|n| n as usize).ok_or_else(|| RuntimeError {
+        msg: format!("invalid shift amount in {} {} {}", r1, op.stringify(), r2),
+        kind: ErrorKind::Arith,
+        span: None,
+        unwind: None,
+    })
+}
+
+fn float_calc(n1: f64, n2: f64, op: &InfixOp) -> Result<Value, RuntimeError> {
+    let num = match op {
+       InfixOp::Add => n1 + n2,
+       InfixOp::Mul => n1 * n2,
+       InfixOp::Sub => n1 - n2,
+       InfixOp::Div if n2 == 0.0 && numerics().div_by_zero == DivByZero::Error => return Err(RuntimeError {
+           msg: "division by zero".to_string(),
+           kind: ErrorKind::Arith,
+           span: None,
+           unwind: None,
+       }),
+       InfixOp::Div => n1 / n2,
+       InfixOp::Pow => n1.powf(n2),
+       InfixOp::IntDiv | InfixOp::Mod | InfixOp::BitAnd | InfixOp::BitOr
+       | InfixOp::BitXor | InfixOp::Shl | InfixOp::Shr => return Err(RuntimeError {
+           msg: format!("{} needs Ints but was given {:?} and {:?}", op.stringify(), n1, n2),
+           kind: ErrorKind::Type,
+           span: None,
+           unwind: None,
+       }),
+       InfixOp::Lt => return Ok(Value::Bool(n1 < n2)),
+       InfixOp::LtEq => return Ok(Value::Bool(n1 <= n2)),
+       InfixOp::Gt => return Ok(Value::Bool(n1 > n2)),
+       InfixOp::GtEq => return Ok(Value::Bool(n1 >= n2)),
+       InfixOp::Eq | InfixOp::NotEq => unreachable!(),
+    };
+    Ok(Value::Float(num))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ratio_of(n: i64, d: i64) -> Value {
+        ratio(BigRational::new(BigInt::from(n), BigInt::from(d)))
+    }
+
+    #[test]
+    fn results_are_normalized() {
+        assert_eq!(big(BigInt::from(3)), Value::Int(3));
+        assert_eq!(ratio_of(4, 2), Value::Int(2));
+        assert_eq!(ratio_of(2, 4).to_string(), "1/2");
+        assert_eq!(neg(Value::Int(i64::MIN)).to_string(), "9223372036854775808");
+    }
+
+    #[test]
+    fn overflow_promotes_to_bigints() {
+        let max = Value::Int(i64::MAX);
+        let sum = num_calc(max.clone(), Value::Int(1), &InfixOp::Add).unwrap();
+        assert_eq!(sum.to_string(), "9223372036854775808");
+        assert_eq!(num_calc(sum, Value::Int(1), &InfixOp::Sub).unwrap(), max);
+    }
+
+    #[test]
+    fn exact_division() {
+        assert_eq!(num_calc(Value::Int(1), Value::Int(3), &InfixOp::Div).unwrap(), ratio_of(1, 3));
+        assert_eq!(num_calc(ratio_of(1, 3), ratio_of(2, 3), &InfixOp::Add).unwrap(), Value::Int(1));
+        assert_eq!(num_calc(ratio_of(1, 2), Value::Int(1), &InfixOp::Lt).unwrap(), Value::Bool(true));
+        assert_eq!(
+            num_calc(ratio_of(1, 2), Value::Int(1), &InfixOp::Mod).unwrap_err().to_string(),
+            "MSG: % needs Ints but was given 1/2 and 1");
+    }
+}
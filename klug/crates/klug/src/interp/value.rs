@@ -1,6 +1,117 @@
-#[derive(Debug, PartialEq)]
+use std::fmt;
+use std::rc::Rc;
+use super::env::Env;
+use super::cast::Guard;
+use super::builtin::Builtin;
+use super::num::{float_eq, FloatEq};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use crate::parser::{decl::{Function, Variant}, ty::Type};
+use crate::vm::Closure;
+
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Value {
-    Number(f64),
+    // `()`, the value of code that's only run for its effects
+    Unit,
+    Int(i64),
+    // NOTE normalized, only ints that don't fit in an i64 are BigInts
+    BigInt(BigInt),
+    // never has a denominator of 1, that would be an int
+    Rational(BigRational),
+    Float(f64),
     Str(String),
     Bool(bool),
+    // a constructed value of a user-defined enum, e.g. `Rect(1, 2)`
+    Variant(Rc<Ctor>, Vec<Value>),
+    // a constructor still waiting for its arguments, e.g. `Rect`
+    Constructor(Rc<Ctor>),
+    Closure(Rc<Function>, Env),
+    // a function compiled for the `vm`
+    Compiled(Closure),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    // a function cast to a function type, e.g. passed to a `f: Int -> Int` parameter
+    Guarded(Rc<Guard>),
+    Builtin(Builtin),
+}
+
+// A variant as it was declared, so a cast can tell which enum a value
+// belongs to and what its fields should be
+#[derive(Debug, PartialEq)]
+pub(crate) struct Ctor {
+    pub(crate) name: String,
+    // the enum and its type parameters
+    pub(crate) ty: String,
+    pub(crate) generics: Vec<String>,
+    // NOTE an untyped field holds anything
+    pub(crate) fields: Vec<Option<Type>>,
+}
+
+impl Ctor {
+    pub(crate) fn new(ty: &str, generics: &[String], variant: &Variant) -> Rc<Self> {
+        Rc::new(Self {
+            name: variant.name.clone(),
+            ty: ty.to_string(),
+            generics: generics.to_vec(),
+            fields: variant.fields.iter().map(|field| field.ty.clone()).collect(),
+        })
+    }
+
+    // what the variant's name is bound to, the variant itself if it has no fields
+    pub(crate) fn value(self: Rc<Self>) -> Value {
+        match self.fields.len() {
+            0 => Value::Variant(self, vec![]),
+            _ => Value::Constructor(self),
+        }
+    }
+}
+
+impl Value {
+    pub(crate) fn is_number(&self) -> bool {
+        matches!(self, Self::Int(_) | Self::BigInt(_) | Self::Rational(_) | Self::Float(_))
+    }
+
+    // `==`, structural except that floats are compared with `eq`
+    pub(crate) fn equals(&self, other: &Self, eq: FloatEq) -> bool {
+        let all = |xs: &[Value], ys: &[Value]| xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| x.equals(y, eq));
+        match (self, other) {
+            (Self::Float(n1), Self::Float(n2)) => float_eq(*n1, *n2, eq),
+            (Self::Variant(c1, xs), Self::Variant(c2, ys)) => c1 == c2 && all(xs, ys),
+            (Self::Tuple(xs), Self::Tuple(ys)) | (Self::List(xs), Self::List(ys)) => all(xs, ys),
+            _ => self == other,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unit => write!(f, "()"),
+            Self::Int(n) => write!(f, "{}", n),
+            Self::BigInt(n) => write!(f, "{}", n),
+            Self::Rational(r) => write!(f, "{}", r),
+            // NOTE Debug keeps the '.0' of whole floats, so they don't look like ints
+            Self::Float(n) => write!(f, "{:?}", n),
+            Self::Str(s) => write!(f, "{}", s),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Variant(ctor, args) if args.is_empty() => write!(f, "{}", ctor.name),
+            Self::Variant(ctor, args) => {
+                write!(f, "{}({})", ctor.name, join(args))
+            }
+            Self::Constructor(ctor) => write!(f, "<constructor {}>", ctor.name),
+            Self::Closure(func, _) if func.name.is_empty() => write!(f, "<fn>"),
+            Self::Closure(func, _) => write!(f, "<fn {}>", func.name),
+            Self::Compiled(func) if func.name().is_empty() => write!(f, "<fn>"),
+            Self::Compiled(func) => write!(f, "<fn {}>", func.name()),
+            Self::Tuple(items) => write!(f, "({})", join(items)),
+            Self::List(items) => write!(f, "[{}]", join(items)),
+            Self::Guarded(guard) => write!(f, "{}", guard.value),
+            Self::Builtin(builtin) => write!(f, "<builtin {}>", builtin),
+        }
+    }
+}
+
+fn join(vals: &[Value]) -> String {
+    let vals: Vec<String> = vals.iter().map(|v| v.to_string()).collect();
+    vals.join(", ")
 }
@@ -9,6 +9,9 @@ pub(crate) enum SyntaxKind {
     // NOTE I do care about newlines though :)
     #[regex(r"\n")]
     Newline,
+    // NOTE `//` is integer division, so comments start with '#'
+    #[regex(r"#[^\n]*")]
+    Comment,
 
     #[token("fn")]
     FnKw,
@@ -18,10 +21,31 @@ pub(crate) enum SyntaxKind {
     TrueKw,
     #[token("false")]
     FalseKw,
+    #[token("enum")]
+    EnumKw,
+    #[token("match")]
+    MatchKw,
+    #[token("if")]
+    IfKw,
+    #[token("requires")]
+    RequiresKw,
+    #[token("ensures")]
+    EnsuresKw,
+    #[token("throw")]
+    ThrowKw,
+    #[token("try")]
+    TryKw,
+    #[token("catch")]
+    CatchKw,
+    #[token("finally")]
+    FinallyKw,
     #[regex(r"[_a-zA-Z][\w]*")]
     Ident,
-    #[regex(r"[\d]+")]
-    Number,
+    // NOTE only ASCII digits, `\d` would also match digits that Rust can't parse
+    #[regex(r"[0-9]+\.[0-9]+")]
+    Float,
+    #[regex(r"[0-9]+")]
+    Int,
     // NOTE strings in Klug are written like this: 'string'
     #[regex(r"'([^']*)'")]
     StringKw,
@@ -42,17 +66,40 @@ pub(crate) enum SyntaxKind {
     BitAnd,
     #[token("|")]
     BitOr,
+    #[token("^")]
+    Caret,
+    #[token("~")]
+    Tilde,
+
+    #[token("=>")]
+    FatArrow,
+    #[token("->")]
+    Arrow,
+    #[token(":")]
+    Colon,
+    #[token(",")]
+    Comma,
+    #[token("_")]
+    Underscore,
 
     #[token("!")]
     Bang,
+    #[token("?")]
+    Question,
     #[token("+")]
     Plus,
     #[token("-")]
     Minus,
     #[token("*")]
     Star,
+    #[token("**")]
+    StarStar,
     #[token("/")]
     Slash,
+    #[token("//")]
+    SlashSlash,
+    #[token("%")]
+    Percent,
     #[token("=")]
     Equals,
     #[token("{")]
@@ -63,19 +110,36 @@ pub(crate) enum SyntaxKind {
     LParen,
     #[token(")")]
     RParen,
+    #[token("[")]
+    LBracket,
+    #[token("]")]
+    RBracket,
+    #[token("..")]
+    DotDot,
     Root, // TODO remove
     #[error]
     Error,
 }
 
+#[derive(Clone)]
 pub(crate) struct Lexer<'a> {
     inner: logos::Lexer<'a, SyntaxKind>,
+    // only the formatter wants comments, the parser never sees them
+    comments: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub(crate) fn new(input: &'a str) -> Self {
         Self {
             inner: SyntaxKind::lexer(input),
+            comments: false,
+        }
+    }
+
+    pub(crate) fn with_comments(input: &'a str) -> Self {
+        Self {
+            inner: SyntaxKind::lexer(input),
+            comments: true,
         }
     }
 }
@@ -84,7 +148,10 @@ impl<'a> Iterator for Lexer<'a> {
     type Item = (SyntaxKind, &'a str);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let kind = self.inner.next()?;
+        let mut kind = self.inner.next()?;
+        while kind == SyntaxKind::Comment && !self.comments {
+            kind = self.inner.next()?;
+        }
         let text = self.inner.slice();
 
         Some((kind, text))
@@ -106,6 +173,25 @@ mod tests {
         assert_eq!(lexer.next(), None);
     }
 
+    #[test]
+    fn lex_comments() {
+        let mut lexer = Lexer::with_comments("# a comment\n1 # another");
+        assert_eq!(lexer.next(), Some((SyntaxKind::Comment, "# a comment")));
+        assert_eq!(lexer.next(), Some((SyntaxKind::Newline, "\n")));
+        assert_eq!(lexer.next(), Some((SyntaxKind::Int, "1")));
+        assert_eq!(lexer.next(), Some((SyntaxKind::Comment, "# another")));
+        // NOTE but not in strings
+        check("'# not a comment'", SyntaxKind::StringKw);
+    }
+
+    #[test]
+    fn lex_skips_comments() {
+        let mut lexer = Lexer::new("# a comment\n1 # another");
+        assert_eq!(lexer.next(), Some((SyntaxKind::Newline, "\n")));
+        assert_eq!(lexer.next(), Some((SyntaxKind::Int, "1")));
+        assert_eq!(lexer.next(), None);
+    }
+
     #[test]
     fn lex_fn_keyword() {
         check("fn", SyntaxKind::FnKw);
@@ -115,6 +201,34 @@ mod tests {
         check("let", SyntaxKind::LetKw);
     }
 
+    #[test]
+    fn lex_enum_keyword() {
+        check("enum", SyntaxKind::EnumKw);
+    }
+
+    #[test]
+    fn lex_match_keyword() {
+        check("match", SyntaxKind::MatchKw);
+    }
+
+    #[test]
+    fn lex_exception_keywords() {
+        check("throw", SyntaxKind::ThrowKw);
+        check("try", SyntaxKind::TryKw);
+        check("catch", SyntaxKind::CatchKw);
+        check("finally", SyntaxKind::FinallyKw);
+    }
+
+    #[test]
+    fn lex_requires_keyword() {
+        check("requires", SyntaxKind::RequiresKw);
+    }
+
+    #[test]
+    fn lex_ensures_keyword() {
+        check("ensures", SyntaxKind::EnsuresKw);
+    }
+
     #[test]
     fn lex_alphabetic_identifier() {
         check("abcd", SyntaxKind::Ident);
@@ -131,8 +245,63 @@ mod tests {
     }
 
     #[test]
-    fn lex_number() {
-        check("123456", SyntaxKind::Number);
+    fn lex_int() {
+        check("123456", SyntaxKind::Int);
+    }
+
+    #[test]
+    fn lex_float() {
+        check("3.25", SyntaxKind::Float);
+    }
+
+    #[test]
+    fn lex_non_ascii_digits_as_errors() {
+        check("\u{7c4}", SyntaxKind::Error);
+        let mut lexer = Lexer::new("55.\u{7c4}");
+        assert_eq!(lexer.next(), Some((SyntaxKind::Int, "55")));
+    }
+
+    #[test]
+    fn lex_int_before_dot_dot() {
+        let mut lexer = Lexer::new("1..xs");
+        assert_eq!(lexer.next(), Some((SyntaxKind::Int, "1")));
+        assert_eq!(lexer.next(), Some((SyntaxKind::DotDot, "..")));
+    }
+
+    #[test]
+    fn lex_double_slash() {
+        check("//", SyntaxKind::SlashSlash);
+    }
+
+    #[test]
+    fn lex_star_star() {
+        check("**", SyntaxKind::StarStar);
+    }
+
+    #[test]
+    fn lex_bitwise_operators() {
+        check("&", SyntaxKind::BitAnd);
+        check("|", SyntaxKind::BitOr);
+        check("^", SyntaxKind::Caret);
+        check("~", SyntaxKind::Tilde);
+    }
+
+    // NOTE `>>` is two tokens so it can close nested generics, e.g. `List<List<Int>>`
+    #[test]
+    fn lex_shift_as_two_tokens() {
+        let mut lexer = Lexer::new(">>");
+        assert_eq!(lexer.next(), Some((SyntaxKind::GreaterThan, ">")));
+        assert_eq!(lexer.next(), Some((SyntaxKind::GreaterThan, ">")));
+    }
+
+    #[test]
+    fn lex_question() {
+        check("?", SyntaxKind::Question);
+    }
+
+    #[test]
+    fn lex_percent() {
+        check("%", SyntaxKind::Percent);
     }
 
     #[test]
@@ -185,6 +354,42 @@ mod tests {
         check(")", SyntaxKind::RParen);
     }
 
+    #[test]
+    fn lex_fat_arrow() {
+        check("=>", SyntaxKind::FatArrow);
+    }
+
+    #[test]
+    fn lex_arrow() {
+        check("->", SyntaxKind::Arrow);
+    }
+
+    #[test]
+    fn lex_colon() {
+        check(":", SyntaxKind::Colon);
+    }
+
+    #[test]
+    fn lex_underscore() {
+        check("_", SyntaxKind::Underscore);
+    }
+
+    #[test]
+    fn lex_underscore_prefixed_identifier() {
+        check("_x", SyntaxKind::Ident);
+    }
+
+    #[test]
+    fn lex_brackets() {
+        check("[", SyntaxKind::LBracket);
+        check("]", SyntaxKind::RBracket);
+    }
+
+    #[test]
+    fn lex_dot_dot() {
+        check("..", SyntaxKind::DotDot);
+    }
+
     #[test]
     fn match_string1() {
         check("'hello world'", SyntaxKind::StringKw);
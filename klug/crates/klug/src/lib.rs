@@ -2,28 +2,639 @@ mod parser;
 mod lexer;
 mod syntax;
 mod interp;
+mod compile;
+mod vm;
+mod check;
+mod typeck;
+mod prelude;
+mod pretty;
+mod fmt;
+mod ide;
+mod highlight;
+#[cfg(test)]
+mod diff;
+#[cfg(test)]
+mod gen;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+
+use check::Checker;
+use typeck::TypeChecker;
+use compile::klc;
+use interp::{env::Env, exec_decls, num, value::Value, ErrorKind, RuntimeError};
+use parser::{decl::Decl, stmt::Stmt, Parse, Parser, Span};
+
+pub use interp::num::{DivByZero, FloatEq, Numerics};
+pub use ide::{Analysis, Completion, Diagnostic, NameKind, Severity, Symbol};
+pub use highlight::{Token, TokenKind};
+
+// How much static type checking is done before running a program
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Typing {
+    #[default]
+    Off,
+    // every type is inferred, annotations are optional
+    Inferred,
+    // unannotated code is `Dyn` and checked by casts at runtime
+    Gradual,
+}
+
+// What runs programs, they give the same results
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Engine {
+    // walks the syntax tree
+    #[default]
+    Tree,
+    // compiles to bytecode for a stack machine
+    Vm,
+}
+
+// How deeply a program may nest, going deeper is an error rather than a crash
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    // expressions, patterns and types nested inside each other in the source
+    pub nesting: usize,
+    // expressions being evaluated inside each other, including the calls
+    // that aren't tail calls, or only those calls for the `Vm` engine
+    pub depth: usize,
+    // how many times each input may go a level deeper in all, or no limit,
+    // so that a program that never ends fails instead
+    pub fuel: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { nesting: parser::MAX_NESTING, depth: interp::MAX_DEPTH, fuel: None }
+    }
+}
+
+// A REPL session, declarations made by one input are visible to the next
+pub struct Session {
+    env: Env,
+    checker: Checker,
+    // NOTE always kept up to date for `:type`, but its errors only stop
+    // input from running when type checking is turned on
+    type_checker: TypeChecker,
+    typing: Typing,
+    numerics: Numerics,
+    limits: Limits,
+    engine: Engine,
+    // whether the last input had an error, whether it was run or not
+    failed: bool,
+}
+
+// NOTE every session starts with the prelude declared
+impl Default for Session {
+    fn default() -> Self {
+        let mut session = Self {
+            env: Env::default(),
+            checker: Checker::default(),
+            type_checker: TypeChecker::default(),
+            typing: Typing::default(),
+            numerics: Numerics::default(),
+            limits: Limits::default(),
+            engine: Engine::default(),
+            failed: false,
+        };
+        let prelude = prelude::parse();
+        session.checker.check_decls(&prelude.declarations);
+        session.type_checker.check_decls(&prelude.declarations, &prelude.spans);
+        exec_decls(&prelude.declarations, &mut session.env).expect("the prelude only declares things");
+        session
+    }
+}
 
-use interp::{interp_decl, value::Value};
-use parser::Parser;
+impl Session {
+    // inputs that fail to type check aren't run
+    pub fn set_typing(&mut self, typing: Typing) {
+        self.typing = typing;
+        self.type_checker.set_gradual(typing == Typing::Gradual);
+    }
+
+    // how floats are compared and divided by zero
+    pub fn set_numerics(&mut self, numerics: Numerics) {
+        self.numerics = numerics;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    // NOTE warnings aren't errors
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    // NOTE match warnings are printed on their own lines before the result,
+    // and an input that only declares things, or produces unit, prints nothing else
+    pub fn run(&mut self, input: &str) -> String {
+        self.failed = true;
+        if let Some(expr) = input.trim_start().strip_prefix(":type ") {
+            return self.type_of(expr);
+        }
+        let parse = Parser::new(input).with_max_depth(self.limits.nesting).parse();
+        // NOTE none of an input with a syntax error is run, or checked
+        let errors: Vec<String> = parse.declarations.iter()
+            .filter_map(|decl| match decl {
+                Decl::Error(msg) => Some(RuntimeError { msg: msg.clone(), kind: ErrorKind::Syntax, span: None, unwind: None }.to_string()),
+                _ => None,
+            })
+            .collect();
+        if !errors.is_empty() {
+            return errors.join("\n");
+        }
+        let mut lines: Vec<String> = self.checker.check_decls(&parse.declarations)
+            .into_iter()
+            .map(|w| w.to_string())
+            .collect();
+        let errors = self.type_checker.check_decls(&parse.declarations, &parse.spans);
+        if self.typing != Typing::Off && !errors.is_empty() {
+            lines.extend(errors.iter().map(|e| format!("{}: {}", position(input, e.span.start), e)));
+            return lines.join("\n");
+        }
+        self.failed = false;
+        num::set_numerics(self.numerics);
+        interp::set_max_depth(self.limits.depth);
+        interp::set_fuel(self.limits.fuel);
+        let ret = match self.engine {
+            Engine::Tree => exec_decls(&parse.declarations, &mut self.env),
+            Engine::Vm => vm::run(&parse.declarations, &mut self.env),
+        };
+        self.failed = ret.is_err();
+        // NOTE a span from an earlier input can't be located in this one
+        lines.extend(outcome(ret, |span| input.get(span.clone()).map(|_| position(input, span.start))));
+        lines.join("\n")
+    }
+
+    // runs a program made by `build` with the `Vm`, failing if it can't be loaded
+    pub fn run_compiled(&mut self, klc: &[u8]) -> Result<String, String> {
+        self.failed = true;
+        let mut program = klc::read(klc).map_err(|e| e.to_string())?;
+        num::set_numerics(self.numerics);
+        interp::set_max_depth(self.limits.depth);
+        interp::set_fuel(self.limits.fuel);
+        let ret = vm::run_main(std::mem::take(&mut program.main), &mut self.env);
+        self.failed = ret.is_err();
+        Ok(outcome(ret, |span| program.position(span.start)).unwrap_or_default())
+    }
+
+    // the inferred type of an expression, e.g. `:type fn(x) x` is `'a -> 'a`
+    fn type_of(&mut self, input: &str) -> String {
+        let mut parse = Parser::new(input).with_max_depth(self.limits.nesting).parse();
+        match (parse.declarations.pop(), parse.declarations.is_empty()) {
+            (Some(Decl::Stmt(bx_stmt)), true) => match *bx_stmt {
+                Stmt::Expr(expr) => match self.type_checker.type_of(&expr) {
+                    Ok(ty) => {
+                        self.failed = false;
+                        ty.to_string()
+                    }
+                    Err(msg) => format!("type error: {}", msg),
+                },
+            },
+            (Some(Decl::Error(msg)), true) => format!("error: {}", msg),
+            _ => "error: `:type` expects a single expression".to_string(),
+        }
+    }
+}
+
+// what a program printed, nothing for unit, and a failure placed by `locate` if it can be
+fn outcome(ret: Result<Value, RuntimeError>, locate: impl Fn(&Span) -> Option<String>) -> Option<String> {
+    match ret {
+        Ok(Value::Unit) => None,
+        Ok(val) => Some(val.to_string()),
+        Err(e) => Some(match e.span.as_ref().and_then(locate) {
+            Some(position) => format!("{}: {}", position, e),
+            None => e.to_string(),
+        }),
+    }
+}
 
 pub fn run(input: &str) -> String {
+    Session::default().run(input)
+}
+
+// Statically checks a program without running it, returning every diagnostic
+pub fn check(input: &str, typing: Typing) -> Vec<String> {
     let parse = Parser::new(input).parse();
-    let mut v = "null".to_string();
-    for decl in parse.declarations {
-        v = match interp_decl(stmt) {
-            Ok(Value::Str(s)) => s.to_string(),
-            Ok(Value::Number(n)) => n.to_string(),
-            _ => "TODO".to_string(),
-        };
-        return v;
+    let mut diagnostics = syntax_errors(&parse);
+    let prelude = prelude::parse();
+    let mut checker = Checker::default();
+    checker.check_decls(&prelude.declarations);
+    diagnostics.extend(checker
+        .check_decls(&parse.declarations)
+        .into_iter()
+        .map(|w| format!("{}: {}", position(input, w.span.start), w)));
+    diagnostics.extend(type_errors(input, &parse, typing));
+    diagnostics
+}
+
+// Compiles a program to bytecode that `Session::run_compiled` runs without
+// its source, unless it has syntax errors, or type errors when typing is on
+pub fn build(input: &str, typing: Typing) -> Result<Vec<u8>, Vec<String>> {
+    let parse = Parser::new(input).parse();
+    let mut errors = syntax_errors(&parse);
+    errors.extend(type_errors(input, &parse, typing));
+    if !errors.is_empty() {
+        return Err(errors);
     }
-    v
+    let main = compile::compile(&parse.declarations, &Session::default().env);
+    Ok(klc::write(&main, input))
+}
+
+// Lays a program out the one way it's always formatted, keeping its comments,
+// unless it has syntax errors
+pub fn format(input: &str) -> Result<String, Vec<String>> {
+    let parse = Parser::new(input).parse();
+    let errors = syntax_errors(&parse);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(fmt::format(input, &parse.declarations))
+}
+
+// What each token of a program is, for coloring it, even if it doesn't parse
+pub fn highlight(input: &str) -> Vec<Token> {
+    let resolution = ide::resolve::resolve(input, &Parser::new(input).parse());
+    highlight::classify(input, &resolution)
+}
+
+// A program as HTML for docs, see `highlight`
+pub fn highlight_html(input: &str) -> String {
+    highlight::html(input, &highlight(input))
+}
+
+// A program with ANSI colors for a terminal, see `highlight`
+pub fn highlight_ansi(input: &str) -> String {
+    highlight::ansi(input, &highlight(input))
+}
+
+// whether a file was made by `build`, rather than being source
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    klc::is_compiled(bytes)
+}
+
+fn syntax_errors(parse: &Parse) -> Vec<String> {
+    parse.declarations.iter()
+        .filter_map(|decl| match decl {
+            Decl::Error(msg) => Some(format!("error: {}", msg)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn type_errors(input: &str, parse: &Parse, typing: Typing) -> Vec<String> {
+    if typing == Typing::Off {
+        return vec![];
+    }
+    let prelude = prelude::parse();
+    let mut type_checker = TypeChecker::default();
+    type_checker.check_decls(&prelude.declarations, &prelude.spans);
+    type_checker.set_gradual(typing == Typing::Gradual);
+    type_checker
+        .check_decls(&parse.declarations, &parse.spans)
+        .into_iter()
+        .map(|e| format!("{}: {}", position(input, e.span.start), e))
+        .collect()
+}
+
+// the 1-based line:column of a byte offset
+fn position(input: &str, offset: usize) -> String {
+    let before = &input[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    format!("{}:{}", line, col)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn session_keeps_declarations() {
+        let mut session = Session::default();
+        assert_eq!(session.run("enum Shape { Circle(r), Rect(w, h) }\n"), "");
+        assert_eq!(session.run("match Circle(2) { Circle(r) => r * r, Rect(w, h) => w * h }\n"), "4");
+    }
+
+    #[test]
+    fn run_reports_unmatched_value() {
+        assert_eq!(
+            run("enum Shape { Circle(r), Rect(w, h) }\nmatch Rect(1, 2) { Circle(r) => r }"),
+            "warning: non-exhaustive match on `Rect(1, 2)`: `Rect(_, _)` not covered\n\
+             MSG: no match arm matches the value Rect(1, 2)");
+    }
+
+    #[test]
+    fn check_reports_type_errors_with_positions() {
+        assert_eq!(
+            check("let x = 1\n  let y: Str = x", Typing::Inferred),
+            vec!["2:3: type error: mismatched types in `x`, expected Str but found Int"]);
+        assert!(check("let x = 1\n  let y: Str = x", Typing::Off).is_empty());
+    }
+
+    #[test]
+    fn typechecked_session_rejects_ill_typed_input() {
+        let mut session = Session::default();
+        session.set_typing(Typing::Inferred);
+        assert_eq!(session.run("fn f(a: Int) -> Int { a * 2 }\n"), "");
+        assert_eq!(
+            session.run("f('a')\n"),
+            "1:1: type error: mismatched types in `'a'`, expected Int but found Str");
+        assert_eq!(session.run("f(2)\n"), "4");
+    }
+
+    #[test]
+    fn type_command_prints_inferred_types() {
+        let mut session = Session::default();
+        assert_eq!(session.run(":type fn(x) x"), "'a -> 'a");
+        assert_eq!(session.run("fn twice(f, x) { f(f(x)) }\n"), "");
+        assert_eq!(session.run(":type twice"), "('a -> 'a, 'a) -> 'a");
+        assert_eq!(session.run(":type twice(fn(s) s, 'a')"), "Str");
+        assert_eq!(session.run(":type 1 + true"), "type error: mismatched types in `1 + true`, expected Int but found Bool");
+        assert_eq!(session.run(":type let x = 1"), "error: `:type` expects a single expression");
+    }
+
+    #[test]
+    fn gradual_mode_accepts_untyped_code() {
+        let input = "fn double(x: Int) -> Int { x * 2 }\nfn untyped(v) { double(v) }";
+        assert_eq!(
+            check(input, Typing::Inferred),
+            Vec::<String>::new());
+        assert!(check(&format!("{}\nuntyped('a')", input), Typing::Gradual).is_empty());
+        assert_eq!(
+            check(&format!("{}\nuntyped('a')", input), Typing::Inferred),
+            vec!["3:1: type error: mismatched types in `'a'`, expected Int but found Str"]);
+        assert_eq!(
+            check("let x: Int = 'a'", Typing::Gradual),
+            vec!["1:1: type error: mismatched types in `'a'`, expected Int but found Str"]);
+    }
+
+    #[test]
+    fn failed_cast_blames_the_caller() {
+        let mut session = Session::default();
+        session.set_typing(Typing::Gradual);
+        assert_eq!(
+            session.run("fn double(x: Int) -> Int { x * 2 }\nfn untyped(v) { double(v) }\nuntyped('a')"),
+            "2:17: MSG: cast failed: a is not Int, blame the call `double(v)`");
+        assert_eq!(session.run("untyped(2)"), "4");
+    }
+
+    #[test]
+    fn failed_cast_blames_the_function() {
+        let input = "let d: Dyn = 'oops'\nfn bad(x: Int) -> Int { d }\nbad(1)";
+        assert!(check(input, Typing::Gradual).is_empty());
+        assert_eq!(run(input), "2:1: MSG: cast failed: oops is not Int, blame `bad`");
+    }
+
+    #[test]
+    fn failed_cast_blames_the_binding() {
+        let input = "fn untyped(v) { v }\nlet x: Int = untyped('a')\nx";
+        assert!(check(input, Typing::Gradual).is_empty());
+        let generic = "fn f<T>(v: T) -> T { let g = fn() { let y: T = v\ny }\ng() }\nf('a')";
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::default();
+            session.set_engine(engine);
+            assert_eq!(session.run(input), "2:1: MSG: cast failed: a is not Int, blame the binding `x`");
+            assert_eq!(session.run("{ let y: Str = untyped(1)\ny }"), "1:3: MSG: cast failed: 1 is not Str, blame the binding `y`");
+            assert_eq!(session.run(generic), "a");
+        }
+    }
+
+    #[test]
+    fn enum_casts_check_the_enum_and_its_fields() {
+        let f = "fn f(o: Option<Int>) -> Int { match o { Some(n) => n + 1, None => 0 } }\nfn g(x) { x }\n";
+        let call = "f(g(Some('a')))";
+        let start = "try { ".len();
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::default();
+            session.set_engine(engine);
+            assert_eq!(session.run(&format!("{}{}", f, call)), "3:1: MSG: cast failed: a is not Int, blame the call `f(g(Some('a')))`");
+            assert_eq!(
+                session.run(&format!("try {{ {} }} catch e {{ match e {{ Error(_, _, span) => span }} }}", call)),
+                format!("Some(({}, {}))", start, start + call.len()));
+            assert_eq!(session.run("f(g(Some(1))) + f(g(None))"), "2");
+            assert_eq!(
+                session.run("enum Shape { Circle(Int) }\nf(g(Circle(1)))"),
+                "2:1: MSG: cast failed: Circle(1) is not Option<Int>, blame the call `f(g(Circle(1)))`");
+        }
+    }
+
+    #[test]
+    fn higher_order_casts_blame_both_sides() {
+        // the function passed in returns the wrong type
+        let input = "let d: Dyn = 1\nfn apply(f: Str -> Str) -> Str { f('a') }\napply(fn(s) d)";
+        assert_eq!(run(input), "3:1: MSG: cast failed: 1 is not Str, blame the call `apply(fn(s) d)`");
+        // the typed function misuses the function it was given
+        let input = "fn apply(f: Int -> Int, x) { f(x) }\napply(fn(n) n + 1, 'a')";
+        assert_eq!(run(input), "1:1: MSG: cast failed: a is not Int, blame `apply`");
+    }
+
+    #[test]
+    fn contract_violations_have_positions() {
+        assert_eq!(
+            run("fn f(x) requires x > 0 { x }\n  f(0)"),
+            "2:3: MSG: contract violation: `x > 0` does not hold, blame the call `f(0)`");
+        assert_eq!(
+            run("fn f(x) ensures result > 0 { x }\nf(0)"),
+            "1:1: MSG: contract violation: `result > 0` does not hold, blame `f`");
+    }
+
+    #[test]
+    fn arity_errors_name_the_function() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::default();
+            session.set_engine(engine);
+            assert_eq!(session.run("let f = fn(x) x\nf(1, 2)"), "2:1: MSG: anonymous function expects 1 argument(s) but was given 2");
+            assert_eq!(session.run("fn g(x) { x }\ng()"), "2:1: MSG: g expects 1 argument(s) but was given 0");
+        }
+    }
+
+    #[test]
+    fn inputs_with_syntax_errors_dont_run() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::default();
+            session.set_engine(engine);
+            assert_eq!(session.run("let x = 1\nlet = 2\nlet y = 3"), "MSG: Expected Ident but got \"=\"");
+            assert_eq!(session.run("x"), "MSG: unbound identifier x");
+            assert_eq!(session.run("let = 1\n("), "MSG: Expected Ident but got \"=\"\nMSG: expecting expr token, received end of input");
+        }
+    }
+
+    #[test]
+    fn check_reports_parse_errors_and_warnings() {
+        assert_eq!(
+            check("let = 1\nmatch true { true => 1 }", Typing::Off),
+            vec!["error: Expected Ident but got \"=\"",
+                 "2:1: warning: non-exhaustive match on `true`: `false` not covered"]);
+    }
+
+    #[test]
+    fn float_equality_is_exact_by_default() {
+        assert_eq!(run("0.1 + 0.2 == 0.3"), "false");
+        assert_eq!(run("approx_eq(0.1 + 0.2, 0.3, 4)"), "true");
+        assert_eq!(run("approx_eq(1.0, 1.1, 0.2)"), "true");
+        assert_eq!(run("-0.0 == 0.0"), "true");
+        assert_eq!(run("1.0 / 0.0"), "MSG: division by zero");
+    }
+
+    #[test]
+    fn sessions_configure_numerics() {
+        let mut session = Session::default();
+        session.set_numerics(Numerics { float_eq: FloatEq::Ulps(4), div_by_zero: DivByZero::Infinity });
+        assert_eq!(session.run("0.1 + 0.2 == 0.3"), "true");
+        assert_eq!(session.run("[(0.1 + 0.2, 1)] == [(0.3, 1)]"), "true");
+        assert_eq!(session.run("1.0 / 0.0"), "inf");
+        assert_eq!(session.run("-1.0 / 0.0"), "-inf");
+        assert_eq!(session.run("let nan = 0.0 / 0.0\nnan == nan"), "false");
+        assert_eq!(session.run("is_nan(nan)"), "true");
+        assert_eq!(session.run("is_finite(1.0 / 0.0)"), "false");
+        // NOTE infinities aren't within any number of ulps of a finite float
+        assert_eq!(session.run("1.0 / 0.0 == 1.0 / 0.0"), "true");
+        assert_eq!(session.run("approx_eq(1.0 / 0.0, 179769313486231570000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000.0, 1000)"), "false");
+        assert_eq!(Session::default().run("0.1 + 0.2 == 0.3"), "false");
+    }
+
+    #[test]
+    fn unit_results_print_nothing() {
+        let mut session = Session::default();
+        assert_eq!(session.run("let x = 1"), "");
+        assert_eq!(session.run("()"), "");
+        assert_eq!(session.run("(x, ())"), "(1, ())");
+        assert_eq!(session.run("match () { () => x }"), "1");
+        assert_eq!(session.run("let x = 2\nx\nlet y = 3"), "");
+    }
+
+    #[test]
+    fn question_mark_returns_early() {
+        let mut session = Session::default();
+        session.set_typing(Typing::Inferred);
+        assert_eq!(session.run("fn add(a, b) { Ok(parse_int(a)? + parse_int(b)?) }"), "");
+        assert_eq!(session.run("add('1', ' 2')"), "Ok(3)");
+        assert_eq!(session.run("add('1', 'two')"), "Err('two' isn't an Int)");
+        assert_eq!(session.run("fn second(xs) { match xs { [_, ..rest] => head(rest), _ => None } }"), "");
+        assert_eq!(session.run("fn sum2(xs) { Some(head(xs)? + second(xs)?) }"), "");
+        assert_eq!(session.run("sum2([1, 2, 3])"), "Some(3)");
+        assert_eq!(session.run("sum2([1])"), "None");
+        assert_eq!(session.run(":type fn(s) Ok(parse_float(s)? * 2.0)"), "Str -> Result<Float, Str>");
+        assert_eq!(session.run(":type parse_float('1')?"), "type error: `parse_float('1')?` can only be used in a function");
+        assert_eq!(session.run(":type fn(xs) Ok(head(xs)?)"), "type error: mismatched types in `Ok(head(xs)?)`, expected Option<'a> but found Result<'b, 'c>");
+    }
+
+    #[test]
+    fn question_mark_outside_a_function_is_an_error() {
+        assert_eq!(run("parse_int('1')?"), "1");
+        assert_eq!(run("parse_int('x')?"), "MSG: `?` returned Err('x' isn't an Int) outside of a function");
+        assert_eq!(run("1?"), "MSG: `?` needs an Option or a Result, got 1");
+    }
+
+    #[test]
+    fn caught_errors_are_typed_values() {
+        let mut session = Session::default();
+        session.set_typing(Typing::Inferred);
+        assert_eq!(
+            session.run("fn safe_div(a, b) { try { a // b } catch e { match e { Error(kind, _, _) => throw Error(kind, 'in safe_div', None) } } }"),
+            "");
+        assert_eq!(session.run(":type safe_div"), "(Int, Int) -> Int");
+        assert_eq!(
+            session.run("try { safe_div(1, 0) } catch e { e }"),
+            "1:1: type error: mismatched types in `{ e }`, expected Int but found Error");
+        assert_eq!(session.run("try { safe_div(1, 0) } catch e { 0 } finally { 1 }"), "0");
+        assert_eq!(
+            session.run("throw 'oops'"),
+            "1:1: type error: mismatched types in `'oops'`, expected Error but found Str");
+        assert_eq!(session.run("safe_div(1, 0)"), "MSG: uncaught exception Error(arith, in safe_div, None)");
+    }
+
+    #[test]
+    fn uncaught_errors_in_calls_have_positions() {
+        assert_eq!(run("fn f(x) { x // 0 }\nf(1)"), "2:1: MSG: division by zero");
+    }
+
+    #[test]
+    fn limits_are_clean_errors() {
+        let mut session = Session::default();
+        session.set_limits(Limits { nesting: 20, depth: 100, fuel: None });
+        let nested = |n| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+        assert_eq!(session.run(&nested(30)), "MSG: expression too deeply nested");
+        assert_eq!(check(&nested(parser::MAX_NESTING + 10), Typing::Off), vec!["error: expression too deeply nested"]);
+        assert_eq!(session.run("fn f(n) { match n { 0 => 0, _ => 1 + f(n - 1) } }"), "");
+        assert_eq!(session.run("f(3)"), "3");
+        assert_eq!(session.run("f(1000)"), "MSG: stack overflow");
+        assert_eq!(session.run("try { f(1000) } catch e { match e { Error(kind, msg, _) => (kind, msg) } }"), "(stack, stack overflow)");
+    }
+
+    #[test]
+    fn fuel_stops_programs_that_never_end() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::default();
+            session.set_engine(engine);
+            session.set_limits(Limits { fuel: Some(1000), ..Limits::default() });
+            assert_eq!(session.run("fn f(n) { f(n + 1) }\nf(0)"), "1:11: MSG: out of fuel");
+            // every input gets all of it
+            assert_eq!(session.run("fn g(n) { match n { 0 => 0, _ => g(n - 1) } }\ng(50)"), "0");
+            assert_eq!(session.run("g(50)"), "0");
+        }
+    }
+
+    #[test]
+    fn sessions_know_whether_the_last_input_failed() {
+        let mut session = Session::default();
+        session.set_typing(Typing::Inferred);
+        for (input, failed) in [
+            ("1 + 1", false),
+            ("match true { true => 1 }", false),
+            ("1 // 0", true),
+            ("1 + true", true),
+            ("let = 1", true),
+            (":type 1 + true", true),
+            (":type 1", false),
+        ] {
+            session.run(input);
+            assert_eq!(session.failed(), failed, "{:?}", input);
+        }
+        let klc = build("1 // 0", Typing::Off).unwrap();
+        assert!(session.run_compiled(&klc).is_ok());
+        assert!(session.failed());
+    }
+
+    #[test]
+    fn vm_sessions_keep_declarations() {
+        let mut session = Session::default();
+        session.set_engine(Engine::Vm);
+        assert_eq!(session.run("enum Shape { Circle(r), Rect(w, h) }\nfn area(s) { match s { Circle(r) => 3 * r * r, Rect(w, h) => w * h } }"), "");
+        assert_eq!(session.run("let shapes = [Circle(1), Rect(2, 3)]"), "");
+        assert_eq!(session.run("match shapes { [c, r] => (area(c), area(r)), _ => (0, 0) }"), "(3, 6)");
+        assert_eq!(session.run("parse_int('x')?"), "MSG: `?` returned Err('x' isn't an Int) outside of a function");
+        assert_eq!(session.run("fn f(x) { x // 0 }\nf(1)"), "2:1: MSG: division by zero");
+    }
+
+    #[test]
+    fn built_programs_run_without_their_source() {
+        let input = "fn f(n) requires n > 0 { n }\nlet x = f(2) * 10\n\nf(x - 20)";
+        let klc = build(input, Typing::Off).unwrap();
+        assert!(is_compiled(&klc));
+        let mut session = Session::default();
+        assert_eq!(session.run_compiled(&klc), Ok("4:1: MSG: contract violation: `n > 0` does not hold, blame the call `f(x - 20)`".to_string()));
+        assert_eq!(session.run("x"), "20");
+        assert_eq!(run(input), "4:1: MSG: contract violation: `n > 0` does not hold, blame the call `f(x - 20)`");
+    }
+
+    #[test]
+    fn builds_fail_on_errors() {
+        assert_eq!(build("let = 1", Typing::Off), Err(vec!["error: Expected Ident but got \"=\"".to_string()]));
+        assert!(build("let x: Str = 1", Typing::Off).is_ok());
+        assert!(build("let x: Str = 1", Typing::Inferred).is_err());
+        let mut klc = build("1 + 1", Typing::Off).unwrap();
+        klc[4] += 1;
+        assert_eq!(Session::default().run_compiled(&klc), Err(format!("compiled for bytecode version {} but this is version 2, rebuild it", 3)));
+    }
 }
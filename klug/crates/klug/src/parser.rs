@@ -2,100 +2,330 @@ pub mod expr;
 pub mod stmt;
 pub mod decl;
 pub mod literal;
+pub mod pattern;
+pub mod ty;
+pub mod contract;
 
-use expr::Expr;
-use stmt::Stmt;
-use logos::Logos;
+use std::fmt;
 use std::iter::Peekable;
+use std::ops::Range;
+use decl::Decl;
 use crate::lexer::{Lexer, SyntaxKind};
-use crate::syntax::KlugLanguage;
+
+// byte offsets into the source
+pub(crate) type Span = Range<usize>;
 
 #[derive(Debug)]
 pub struct ParseError {
-    msg: String,
+    pub(crate) msg: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(msg: String) -> Self {
+        Self { msg }
+    }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "MSG: {}", self.msg)
-    }    
+    }
 }
 
 pub struct Parser<'a> {
+    source: &'a str,
     lexer: Peekable<Lexer<'a>>,
     has_error: bool,
+    // where the last consumed token (newlines aside) ended
+    last_end: usize,
+    // how many expressions, patterns, types and contracts are being parsed
+    // inside each other, more than `max_depth` is an error
+    depth: usize,
+    max_depth: usize,
 }
 
+// NOTE deeper input would overflow the stack of the parser, or of the
+// checkers and the interpreter that walk what it produces
+pub const MAX_NESTING: usize = 200;
+
 impl<'a> Parser<'a> {
     pub fn new(s: &'a str) -> Self {
-        Self { 
-            lexer: Lexer::new(s).peekable(), 
+        Self {
+            source: s,
+            lexer: Lexer::new(s).peekable(),
             has_error: false,
+            last_end: 0,
+            depth: 0,
+            max_depth: MAX_NESTING,
         }
     }
 
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
     pub fn parse(mut self) -> Parse {
         let mut decls = Vec::<Decl>::new();
+        let mut spans = Vec::<Span>::new();
 
-        while !self.is_end() {
+        loop {
+            self.skip_newlines();
+            if self.is_end() {
+                break;
+            }
+            let start = self.peek_offset();
             decls.push(Decl::new(&mut self));
+            spans.push(start..self.last_end);
         }
 
-        Parse { declarations: decls }
+        Parse { declarations: decls, spans }
     }
 
     fn peek(&mut self) -> Option<SyntaxKind> {
         self.lexer.peek().map(|(kind, _)| *kind)
     }
 
-    fn next(&mut self) -> (SyntaxKind, &str) {
-        self.lexer.next().unwrap()
+    fn peek_text(&mut self) -> Option<&'a str> {
+        self.lexer.peek().map(|(_, txt)| *txt)
+    }
+
+    // the kind of the token after the next one
+    fn peek2(&self) -> Option<SyntaxKind> {
+        self.lexer.clone().nth(1).map(|(kind, _)| kind)
+    }
+
+    // whether the next two tokens are `sk` with nothing between them, e.g. the `>>` of a shift
+    fn peek_joined(&self, sk: SyntaxKind) -> bool {
+        let mut lexer = self.lexer.clone();
+        match (lexer.next(), lexer.next()) {
+            (Some((k1, t1)), Some((k2, t2))) if k1 == sk && k2 == sk => {
+                self.offset(t1) + t1.len() == self.offset(t2)
+            }
+            _ => false,
+        }
+    }
+
+    // NOTE every token is a slice of the source, so its offset is recoverable from the pointers
+    fn offset(&self, txt: &str) -> usize {
+        txt.as_ptr() as usize - self.source.as_ptr() as usize
+    }
+
+    fn peek_offset(&mut self) -> usize {
+        match self.lexer.peek() {
+            Some((_, txt)) => {
+                let txt = *txt;
+                self.offset(txt)
+            }
+            None => self.source.len(),
+        }
+    }
+
+    fn bump(&mut self) -> Option<(SyntaxKind, &'a str)> {
+        let tok = self.lexer.next()?;
+        if tok.0 != SyntaxKind::Newline {
+            self.last_end = self.offset(tok.1) + tok.1.len();
+        }
+        Some(tok)
+    }
+
+    fn next(&mut self) -> (SyntaxKind, &'a str) {
+        self.bump().unwrap()
     }
 
     fn consume(&mut self) {
-        let _ = self.lexer.next();
+        let _ = self.bump();
     }
 
-    fn expect(&mut self, sk: SyntaxKind) -> Result<String, ParseError> {
-        if self.peek() != Some(sk) {
-            return Err(ParseError { 
-                msg: format!("Expected {} but got {}", sk, self.next().map(|(_, txt)| txt)) 
-            })
-        } else {
-            let (_, txt) = self.next();
-            Ok(txt)
+    fn expect(&mut self, sk: SyntaxKind) -> Result<&'a str, ParseError> {
+        match self.bump() {
+            Some((kind, txt)) if kind == sk => Ok(txt),
+            Some((_, txt)) => Err(ParseError::new(format!("Expected {:?} but got {:?}", sk, txt))),
+            None => Err(ParseError::new(format!("Expected {:?} but got end of input", sk))),
+        }
+    }
+
+    // A declaration ends at a newline, at the end of its block or at the end of the input
+    fn expect_end(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            None | Some(SyntaxKind::RBrace) => Ok(()),
+            Some(SyntaxKind::Newline) => {
+                self.consume();
+                Ok(())
+            }
+            Some(_) => {
+                let (_, txt) = self.next();
+                Err(ParseError::new(format!("Expected end of line but got {:?}", txt)))
+            }
+        }
+    }
+
+    // NOTE newlines are only significant at the end of a declaration
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(SyntaxKind::Newline) {
+            self.consume();
         }
     }
 
     fn synchronize(&mut self) {
-        // TODO consume input until we are  on a decl boundary
+        // consume input until we are on a decl boundary
         self.has_error = true;
         loop {
             match self.peek() {
                 None => break,
-                _ => p.consume(),
+                Some(SyntaxKind::Newline) => {
+                    self.consume();
+                    break;
+                }
+                _ => self.consume(),
             }
         }
     }
 
-    fn is_end(&mut self) -> bool {
-        self.peek() == None
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::new("expression too deeply nested".to_string()));
+        }
+        self.depth += 1;
+        let ret = parse(self);
+        self.depth -= 1;
+        ret
     }
 
-    fn error_occurred<T>(&mut self, obj: &T) 
-        where T: HasError {
-            // TODO this doesn't feel right
-            obj.synchronize(&mut *self);
+    fn is_end(&mut self) -> bool {
+        self.peek().is_none()
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Parse {
     pub(crate) declarations: Vec<Decl>,
+    // the span of each declaration
+    pub(crate) spans: Vec<Span>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    // TODO tests maybe?
+    use decl::{Field, Variant};
+    use expr::Expr;
+    use literal::Literal;
+    use stmt::Stmt;
+
+    fn check(input: &str, to_check: Vec<Decl>) {
+        assert_eq!(Parser::new(input).parse().declarations, to_check);
+    }
+
+    // a variant whose fields are only named
+    fn variant(name: &str, fields: &[&str]) -> Variant {
+        let fields = fields.iter()
+            .map(|f| Field { name: Some(f.to_string()), ty: None })
+            .collect();
+        Variant { name: name.to_string(), fields }
+    }
+
+    #[test]
+    fn parse_enum_decl() {
+        check("enum Shape { Circle(r), Rect(w, h), Empty }",
+              vec![Decl::Enum("Shape".to_string(), vec![], vec![
+                  variant("Circle", &["r"]),
+                  variant("Rect", &["w", "h"]),
+                  variant("Empty", &[]),
+              ])]);
+    }
+
+    #[test]
+    fn parse_multiline_enum_decl() {
+        check("enum Tree {\n  Leaf,\n  Node(l, v, r)\n}\n",
+              vec![Decl::Enum("Tree".to_string(), vec![], vec![
+                  variant("Leaf", &[]),
+                  variant("Node", &["l", "v", "r"]),
+              ])]);
+    }
+
+    #[test]
+    fn parse_generic_enum_decl() {
+        let named = |name: &str| Some(ty::Type::Named(name.to_string(), vec![]));
+        check("enum Result<T, E> { Ok(T), Err(error: E) }",
+              vec![Decl::Enum("Result".to_string(), vec!["T".to_string(), "E".to_string()], vec![
+                  Variant { name: "Ok".to_string(), fields: vec![Field { name: None, ty: named("T") }] },
+                  Variant { name: "Err".to_string(), fields: vec![Field { name: Some("error".to_string()), ty: named("E") }] },
+              ])]);
+    }
+
+    #[test]
+    fn parse_generic_fn_decl() {
+        let decls = Parser::new("fn map<A, B>(xs: List<A>, f: A -> B) -> List<B> { xs }").parse().declarations;
+        match &decls[..] {
+            [Decl::Fn(f)] => {
+                assert_eq!(f.generics, vec!["A", "B"]);
+                assert_eq!(f.stringify(), "fn map<A, B>(xs: List<A>, f: A -> B) -> List<B> { xs }");
+            }
+            decls => panic!("expected a single fn, got {:?}", decls),
+        }
+    }
+
+    #[test]
+    fn parse_let_then_expr() {
+        check("let x = 1\nx\n",
+              vec![Decl::Let("x".to_string(), None, Some(Expr::Literal(Literal::INT(1))), 0..9),
+                   Decl::Stmt(Box::new(Stmt::Expr(Box::new(Expr::Literal(Literal::IDENT("x".to_string()))))))]);
+    }
+
+    #[test]
+    fn parse_annotated_let() {
+        check("let x: Int = 1",
+              vec![Decl::Let("x".to_string(), Some(ty::Type::Named("Int".to_string(), vec![])), Some(Expr::Literal(Literal::INT(1))), 0..14)]);
+    }
+
+    #[test]
+    fn parse_fn_decl() {
+        let decls = Parser::new("fn f(a: Str, b) -> Bool {\n  let c = a\n  b\n}").parse().declarations;
+        match &decls[..] {
+            [Decl::Fn(f)] => {
+                assert_eq!(f.name, "f");
+                assert_eq!(f.params[0].ty, Some(ty::Type::Named("Str".to_string(), vec![])));
+                assert_eq!(f.params[1].ty, None);
+                assert_eq!(f.ret, Some(ty::Type::Named("Bool".to_string(), vec![])));
+                assert_eq!(f.body.stringify(), "{ let c = a; b }");
+            }
+            decls => panic!("expected a single fn, got {:?}", decls),
+        }
+    }
+
+    #[test]
+    fn parse_lambda_stmt() {
+        let decls = Parser::new("fn(x) x\n").parse().declarations;
+        assert_eq!(decls.len(), 1);
+        assert_eq!(decls[0].stringify(), "fn(x) x");
+    }
+
+    #[test]
+    fn decl_spans() {
+        let parse = Parser::new("let x = 1\n\n  x + 2\n").parse();
+        assert_eq!(parse.spans, vec![0..9, 13..18]);
+    }
+
+    #[test]
+    fn parse_error_recovers_at_newline() {
+        let decls = Parser::new("let = 1\n2").parse().declarations;
+        assert!(matches!(decls[0], Decl::Error(_)));
+        assert_eq!(decls[1], Decl::Stmt(Box::new(Stmt::Expr(Box::new(Expr::Literal(Literal::INT(2)))))));
+    }
+
+    // NOTE found by fuzzing, these used to panic
+    #[test]
+    fn parse_malformed_numbers_as_errors() {
+        for input in ["55.\u{7c4}", "\u{7c4}", "1.\u{664}5"] {
+            let decls = Parser::new(input).parse().declarations;
+            assert!(matches!(decls[0], Decl::Error(_)), "{:?} parsed to {:?}", input, decls);
+        }
+    }
+
+    #[test]
+    fn parse_fn_with_contracts() {
+        let decls = Parser::new("fn f(x) requires x > 0, x < 10 ensures result: pos {\n  x\n}").parse().declarations;
+        assert_eq!(decls.len(), 1);
+        assert_eq!(decls[0].stringify(), "fn f(x) requires x > 0, x < 10 ensures result: pos { x }");
+    }
 }
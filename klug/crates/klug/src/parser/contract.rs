@@ -0,0 +1,227 @@
+use std::fmt;
+use super::decl::Decl;
+use super::expr::{self, Expr};
+use super::stmt::Stmt;
+use super::{Parser, ParseError};
+use crate::lexer::SyntaxKind;
+
+// A `requires` or `ensures` clause of a function
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Clause {
+    // a condition that must hold, e.g. `x > 0`
+    Holds(Expr),
+    // a value that must satisfy a contract, e.g. `f: pos -> pos`
+    Satisfies(String, Contract),
+}
+
+// NOTE a flat contract is a predicate, an arrow contract wraps a function so
+// its arguments and result are checked on every call
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Contract {
+    Flat(Expr),
+    Fn(Vec<Contract>, Box<Contract>),
+}
+
+impl Clause {
+    pub(crate) fn stringify(&self) -> String {
+        match self {
+            Self::Holds(expr) => expr.stringify(),
+            Self::Satisfies(name, contract) => format!("{}: {}", name, contract),
+        }
+    }
+}
+
+impl fmt::Display for Clause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.stringify())
+    }
+}
+
+impl Contract {
+    pub(crate) fn stringify(&self) -> String {
+        match self {
+            Self::Flat(expr) => expr.stringify(),
+            Self::Fn(params, ret) if params.len() == 1 && !matches!(params[0], Self::Fn(_, _)) => {
+                format!("{} -> {}", params[0].stringify(), ret.stringify())
+            }
+            Self::Fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.stringify()).collect();
+                format!("({}) -> {}", params.join(", "), ret.stringify())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Contract {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.stringify())
+    }
+}
+
+// [requires clause, ...] [ensures clause, ...]
+pub(super) fn clauses(p: &mut Parser) -> Result<(Vec<Clause>, Vec<Clause>), ParseError> {
+    let requires = clause_list(p, SyntaxKind::RequiresKw)?;
+    let ensures = clause_list(p, SyntaxKind::EnsuresKw)?;
+    Ok((requires, ensures))
+}
+
+fn clause_list(p: &mut Parser, kw: SyntaxKind) -> Result<Vec<Clause>, ParseError> {
+    let mut clauses = Vec::new();
+    if p.peek() != Some(kw) {
+        return Ok(clauses);
+    }
+    p.consume();
+    loop {
+        clauses.push(clause(p)?);
+        match p.peek() {
+            Some(SyntaxKind::Comma) => p.consume(),
+            _ => return Ok(clauses),
+        }
+    }
+}
+
+// NOTE a clause is checked, it can't return from its function with `?`
+fn clause(p: &mut Parser) -> Result<Clause, ParseError> {
+    let clause = if p.peek() == Some(SyntaxKind::Ident) && p.peek2() == Some(SyntaxKind::Colon) {
+        let name = p.expect(SyntaxKind::Ident)?.to_string();
+        p.consume(); // eat the ':'
+        Clause::Satisfies(name, parse(p)?)
+    } else {
+        Clause::Holds(expr::parse(p)?)
+    };
+    let returns = match &clause {
+        Clause::Holds(expr) => returns(expr),
+        Clause::Satisfies(_, contract) => contract_returns(contract),
+    };
+    match returns {
+        true => Err(ParseError::new(format!("`?` can't return from a contract clause, in `{}`", clause))),
+        false => Ok(clause),
+    }
+}
+
+fn contract_returns(contract: &Contract) -> bool {
+    match contract {
+        Contract::Flat(expr) => returns(expr),
+        Contract::Fn(params, ret) => params.iter().any(contract_returns) || contract_returns(ret),
+    }
+}
+
+// whether a `?` in `e` returns from the function it's in, rather than from a lambda
+fn returns(e: &Expr) -> bool {
+    match e {
+        Expr::Try(_) => true,
+        Expr::Literal(_) | Expr::Lambda(_) => false,
+        Expr::Unary(_, e) | Expr::Grouping(e) | Expr::Throw(e) => returns(e),
+        Expr::Binary(lhs, _, rhs) => returns(lhs) || returns(rhs),
+        Expr::Call(callee, args, _) => returns(callee) || args.iter().any(returns),
+        Expr::Match(scrutinee, arms, _) => {
+            returns(scrutinee) || arms.iter().any(|arm| arm.guard.iter().any(returns) || returns(&arm.body))
+        }
+        Expr::Block(decls, e) => {
+            let decl_returns = |decl: &Decl| match decl {
+                Decl::Let(_, _, Some(e), _) => returns(e),
+                Decl::Stmt(stmt) => match &**stmt {
+                    Stmt::Expr(e) => returns(e),
+                },
+                _ => false,
+            };
+            decls.iter().any(decl_returns) || returns(e)
+        }
+        Expr::Tuple(items) => items.iter().any(returns),
+        Expr::List(items, rest) => items.iter().any(returns) || rest.iter().any(|e| returns(e)),
+        Expr::TryCatch(body, catch, finally) => {
+            returns(body) || catch.iter().any(|(_, e)| returns(e)) || finally.iter().any(|e| returns(e))
+        }
+    }
+}
+
+// NOTE like types, '->' is right associative and parenthesized contracts
+// without an arrow are just grouped
+pub(super) fn parse(p: &mut Parser) -> Result<Contract, ParseError> {
+    p.nested(contract)
+}
+
+fn contract(p: &mut Parser) -> Result<Contract, ParseError> {
+    let mut params = match p.peek() {
+        Some(SyntaxKind::LParen) => {
+            p.consume();
+            let mut contracts = Vec::new();
+            if p.peek() != Some(SyntaxKind::RParen) {
+                loop {
+                    contracts.push(parse(p)?);
+                    match p.peek() {
+                        Some(SyntaxKind::Comma) => p.consume(),
+                        _ => break,
+                    }
+                }
+            }
+            p.expect(SyntaxKind::RParen)?;
+            contracts
+        }
+        _ => vec![Contract::Flat(expr::parse(p)?)],
+    };
+
+    if p.peek() == Some(SyntaxKind::Arrow) {
+        p.consume();
+        let ret = parse(p)?;
+        return Ok(Contract::Fn(params, Box::new(ret)));
+    }
+    match params.len() {
+        1 => Ok(params.remove(0)),
+        _ => Err(ParseError::new("expecting '->' after a list of contracts".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &str, requires: &[&str], ensures: &[&str]) {
+        let (req, ens) = clauses(&mut Parser::new(input)).unwrap();
+        assert_eq!(req.iter().map(|c| c.stringify()).collect::<Vec<_>>(), requires);
+        assert_eq!(ens.iter().map(|c| c.stringify()).collect::<Vec<_>>(), ensures);
+    }
+
+    #[test]
+    fn parse_conditions() {
+        check("requires x > 0, y > 0 ensures result < 100", &["x > 0", "y > 0"], &["result < 100"]);
+    }
+
+    #[test]
+    fn parse_flat_contract() {
+        check("requires x: pos", &["x: pos"], &[]);
+    }
+
+    #[test]
+    fn parse_lambda_contract() {
+        check("ensures result: fn(r) r != 0", &[], &["result: fn(r) r != 0"]);
+    }
+
+    #[test]
+    fn parse_arrow_contract() {
+        let (requires, _) = clauses(&mut Parser::new("requires f: (pos, pos) -> pos -> pos")).unwrap();
+        match &requires[..] {
+            [Clause::Satisfies(name, Contract::Fn(params, ret))] => {
+                assert_eq!(name, "f");
+                assert_eq!(params.len(), 2);
+                assert_eq!(ret.stringify(), "pos -> pos");
+            }
+            clauses => panic!("expected a single arrow contract, got {:?}", clauses),
+        }
+    }
+
+    #[test]
+    fn clauses_cant_return() {
+        let err = |input| clauses(&mut Parser::new(input)).unwrap_err().to_string();
+        assert_eq!(err("requires int(x)? > 0"), "MSG: `?` can't return from a contract clause, in `int(x)? > 0`");
+        assert!(err("ensures result: { let y = x? \n pos }").contains("can't return"));
+        assert!(err("requires f: pos -> try { x? } catch e { pos }").contains("can't return"));
+        // NOTE but a lambda's `?` returns from the lambda
+        check("ensures result: fn(r) int(r)? > 0", &[], &["result: fn(r) int(r)? > 0"]);
+    }
+
+    #[test]
+    fn parse_contract_list_needs_arrow() {
+        assert!(parse(&mut Parser::new("(pos, pos)")).is_err());
+    }
+}
@@ -1,43 +1,281 @@
-use std::fmt;
+use std::rc::Rc;
 use crate::lexer::SyntaxKind;
 use super::{
-    stmt::Stmt, 
-    expr::Expr, 
-    literal::Literal, 
-    Parser
+    stmt::{self, Stmt},
+    expr::{self, Expr},
+    ty::{self, Type},
+    contract::{self, Clause},
+    pattern::is_constructor_name,
+    Parser,
+    ParseError,
+    Span,
 };
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Decl {
-    Let(Literal::IDENT, Option<Expr>),
+    // NOTE the span is the declaration's, a cast to its annotation blames it
+    Let(String, Option<Type>, Option<Expr>, Span),
+    // NOTE shared with the closures created from it
+    Fn(Rc<Function>),
+    // enum Name<Param, ...> { Variant, ... }
+    Enum(String, Vec<String>, Vec<Variant>),
     Stmt(Box<Stmt>),
+    // the message of the error it failed to parse with
     Error(String),
 }
 
+// A single constructor of an `enum` declaration, e.g. `Rect(w, h)` or `Some(T)`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Variant {
+    pub(crate) name: String,
+    pub(crate) fields: Vec<Field>,
+}
+
+// NOTE a field is a name, a type, or both, e.g. `w`, `Int` or `w: Int`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Field {
+    pub(crate) name: Option<String>,
+    pub(crate) ty: Option<Type>,
+}
+
+// fn name[<Param, ...>](param[: Type], ...) [-> Type] [requires ...] [ensures ...] { body }
+// NOTE anonymous functions, `fn(x) x + 1`, have an empty name
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Function {
+    pub(crate) name: String,
+    // the type parameters, always empty for anonymous functions
+    pub(crate) generics: Vec<String>,
+    pub(crate) params: Vec<Param>,
+    pub(crate) ret: Option<Type>,
+    // checked on every call, `result` is bound in the `ensures` clauses
+    // NOTE always empty for anonymous functions
+    pub(crate) requires: Vec<Clause>,
+    pub(crate) ensures: Vec<Clause>,
+    pub(crate) body: Expr,
+    // from the `fn` through the end of the body
+    pub(crate) span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Param {
+    pub(crate) name: String,
+    pub(crate) ty: Option<Type>,
+}
+
 impl Decl {
     pub(super) fn new(p: &mut Parser) -> Self {
-        if let Ok(decl) = new_decl(p) {
-            decl
-        } else {
-            Self::Error("TODO".to_string()) // TODO actually report the error
+        parse(p).unwrap_or_else(|e| {
+            p.synchronize();
+            Self::Error(e.msg)
+        })
+    }
+
+    pub(crate) fn stringify(&self) -> String {
+        match self {
+            Self::Let(name, ty, expr, _) => {
+                let ty = ty.as_ref().map_or(String::new(), |ty| format!(": {}", ty));
+                let expr = expr.as_ref().map_or(String::new(), |expr| format!(" = {}", expr));
+                format!("let {}{}{}", name, ty, expr)
+            }
+            Self::Fn(f) => f.stringify(),
+            Self::Enum(name, generics, variants) => {
+                let variants: Vec<String> = variants.iter().map(|v| v.stringify()).collect();
+                format!("enum {}{} {{ {} }}", name, stringify_generics(generics), variants.join(", "))
+            }
+            Self::Stmt(bx_stmt) => bx_stmt.stringify(),
+            Self::Error(msg) => msg.to_string(),
         }
     }
 }
 
-fn new_decl(p: &mut Parser) -> Result<Decl, Decl>  {
+impl Variant {
+    pub(crate) fn stringify(&self) -> String {
+        if self.fields.is_empty() {
+            return self.name.clone();
+        }
+        let fields: Vec<String> = self.fields.iter().map(|field| match (&field.name, &field.ty) {
+            (Some(name), Some(ty)) => format!("{}: {}", name, ty),
+            (Some(name), None) => name.clone(),
+            (None, Some(ty)) => ty.to_string(),
+            (None, None) => unreachable!(),
+        }).collect();
+        format!("{}({})", self.name, fields.join(", "))
+    }
+}
+
+impl Function {
+    pub(crate) fn stringify(&self) -> String {
+        format!("{} {}", self.signature(), self.body)
+    }
+
+    // everything but the body, e.g. `fn f(x: Int) -> Int requires x > 0`
+    pub(crate) fn signature(&self) -> String {
+        let params: Vec<String> = self.params.iter().map(|param| match &param.ty {
+            Some(ty) => format!("{}: {}", param.name, ty),
+            None => param.name.clone(),
+        }).collect();
+        let mut ret = self.ret.as_ref().map_or(String::new(), |ty| format!(" -> {}", ty));
+        for (kw, clauses) in [("requires", &self.requires), ("ensures", &self.ensures)] {
+            if !clauses.is_empty() {
+                let clauses: Vec<String> = clauses.iter().map(|c| c.stringify()).collect();
+                ret.push_str(&format!(" {} {}", kw, clauses.join(", ")));
+            }
+        }
+        match &self.name[..] {
+            "" => format!("fn({}){}", params.join(", "), ret),
+            name => format!("fn {}{}({}){}", name, stringify_generics(&self.generics), params.join(", "), ret),
+        }
+    }
+}
+
+// the adjacent function declarations at the start of `decls`
+// NOTE these are declared together, so they can call each other
+pub(crate) fn leading_functions(decls: &[Decl]) -> Vec<&Rc<Function>> {
+    decls.iter()
+        .map_while(|decl| match decl {
+            Decl::Fn(f) => Some(f),
+            _ => None,
+        })
+        .collect()
+}
+
+fn stringify_generics(generics: &[String]) -> String {
+    match generics {
+        [] => String::new(),
+        _ => format!("<{}>", generics.join(", ")),
+    }
+}
+
+pub(super) fn parse(p: &mut Parser) -> Result<Decl, ParseError>  {
     match p.peek() {
         Some(SyntaxKind::LetKw) => {
+            let start = p.peek_offset();
             p.consume(); // eat the 'let'
-            let ident = p.expect(SyntaxKind::Ident);
-            let lit_ident = Literal::new(SyntaxKind::Ident, ident);
-            let expr = None;
+            let ident = p.expect(SyntaxKind::Ident)?;
+            let ty = annotation(p, SyntaxKind::Colon)?;
+            let mut expr = None;
             if p.peek() == Some(SyntaxKind::Equals) {
                 // There must be an expression
-                expr = Some(Expr::new(p));
+                p.consume();
+                expr = Some(expr::parse(p)?);
             } // o.t. remains None
-            p.expect(SyntaxKind::Newline)?;
-            Decl::Let(lit_ident, expr)
+            let span = start..p.last_end;
+            p.expect_end()?;
+            Ok(Decl::Let(ident.to_string(), ty, expr, span))
+        }
+        Some(SyntaxKind::FnKw) if p.peek2() == Some(SyntaxKind::Ident) => {
+            let start = p.peek_offset();
+            p.consume(); // eat the 'fn'
+            let name = p.expect(SyntaxKind::Ident)?.to_string();
+            let generics = generics(p)?;
+            let (params, ret) = signature(p)?;
+            let (requires, ensures) = contract::clauses(p)?;
+            let body = expr::block(p)?;
+            let span = start..p.last_end;
+            p.expect_end()?;
+            Ok(Decl::Fn(Rc::new(Function { name, generics, params, ret, requires, ensures, body, span })))
+        }
+        Some(SyntaxKind::EnumKw) => {
+            p.consume(); // eat the 'enum'
+            let name = p.expect(SyntaxKind::Ident)?;
+            let generics = generics(p)?;
+            p.expect(SyntaxKind::LBrace)?;
+            let mut variants = Vec::new();
+            loop {
+                p.skip_newlines();
+                if p.peek() == Some(SyntaxKind::RBrace) {
+                    break;
+                }
+                variants.push(new_variant(p)?);
+                p.skip_newlines();
+                match p.peek() {
+                    Some(SyntaxKind::Comma) => p.consume(),
+                    _ => break,
+                }
+            }
+            p.skip_newlines();
+            p.expect(SyntaxKind::RBrace)?;
+            p.expect_end()?;
+            Ok(Decl::Enum(name.to_string(), generics, variants))
+        }
+        _ => Ok(Decl::Stmt(Box::new(stmt::parse(p)?))),
+    }
+}
+
+// an optional list of type parameters, e.g. `<A, B>`
+fn generics(p: &mut Parser) -> Result<Vec<String>, ParseError> {
+    let mut generics = Vec::new();
+    if p.peek() != Some(SyntaxKind::LessThan) {
+        return Ok(generics);
+    }
+    p.consume();
+    loop {
+        generics.push(p.expect(SyntaxKind::Ident)?.to_string());
+        match p.peek() {
+            Some(SyntaxKind::Comma) => p.consume(),
+            _ => break,
+        }
+    }
+    p.expect(SyntaxKind::GreaterThan)?;
+    Ok(generics)
+}
+
+// (param[: Type], ...) [-> Type]
+pub(super) fn signature(p: &mut Parser) -> Result<(Vec<Param>, Option<Type>), ParseError> {
+    p.expect(SyntaxKind::LParen)?;
+    let mut params = Vec::new();
+    if p.peek() != Some(SyntaxKind::RParen) {
+        loop {
+            let name = p.expect(SyntaxKind::Ident)?.to_string();
+            let ty = annotation(p, SyntaxKind::Colon)?;
+            params.push(Param { name, ty });
+            match p.peek() {
+                Some(SyntaxKind::Comma) => p.consume(),
+                _ => break,
+            }
+        }
+    }
+    p.expect(SyntaxKind::RParen)?;
+    let ret = annotation(p, SyntaxKind::Arrow)?;
+    Ok((params, ret))
+}
+
+// an optional type annotation introduced by `sk`
+fn annotation(p: &mut Parser, sk: SyntaxKind) -> Result<Option<Type>, ParseError> {
+    if p.peek() != Some(sk) {
+        return Ok(None);
+    }
+    p.consume();
+    Ok(Some(ty::parse(p)?))
+}
+
+fn new_variant(p: &mut Parser) -> Result<Variant, ParseError> {
+    let name = p.expect(SyntaxKind::Ident)?.to_string();
+    let mut fields = Vec::new();
+    if p.peek() == Some(SyntaxKind::LParen) {
+        p.consume();
+        if p.peek() != Some(SyntaxKind::RParen) {
+            loop {
+                fields.push(field(p)?);
+                match p.peek() {
+                    Some(SyntaxKind::Comma) => p.consume(),
+                    _ => break,
+                }
+            }
+        }
+        p.expect(SyntaxKind::RParen)?;
+    }
+    Ok(Variant { name, fields })
+}
+
+// NOTE like patterns, a lowercase identifier names the field and anything else is its type
+fn field(p: &mut Parser) -> Result<Field, ParseError> {
+    match p.peek() {
+        Some(SyntaxKind::Ident) if !p.peek_text().is_some_and(is_constructor_name) => {
+            let name = p.expect(SyntaxKind::Ident)?.to_string();
+            let ty = annotation(p, SyntaxKind::Colon)?;
+            Ok(Field { name: Some(name), ty })
         }
-        _ => Decl::Stmt(Box::new(Stmt::new(p))),
+        _ => Ok(Field { name: None, ty: Some(ty::parse(p)?) }),
     }
 }
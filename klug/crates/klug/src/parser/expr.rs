@@ -1,39 +1,133 @@
 pub(crate) mod op;
 
 use std::fmt;
-use literal::Literal;
+use std::rc::Rc;
+use super::literal::Literal;
+use super::pattern::{self, Pattern};
+use super::decl::{self, Decl, Function};
+use super::stmt::Stmt;
 use op::{InfixOp, PrefixOp};
-use super::Parser;
+use super::{Parser, ParseError, Span};
 use crate::lexer::SyntaxKind;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Expr{
   Unary(PrefixOp, Box<Expr>),
   Binary(Box<Expr>, InfixOp, Box<Expr>),
   Grouping(Box<Expr>),
   Literal(Literal),
-  Error(String),
+  // NOTE the span covers the callee through the closing ')', so a failed cast can blame the call
+  Call(Box<Expr>, Vec<Expr>, Span),
+  // NOTE the span covers 'match' through the closing '}', so a warning can point at it
+  Match(Box<Expr>, Vec<Arm>, Span),
+  // { decl ... expr }, evaluates to its final expression
+  Block(Vec<Decl>, Box<Expr>),
+  Lambda(Rc<Function>),
+  Tuple(Vec<Expr>),
+  // [e, ...] or [e, ..rest]
+  List(Vec<Expr>, Option<Box<Expr>>),
+  // e?, unwraps a `Some` or `Ok` and returns anything else from the enclosing function
+  Try(Box<Expr>),
+  Throw(Box<Expr>),
+  // try { ... } catch e { ... } finally { ... }, either clause can be left out but not both
+  TryCatch(Box<Expr>, Option<(String, Box<Expr>)>, Option<Box<Expr>>),
+}
+
+// pattern [if guard] => body
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Arm {
+    pub(crate) pattern: Pattern,
+    pub(crate) guard: Option<Expr>,
+    pub(crate) body: Expr,
+    // the pattern through the body
+    pub(crate) span: Span,
 }
 
 impl Expr {
+    #[cfg(test)]
     pub(super) fn new(p: &mut Parser) -> Self {
-        expr_binding_power(p, 0)
-            .map_or_else(|e|{
-                p.error_occurred(&e);
-                Self::Error("TODO".to_string())
-//                e
-            }, |v| v)
+        parse(p).unwrap()
     }
 
     pub(crate) fn stringify(&self) -> String {
+        self.stringify_between(0, 0)
+    }
+
+    // whether this needs parentheses to parse back the same between operators
+    // binding `left` and `right` tightly, 0 when there's none on that side
+    // NOTE `right` is `CALL_BINDING_POWER` when it's called or has a `?`
+    pub(crate) fn needs_parens(&self, left: u8, right: u8) -> bool {
+        match self {
+            Self::Binary(_, op, _) => {
+                let (lbind, rbind) = op.binding_power();
+                lbind < left || right >= rbind
+            }
+            Self::Unary(op, _) => right >= op.binding_power().1,
+            // NOTE a lambda body and what's thrown extend as far as they can
+            Self::Lambda(_) | Self::Throw(_) => right > 0,
+            _ => false,
+        }
+    }
+
+    // NOTE prints the groupings it has and the fewest parentheses it needs on top
+    fn stringify_between(&self, left: u8, right: u8) -> String {
+        if self.needs_parens(left, right) {
+            return format!("({})", self.stringify_between(0, 0));
+        }
         match self {
-            Self::Unary(op, bdy) => format!("{}{}", &(op.stringify())[..], &(*bdy.stringify())[..]), 
+            Self::Unary(op, bdy) => format!("{}{}", op.stringify(), bdy.stringify_between(op.binding_power().1, right)),
             Self::Binary(lhs, op, rhs) => {
-                format!("{} {} {}", &(*lhs.stringify())[..], &(op.stringify())[..], &(*rhs.stringify())[..])
+                let (lbind, rbind) = op.binding_power();
+                format!("{} {} {}", lhs.stringify_between(left, lbind), op.stringify(), rhs.stringify_between(rbind, right))
             }
-            Self::Grouping(bdy) => format!("( {} )", &(*bdy.stringify())[..]), 
+            Self::Grouping(bdy) => format!("({})", bdy.stringify()),
             Self::Literal(lit) => lit.stringify(), 
-            Self::Error(msg) => msg.to_string(),
+            Self::Call(callee, args, _) => {
+                let args: Vec<String> = args.iter().map(|a| a.stringify()).collect();
+                format!("{}({})", callee.stringify_between(left, CALL_BINDING_POWER), args.join(", "))
+            }
+            Self::Match(scrut, arms, _) => {
+                let arms: Vec<String> = arms.iter().map(|a| a.stringify()).collect();
+                format!("match {} {{ {} }}", scrut.stringify(), arms.join(", "))
+            }
+            Self::Block(decls, expr) => {
+                let mut items: Vec<String> = decls.iter().map(|d| d.stringify()).collect();
+                items.push(expr.stringify());
+                format!("{{ {} }}", items.join("; "))
+            }
+            Self::Lambda(f) => f.stringify(),
+            Self::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|i| i.stringify()).collect();
+                format!("({})", items.join(", "))
+            }
+            Self::List(items, rest) => {
+                let mut items: Vec<String> = items.iter().map(|i| i.stringify()).collect();
+                if let Some(rest) = rest {
+                    items.push(format!("..{}", rest.stringify()));
+                }
+                format!("[{}]", items.join(", "))
+            }
+            Self::Try(bdy) => format!("{}?", bdy.stringify_between(left, CALL_BINDING_POWER)),
+            Self::Throw(bdy) => format!("throw {}", bdy.stringify()),
+            Self::TryCatch(bdy, catch, finally) => {
+                let mut s = format!("try {}", bdy.stringify());
+                if let Some((name, handler)) = catch {
+                    s.push_str(&format!(" catch {} {}", name, handler.stringify()));
+                }
+                if let Some(finally) = finally {
+                    s.push_str(&format!(" finally {}", finally.stringify()));
+                }
+                s
+            }
+        }
+    }
+}
+
+impl Arm {
+    pub(crate) fn stringify(&self) -> String {
+        match &self.guard {
+            Some(guard) => format!("{} if {} => {}", self.pattern, guard.stringify(), self.body.stringify()),
+            None => format!("{} => {}", self.pattern, self.body.stringify()),
         }
     }
 }
@@ -44,38 +138,116 @@ impl fmt::Display for Expr {
     }    
 }
 
+// NOTE binds tighter than any prefix operator, so `-f(x)` is `-(f(x))`,
+// and so does a postfix `?`
+pub(crate) const CALL_BINDING_POWER: u8 = 21;
+
+pub(super) fn parse(p: &mut Parser) -> Result<Expr, ParseError> {
+    expr_binding_power(p, 0)
+}
+
 fn expr_binding_power(p: &mut Parser, min_bind: u8) -> Result<Expr, ParseError> {
+    p.nested(|p| expr(p, min_bind))
+}
+
+fn expr(p: &mut Parser, min_bind: u8) -> Result<Expr, ParseError> {
 
     let mut poss_expr: Expr;
 
+    // NOTE an expression can't start with a newline, so these are never significant here
+    p.skip_newlines();
+    let start = p.peek_offset();
+
     match p.peek() {
-        Some(SyntaxKind::Number) 
+        Some(SyntaxKind::Int)
+        | Some(SyntaxKind::Float)
         | Some(SyntaxKind::StringKw)
         | Some(SyntaxKind::TrueKw)
         | Some(SyntaxKind::FalseKw)
         | Some(SyntaxKind::Ident) => {
             // NOTE: next consumes the token
             let (sk, txt) = p.next();
-            poss_expr = Expr::Literal(Literal::new(sk, txt)); 
+            poss_expr = Expr::Literal(Literal::new(sk, txt)?);
         }
-        Some(SyntaxKind::Minus) 
-        | Some(SyntaxKind::Bang) => {
+        Some(SyntaxKind::Minus)
+        | Some(SyntaxKind::Bang)
+        | Some(SyntaxKind::Tilde) => {
             let (kind, _) = p.next();
             let op = PrefixOp::from_kind(kind);
             let ((), rbind) = op.binding_power();
             let new_expr = expr_binding_power(p, rbind)?;
             poss_expr =  Expr::Unary(op, Box::new(new_expr));
         }
+        // NOTE `()` is the empty tuple, unit
+        Some(SyntaxKind::LParen) if p.peek2() == Some(SyntaxKind::RParen) => {
+            p.consume();
+            p.consume();
+            poss_expr = Expr::Tuple(vec![]);
+        }
         Some(SyntaxKind::LParen) => {
             p.consume();
             let new_expr = expr_binding_power(p, 0)?;
-            p.expect(SyntaxKind::RParen)?;
-            poss_expr =  Expr::Grouping(Box::new(new_expr));
+            if p.peek() == Some(SyntaxKind::Comma) {
+                p.consume();
+                let mut items = vec![new_expr];
+                items.extend(exprs(p, SyntaxKind::RParen)?);
+                p.expect(SyntaxKind::RParen)?;
+                poss_expr = Expr::Tuple(items);
+            } else {
+                p.expect(SyntaxKind::RParen)?;
+                poss_expr =  Expr::Grouping(Box::new(new_expr));
+            }
         }
-        _ => {
+        Some(SyntaxKind::LBracket) => {
+            p.consume();
+            let items = exprs(p, SyntaxKind::RBracket)?;
+            let mut rest = None;
+            if p.peek() == Some(SyntaxKind::DotDot) {
+                p.consume();
+                rest = Some(Box::new(expr_binding_power(p, 0)?));
+                p.skip_newlines();
+            }
+            p.expect(SyntaxKind::RBracket)?;
+            poss_expr = Expr::List(items, rest);
+        }
+        Some(SyntaxKind::FnKw) => {
+            p.consume();
+            let (params, ret) = decl::signature(p)?;
+            // NOTE the body extends as far as possible, `fn(x) x + 1` adds to x
+            let body = expr_binding_power(p, 0)?;
+            let span = start..p.last_end;
+            poss_expr = Expr::Lambda(Rc::new(Function {
+                name: String::new(),
+                generics: vec![],
+                params,
+                ret,
+                requires: vec![],
+                ensures: vec![],
+                body,
+                span,
+            }));
+        }
+        Some(SyntaxKind::MatchKw) => {
+            p.consume();
+            poss_expr = match_expr(p, start)?;
+        }
+        Some(SyntaxKind::LBrace) => {
+            poss_expr = block(p)?;
+        }
+        Some(SyntaxKind::ThrowKw) => {
+            p.consume();
+            // NOTE like a lambda body, the thrown value extends as far as possible
+            poss_expr = Expr::Throw(Box::new(expr_binding_power(p, 0)?));
+        }
+        Some(SyntaxKind::TryKw) => {
+            p.consume();
+            poss_expr = try_expr(p)?;
+        }
+        Some(_) => {
             let(_, txt) = p.next();
-            return Err(Expr::Error(format!("expecting expr token, received {}", txt))); 
+            return Err(ParseError::new(format!("expecting expr token, received {:?}", txt))); 
         }
+        None => return Err(ParseError::new("expecting expr token, received end of input".to_string())),
     }
 
     loop {
@@ -84,6 +256,38 @@ fn expr_binding_power(p: &mut Parser, min_bind: u8) -> Result<Expr, ParseError>
             Some(SyntaxKind::Minus) => InfixOp::Sub,
             Some(SyntaxKind::Star) => InfixOp::Mul,
             Some(SyntaxKind::Slash) => InfixOp::Div,
+            Some(SyntaxKind::SlashSlash) => InfixOp::IntDiv,
+            Some(SyntaxKind::Percent) => InfixOp::Mod,
+            Some(SyntaxKind::StarStar) => InfixOp::Pow,
+            Some(SyntaxKind::BitAnd) => InfixOp::BitAnd,
+            Some(SyntaxKind::BitOr) => InfixOp::BitOr,
+            Some(SyntaxKind::Caret) => InfixOp::BitXor,
+            // NOTE shifts are two adjacent tokens, see the lexer
+            Some(SyntaxKind::LessThan) if p.peek_joined(SyntaxKind::LessThan) => InfixOp::Shl,
+            Some(SyntaxKind::GreaterThan) if p.peek_joined(SyntaxKind::GreaterThan) => InfixOp::Shr,
+            Some(SyntaxKind::Equal) => InfixOp::Eq,
+            Some(SyntaxKind::NotEqual) => InfixOp::NotEq,
+            Some(SyntaxKind::LessThan) => InfixOp::Lt,
+            Some(SyntaxKind::LessThanEq) => InfixOp::LtEq,
+            Some(SyntaxKind::GreaterThan) => InfixOp::Gt,
+            Some(SyntaxKind::GreaterThanEq) => InfixOp::GtEq,
+            Some(SyntaxKind::LParen) => {
+                if CALL_BINDING_POWER < min_bind {
+                    return Ok(poss_expr);
+                }
+                p.consume();
+                let args = call_args(p)?;
+                poss_expr = Expr::Call(Box::new(poss_expr), args, start..p.last_end);
+                continue;
+            }
+            Some(SyntaxKind::Question) => {
+                if CALL_BINDING_POWER < min_bind {
+                    return Ok(poss_expr);
+                }
+                p.consume();
+                poss_expr = Expr::Try(Box::new(poss_expr));
+                continue;
+            }
             _ => return Ok(poss_expr), // If it's not an op, we're done with the expr
         };
 
@@ -95,19 +299,117 @@ fn expr_binding_power(p: &mut Parser, min_bind: u8) -> Result<Expr, ParseError>
         }
 
         p.consume(); // consume the operator token
+        if matches!(op, InfixOp::Shl | InfixOp::Shr) {
+            p.consume();
+        }
         let rhs = expr_binding_power(p, rbind)?;
         poss_expr = Expr::Binary(Box::new(poss_expr), op, Box::new(rhs));
     }
 }
 
-// fn expect(sk: SyntaxKind, p: &mut Parser) -> Result<String, Expr> {
-//     if p.peek() != Some(sk) {
-//         return Err(Expr::Error(format!("Expected {:?}", sk)))
-//     } else {
-//         let (_, txt) = p.next();
-//         Ok(txt)
-//     }
-// }
+// parses the arguments of a call, the opening '(' has already been consumed
+fn call_args(p: &mut Parser) -> Result<Vec<Expr>, ParseError> {
+    let args = exprs(p, SyntaxKind::RParen)?;
+    p.expect(SyntaxKind::RParen)?;
+    Ok(args)
+}
+
+// comma separated expressions up to `close` or a `..rest`, a trailing comma is allowed
+fn exprs(p: &mut Parser, close: SyntaxKind) -> Result<Vec<Expr>, ParseError> {
+    let mut items = Vec::new();
+    loop {
+        p.skip_newlines();
+        if p.peek() == Some(close) || p.peek() == Some(SyntaxKind::DotDot) {
+            break;
+        }
+        items.push(expr_binding_power(p, 0)?);
+        p.skip_newlines();
+        match p.peek() {
+            Some(SyntaxKind::Comma) => p.consume(),
+            _ => break,
+        }
+    }
+    Ok(items)
+}
+
+// { decl ... expr }, the final declaration must be an expression
+pub(super) fn block(p: &mut Parser) -> Result<Expr, ParseError> {
+    p.expect(SyntaxKind::LBrace)?;
+    let mut decls = Vec::new();
+    loop {
+        p.skip_newlines();
+        if p.peek() == Some(SyntaxKind::RBrace) {
+            break;
+        }
+        decls.push(decl::parse(p)?);
+    }
+    p.expect(SyntaxKind::RBrace)?;
+    match decls.pop() {
+        Some(Decl::Stmt(bx_stmt)) => match *bx_stmt {
+            Stmt::Expr(expr) => Ok(Expr::Block(decls, expr)),
+        },
+        _ => Err(ParseError::new("a block must end with an expression".to_string())),
+    }
+}
+
+// match scrutinee { arm, ... }, the 'match' has already been consumed
+fn match_expr(p: &mut Parser, start: usize) -> Result<Expr, ParseError> {
+    let scrut = expr_binding_power(p, 0)?;
+    p.skip_newlines();
+    p.expect(SyntaxKind::LBrace)?;
+    let mut arms = Vec::new();
+    loop {
+        p.skip_newlines();
+        if p.peek() == Some(SyntaxKind::RBrace) {
+            break;
+        }
+        let arm_start = p.peek_offset();
+        let pattern = pattern::parse(p)?;
+        let mut guard = None;
+        if p.peek() == Some(SyntaxKind::IfKw) {
+            p.consume();
+            guard = Some(expr_binding_power(p, 0)?);
+        }
+        p.expect(SyntaxKind::FatArrow)?;
+        let body = expr_binding_power(p, 0)?;
+        arms.push(Arm { pattern, guard, body, span: arm_start..p.last_end });
+        // arms are separated by commas and/or newlines
+        match p.peek() {
+            Some(SyntaxKind::Comma) | Some(SyntaxKind::Newline) => p.consume(),
+            _ => break,
+        }
+    }
+    p.skip_newlines();
+    p.expect(SyntaxKind::RBrace)?;
+    Ok(Expr::Match(Box::new(scrut), arms, start..p.last_end))
+}
+
+// try { ... } catch e { ... } finally { ... }, the 'try' has already been consumed
+fn try_expr(p: &mut Parser) -> Result<Expr, ParseError> {
+    let bdy = block(p)?;
+    // NOTE a clause can start on the line after the '}' before it
+    let clause = |p: &mut Parser, kw: SyntaxKind| {
+        if p.peek() == Some(SyntaxKind::Newline) && p.peek2() == Some(kw) {
+            p.consume();
+        }
+        p.peek() == Some(kw)
+    };
+    let mut catch = None;
+    if clause(p, SyntaxKind::CatchKw) {
+        p.consume();
+        let name = p.expect(SyntaxKind::Ident)?.to_string();
+        catch = Some((name, Box::new(block(p)?)));
+    }
+    let mut finally = None;
+    if clause(p, SyntaxKind::FinallyKw) {
+        p.consume();
+        finally = Some(Box::new(block(p)?));
+    }
+    if catch.is_none() && finally.is_none() {
+        return Err(ParseError::new("a try needs a catch or a finally".to_string()));
+    }
+    Ok(Expr::TryCatch(Box::new(bdy), catch, finally))
+}
 
 #[cfg(test)]
 fn check(input: &str, to_check: Expr) {
@@ -120,7 +422,7 @@ mod tests {
 
     #[test]
     fn parse_num() {
-        check("5", Expr::Literal(Literal::NUMBER(5.0)));
+        check("5", Expr::Literal(Literal::INT(5)));
     }
 
     #[test]
@@ -131,9 +433,9 @@ mod tests {
     #[test]
     fn simple_binary() {
         check("1 + 2", 
-              Expr::Binary(Box::new(Expr::Literal(Literal::NUMBER(1.0))), 
+              Expr::Binary(Box::new(Expr::Literal(Literal::INT(1))), 
                            InfixOp::Add, 
-                           Box::new(Expr::Literal(Literal::NUMBER(2.0)))));  
+                           Box::new(Expr::Literal(Literal::INT(2)))));  
     }
 
     #[test]
@@ -141,23 +443,23 @@ mod tests {
         check("1 + 2 - 4", 
               Expr::Binary(
                   Box::new(Expr::Binary(
-                      Box::new(Expr::Literal(Literal::NUMBER(1.0))),
+                      Box::new(Expr::Literal(Literal::INT(1))),
                       InfixOp::Add, 
-                      Box::new(Expr::Literal(Literal::NUMBER(2.0))))),
+                      Box::new(Expr::Literal(Literal::INT(2))))),
                   InfixOp::Sub, 
-                  Box::new(Expr::Literal(Literal::NUMBER(4.0)))));
+                  Box::new(Expr::Literal(Literal::INT(4)))));
     }
 
     #[test]
     fn simple_precedence1() {
         check("1 + 2 * 4", 
               Expr::Binary(
-                  Box::new(Expr::Literal(Literal::NUMBER(1.0))),
+                  Box::new(Expr::Literal(Literal::INT(1))),
                   InfixOp::Add, 
                   Box::new(Expr::Binary(
-                      Box::new(Expr::Literal(Literal::NUMBER(2.0))),
+                      Box::new(Expr::Literal(Literal::INT(2))),
                       InfixOp::Mul, 
-                      Box::new(Expr::Literal(Literal::NUMBER(4.0)))))));
+                      Box::new(Expr::Literal(Literal::INT(4)))))));
     }
 
     #[test]
@@ -165,11 +467,11 @@ mod tests {
         check("1 * 2 - 4", 
             Expr::Binary(
                 Box::new(Expr::Binary(
-                    Box::new(Expr::Literal(Literal::NUMBER(1.0))),
+                    Box::new(Expr::Literal(Literal::INT(1))),
                     InfixOp::Mul,
-                    Box::new(Expr::Literal(Literal::NUMBER(2.0))))),
+                    Box::new(Expr::Literal(Literal::INT(2))))),
                 InfixOp::Sub,
-                Box::new(Expr::Literal(Literal::NUMBER(4.0)))));
+                Box::new(Expr::Literal(Literal::INT(4)))));
     }
 
     // rough ...
@@ -178,24 +480,24 @@ mod tests {
         check("1 * (2 + 2) / 4", 
               Expr::Binary(
                   Box::new(Expr::Binary(
-                          Box::new(Expr::Literal(Literal::NUMBER(1.0))),
+                          Box::new(Expr::Literal(Literal::INT(1))),
                           InfixOp::Mul,
                           Box::new(Expr::Grouping(
                                   Box::new(Expr::Binary(
-                                          Box::new(Expr::Literal(Literal::NUMBER(2.0))),
+                                          Box::new(Expr::Literal(Literal::INT(2))),
                                           InfixOp::Add,
-                                          Box::new(Expr::Literal(Literal::NUMBER(2.0))))))))),
+                                          Box::new(Expr::Literal(Literal::INT(2))))))))),
                   InfixOp::Div,
-                  Box::new(Expr::Literal(Literal::NUMBER(4.0)))));
+                  Box::new(Expr::Literal(Literal::INT(4)))));
     }
 
     #[test]
     fn unary_expr_minus() {
         check("-10 + 20", 
               Expr::Binary(
-                  Box::new(Expr::Unary(PrefixOp::Neg, Box::new(Expr::Literal(Literal::NUMBER(10.0))))),
+                  Box::new(Expr::Unary(PrefixOp::Neg, Box::new(Expr::Literal(Literal::INT(10))))),
                   InfixOp::Add,
-                  Box::new(Expr::Literal(Literal::NUMBER(20.0)))));
+                  Box::new(Expr::Literal(Literal::INT(20)))));
     }
     #[test]
     fn unary_expr_bang() {
@@ -205,4 +507,273 @@ mod tests {
                   InfixOp::Add,
                   Box::new(Expr::Literal(Literal::FALSE))));
     }
+
+    #[test]
+    fn comparison_binds_looser_than_arithmetic() {
+        check("1 + 2 < 4",
+              Expr::Binary(
+                  Box::new(Expr::Binary(
+                      Box::new(Expr::Literal(Literal::INT(1))),
+                      InfixOp::Add,
+                      Box::new(Expr::Literal(Literal::INT(2))))),
+                  InfixOp::Lt,
+                  Box::new(Expr::Literal(Literal::INT(4)))));
+    }
+
+    #[test]
+    fn constructor_call() {
+        check("Rect(1, 2)",
+              Expr::Call(
+                  Box::new(Expr::Literal(Literal::IDENT("Rect".to_string()))),
+                  vec![Expr::Literal(Literal::INT(1)), Expr::Literal(Literal::INT(2))],
+                  0..10));
+    }
+
+    #[test]
+    fn call_binds_tighter_than_negation() {
+        check("-f(1)",
+              Expr::Unary(PrefixOp::Neg, Box::new(Expr::Call(
+                  Box::new(Expr::Literal(Literal::IDENT("f".to_string()))),
+                  vec![Expr::Literal(Literal::INT(1))],
+                  1..5))));
+    }
+
+    #[test]
+    fn try_catch_finally() {
+        let expr = Expr::new(&mut Parser::new("try { f(x) }\ncatch e { throw e } finally { g() }"));
+        assert_eq!(expr.stringify(), "try { f(x) } catch e { throw e } finally { g() }");
+        let expr = Expr::new(&mut Parser::new("try { 1 } finally { 2 }"));
+        assert_eq!(expr.stringify(), "try { 1 } finally { 2 }");
+        assert!(parse(&mut Parser::new("try { 1 }")).is_err());
+    }
+
+    #[test]
+    fn throw_extends_right() {
+        let expr = Expr::new(&mut Parser::new("throw a + b"));
+        assert_eq!(parenthesize(&expr), "throw a + b");
+        assert!(matches!(expr, Expr::Throw(bdy) if matches!(*bdy, Expr::Binary(_, InfixOp::Add, _))));
+    }
+
+    #[test]
+    fn match_with_guard() {
+        check("match s { Circle(r) if r > 0 => r, _ => 0 }",
+              Expr::Match(
+                  Box::new(Expr::Literal(Literal::IDENT("s".to_string()))),
+                  vec![
+                      Arm {
+                          pattern: Pattern::Constructor("Circle".to_string(), vec![Pattern::Binding("r".to_string())]),
+                          guard: Some(Expr::Binary(
+                              Box::new(Expr::Literal(Literal::IDENT("r".to_string()))),
+                              InfixOp::Gt,
+                              Box::new(Expr::Literal(Literal::INT(0))))),
+                          body: Expr::Literal(Literal::IDENT("r".to_string())),
+                          span: 10..33,
+                      },
+                      Arm {
+                          pattern: Pattern::Wildcard,
+                          guard: None,
+                          body: Expr::Literal(Literal::INT(0)),
+                          span: 35..41,
+                      },
+                  ],
+                  0..43));
+    }
+
+    #[test]
+    fn block_expr() {
+        check("{ let x = 1\n x }",
+              Expr::Block(
+                  vec![Decl::Let("x".to_string(), None, Some(Expr::Literal(Literal::INT(1))), 2..11)],
+                  Box::new(Expr::Literal(Literal::IDENT("x".to_string())))));
+    }
+
+    #[test]
+    fn block_must_end_with_expr() {
+        assert!(parse(&mut Parser::new("{ let x = 1 }")).is_err());
+    }
+
+    #[test]
+    fn tuple_expr() {
+        check("(1, x)",
+              Expr::Tuple(vec![Expr::Literal(Literal::INT(1)), Expr::Literal(Literal::IDENT("x".to_string()))]));
+    }
+
+    #[test]
+    fn list_expr() {
+        check("[1, 2]",
+              Expr::List(vec![Expr::Literal(Literal::INT(1)), Expr::Literal(Literal::INT(2))], None));
+        check("[]", Expr::List(vec![], None));
+    }
+
+    #[test]
+    fn list_expr_with_rest() {
+        check("[x, ..xs]",
+              Expr::List(vec![Expr::Literal(Literal::IDENT("x".to_string()))],
+                         Some(Box::new(Expr::Literal(Literal::IDENT("xs".to_string()))))));
+    }
+
+    #[test]
+    fn lambda_body_extends_right() {
+        let expr = Expr::new(&mut Parser::new("fn(x, y: Int) x + y"));
+        assert_eq!(expr.stringify(), "fn(x, y: Int) x + y");
+        match expr {
+            Expr::Lambda(f) => assert_eq!(f.params.len(), 2),
+            _ => panic!("expected a lambda"),
+        }
+    }
+
+    #[test]
+    fn multiline_match() {
+        let expr = Expr::new(&mut Parser::new("match n {\n  0 => 'zero'\n  _ => 'many'\n}"));
+        assert_eq!(expr.stringify(), "match n { 0 => 'zero', _ => 'many' }");
+    }
+
+    // NOTE every operator application is wrapped in parens, so the tree's shape is visible
+    fn parenthesize(expr: &Expr) -> String {
+        match expr {
+            Expr::Unary(op, bdy) => format!("({}{})", op.stringify(), parenthesize(bdy)),
+            Expr::Binary(lhs, op, rhs) => format!("({} {} {})", parenthesize(lhs), op.stringify(), parenthesize(rhs)),
+            Expr::Grouping(bdy) => parenthesize(bdy),
+            Expr::Try(bdy) => format!("({}?)", parenthesize(bdy)),
+            Expr::Call(callee, args, _) => {
+                let args: Vec<String> = args.iter().map(parenthesize).collect();
+                format!("{}({})", parenthesize(callee), args.join(", "))
+            }
+            expr => expr.stringify(),
+        }
+    }
+
+    // from loosest to tightest: == != , < <= > >= , | , ^ , & , << >> , + - , * / // % , prefix , **
+    #[test]
+    fn precedence_table() {
+        let table = [
+            // each level binds tighter than the one before it
+            ("a == b < c", "(a == (b < c))"),
+            ("a < b | c", "(a < (b | c))"),
+            ("a | b ^ c", "(a | (b ^ c))"),
+            ("a ^ b & c", "(a ^ (b & c))"),
+            ("a & b << c", "(a & (b << c))"),
+            ("a >> b + c", "(a >> (b + c))"),
+            ("a + b * c", "(a + (b * c))"),
+            ("a - b // c", "(a - (b // c))"),
+            ("a + b % c", "(a + (b % c))"),
+            ("-a * b", "((-a) * b)"),
+            ("~a & b", "((~a) & b)"),
+            ("!a == b", "((!a) == b)"),
+            ("a * b ** c", "(a * (b ** c))"),
+            // and the other way around
+            ("a < b == c", "((a < b) == c)"),
+            ("a | b < c", "((a | b) < c)"),
+            ("a & b ^ c", "((a & b) ^ c)"),
+            ("a + b >> c", "((a + b) >> c)"),
+            ("a ** b * c", "((a ** b) * c)"),
+            // binary operators are left associative, except `**`
+            ("a - b - c", "((a - b) - c)"),
+            ("a / b * c", "((a / b) * c)"),
+            ("a // b % c", "((a // b) % c)"),
+            ("a << b >> c", "((a << b) >> c)"),
+            ("a | b | c", "((a | b) | c)"),
+            ("a ** b ** c", "(a ** (b ** c))"),
+            // `**` binds tighter than a prefix operator on its left, but not on its right
+            ("-a ** b", "(-(a ** b))"),
+            ("a ** -b", "(a ** (-b))"),
+            ("~a ** b", "(~(a ** b))"),
+            ("--a", "(-(-a))"),
+            // calls bind tightest
+            ("-f(x) ** 2", "(-(f(x) ** 2))"),
+            ("(a + b) * c", "((a + b) * c)"),
+            // and so does `?`, which can follow a call
+            ("-a?", "(-(a?))"),
+            ("a + b? * c", "(a + ((b?) * c))"),
+            ("f(x)?(y)?", "((f(x)?)(y)?)"),
+            ("a?? ** b", "(((a?)?) ** b)"),
+        ];
+        for (input, expected) in table {
+            let expr = Expr::new(&mut Parser::new(input));
+            assert_eq!(parenthesize(&expr), expected, "parsing {}", input);
+        }
+    }
+
+    #[test]
+    fn shifts_need_adjacent_tokens() {
+        assert!(parse(&mut Parser::new("a > > b")).is_err());
+        let expr = Expr::new(&mut Parser::new("a >= b"));
+        assert_eq!(parenthesize(&expr), "(a >= b)");
+    }
+
+    // the same tree without the parentheses it was written with
+    fn ungroup(expr: Expr) -> Expr {
+        let ungroup_box = |e: Box<Expr>| Box::new(ungroup(*e));
+        match expr {
+            Expr::Grouping(bdy) => ungroup(*bdy),
+            Expr::Unary(op, bdy) => Expr::Unary(op, ungroup_box(bdy)),
+            Expr::Binary(lhs, op, rhs) => Expr::Binary(ungroup_box(lhs), op, ungroup_box(rhs)),
+            Expr::Call(callee, args, span) => Expr::Call(ungroup_box(callee), args.into_iter().map(ungroup).collect(), span),
+            Expr::Try(bdy) => Expr::Try(ungroup_box(bdy)),
+            Expr::Throw(bdy) => Expr::Throw(ungroup_box(bdy)),
+            expr => expr,
+        }
+    }
+
+    #[test]
+    fn stringify_adds_the_fewest_parens() {
+        let table = [
+            ("(a + b) * c", "(a + b) * c"),
+            ("a + (b * c)", "a + b * c"),
+            ("(a - b) - c", "a - b - c"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("(a ** b) ** c", "(a ** b) ** c"),
+            ("a ** (b ** c)", "a ** b ** c"),
+            ("(-a) ** b", "(-a) ** b"),
+            ("-(a ** b)", "-a ** b"),
+            ("-(a + b)", "-(a + b)"),
+            ("-(-a)", "--a"),
+            ("(a + b)(c)", "(a + b)(c)"),
+            ("(-f)(x)", "(-f)(x)"),
+            ("-(f(x))", "-f(x)"),
+            ("(a * b)?", "(a * b)?"),
+            ("(fn(x) x)(1)", "(fn(x) x)(1)"),
+            ("(fn(x) x) + 1", "(fn(x) x) + 1"),
+            ("1 + (fn(x) x)", "1 + fn(x) x"),
+            ("(throw e) * 2", "(throw e) * 2"),
+            ("f((a + b))", "f(a + b)"),
+        ];
+        for (input, expected) in table {
+            let expr = ungroup(Expr::new(&mut Parser::new(input)));
+            assert_eq!(expr.stringify(), expected, "printing {}", input);
+        }
+    }
+
+    #[test]
+    fn stringified_trees_parse_back_the_same() {
+        let inputs = ["a == b < c", "a < b == c", "a - b - c", "a ** b ** c", "-a ** b", "a ** -b", "--a",
+            "-f(x) ** 2", "(a + b) * c", "-a?", "a + b? * c", "f(x)?(y)?", "a?? ** b", "(-a) ** (b + c)",
+            "(a | b) & (c ^ d)", "-(a << b) >> (c - d)", "((a + b)(c) - d)?", "(fn(x) x + 1)(2) * 3",
+            "2 * (fn(x) x) + 1", "((throw a) - b)?"];
+        for input in inputs {
+            let expr = ungroup(Expr::new(&mut Parser::new(input)));
+            let printed = expr.stringify();
+            let parsed = Expr::new(&mut Parser::new(&printed));
+            assert_eq!(parenthesize(&parsed), parenthesize(&expr), "{} printed as {}", input, printed);
+        }
+    }
+
+    #[test]
+    fn unit_expr() {
+        check("()", Expr::Tuple(vec![]));
+        check("f(())", Expr::Call(
+            Box::new(Expr::Literal(Literal::IDENT("f".to_string()))),
+            vec![Expr::Tuple(vec![])],
+            0..5));
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let input = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        let err = parse(&mut Parser::new(&input)).unwrap_err();
+        assert_eq!(err.to_string(), "MSG: expression too deeply nested");
+        let input = format!("{}1{}", "(".repeat(10), ")".repeat(10));
+        assert!(parse(&mut Parser::new(&input).with_max_depth(10)).is_err());
+        assert!(parse(&mut Parser::new(&input).with_max_depth(11)).is_ok());
+    }
 }
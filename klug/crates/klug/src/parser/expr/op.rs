@@ -1,51 +1,91 @@
 use super::*;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum InfixOp {
     Add,
     Sub,
     Mul,
     Div,
+    // `//`, integer division rounding towards negative infinity
+    IntDiv,
+    Mod,
+    // `**`, right associative
+    Pow,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
 }
 
 impl InfixOp {
-    pub(super) fn binding_power(&self) -> (u8, u8) {
+    pub(crate) fn binding_power(&self) -> (u8, u8) {
         match self {
-            Self::Add | Self::Sub => (1, 2),
-            Self::Mul | Self::Div => (3, 4),
+            Self::Eq | Self::NotEq => (1, 2),
+            Self::Lt | Self::LtEq | Self::Gt | Self::GtEq => (3, 4),
+            Self::BitOr => (5, 6),
+            Self::BitXor => (7, 8),
+            Self::BitAnd => (9, 10),
+            Self::Shl | Self::Shr => (11, 12),
+            Self::Add | Self::Sub => (13, 14),
+            Self::Mul | Self::Div | Self::IntDiv | Self::Mod => (15, 16),
+            // NOTE binds tighter than a prefix operator on its left, `-2 ** 2` is `-(2 ** 2)`
+            Self::Pow => (20, 19),
         }
     }
 
-    pub(super) fn stringify(&self) -> String {
+    pub(crate) fn stringify(&self) -> String {
         let s = match self {
             Self::Add  => "+",
             Self::Sub => "-",
             Self::Mul  => "*",
             Self::Div => "/",
+            Self::IntDiv => "//",
+            Self::Mod => "%",
+            Self::Pow => "**",
+            Self::BitAnd => "&",
+            Self::BitOr => "|",
+            Self::BitXor => "^",
+            Self::Shl => "<<",
+            Self::Shr => ">>",
+            Self::Eq => "==",
+            Self::NotEq => "!=",
+            Self::Lt => "<",
+            Self::LtEq => "<=",
+            Self::Gt => ">",
+            Self::GtEq => ">=",
         };
         s.to_string()
     }
 }
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum PrefixOp {
     Neg,
     Not,
+    // `~`, bitwise complement
+    BitNot,
 }
 
 impl PrefixOp {
-    pub(super) fn binding_power(&self) -> ((), u8) {
+    pub(crate) fn binding_power(&self) -> ((), u8) {
         match self {
-            Self::Neg => ((), 5),
-            Self::Not => ((), 5)
+            Self::Neg | Self::Not | Self::BitNot => ((), 17),
         }
     }
 
-    pub(super) fn stringify(&self) -> String {
+    pub(crate) fn stringify(&self) -> String {
         let s = match self {
             Self::Neg => "-",
             Self::Not => "!",
+            Self::BitNot => "~",
         };
         s.to_string()
     }
@@ -54,8 +94,8 @@ impl PrefixOp {
         match sk {
             SyntaxKind::Bang => Self::Not,
             SyntaxKind::Minus => Self::Neg,
+            SyntaxKind::Tilde => Self::BitNot,
             _ => unreachable!(),
         }
     }
 }
-
@@ -1,8 +1,14 @@
+use num_bigint::BigInt;
 use crate::lexer::SyntaxKind;
+use super::ParseError;
 
-#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Literal {
-    NUMBER(f64),
+    INT(i64),
+    // an integer literal that doesn't fit in an i64
+    BIG(BigInt),
+    FLOAT(f64),
     IDENT(String),
     STRING(String),
     TRUE,
@@ -11,23 +17,37 @@ pub(crate) enum Literal {
 
 // create a new literal from a string
 impl Literal {
-    pub(crate) fn new(sk: SyntaxKind, s: &str) -> Self {
-        match sk {
+    pub(crate) fn new(sk: SyntaxKind, s: &str) -> Result<Self, ParseError> {
+        Ok(match sk {
             SyntaxKind::TrueKw => Self::TRUE,
             SyntaxKind::FalseKw => Self::FALSE,
             SyntaxKind::Ident => Self::IDENT(s.to_string()),
-            SyntaxKind::Number => Self::NUMBER(s.parse::<f64>().unwrap()),
+            SyntaxKind::Int => match s.parse::<i64>() {
+                Ok(n) => Self::INT(n),
+                Err(_) => Self::BIG(s.parse::<BigInt>().map_err(|_| {
+                    ParseError::new(format!("invalid integer literal {}", s))
+                })?),
+            },
+            // NOTE one too big for an f64 parses to inf, which can't be written back
+            SyntaxKind::Float => match s.parse::<f64>() {
+                Ok(n) if n.is_finite() => Self::FLOAT(n),
+                Ok(_) => return Err(ParseError::new(format!("float literal {} is too large", s))),
+                Err(_) => return Err(ParseError::new(format!("invalid float literal {}", s))),
+            },
             // NOTE strip off the '...' wrapping the string
             SyntaxKind::StringKw => Self::STRING(s[1..s.len()-1].to_string()),
             _ => unreachable!(),
-        }
+        })
     }
 
     pub(super) fn stringify(&self) -> String {
         match self {
-           Self::NUMBER(n) => n.to_string(),
+           Self::INT(n) => n.to_string(),
+           Self::BIG(n) => n.to_string(),
+           // NOTE Debug keeps the '.0' of whole floats, so they don't look like ints
+           Self::FLOAT(n) => format!("{:?}", n),
            Self::IDENT(s) => (*s).clone(),
-           Self::STRING(s) => (*s).clone(),
+           Self::STRING(s) => format!("'{}'", s),
            Self::TRUE => "true".to_string(),
            Self::FALSE => "false".to_string(),
         }
@@ -0,0 +1,207 @@
+use std::fmt;
+use super::literal::Literal;
+use super::{Parser, ParseError};
+use crate::lexer::SyntaxKind;
+
+// NOTE capitalized identifiers are constructors, everything else binds a name
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Pattern {
+    Wildcard,
+    Binding(String),
+    Literal(Literal),
+    Constructor(String, Vec<Pattern>),
+    Tuple(Vec<Pattern>),
+    // [p, ...] or [p, ..rest]
+    List(Vec<Pattern>, Option<Box<Pattern>>),
+}
+
+impl Pattern {
+    pub(crate) fn stringify(&self) -> String {
+        match self {
+            Self::Wildcard => "_".to_string(),
+            Self::Binding(name) => name.clone(),
+            Self::Literal(Literal::STRING(s)) => format!("'{}'", s),
+            Self::Literal(lit) => lit.stringify(),
+            Self::Constructor(name, args) if args.is_empty() => name.clone(),
+            Self::Constructor(name, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.stringify()).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            Self::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|i| i.stringify()).collect();
+                format!("({})", items.join(", "))
+            }
+            Self::List(items, rest) => {
+                let mut items: Vec<String> = items.iter().map(|i| i.stringify()).collect();
+                if let Some(rest) = rest {
+                    items.push(format!("..{}", rest.stringify()));
+                }
+                format!("[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.stringify())
+    }
+}
+
+pub(crate) fn is_constructor_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+pub(super) fn parse(p: &mut Parser) -> Result<Pattern, ParseError> {
+    p.nested(pattern)
+}
+
+fn pattern(p: &mut Parser) -> Result<Pattern, ParseError> {
+    match p.peek() {
+        Some(SyntaxKind::Underscore) => {
+            p.consume();
+            Ok(Pattern::Wildcard)
+        }
+        Some(SyntaxKind::Int)
+        | Some(SyntaxKind::Float)
+        | Some(SyntaxKind::StringKw)
+        | Some(SyntaxKind::TrueKw)
+        | Some(SyntaxKind::FalseKw) => {
+            let (sk, txt) = p.next();
+            Ok(Pattern::Literal(Literal::new(sk, txt)?))
+        }
+        Some(SyntaxKind::Minus) => {
+            p.consume();
+            // NOTE the sign is parsed with the digits so `-9223372036854775808` fits
+            let sk = match p.peek() {
+                Some(SyntaxKind::Float) => SyntaxKind::Float,
+                _ => SyntaxKind::Int,
+            };
+            let txt = p.expect(sk)?;
+            Ok(Pattern::Literal(Literal::new(sk, &format!("-{}", txt))?))
+        }
+        Some(SyntaxKind::Ident) => {
+            let (_, name) = p.next();
+            if !is_constructor_name(name) {
+                return Ok(Pattern::Binding(name.to_string()));
+            }
+            let mut args = Vec::new();
+            if p.peek() == Some(SyntaxKind::LParen) {
+                p.consume();
+                args = patterns(p, SyntaxKind::RParen)?;
+                p.expect(SyntaxKind::RParen)?;
+            }
+            Ok(Pattern::Constructor(name.to_string(), args))
+        }
+        Some(SyntaxKind::LParen) => {
+            p.consume();
+            let mut items = patterns(p, SyntaxKind::RParen)?;
+            p.expect(SyntaxKind::RParen)?;
+            // NOTE a single pattern in parentheses is just grouped
+            match items.len() {
+                1 => Ok(items.remove(0)),
+                _ => Ok(Pattern::Tuple(items)),
+            }
+        }
+        Some(SyntaxKind::LBracket) => {
+            p.consume();
+            let items = patterns(p, SyntaxKind::RBracket)?;
+            let mut rest = None;
+            if p.peek() == Some(SyntaxKind::DotDot) {
+                p.consume();
+                rest = Some(Box::new(parse(p)?));
+            }
+            p.expect(SyntaxKind::RBracket)?;
+            Ok(Pattern::List(items, rest))
+        }
+        Some(_) => {
+            let (_, txt) = p.next();
+            Err(ParseError::new(format!("expecting pattern, received {:?}", txt)))
+        }
+        None => Err(ParseError::new("expecting pattern, received end of input".to_string())),
+    }
+}
+
+// comma separated patterns up to `close` or a `..rest`, a trailing comma is allowed
+fn patterns(p: &mut Parser, close: SyntaxKind) -> Result<Vec<Pattern>, ParseError> {
+    let mut pats = Vec::new();
+    while p.peek() != Some(close) && p.peek() != Some(SyntaxKind::DotDot) {
+        pats.push(parse(p)?);
+        match p.peek() {
+            Some(SyntaxKind::Comma) => p.consume(),
+            _ => break,
+        }
+    }
+    Ok(pats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &str, to_check: Pattern) {
+        assert_eq!(parse(&mut Parser::new(input)).unwrap(), to_check);
+    }
+
+    #[test]
+    fn parse_wildcard() {
+        check("_", Pattern::Wildcard);
+    }
+
+    #[test]
+    fn parse_binding() {
+        check("radius", Pattern::Binding("radius".to_string()));
+    }
+
+    #[test]
+    fn parse_negative_number() {
+        check("-3", Pattern::Literal(Literal::INT(-3)));
+    }
+
+    #[test]
+    fn parse_most_negative_int() {
+        check("-9223372036854775808", Pattern::Literal(Literal::INT(i64::MIN)));
+    }
+
+    #[test]
+    fn parse_negative_float() {
+        check("-2.5", Pattern::Literal(Literal::FLOAT(-2.5)));
+    }
+
+    #[test]
+    fn parse_nullary_constructor() {
+        check("Empty", Pattern::Constructor("Empty".to_string(), vec![]));
+    }
+
+    #[test]
+    fn parse_tuple() {
+        check("(a, _)", Pattern::Tuple(vec![Pattern::Binding("a".to_string()), Pattern::Wildcard]));
+    }
+
+    #[test]
+    fn parse_grouped() {
+        check("(a)", Pattern::Binding("a".to_string()));
+    }
+
+    #[test]
+    fn parse_empty_list() {
+        check("[]", Pattern::List(vec![], None));
+    }
+
+    #[test]
+    fn parse_list_with_rest() {
+        check("[x, ..xs]",
+              Pattern::List(vec![Pattern::Binding("x".to_string())],
+                            Some(Box::new(Pattern::Binding("xs".to_string())))));
+    }
+
+    #[test]
+    fn parse_nested_constructor() {
+        check("Node(Leaf, v, _)",
+              Pattern::Constructor("Node".to_string(), vec![
+                  Pattern::Constructor("Leaf".to_string(), vec![]),
+                  Pattern::Binding("v".to_string()),
+                  Pattern::Wildcard,
+              ]));
+    }
+}
@@ -1,27 +1,22 @@
-use std::fmt;
-use super::expr::Expr;
-use super::Parser;
-use crate::lexer::SyntaxKind;
+use super::expr::{self, Expr};
+use super::{Parser, ParseError};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Stmt {
     Expr(Box<Expr>),
-    Error(String),
 }
 
 impl Stmt {
-    pub(super) fn new(p: &mut Parser) -> Self {
-        if let Ok(stmt) = new_stmt(p) {
-            stmt
-        } else {
-            Self::Error("TODO".to_string()) // TODO actually report the error
+    pub(crate) fn stringify(&self) -> String {
+        match self {
+            Self::Expr(expr) => expr.stringify(),
         }
     }
 }
 
-fn new_stmt(p: &mut Parser) -> Result<Stmt, Stmt>  {
+pub(super) fn parse(p: &mut Parser) -> Result<Stmt, ParseError>  {
     // TODO actually parse statements
-//    match p.peek() {
-//    }
-    Ok( Stmt::Expr(Box::new(Expr::new(p))) )
+    let stmt = Stmt::Expr(Box::new(expr::parse(p)?));
+    p.expect_end()?;
+    Ok(stmt)
 }
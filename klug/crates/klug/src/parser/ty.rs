@@ -0,0 +1,159 @@
+use std::fmt;
+use super::{Parser, ParseError};
+use crate::lexer::SyntaxKind;
+
+// A type annotation as written in the source, e.g. `(Int, Str) -> Bool`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Type {
+    // a type with its arguments, if any, e.g. `Int` or `Option<T>`
+    Named(String, Vec<Type>),
+    Fn(Vec<Type>, Box<Type>),
+    Tuple(Vec<Type>),
+}
+
+impl Type {
+    pub(crate) fn stringify(&self) -> String {
+        match self {
+            Self::Named(name, args) if args.is_empty() => name.clone(),
+            Self::Named(name, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.stringify()).collect();
+                format!("{}<{}>", name, args.join(", "))
+            }
+            Self::Fn(params, ret) if params.len() == 1 && !matches!(params[0], Self::Fn(_, _)) => {
+                format!("{} -> {}", params[0].stringify(), ret.stringify())
+            }
+            Self::Fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.stringify()).collect();
+                format!("({}) -> {}", params.join(", "), ret.stringify())
+            }
+            Self::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|i| i.stringify()).collect();
+                format!("({})", items.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.stringify())
+    }
+}
+
+// NOTE '->' is right associative, `Int -> Int -> Int` is `Int -> (Int -> Int)`
+pub(super) fn parse(p: &mut Parser) -> Result<Type, ParseError> {
+    p.nested(ty)
+}
+
+fn ty(p: &mut Parser) -> Result<Type, ParseError> {
+    let mut params = match p.peek() {
+        Some(SyntaxKind::Ident) => {
+            let (_, name) = p.next();
+            let mut args = Vec::new();
+            if p.peek() == Some(SyntaxKind::LessThan) {
+                p.consume();
+                loop {
+                    args.push(parse(p)?);
+                    match p.peek() {
+                        Some(SyntaxKind::Comma) => p.consume(),
+                        _ => break,
+                    }
+                }
+                p.expect(SyntaxKind::GreaterThan)?;
+            }
+            vec![Type::Named(name.to_string(), args)]
+        }
+        Some(SyntaxKind::LParen) => {
+            p.consume();
+            let mut tys = Vec::new();
+            if p.peek() != Some(SyntaxKind::RParen) {
+                loop {
+                    tys.push(parse(p)?);
+                    match p.peek() {
+                        Some(SyntaxKind::Comma) => p.consume(),
+                        _ => break,
+                    }
+                }
+            }
+            p.expect(SyntaxKind::RParen)?;
+            tys
+        }
+        Some(_) => {
+            let (_, txt) = p.next();
+            return Err(ParseError::new(format!("expecting type, received {:?}", txt)));
+        }
+        None => return Err(ParseError::new("expecting type, received end of input".to_string())),
+    };
+
+    if p.peek() == Some(SyntaxKind::Arrow) {
+        p.consume();
+        let ret = parse(p)?;
+        return Ok(Type::Fn(params, Box::new(ret)));
+    }
+    // NOTE without an arrow the parenthesized types are a tuple
+    match params.len() {
+        1 => Ok(params.remove(0)),
+        _ => Ok(Type::Tuple(params)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &str, to_check: Type) {
+        assert_eq!(parse(&mut Parser::new(input)).unwrap(), to_check);
+    }
+
+    fn named(name: &str) -> Type {
+        Type::Named(name.to_string(), vec![])
+    }
+
+    #[test]
+    fn parse_named() {
+        check("Int", named("Int"));
+    }
+
+    #[test]
+    fn parse_arrow_is_right_associative() {
+        check("Int -> Str -> Bool",
+              Type::Fn(vec![named("Int")], Box::new(Type::Fn(vec![named("Str")], Box::new(named("Bool"))))));
+    }
+
+    #[test]
+    fn parse_multi_param_fn() {
+        check("(Int, Str) -> Bool", Type::Fn(vec![named("Int"), named("Str")], Box::new(named("Bool"))));
+    }
+
+    #[test]
+    fn parse_tuple() {
+        check("(Int, Str)", Type::Tuple(vec![named("Int"), named("Str")]));
+    }
+
+    #[test]
+    fn parse_type_arguments() {
+        check("Result<List<A>, Str>",
+              Type::Named("Result".to_string(), vec![
+                  Type::Named("List".to_string(), vec![named("A")]),
+                  named("Str"),
+              ]));
+    }
+
+    #[test]
+    fn parse_generic_fn_type() {
+        let ty = parse(&mut Parser::new("(List<A>, A -> B) -> List<B>")).unwrap();
+        assert_eq!(ty.stringify(), "(List<A>, A -> B) -> List<B>");
+    }
+
+    #[test]
+    fn parse_higher_order_fn() {
+        let ty = parse(&mut Parser::new("(Int -> Int) -> Int")).unwrap();
+        assert_eq!(ty.stringify(), "(Int -> Int) -> Int");
+    }
+
+    #[test]
+    fn parse_nested_type_arguments() {
+        check("List<List<Int>>",
+              Type::Named("List".to_string(), vec![Type::Named("List".to_string(), vec![named("Int")])]));
+    }
+}
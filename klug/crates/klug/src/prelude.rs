@@ -0,0 +1,34 @@
+// prelude - the declarations every program starts with
+//
+// NOTE `?` and the builtins that can fail use these, e.g. `parse_int('12')`
+// is `Ok(12)`, `catch` is given an `Error(kind, message, span)`, and a
+// program can shadow them like any other declaration
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::interp::value::Ctor;
+use crate::parser::{decl::Decl, Parse, Parser};
+
+const PRELUDE: &str = "\
+enum Option<T> { Some(T), None }
+enum Result<T, E> { Ok(T), Err(E) }
+enum Error { Error(Str, Str, Option<(Int, Int)>) }
+";
+
+thread_local! {
+    static CTORS: HashMap<String, Rc<Ctor>> = parse().declarations.iter()
+        .flat_map(|decl| match decl {
+            Decl::Enum(ty, generics, variants) => variants.iter().map(|v| (v.name.clone(), Ctor::new(ty, generics, v))).collect(),
+            _ => vec![],
+        })
+        .collect();
+}
+
+pub(crate) fn parse() -> Parse {
+    Parser::new(PRELUDE).parse()
+}
+
+// the prelude's variant `name`, for the values made by the runtime, e.g. `Some`
+pub(crate) fn ctor(name: &str) -> Rc<Ctor> {
+    CTORS.with(|ctors| ctors[name].clone())
+}
@@ -0,0 +1,300 @@
+// pretty - prints syntax trees back as source
+//
+// What's printed parses back to the same tree, spans aside. Nothing is left
+// out, e.g. a `Grouping` is printed as the parentheses it was parsed from, and
+// literals are written the way the lexer reads them, so strings are quoted and
+// floats always have a '.'. A tree that wasn't parsed, without a `Grouping`
+// wherever precedence calls for one, gets the fewest parentheses that parse
+// back to it, see `Expr::needs_parens`. Blocks with declarations are laid out
+// over several lines, and anything else that doesn't fit in `doc::WIDTH` is
+// broken where the parser allows a newline, see `doc`.
+// NOTE `stringify` is what error messages use, it stays on one line
+
+pub(crate) mod doc;
+
+use crate::parser::{
+    contract::{Clause, Contract},
+    decl::{Decl, Field, Function, Variant},
+    expr::{Arm, Expr, CALL_BINDING_POWER},
+    literal::Literal,
+    pattern::Pattern,
+    stmt::Stmt,
+    ty::Type,
+};
+use doc::{text, Doc};
+
+#[cfg(test)]
+pub(crate) fn program(decls: &[Decl]) -> String {
+    doc::render(&document(decls))
+}
+
+// every declaration on its own line
+pub(crate) fn document(decls: &[Decl]) -> Doc {
+    Doc::Concat(decls.iter().flat_map(|d| [decl(d), Doc::HardLine]).collect())
+}
+
+fn decl(d: &Decl) -> Doc {
+    match d {
+        Decl::Let(name, ty, expr, _) => {
+            let ty = ty.as_ref().map_or(String::new(), |t| format!(": {}", self::ty(t)));
+            let mut docs = vec![text(format!("let {}{}", name, ty))];
+            if let Some(e) = expr {
+                docs.extend([text(" = "), self::expr(e)]);
+            }
+            Doc::Concat(docs)
+        }
+        Decl::Fn(f) => function(f),
+        Decl::Enum(name, generics, variants) => {
+            let variants = variants.iter().map(|v| text(variant(v))).collect();
+            Doc::Concat(vec![
+                text(format!("enum {}{} ", name, self::generics(generics))),
+                bracketed("{", variants, text(","), Doc::Line, Doc::Concat(vec![]), "}"),
+            ])
+        }
+        Decl::Stmt(stmt) => match &**stmt {
+            Stmt::Expr(e) => expr(e),
+        },
+        Decl::Error(msg) => text(msg.clone()),
+    }
+}
+
+// `items` separated by `sep` between `open` and `close`, either all on one
+// line with `line` inside the brackets, or each on its own line followed by `last`
+fn bracketed(open: &str, items: Vec<Doc>, sep: Doc, line: Doc, last: Doc, close: &str) -> Doc {
+    if items.is_empty() {
+        return text(format!("{}{}", open, close));
+    }
+    let mut inner = vec![line.clone()];
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            inner.extend([sep.clone(), Doc::Line]);
+        }
+        inner.push(item);
+    }
+    inner.push(last);
+    Doc::Group(vec![text(open), Doc::Nest(inner), line, text(close)])
+}
+
+// NOTE a trailing comma is allowed in calls, lists and tuples, but not after `..rest`
+fn args(open: &str, items: Vec<Doc>, close: &str) -> Doc {
+    bracketed(open, items, text(","), Doc::SoftLine, Doc::Broken(","), close)
+}
+
+fn variant(v: &Variant) -> String {
+    if v.fields.is_empty() {
+        return v.name.clone();
+    }
+    let fields: Vec<String> = v.fields.iter().map(|Field { name, ty }| match (name, ty) {
+        (Some(name), Some(t)) => format!("{}: {}", name, self::ty(t)),
+        (Some(name), None) => name.clone(),
+        (None, Some(t)) => self::ty(t),
+        (None, None) => String::new(),
+    }).collect();
+    format!("{}({})", v.name, fields.join(", "))
+}
+
+fn generics(generics: &[String]) -> String {
+    match generics {
+        [] => String::new(),
+        _ => format!("<{}>", generics.join(", ")),
+    }
+}
+
+fn function(f: &Function) -> Doc {
+    let params: Vec<String> = f.params.iter().map(|param| match &param.ty {
+        Some(t) => format!("{}: {}", param.name, ty(t)),
+        None => param.name.clone(),
+    }).collect();
+    let mut sig = match &f.name[..] {
+        "" => format!("fn({})", params.join(", ")),
+        name => format!("fn {}{}({})", name, generics(&f.generics), params.join(", ")),
+    };
+    if let Some(t) = &f.ret {
+        sig.push_str(&format!(" -> {}", ty(t)));
+    }
+    let mut docs = vec![text(sig)];
+    for (kw, clauses) in [("requires", &f.requires), ("ensures", &f.ensures)] {
+        for (i, c) in clauses.iter().enumerate() {
+            docs.push(text(if i == 0 { format!(" {} ", kw) } else { ", ".to_string() }));
+            docs.push(clause(c));
+        }
+    }
+    docs.extend([text(" "), expr(&f.body)]);
+    Doc::Concat(docs)
+}
+
+fn expr(e: &Expr) -> Doc {
+    operand(e, 0, 0)
+}
+
+// `e` between operators binding `left` and `right` tightly, 0 when there's none
+fn operand(e: &Expr, left: u8, right: u8) -> Doc {
+    if e.needs_parens(left, right) {
+        return Doc::Concat(vec![text("("), expr(e), text(")")]);
+    }
+    match e {
+        Expr::Unary(op, inner) => Doc::Concat(vec![text(op.stringify()), operand(inner, op.binding_power().1, right)]),
+        // NOTE the parser skips newlines before an operand, so a long expression breaks after an operator
+        Expr::Binary(lhs, op, rhs) => {
+            let (lbind, rbind) = op.binding_power();
+            Doc::Group(vec![
+                operand(lhs, left, lbind),
+                text(format!(" {}", op.stringify())),
+                Doc::Nest(vec![Doc::Line, operand(rhs, rbind, right)]),
+            ])
+        }
+        Expr::Grouping(inner) => Doc::Concat(vec![text("("), expr(inner), text(")")]),
+        Expr::Literal(lit) => text(literal(lit)),
+        Expr::Call(callee, items, _) => {
+            Doc::Concat(vec![operand(callee, left, CALL_BINDING_POWER), args("(", exprs(items), ")")])
+        }
+        // NOTE arms are separated by commas on one line, and by newlines otherwise
+        Expr::Match(scrut, arms, _) => {
+            let arms = arms.iter().map(|Arm { pattern, guard, body, .. }| {
+                let mut docs = vec![text(self::pattern(pattern))];
+                if let Some(guard) = guard {
+                    docs.extend([text(" if "), expr(guard)]);
+                }
+                docs.extend([text(" => "), expr(body)]);
+                Doc::Concat(docs)
+            }).collect();
+            Doc::Concat(vec![
+                text("match "),
+                expr(scrut),
+                text(" "),
+                bracketed("{", arms, Doc::Flat(","), Doc::Line, Doc::Concat(vec![]), "}"),
+            ])
+        }
+        Expr::Block(decls, last) if decls.is_empty() => Doc::Group(vec![
+            text("{"),
+            Doc::Nest(vec![Doc::Line, expr(last)]),
+            Doc::Line,
+            text("}"),
+        ]),
+        Expr::Block(decls, last) => {
+            let mut inner = Vec::new();
+            for d in decls {
+                inner.extend([Doc::HardLine, decl(d)]);
+            }
+            inner.extend([Doc::HardLine, expr(last)]);
+            Doc::Concat(vec![text("{"), Doc::Nest(inner), Doc::HardLine, text("}")])
+        }
+        Expr::Lambda(f) => function(f),
+        Expr::Tuple(items) if items.len() == 1 => {
+            bracketed("(", exprs(items), text(","), Doc::SoftLine, text(","), ")")
+        }
+        Expr::Tuple(items) => args("(", exprs(items), ")"),
+        Expr::List(items, None) => args("[", exprs(items), "]"),
+        Expr::List(items, Some(rest)) => {
+            let mut items = exprs(items);
+            items.push(Doc::Concat(vec![text(".."), expr(rest)]));
+            bracketed("[", items, text(","), Doc::SoftLine, Doc::Concat(vec![]), "]")
+        }
+        Expr::Try(inner) => Doc::Concat(vec![operand(inner, left, CALL_BINDING_POWER), text("?")]),
+        Expr::Throw(inner) => Doc::Concat(vec![text("throw "), expr(inner)]),
+        // NOTE a clause can start on the line after the `}` before it, so each
+        // gets a break of its own for a comment at the end of that line, see `fmt`
+        Expr::TryCatch(body, catch, finally) => {
+            let mut docs = vec![text("try "), expr(body)];
+            if let Some((name, handler)) = catch {
+                docs.extend([Doc::Group(vec![Doc::Line]), text(format!("catch {} ", name)), expr(handler)]);
+            }
+            if let Some(finally) = finally {
+                docs.extend([Doc::Group(vec![Doc::Line]), text("finally "), expr(finally)]);
+            }
+            Doc::Concat(docs)
+        }
+    }
+}
+
+fn exprs(items: &[Expr]) -> Vec<Doc> {
+    items.iter().map(expr).collect()
+}
+
+// NOTE unlike in an expression, a literal in a pattern can be negative
+fn pattern(p: &Pattern) -> String {
+    match p {
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Binding(name) => name.clone(),
+        Pattern::Literal(lit) => literal(lit),
+        Pattern::Constructor(name, args) if args.is_empty() => name.clone(),
+        Pattern::Constructor(name, args) => format!("{}({})", name, patterns(args)),
+        Pattern::Tuple(items) => format!("({})", patterns(items)),
+        Pattern::List(items, rest) => {
+            let mut items: Vec<String> = items.iter().map(pattern).collect();
+            if let Some(rest) = rest {
+                items.push(format!("..{}", pattern(rest)));
+            }
+            format!("[{}]", items.join(", "))
+        }
+    }
+}
+
+fn patterns(items: &[Pattern]) -> String {
+    items.iter().map(pattern).collect::<Vec<_>>().join(", ")
+}
+
+fn literal(lit: &Literal) -> String {
+    match lit {
+        Literal::INT(n) => n.to_string(),
+        Literal::BIG(n) => n.to_string(),
+        // NOTE Display never uses an exponent, which the lexer can't read
+        Literal::FLOAT(n) => match n.to_string() {
+            s if s.contains('.') => s,
+            s => format!("{}.0", s),
+        },
+        Literal::IDENT(name) => name.clone(),
+        Literal::STRING(s) => format!("'{}'", s),
+        Literal::TRUE => "true".to_string(),
+        Literal::FALSE => "false".to_string(),
+    }
+}
+
+// NOTE only a single named parameter goes without parentheses, `((A, B)) -> C`
+// takes a pair where `(A, B) -> C` takes two arguments
+fn ty(t: &Type) -> String {
+    match t {
+        Type::Named(name, args) if args.is_empty() => name.clone(),
+        Type::Named(name, args) => format!("{}<{}>", name, types(args)),
+        Type::Fn(params, ret) => match &params[..] {
+            [param @ Type::Named(..)] => format!("{} -> {}", ty(param), ty(ret)),
+            _ => format!("({}) -> {}", types(params), ty(ret)),
+        },
+        Type::Tuple(items) => format!("({})", types(items)),
+    }
+}
+
+fn types(items: &[Type]) -> String {
+    items.iter().map(ty).collect::<Vec<_>>().join(", ")
+}
+
+fn clause(c: &Clause) -> Doc {
+    match c {
+        Clause::Holds(e) => expr(e),
+        Clause::Satisfies(name, contract) => Doc::Concat(vec![text(format!("{}: ", name)), self::contract(contract)]),
+    }
+}
+
+fn contract(c: &Contract) -> Doc {
+    match c {
+        Contract::Flat(e) => expr(e),
+        Contract::Fn(params, ret) => {
+            let mut docs = match &params[..] {
+                [param @ Contract::Flat(_)] => vec![contract(param)],
+                _ => {
+                    let mut docs = vec![text("(")];
+                    for (i, param) in params.iter().enumerate() {
+                        if i > 0 {
+                            docs.push(text(", "));
+                        }
+                        docs.push(contract(param));
+                    }
+                    docs.push(text(")"));
+                    docs
+                }
+            };
+            docs.extend([text(" -> "), contract(ret)]);
+            Doc::Concat(docs)
+        }
+    }
+}
@@ -0,0 +1,204 @@
+// doc - lays out text that may be broken over several lines
+//
+// A document is text with places it may break, grouped so that a group is
+// either printed on one line, when it fits in `WIDTH`, or has every one of its
+// own breaks taken. Groups inside a broken group get the same choice, so the
+// outermost constructs are spread over lines first.
+
+use std::collections::HashSet;
+
+pub(crate) const WIDTH: usize = 100;
+
+const INDENT: &str = "  ";
+
+#[derive(Debug, Clone)]
+pub(crate) enum Doc {
+    Text(String),
+    // a space, or a new line when its group is broken
+    Line,
+    // nothing, or a new line when its group is broken
+    SoftLine,
+    // always a new line, a group with one is never on one line
+    HardLine,
+    // text only when its group is on one line, e.g. the commas between match arms
+    Flat(&'static str),
+    // text only when its group is broken, e.g. a trailing comma
+    Broken(&'static str),
+    // indents the lines started inside it
+    Nest(Vec<Doc>),
+    Group(Vec<Doc>),
+    // a group that's always broken, e.g. one with a comment inside, see `force`
+    Break(Vec<Doc>),
+    Concat(Vec<Doc>),
+}
+
+pub(crate) fn text(s: impl Into<String>) -> Doc {
+    Doc::Text(s.into())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+#[cfg(test)]
+pub(crate) fn render(doc: &Doc) -> String {
+    layout(doc).0
+}
+
+// the text, and the places a line could have been broken but wasn't, with
+// the group it's in, numbered in the order the groups start
+// NOTE forcing that group to break, see `force`, breaks the groups around it too
+// NOTE the documents still to print are kept on a stack rather than recursed
+// into, so deeply nested programs don't overflow the stack
+pub(crate) fn layout(doc: &Doc) -> (String, Vec<(usize, usize)>) {
+    let mut out = String::new();
+    let mut unbroken = Vec::new();
+    let mut groups = 0;
+    let mut col = 0;
+    // NOTE the outermost document isn't in a group, but it's broken anyway
+    let mut stack = vec![(0, Mode::Break, 0, doc)];
+    while let Some((indent, mode, group, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => {
+                out.push_str(s);
+                col = match s.rfind('\n') {
+                    Some(i) => s[i + 1..].chars().count(),
+                    None => col + s.chars().count(),
+                };
+            }
+            Doc::Line | Doc::SoftLine if mode == Mode::Flat => {
+                unbroken.push((out.len(), group));
+                if let Doc::Line = doc {
+                    out.push(' ');
+                    col += 1;
+                }
+            }
+            Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                out.push('\n');
+                out.push_str(&INDENT.repeat(indent));
+                col = indent * INDENT.len();
+            }
+            Doc::Flat(s) | Doc::Broken(s) => {
+                if matches!(doc, Doc::Flat(_)) == (mode == Mode::Flat) {
+                    out.push_str(s);
+                    col += s.len();
+                }
+            }
+            Doc::Nest(docs) => stack.extend(docs.iter().rev().map(|d| (indent + 1, mode, group, d))),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|d| (indent, mode, group, d))),
+            Doc::Group(docs) | Doc::Break(docs) => {
+                let mode = match (doc, mode) {
+                    (Doc::Break(_), _) => Mode::Break,
+                    (_, Mode::Flat) => Mode::Flat,
+                    _ if fits(WIDTH.saturating_sub(col), docs, &stack) => Mode::Flat,
+                    _ => Mode::Break,
+                };
+                let group = groups;
+                groups += 1;
+                stack.extend(docs.iter().rev().map(|d| (indent, mode, group, d)));
+            }
+        }
+    }
+    (out, unbroken)
+}
+
+// whether `docs` fit on one line in `width`, along with whatever follows
+// them up to the next line break
+fn fits(mut width: usize, docs: &[Doc], rest: &[(usize, Mode, usize, &Doc)]) -> bool {
+    let mut stack: Vec<(Mode, &Doc)> = docs.iter().rev().map(|d| (Mode::Flat, d)).collect();
+    let mut rest = rest.iter().rev().map(|(_, mode, _, doc)| (*mode, *doc));
+    loop {
+        let (mode, doc) = match stack.pop().or_else(|| rest.next()) {
+            Some(next) => next,
+            None => return true,
+        };
+        let len = match doc {
+            Doc::Text(s) => match s.find('\n') {
+                Some(i) => return s[..i].chars().count() <= width,
+                None => s.chars().count(),
+            },
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => return true,
+            Doc::HardLine | Doc::Break(_) if mode == Mode::Flat => return false,
+            Doc::HardLine => return true,
+            Doc::Line => 1,
+            Doc::SoftLine | Doc::Broken(_) => 0,
+            Doc::Flat(s) => s.len(),
+            Doc::Nest(docs) | Doc::Group(docs) | Doc::Break(docs) | Doc::Concat(docs) => {
+                stack.extend(docs.iter().rev().map(|d| (mode, d)));
+                0
+            }
+        };
+        width = match width.checked_sub(len) {
+            Some(width) => width,
+            None => return false,
+        };
+    }
+}
+
+// makes the groups numbered `ids` always broken, numbered as by `layout`
+pub(crate) fn force(doc: &mut Doc, ids: &HashSet<usize>) {
+    let mut next = 0;
+    let mut stack = vec![doc];
+    while let Some(doc) = stack.pop() {
+        if let Doc::Group(docs) = doc {
+            if ids.contains(&next) {
+                *doc = Doc::Break(std::mem::take(docs));
+            }
+        }
+        match doc {
+            Doc::Group(docs) | Doc::Break(docs) => {
+                next += 1;
+                stack.extend(docs.iter_mut().rev());
+            }
+            Doc::Nest(docs) | Doc::Concat(docs) => stack.extend(docs.iter_mut().rev()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[&str]) -> Doc {
+        let mut inner = vec![Doc::SoftLine];
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                inner.extend([text(","), Doc::Line]);
+            }
+            inner.push(text(*item));
+        }
+        inner.push(Doc::Broken(","));
+        Doc::Group(vec![text("["), Doc::Nest(inner), Doc::SoftLine, text("]")])
+    }
+
+    #[test]
+    fn groups_that_fit_stay_on_one_line() {
+        assert_eq!(render(&list(&["1", "2", "3"])), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn groups_that_dont_fit_are_broken() {
+        let item = "x".repeat(WIDTH / 2);
+        assert_eq!(
+            render(&list(&[&item, &item])),
+            format!("[\n  {},\n  {},\n]", item, item));
+    }
+
+    #[test]
+    fn hard_lines_break_their_groups() {
+        let doc = Doc::Group(vec![text("{"), Doc::Nest(vec![Doc::Line, text("a"), Doc::HardLine, text("b")]), Doc::Line, text("}")]);
+        assert_eq!(render(&doc), "{\n  a\n  b\n}");
+    }
+
+    #[test]
+    fn what_follows_a_group_counts_towards_its_width() {
+        let item = "x".repeat(WIDTH - 10);
+        let doc = Doc::Concat(vec![list(&["1"]), text(&item)]);
+        assert_eq!(render(&doc), format!("[1]{}", item));
+        let doc = Doc::Concat(vec![list(&["1"]), text(format!("{}{}", item, item))]);
+        assert_eq!(render(&doc), format!("[\n  1,\n]{}{}", item, item));
+    }
+}
@@ -1,6 +1,7 @@
-use crate::lexer::SyntaxKind;
+//use crate::lexer::SyntaxKind;
 //use num_traits::{FromPrimitive, ToPrimitive};
 
+#[allow(unused)]
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub(crate) enum KlugLanguage {}
 
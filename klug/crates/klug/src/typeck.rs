@@ -0,0 +1,1436 @@
+// typeck - Hindley-Milner type inference
+//
+// Types are inferred by unification, annotations are optional and only add
+// constraints. `let` and `fn` declarations are generalized so they can be
+// used at different types, e.g. `let id = fn(x) x` can be applied to both
+// `1` and `'one'`. Function parameters are never generalized.
+//
+// Declared type parameters, `fn id<A>(x: A) -> A`, are rigid inside the body
+// of their function, so it must work for any `A`, and generalized outside.
+//
+// In gradual mode a missing annotation is `Dyn` instead of a fresh variable.
+// `Dyn` is consistent with every type, the interpreter casts the values that
+// flow from dynamically typed code into annotated functions.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use crate::interp::builtin::Builtin;
+use crate::parser::{
+    Span,
+    decl::{self, Decl, Function},
+    stmt::Stmt,
+    contract::{Clause, Contract},
+    literal::Literal,
+    pattern::Pattern,
+    ty::Type,
+    expr::{
+    Expr,
+    op::{InfixOp, PrefixOp}
+}};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Ty {
+    Int,
+    Rat,
+    Float,
+    Str,
+    Bool,
+    Enum(String, Vec<Ty>),
+    Fn(Vec<Ty>, Box<Ty>),
+    Tuple(Vec<Ty>),
+    List(Box<Ty>),
+    Var(usize),
+    // a declared type parameter, only unifies with itself
+    Param(usize, String),
+    // the type of dynamically typed code, checked at runtime instead
+    Dyn,
+}
+
+impl Ty {
+    fn fmt_with(&self, names: &mut Vec<usize>) -> String {
+        match self {
+            Self::Int => "Int".to_string(),
+            Self::Rat => "Rat".to_string(),
+            Self::Float => "Float".to_string(),
+            Self::Dyn => "Dyn".to_string(),
+            Self::Str => "Str".to_string(),
+            Self::Bool => "Bool".to_string(),
+            Self::Enum(name, args) if args.is_empty() => name.clone(),
+            Self::Enum(name, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.fmt_with(names)).collect();
+                format!("{}<{}>", name, args.join(", "))
+            }
+            Self::Fn(params, ret) if params.len() == 1 && !matches!(params[0], Self::Fn(_, _)) => {
+                format!("{} -> {}", params[0].fmt_with(names), ret.fmt_with(names))
+            }
+            Self::Fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.fmt_with(names)).collect();
+                format!("({}) -> {}", params.join(", "), ret.fmt_with(names))
+            }
+            Self::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|i| i.fmt_with(names)).collect();
+                format!("({})", items.join(", "))
+            }
+            Self::List(item) => format!("List<{}>", item.fmt_with(names)),
+            Self::Param(_, name) => name.clone(),
+            // NOTE variables are named 'a, 'b, ... in the order they're printed
+            Self::Var(v) => {
+                let i = names.iter().position(|n| n == v).unwrap_or_else(|| {
+                    names.push(*v);
+                    names.len() - 1
+                });
+                let letter = (b'a' + (i % 26) as u8) as char;
+                match i / 26 {
+                    0 => format!("'{}", letter),
+                    n => format!("'{}{}", letter, n),
+                }
+            }
+        }
+    }
+
+    fn occurs(&self, v: usize) -> bool {
+        match self {
+            Self::Var(u) => *u == v,
+            Self::Fn(params, ret) => params.iter().any(|p| p.occurs(v)) || ret.occurs(v),
+            Self::Tuple(items) => items.iter().any(|i| i.occurs(v)),
+            Self::List(item) => item.occurs(v),
+            Self::Enum(_, args) => args.iter().any(|a| a.occurs(v)),
+            Self::Int | Self::Rat | Self::Float | Self::Str | Self::Bool | Self::Param(_, _) | Self::Dyn => false,
+        }
+    }
+
+    fn free_vars(&self, vars: &mut Vec<usize>) {
+        match self {
+            Self::Var(v) if !vars.contains(v) => vars.push(*v),
+            Self::Fn(params, ret) => {
+                params.iter().for_each(|p| p.free_vars(vars));
+                ret.free_vars(vars);
+            }
+            Self::Tuple(items) => items.iter().for_each(|i| i.free_vars(vars)),
+            Self::List(item) => item.free_vars(vars),
+            Self::Enum(_, args) => args.iter().for_each(|a| a.free_vars(vars)),
+            _ => (),
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.fmt_with(&mut Vec::new()))
+    }
+}
+
+// forall vars. ty
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Ty,
+}
+
+impl Scheme {
+    fn mono(ty: Ty) -> Self {
+        Self { vars: vec![], ty }
+    }
+}
+
+// a class of types an operator works on
+#[derive(Debug, Clone, Copy)]
+enum Class {
+    // `<` and friends, numbers and strings
+    Ordered,
+    // arithmetic, ints, rationals and floats
+    Numeric,
+}
+
+impl Class {
+    fn admits(&self, ty: &Ty) -> bool {
+        match self {
+            Self::Ordered => matches!(ty, Ty::Int | Ty::Rat | Ty::Float | Ty::Str),
+            Self::Numeric => matches!(ty, Ty::Int | Ty::Rat | Ty::Float),
+        }
+    }
+
+    fn verb(&self) -> &'static str {
+        match self {
+            Self::Ordered => "order",
+            Self::Numeric => "do arithmetic on",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct TypeError {
+    pub(crate) msg: String,
+    // the innermost call, function or `let` the error occurred in, or else its declaration
+    pub(crate) span: Span,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "type error: {}", self.msg)
+    }
+}
+
+// Remembers the declarations checked so far, a REPL session keeps one around
+#[derive(Debug, Default)]
+pub(crate) struct TypeChecker {
+    // constructor name -> its type, a function of its fields unless it has none
+    ctors: HashMap<String, Scheme>,
+    // enum name -> how many type parameters it takes
+    enums: HashMap<String, usize>,
+    // the type parameters in scope, innermost last
+    params: Vec<(String, Ty)>,
+    // NOTE innermost bindings are last, scopes are popped by truncating
+    env: Vec<(String, Scheme)>,
+    // what each type variable has been unified with
+    subst: Vec<Option<Ty>>,
+    // types that must belong to a class, e.g. operands of `+` must be numbers
+    // NOTE with the innermost span around it, see `spanned`
+    constrained: Vec<(Ty, Class, String, Option<Span>)>,
+    // the return types of the enclosing functions, innermost last, for `?`
+    returns: Vec<Ty>,
+    // the spans of the calls, functions and `let`s being checked, innermost last
+    spans: Vec<Span>,
+    // the innermost of those the last error came out of
+    failed_at: Option<Span>,
+    // the variable and the type it occurs in when the last unification failed for that
+    infinite: Option<(usize, Ty)>,
+    gradual: bool,
+}
+
+impl TypeChecker {
+    // NOTE only affects declarations checked afterwards
+    pub(crate) fn set_gradual(&mut self, gradual: bool) {
+        self.gradual = gradual;
+    }
+
+    pub(crate) fn check_decls(&mut self, decls: &[Decl], spans: &[Span]) -> Vec<TypeError> {
+        self.decls(decls).into_iter()
+            .zip(spans)
+            .filter_map(|(result, span)| result.err().map(|(msg, at)| TypeError { msg, span: at.unwrap_or(span.clone()) }))
+            .collect()
+    }
+
+    // a result for each declaration, in order, with where in it an error is
+    fn decls(&mut self, decls: &[Decl]) -> Vec<Result<(), (String, Option<Span>)>> {
+        let mut results = Vec::new();
+        let mut rest = decls;
+        while let Some(decl) = rest.first() {
+            let fns = decl::leading_functions(rest);
+            if fns.is_empty() {
+                self.failed_at = None;
+                let result = self.decl(decl).and_then(|_| self.check_constrained());
+                results.push(result.map_err(|msg| (msg, self.failed_at.take())));
+                rest = &rest[1..];
+            } else {
+                results.extend(self.functions(&fns));
+                rest = &rest[fns.len()..];
+            }
+        }
+        results
+    }
+
+    // NOTE functions declared together can call each other, but only at a
+    // single type until they've all been checked and are generalized
+    fn functions(&mut self, fns: &[&Rc<Function>]) -> Vec<Result<(), (String, Option<Span>)>> {
+        let len = self.env.len();
+        let uses: Vec<Ty> = fns.iter()
+            .map(|f| {
+                let ty = self.fresh();
+                self.env.push((f.name.clone(), Scheme::mono(ty.clone())));
+                ty
+            })
+            .collect();
+        let tys: Vec<Result<Ty, (String, Option<Span>)>> = fns.iter()
+            .zip(&uses)
+            .map(|(f, used)| {
+                self.failed_at = None;
+                let ty = self.function(f).map_err(|msg| (msg, self.failed_at.take()))?;
+                self.infinite = None;
+                if !self.unify(used, &ty) {
+                    if let Some(msg) = self.infinite(&f.name) {
+                        return Err((msg, Some(f.span.clone())));
+                    }
+                    let (used, ty) = self.show_pair(used, &ty);
+                    return Err((format!("`{}` is used as {} but has type {}", f.name, used, ty), Some(f.span.clone())));
+                }
+                Ok(ty)
+            })
+            .collect();
+        self.env.truncate(len);
+        fns.iter()
+            .zip(tys)
+            .map(|(f, ty)| {
+                let scheme = match &ty {
+                    Ok(ty) => self.generalize(ty).map_err(|msg| (msg, self.failed_at.take()))?,
+                    Err(_) => Scheme::mono(self.fresh()),
+                };
+                self.env.push((f.name.clone(), scheme));
+                ty.and_then(|_| self.check_constrained().map_err(|msg| (msg, self.failed_at.take())))
+            })
+            .collect()
+    }
+
+    // The type of an expression, without binding anything
+    pub(crate) fn type_of(&mut self, expr: &Expr) -> Result<Ty, String> {
+        let ty = self.scoped(|tc| tc.expr(expr))?;
+        // NOTE generalizing defaults the constrained variables, as for a declaration
+        Ok(self.generalize(&ty)?.ty)
+    }
+
+    fn decl(&mut self, decl: &Decl) -> Result<(), String> {
+        match decl {
+            Decl::Let(name, ann, expr, span) => {
+                let ty = self.spanned(span, |tc| tc.let_ty(ann, expr));
+                // NOTE bind the name even if it's ill-typed so later uses don't fail too
+                let scheme = match &ty {
+                    Ok(ty) => self.generalize(ty)?,
+                    Err(_) => Scheme::mono(self.fresh()),
+                };
+                self.env.push((name.clone(), scheme));
+                ty.map(|_| ())
+            }
+            Decl::Fn(f) => self.functions(&[f]).remove(0).map_err(|(msg, at)| self.failed(msg, at)),
+            Decl::Enum(name, generics, variants) => {
+                declared_once(generics)?;
+                // NOTE registered first so variants can refer to the enum itself
+                self.enums.insert(name.clone(), generics.len());
+                let vars: Vec<usize> = generics.iter().map(|_| self.fresh_var()).collect();
+                let result = Ty::Enum(name.clone(), vars.iter().map(|v| Ty::Var(*v)).collect());
+                let params = generics.iter().cloned().zip(vars.iter().map(|v| Ty::Var(*v)));
+                let ctors = self.with_params(params.collect(), |tc| {
+                    variants.iter().map(|variant| {
+                        // NOTE an untyped field isn't generalized, every use of it must agree on its type
+                        let fields = variant.fields.iter()
+                            .map(|field| tc.annotation(&field.ty))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let ty = match fields.len() {
+                            0 => result.clone(),
+                            _ => Ty::Fn(fields, Box::new(result.clone())),
+                        };
+                        Ok((variant.name.clone(), Scheme { vars: vars.clone(), ty }))
+                    }).collect::<Result<Vec<_>, String>>()
+                })?;
+                for (ctor, scheme) in ctors {
+                    self.ctors.insert(ctor.clone(), scheme.clone());
+                    self.env.push((ctor, scheme));
+                }
+                Ok(())
+            }
+            Decl::Stmt(bx_stmt) => match &**bx_stmt {
+                Stmt::Expr(expr) => self.expr(expr).map(|_| ()),
+            },
+            // NOTE the parser already reported these
+            Decl::Error(_) => Ok(()),
+        }
+    }
+
+    fn let_ty(&mut self, ann: &Option<Type>, expr: &Option<Expr>) -> Result<Ty, String> {
+        let ann = self.annotation(ann)?;
+        if let Some(expr) = expr {
+            let ty = self.expr(expr)?;
+            self.expect_widened(&ann, &ty, expr)?;
+        }
+        Ok(ann)
+    }
+
+    fn function(&mut self, f: &Function) -> Result<Ty, String> {
+        self.spanned(&f.span, |tc| tc.generic_function(f))
+    }
+
+    fn generic_function(&mut self, f: &Function) -> Result<Ty, String> {
+        declared_once(&f.generics)?;
+        let generics: Vec<(String, Ty)> = f.generics.iter()
+            .map(|name| (name.clone(), Ty::Param(self.fresh_var(), name.clone())))
+            .collect();
+        let ty = self.with_params(generics.clone(), |tc| tc.function_body(f))?;
+        // NOTE outside the body the type parameters can be anything
+        let mapping: Vec<(usize, Ty)> = generics.iter()
+            .map(|(_, param)| match param {
+                Ty::Param(v, _) => (*v, self.fresh()),
+                _ => unreachable!(),
+            })
+            .collect();
+        Ok(substitute(&self.apply(&ty), &mapping))
+    }
+
+    fn function_body(&mut self, f: &Function) -> Result<Ty, String> {
+        let params = f.params.iter()
+            .map(|param| self.annotation(&param.ty))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ret = self.annotation(&f.ret)?;
+        let ty = Ty::Fn(params.clone(), Box::new(ret.clone()));
+        self.scoped(|tc| {
+            // NOTE a named function can call itself, but only at the one type
+            if !f.name.is_empty() {
+                tc.env.push((f.name.clone(), Scheme::mono(ty.clone())));
+            }
+            for (param, ty) in f.params.iter().zip(params) {
+                tc.env.push((param.name.clone(), Scheme::mono(ty)));
+            }
+            tc.clauses(&f.requires)?;
+            tc.returns.push(ret.clone());
+            let body = tc.expr(&f.body);
+            tc.returns.pop();
+            tc.expect_widened(&ret, &body?, &f.body)?;
+            tc.env.push(("result".to_string(), Scheme::mono(ret.clone())));
+            tc.clauses(&f.ensures)
+        })?;
+        Ok(ty)
+    }
+
+    fn clauses(&mut self, clauses: &[Clause]) -> Result<(), String> {
+        for clause in clauses {
+            match clause {
+                Clause::Holds(cond) => {
+                    let ty = self.expr(cond)?;
+                    self.expect(&Ty::Bool, &ty, cond)?;
+                }
+                Clause::Satisfies(name, contract) => match self.lookup(name) {
+                    Some(scheme) => {
+                        let ty = self.instantiate(&scheme);
+                        self.contract(contract, &ty)?;
+                    }
+                    None => return Err(format!("unbound identifier `{}`", name)),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // NOTE a flat contract is a predicate on `ty`, an arrow contract needs a function
+    fn contract(&mut self, contract: &Contract, ty: &Ty) -> Result<(), String> {
+        match contract {
+            Contract::Flat(pred) => {
+                let pred_ty = self.expr(pred)?;
+                self.expect(&Ty::Fn(vec![ty.clone()], Box::new(Ty::Bool)), &pred_ty, pred)
+            }
+            Contract::Fn(params, ret) => {
+                let param_tys: Vec<Ty> = params.iter().map(|_| self.fresh()).collect();
+                let ret_ty = self.fresh();
+                if !self.unify(ty, &Ty::Fn(param_tys.clone(), Box::new(ret_ty.clone()))) {
+                    return Err(format!(
+                        "contract `{}` needs a function of {} argument(s) but was given {}",
+                        contract, params.len(), self.apply(ty)
+                    ));
+                }
+                params.iter().zip(&param_tys).try_for_each(|(c, ty)| self.contract(c, ty))?;
+                self.contract(ret, &ret_ty)
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Ty, String> {
+        match expr {
+            Expr::Call(_, _, span) => self.spanned(span, |tc| tc.unspanned(expr)),
+            _ => self.unspanned(expr),
+        }
+    }
+
+    // checks something with a span, which an error in it is placed at unless
+    // it's in something inside it with a span of its own
+    fn spanned<T>(&mut self, span: &Span, check: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        self.spans.push(span.clone());
+        let ret = check(self);
+        self.spans.pop();
+        ret.map_err(|msg| self.failed(msg, Some(span.clone())))
+    }
+
+    // NOTE the first place an error is given is the innermost
+    fn failed(&mut self, msg: String, at: Option<Span>) -> String {
+        if self.failed_at.is_none() {
+            self.failed_at = at;
+        }
+        msg
+    }
+
+    fn unspanned(&mut self, expr: &Expr) -> Result<Ty, String> {
+        match expr {
+            Expr::Literal(Literal::INT(_)) | Expr::Literal(Literal::BIG(_)) => Ok(Ty::Int),
+            Expr::Literal(Literal::FLOAT(_)) => Ok(Ty::Float),
+            Expr::Literal(Literal::STRING(_)) => Ok(Ty::Str),
+            Expr::Literal(Literal::TRUE) | Expr::Literal(Literal::FALSE) => Ok(Ty::Bool),
+            Expr::Literal(Literal::IDENT(name)) => match self.lookup(name) {
+                Some(scheme) => Ok(self.instantiate(&scheme)),
+                None => match Builtin::from_name(name) {
+                    Some(builtin) => self.builtin(builtin, expr),
+                    None => Err(format!("unbound identifier `{}`", name)),
+                },
+            },
+
+            Expr::Unary(op, bdy) => {
+                let ty = self.expr(bdy)?;
+                match op {
+                    PrefixOp::Neg => self.constrain(&ty, Class::Numeric, expr)?,
+                    PrefixOp::Not => self.expect(&Ty::Bool, &ty, expr)?,
+                    PrefixOp::BitNot => self.expect(&Ty::Int, &ty, expr)?,
+                }
+                Ok(ty)
+            }
+
+            Expr::Binary(lhs, op, rhs) => {
+                let tl = self.expr(lhs)?;
+                let tr = self.expr(rhs)?;
+                match op {
+                    // NOTE numbers of different types are never mixed, so both operands have the
+                    // same type, except that an Int is a Rat too
+                    InfixOp::Add | InfixOp::Sub | InfixOp::Mul => {
+                        let ty = self.operands(&tl, &tr, expr)?;
+                        self.constrain(&ty, Class::Numeric, expr)?;
+                        Ok(ty)
+                    }
+                    // NOTE dividing exact numbers is exact, unknown operands default to Int
+                    InfixOp::Div => {
+                        let ty = self.operands(&tl, &tr, expr)?;
+                        self.constrain(&ty, Class::Numeric, expr)?;
+                        match self.shallow(&ty) {
+                            Ty::Float => Ok(Ty::Float),
+                            Ty::Dyn => Ok(Ty::Dyn),
+                            ty => {
+                                if let Ty::Var(v) = ty {
+                                    self.unify(&Ty::Var(v), &Ty::Int);
+                                }
+                                Ok(Ty::Rat)
+                            }
+                        }
+                    }
+                    // NOTE a float is raised to a float, an exact number only to an Int
+                    InfixOp::Pow => {
+                        self.constrain(&tl, Class::Numeric, expr)?;
+                        match (self.shallow(&tl), self.shallow(&tr)) {
+                            (Ty::Float, _) | (_, Ty::Float) => self.expect(&tl, &tr, expr)?,
+                            (Ty::Dyn, _) => (),
+                            _ => self.expect(&Ty::Int, &tr, expr)?,
+                        }
+                        Ok(tl)
+                    }
+                    InfixOp::IntDiv | InfixOp::Mod | InfixOp::BitAnd | InfixOp::BitOr
+                    | InfixOp::BitXor | InfixOp::Shl | InfixOp::Shr => {
+                        self.expect(&Ty::Int, &tl, expr)?;
+                        self.expect(&Ty::Int, &tr, expr)?;
+                        Ok(Ty::Int)
+                    }
+                    InfixOp::Lt | InfixOp::LtEq | InfixOp::Gt | InfixOp::GtEq => {
+                        let ty = self.operands(&tl, &tr, expr)?;
+                        self.constrain(&ty, Class::Ordered, expr)?;
+                        Ok(Ty::Bool)
+                    }
+                    InfixOp::Eq | InfixOp::NotEq => {
+                        self.operands(&tl, &tr, expr)?;
+                        Ok(Ty::Bool)
+                    }
+                }
+            }
+
+            Expr::Grouping(bdy) => self.expr(bdy),
+
+            Expr::Call(callee, args, _) => {
+                let ty = self.expr(callee)?;
+                let args = args.iter()
+                    .map(|arg| self.expr(arg).map(|ty| (arg, ty)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match self.shallow(&ty) {
+                    Ty::Fn(params, ret) => {
+                        if params.len() != args.len() {
+                            return Err(format!(
+                                "`{}` expects {} argument(s) but was given {}",
+                                callee, params.len(), args.len()
+                            ));
+                        }
+                        for (param, (arg, ty)) in params.iter().zip(&args) {
+                            self.expect_widened(param, ty, arg)?;
+                        }
+                        Ok(*ret)
+                    }
+                    Ty::Var(_) => {
+                        let ret = self.fresh();
+                        let fn_ty = Ty::Fn(args.into_iter().map(|(_, ty)| ty).collect(), Box::new(ret.clone()));
+                        self.expect(&ty, &fn_ty, callee)?;
+                        Ok(ret)
+                    }
+                    Ty::Dyn => Ok(Ty::Dyn),
+                    ty => Err(format!("`{}` has type {} and can't be called", callee, self.apply(&ty))),
+                }
+            }
+
+            Expr::Match(scrut, arms, _) => {
+                let scrut_ty = self.expr(scrut)?;
+                let mut result = self.fresh();
+                for arm in arms {
+                    let ty = self.scoped(|tc| {
+                        tc.pattern(&arm.pattern, &scrut_ty)?;
+                        if let Some(guard) = &arm.guard {
+                            let ty = tc.expr(guard)?;
+                            tc.expect(&Ty::Bool, &ty, guard)?;
+                        }
+                        tc.expr(&arm.body)
+                    })?;
+                    if let Some(widened) = self.widened(&result, &ty) {
+                        result = widened;
+                        continue;
+                    }
+                    self.infinite = None;
+                    if !self.unify(&result, &ty) {
+                        if let Some(msg) = self.infinite(&arm.body) {
+                            return Err(msg);
+                        }
+                        let (ty, result) = self.show_pair(&ty, &result);
+                        return Err(format!(
+                            "match arms have incompatible types, `{}` has type {} but earlier arms have type {}",
+                            arm.body, ty, result
+                        ));
+                    }
+                }
+                Ok(result)
+            }
+
+            Expr::Block(decls, bdy) => self.scoped(|tc| {
+                if let Some(Err((msg, at))) = tc.decls(decls).into_iter().find(Result::is_err) {
+                    return Err(tc.failed(msg, at));
+                }
+                tc.expr(bdy)
+            }),
+
+            Expr::Lambda(f) => self.function(f),
+
+            Expr::Tuple(items) => {
+                let items = items.iter().map(|i| self.expr(i)).collect::<Result<_, _>>()?;
+                Ok(Ty::Tuple(items))
+            }
+
+            // NOTE the enclosing function must return the same kind of value, e.g. any
+            // `Option` for an `Option`, but a `Result` with the same error type
+            Expr::Try(bdy) => {
+                let ty = self.expr(bdy)?;
+                let ret = self.returns.last().cloned().ok_or_else(|| format!("`{}` can only be used in a function", expr))?;
+                let kind = match (self.shallow(&ty), self.shallow(&ret)) {
+                    (Ty::Dyn, _) => return Ok(Ty::Dyn),
+                    (Ty::Enum(name, _), _) | (Ty::Var(_), Ty::Enum(name, _)) if name == "Option" || name == "Result" => name,
+                    (Ty::Var(_), _) => return Err(format!("can't tell whether `{}` is an Option or a Result", bdy)),
+                    _ => return Err(format!("`{}` needs an Option or a Result, found {}", expr, self.apply(&ty))),
+                };
+                let ok = self.fresh();
+                let (expected, returned) = match &kind[..] {
+                    "Option" => (Ty::Enum(kind.clone(), vec![ok.clone()]), Ty::Enum(kind, vec![self.fresh()])),
+                    _ => {
+                        let err = self.fresh();
+                        (Ty::Enum(kind.clone(), vec![ok.clone(), err.clone()]), Ty::Enum(kind, vec![self.fresh(), err]))
+                    }
+                };
+                self.expect(&expected, &ty, bdy)?;
+                self.expect(&ret, &returned, expr)?;
+                Ok(ok)
+            }
+
+            // NOTE only `Error`s are thrown, so a `catch` knows what it's given
+            Expr::Throw(bdy) => {
+                let ty = self.expr(bdy)?;
+                self.expect(&Ty::Enum("Error".to_string(), vec![]), &ty, bdy)?;
+                Ok(self.fresh())
+            }
+
+            Expr::TryCatch(bdy, catch, finally) => {
+                let ty = self.expr(bdy)?;
+                if let Some((name, handler)) = catch {
+                    let handler_ty = self.scoped(|tc| {
+                        tc.env.push((name.clone(), Scheme::mono(Ty::Enum("Error".to_string(), vec![]))));
+                        tc.expr(handler)
+                    })?;
+                    self.expect(&ty, &handler_ty, handler)?;
+                }
+                // NOTE the finally block is only run for its effects
+                if let Some(finally) = finally {
+                    self.expr(finally)?;
+                }
+                Ok(ty)
+            }
+
+            Expr::List(items, rest) => {
+                let mut item_ty = self.fresh();
+                for item in items {
+                    let ty = self.expr(item)?;
+                    match self.widened(&item_ty, &ty) {
+                        Some(widened) => item_ty = widened,
+                        None => self.expect(&item_ty, &ty, item)?,
+                    }
+                }
+                let list_ty = Ty::List(Box::new(item_ty));
+                if let Some(rest) = rest {
+                    let ty = self.expr(rest)?;
+                    self.expect(&list_ty, &ty, rest)?;
+                }
+                Ok(list_ty)
+            }
+        }
+    }
+
+    // binds the pattern's names, given it matches values of type `ty`
+    fn pattern(&mut self, pat: &Pattern, ty: &Ty) -> Result<(), String> {
+        match pat {
+            Pattern::Wildcard => Ok(()),
+            Pattern::Binding(name) => {
+                self.env.push((name.clone(), Scheme::mono(ty.clone())));
+                Ok(())
+            }
+            Pattern::Literal(lit) => {
+                let lit_ty = match lit {
+                    Literal::INT(_) | Literal::BIG(_) => Ty::Int,
+                    Literal::FLOAT(_) => Ty::Float,
+                    Literal::STRING(_) => Ty::Str,
+                    Literal::TRUE | Literal::FALSE => Ty::Bool,
+                    Literal::IDENT(_) => unreachable!(),
+                };
+                self.expect_pattern(ty, &lit_ty, pat)
+            }
+            Pattern::Constructor(name, args) => {
+                let scheme = self.ctors.get(name)
+                    .cloned()
+                    .ok_or_else(|| format!("unknown constructor `{}`", name))?;
+                let (fields, result) = match self.instantiate(&scheme) {
+                    Ty::Fn(fields, result) => (fields, *result),
+                    result => (vec![], result),
+                };
+                self.expect_pattern(ty, &result, pat)?;
+                if fields.len() != args.len() {
+                    return Err(format!("constructor `{}` has {} field(s) but pattern `{}` has {}", name, fields.len(), pat, args.len()));
+                }
+                args.iter().zip(&fields).try_for_each(|(arg, field)| self.pattern(arg, field))
+            }
+            Pattern::Tuple(items) => {
+                let tys: Vec<Ty> = items.iter().map(|_| self.fresh()).collect();
+                self.expect_pattern(ty, &Ty::Tuple(tys.clone()), pat)?;
+                items.iter().zip(&tys).try_for_each(|(item, ty)| self.pattern(item, ty))
+            }
+            Pattern::List(items, rest) => {
+                let item_ty = self.fresh();
+                let list_ty = Ty::List(Box::new(item_ty.clone()));
+                self.expect_pattern(ty, &list_ty, pat)?;
+                items.iter().try_for_each(|item| self.pattern(item, &item_ty))?;
+                match rest {
+                    Some(rest) => self.pattern(rest, &list_ty),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    // NOTE a missing annotation is a fresh type variable, or Dyn in gradual mode
+    fn annotation(&mut self, ann: &Option<Type>) -> Result<Ty, String> {
+        match ann {
+            Some(ann) => self.resolve(ann),
+            None if self.gradual => Ok(Ty::Dyn),
+            None => Ok(self.fresh()),
+        }
+    }
+
+    fn resolve(&self, ann: &Type) -> Result<Ty, String> {
+        match ann {
+            Type::Named(name, args) => {
+                let param = self.params.iter().rev().find(|(n, _)| n == name).map(|(_, ty)| ty.clone());
+                // NOTE type parameters stand for a type, so they never take arguments
+                let arity = match &name[..] {
+                    _ if param.is_some() => 0,
+                    "Int" | "Rat" | "Float" | "Str" | "Bool" | "Dyn" => 0,
+                    "List" => 1,
+                    _ => *self.enums.get(name).ok_or_else(|| format!("unknown type `{}`", name))?,
+                };
+                if args.len() != arity {
+                    return Err(format!("type `{}` expects {} type argument(s) but was given {}", name, arity, args.len()));
+                }
+                if let Some(param) = param {
+                    return Ok(param);
+                }
+                let mut args = args.iter().map(|a| self.resolve(a)).collect::<Result<Vec<_>, _>>()?;
+                Ok(match &name[..] {
+                    "Int" => Ty::Int,
+                    "Rat" => Ty::Rat,
+                    "Float" => Ty::Float,
+                    "Dyn" => Ty::Dyn,
+                    "Str" => Ty::Str,
+                    "Bool" => Ty::Bool,
+                    "List" => Ty::List(Box::new(args.remove(0))),
+                    _ => Ty::Enum(name.clone(), args),
+                })
+            }
+            Type::Fn(params, ret) => {
+                let params = params.iter().map(|p| self.resolve(p)).collect::<Result<_, _>>()?;
+                Ok(Ty::Fn(params, Box::new(self.resolve(ret)?)))
+            }
+            Type::Tuple(items) => {
+                let items = items.iter().map(|i| self.resolve(i)).collect::<Result<_, _>>()?;
+                Ok(Ty::Tuple(items))
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Scheme> {
+        self.env.iter().rev().find(|(n, _)| n == name).map(|(_, scheme)| scheme.clone())
+    }
+
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let len = self.env.len();
+        let ret = f(self);
+        self.env.truncate(len);
+        ret
+    }
+
+    fn with_params<T>(&mut self, params: Vec<(String, Ty)>, f: impl FnOnce(&mut Self) -> T) -> T {
+        let len = self.params.len();
+        self.params.extend(params);
+        let ret = f(self);
+        self.params.truncate(len);
+        ret
+    }
+
+    fn fresh_var(&mut self) -> usize {
+        self.subst.push(None);
+        self.subst.len() - 1
+    }
+
+    fn fresh(&mut self) -> Ty {
+        Ty::Var(self.fresh_var())
+    }
+
+    // follows variables until reaching one that's unbound or another type
+    fn shallow(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Var(v) => match &self.subst[*v] {
+                Some(ty) => self.shallow(ty),
+                None => ty.clone(),
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    // replaces every bound variable in `ty`
+    fn apply(&self, ty: &Ty) -> Ty {
+        match self.shallow(ty) {
+            Ty::Fn(params, ret) => Ty::Fn(params.iter().map(|p| self.apply(p)).collect(), Box::new(self.apply(&ret))),
+            Ty::Tuple(items) => Ty::Tuple(items.iter().map(|i| self.apply(i)).collect()),
+            Ty::List(item) => Ty::List(Box::new(self.apply(&item))),
+            Ty::Enum(name, args) => Ty::Enum(name, args.iter().map(|a| self.apply(a)).collect()),
+            ty => ty,
+        }
+    }
+
+    fn unify(&mut self, t1: &Ty, t2: &Ty) -> bool {
+        match (self.shallow(t1), self.shallow(t2)) {
+            (Ty::Var(v1), Ty::Var(v2)) if v1 == v2 => true,
+            // NOTE a variable that meets Dyn becomes Dyn, so it stays consistent with everything
+            (Ty::Var(v), Ty::Dyn) | (Ty::Dyn, Ty::Var(v)) => {
+                self.subst[v] = Some(Ty::Dyn);
+                true
+            }
+            (Ty::Dyn, _) | (_, Ty::Dyn) => true,
+            (Ty::Var(v), ty) | (ty, Ty::Var(v)) => {
+                if self.apply(&ty).occurs(v) {
+                    self.infinite = Some((v, ty));
+                    return false;
+                }
+                self.subst[v] = Some(ty);
+                true
+            }
+            (Ty::Fn(p1, r1), Ty::Fn(p2, r2)) => {
+                p1.len() == p2.len()
+                    && p1.iter().zip(&p2).all(|(t1, t2)| self.unify(t1, t2))
+                    && self.unify(&r1, &r2)
+            }
+            (Ty::Tuple(i1), Ty::Tuple(i2)) => {
+                i1.len() == i2.len() && i1.iter().zip(&i2).all(|(t1, t2)| self.unify(t1, t2))
+            }
+            (Ty::List(i1), Ty::List(i2)) => self.unify(&i1, &i2),
+            (Ty::Enum(n1, a1), Ty::Enum(n2, a2)) => {
+                n1 == n2 && a1.len() == a2.len() && a1.iter().zip(&a2).all(|(t1, t2)| self.unify(t1, t2))
+            }
+            (t1, t2) => t1 == t2,
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Ty {
+        let mapping: Vec<(usize, Ty)> = scheme.vars.iter().map(|v| (*v, self.fresh())).collect();
+        substitute(&self.apply(&scheme.ty), &mapping)
+    }
+
+    // quantifies the variables of `ty` that aren't free in the environment
+    fn generalize(&mut self, ty: &Ty) -> Result<Scheme, String> {
+        let mut env_vars = Vec::new();
+        for (_, scheme) in &self.env {
+            let mut vars = Vec::new();
+            self.apply(&scheme.ty).free_vars(&mut vars);
+            env_vars.extend(vars.into_iter().filter(|v| !scheme.vars.contains(v)));
+        }
+        let mut vars = Vec::new();
+        self.apply(ty).free_vars(&mut vars);
+        vars.retain(|v| !env_vars.contains(v));
+        // NOTE a constrained type that would be generalized defaults to Int, but
+        // not one that's only in an expression still being checked, e.g. the
+        // argument of `int({ let x = 1\n 2.5 })`
+        self.check_constrained()?;
+        for (ty, _, _, _) in self.constrained.clone() {
+            if let Ty::Var(v) = self.shallow(&ty) {
+                if vars.contains(&v) {
+                    self.unify(&Ty::Var(v), &Ty::Int);
+                }
+            }
+        }
+        self.check_constrained()?;
+
+        let ty = self.apply(ty);
+        let mut vars = Vec::new();
+        ty.free_vars(&mut vars);
+        vars.retain(|v| !env_vars.contains(v));
+        Ok(Scheme { vars, ty })
+    }
+
+    // NOTE the conversions take any number, and so does the margin of `approx_eq`
+    fn builtin(&mut self, builtin: Builtin, expr: &Expr) -> Result<Ty, String> {
+        let (params, ret) = match builtin {
+            Builtin::Float | Builtin::Int => {
+                let param = self.fresh();
+                self.constrain(&param, Class::Numeric, expr)?;
+                let ret = if builtin == Builtin::Float { Ty::Float } else { Ty::Int };
+                (vec![param], ret)
+            }
+            Builtin::ApproxEq => {
+                let margin = self.fresh();
+                self.constrain(&margin, Class::Numeric, expr)?;
+                (vec![Ty::Float, Ty::Float, margin], Ty::Bool)
+            }
+            Builtin::IsNan | Builtin::IsFinite => (vec![Ty::Float], Ty::Bool),
+            Builtin::ParseInt => (vec![Ty::Str], Ty::Enum("Result".to_string(), vec![Ty::Int, Ty::Str])),
+            Builtin::ParseFloat => (vec![Ty::Str], Ty::Enum("Result".to_string(), vec![Ty::Float, Ty::Str])),
+            Builtin::Head => {
+                let item = self.fresh();
+                (vec![Ty::List(Box::new(item.clone()))], Ty::Enum("Option".to_string(), vec![item]))
+            }
+        };
+        Ok(Ty::Fn(params, Box::new(ret)))
+    }
+
+    fn constrain(&mut self, ty: &Ty, class: Class, expr: &Expr) -> Result<(), String> {
+        self.constrained.push((ty.clone(), class, expr.to_string(), self.spans.last().cloned()));
+        self.check_constrained()
+    }
+
+    // forgets the constrained types that are known to belong to their class
+    fn check_constrained(&mut self) -> Result<(), String> {
+        let mut pending = Vec::new();
+        for (ty, class, expr, at) in std::mem::take(&mut self.constrained) {
+            match self.shallow(&ty) {
+                Ty::Var(_) => pending.push((ty, class, expr, at)),
+                Ty::Dyn => (),
+                ty if class.admits(&ty) => (),
+                ty => {
+                    let msg = format!("`{}` can't {} values of type {}", expr, class.verb(), self.apply(&ty));
+                    return Err(self.failed(msg, at));
+                }
+            }
+        }
+        self.constrained = pending;
+        Ok(())
+    }
+
+    fn expect(&mut self, expected: &Ty, actual: &Ty, expr: &Expr) -> Result<(), String> {
+        self.infinite = None;
+        if self.unify(expected, actual) {
+            return Ok(());
+        }
+        if let Some(msg) = self.infinite(expr) {
+            return Err(msg);
+        }
+        let (expected, actual) = self.show_pair(expected, actual);
+        Err(format!("mismatched types in `{}`, expected {} but found {}", expr, expected, actual))
+    }
+
+    // NOTE an Int is a Rat too, as it is when run, so where one meets the other it's a Rat
+    fn widened(&self, t1: &Ty, t2: &Ty) -> Option<Ty> {
+        match (self.shallow(t1), self.shallow(t2)) {
+            (Ty::Int, Ty::Rat) | (Ty::Rat, Ty::Int) => Some(Ty::Rat),
+            _ => None,
+        }
+    }
+
+    // the type of an operator whose operands have the same type, or widen to it
+    fn operands(&mut self, tl: &Ty, tr: &Ty, expr: &Expr) -> Result<Ty, String> {
+        if let Some(ty) = self.widened(tl, tr) {
+            return Ok(ty);
+        }
+        self.expect(tl, tr, expr)?;
+        Ok(tl.clone())
+    }
+
+    // like `expect`, but an Int is also accepted where a Rat is expected
+    fn expect_widened(&mut self, expected: &Ty, actual: &Ty, expr: &Expr) -> Result<(), String> {
+        if let (Ty::Rat, Ty::Int) = (self.shallow(expected), self.shallow(actual)) {
+            return Ok(());
+        }
+        self.expect(expected, actual, expr)
+    }
+
+    fn expect_pattern(&mut self, scrut: &Ty, pat_ty: &Ty, pat: &Pattern) -> Result<(), String> {
+        self.infinite = None;
+        if self.unify(scrut, pat_ty) {
+            return Ok(());
+        }
+        if let Some(msg) = self.infinite(pat) {
+            return Err(msg);
+        }
+        let (pat_ty, scrut) = self.show_pair(pat_ty, scrut);
+        Err(format!("pattern `{}` has type {} but the value matched has type {}", pat, pat_ty, scrut))
+    }
+
+    // NOTE a type can't contain itself, e.g. `fn(x) x(x)` would need 'a = 'a -> 'b
+    fn infinite(&mut self, what: &dyn fmt::Display) -> Option<String> {
+        let (v, ty) = self.infinite.take()?;
+        let (v, ty) = self.show_pair(&Ty::Var(v), &ty);
+        Some(format!("infinite type in `{}`, {} occurs in {}", what, v, ty))
+    }
+
+    // NOTE both types share variable names, so 'a means the same in each
+    fn show_pair(&self, t1: &Ty, t2: &Ty) -> (String, String) {
+        let mut names = Vec::new();
+        (self.apply(t1).fmt_with(&mut names), self.apply(t2).fmt_with(&mut names))
+    }
+}
+
+fn declared_once(generics: &[String]) -> Result<(), String> {
+    for (i, name) in generics.iter().enumerate() {
+        if generics[..i].contains(name) {
+            return Err(format!("type parameter `{}` is declared twice", name));
+        }
+    }
+    Ok(())
+}
+
+fn substitute(ty: &Ty, mapping: &[(usize, Ty)]) -> Ty {
+    match ty {
+        Ty::Var(v) | Ty::Param(v, _) => mapping.iter()
+            .find(|(u, _)| u == v)
+            .map_or_else(|| ty.clone(), |(_, ty)| ty.clone()),
+        Ty::Fn(params, ret) => Ty::Fn(params.iter().map(|p| substitute(p, mapping)).collect(), Box::new(substitute(ret, mapping))),
+        Ty::Tuple(items) => Ty::Tuple(items.iter().map(|i| substitute(i, mapping)).collect()),
+        Ty::List(item) => Ty::List(Box::new(substitute(item, mapping))),
+        Ty::Enum(name, args) => Ty::Enum(name.clone(), args.iter().map(|a| substitute(a, mapping)).collect()),
+        ty => ty.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn check(input: &str) -> Vec<String> {
+        let parse = Parser::new(input).parse();
+        TypeChecker::default()
+            .check_decls(&parse.declarations, &parse.spans)
+            .into_iter()
+            .map(|e| e.msg)
+            .collect()
+    }
+
+    #[test]
+    fn annotated_let() {
+        assert!(check("let x: Int = 1 + 2").is_empty());
+        assert_eq!(
+            check("let x: Int = 'one'"),
+            vec!["mismatched types in `'one'`, expected Int but found Str"]);
+    }
+
+    #[test]
+    fn unannotated_code_is_inferred() {
+        assert_eq!(
+            check("fn f(a, b) { a + b }\nf('a', true)"),
+            vec!["mismatched types in `'a'`, expected Int but found Str"]);
+    }
+
+    #[test]
+    fn literals_are_always_checked() {
+        assert_eq!(
+            check("1 + 'one'"),
+            vec!["mismatched types in `1 + 'one'`, expected Int but found Str"]);
+    }
+
+    #[test]
+    fn annotated_fn() {
+        assert!(check("fn f(a: Str) -> Bool { a == 'x' }\nlet b: Bool = f('y')").is_empty());
+    }
+
+    #[test]
+    fn fn_argument_mismatch() {
+        assert_eq!(
+            check("fn f(a: Str) -> Bool { a == 'x' }\nf(1)"),
+            vec!["mismatched types in `1`, expected Str but found Int"]);
+    }
+
+    #[test]
+    fn fn_return_mismatch() {
+        assert_eq!(
+            check("fn f(a: Int) -> Bool { a + 1 }"),
+            vec!["mismatched types in `{ a + 1 }`, expected Bool but found Int"]);
+    }
+
+    #[test]
+    fn recursive_fn() {
+        assert!(check("fn f(n: Int) -> Int { match n { 0 => 1, _ => n * f(n - 1) } }").is_empty());
+    }
+
+    #[test]
+    fn mutually_recursive_fns() {
+        let input = "fn even(n) { match n { 0 => true, _ => odd(n - 1) } }\n\
+                     fn odd(n) { match n { 0 => false, _ => even(n - 1) } }\n\
+                     even";
+        assert_eq!(type_of(input), "Int -> Bool");
+        // NOTE a function declared later can't be called early
+        assert_eq!(
+            check("fn f(n) { g(n) }\nlet x = 1\nfn g(n) { n }"),
+            vec!["unbound identifier `g`"]);
+        assert_eq!(
+            check("fn f(n) { g(n) + 1 }\nfn g(n: Int) -> Str { 'g' }"),
+            vec!["`g` is used as Int -> Int but has type Int -> Str"]);
+    }
+
+    #[test]
+    fn fn_arity_mismatch() {
+        assert_eq!(
+            check("fn f(a: Int) -> Int { a }\nf(1, 2)"),
+            vec!["`f` expects 1 argument(s) but was given 2"]);
+    }
+
+    #[test]
+    fn higher_order_fn() {
+        let input = "fn twice(f: Int -> Int, x: Int) -> Int { f(f(x)) }\n\
+                     fn inc(x: Int) -> Int { x + 1 }\n\
+                     fn not(b: Bool) -> Bool { !b }\n\
+                     twice(inc, 1)\n\
+                     twice(not, 1)";
+        assert_eq!(
+            check(input),
+            vec!["mismatched types in `not`, expected Int -> Int but found Bool -> Bool"]);
+    }
+
+    #[test]
+    fn ordering_needs_numbers_or_strings() {
+        assert!(check("fn f(a, b: Str) -> Bool { a < b }").is_empty());
+        assert_eq!(check("true < false"), vec!["`true < false` can't order values of type Bool"]);
+    }
+
+    #[test]
+    fn calling_a_non_function() {
+        assert_eq!(check("let x: Int = 1\nx(2)"), vec!["`x` has type Int and can't be called"]);
+    }
+
+    #[test]
+    fn enum_types() {
+        let input = "enum Shape { Circle(r), Empty }\n\
+                     fn area(s: Shape) -> Int { match s { Circle(r) => r * r, Empty => 0 } }\n\
+                     area(Circle(2))\n\
+                     area(3)";
+        assert_eq!(check(input), vec!["mismatched types in `3`, expected Shape but found Int"]);
+    }
+
+    #[test]
+    fn pattern_type_mismatch() {
+        let input = "enum Shape { Circle(r), Empty }\n\
+                     fn f(n: Int) -> Int { match n { Empty => 0, _ => 1 } }";
+        assert_eq!(check(input), vec!["pattern `Empty` has type Shape but the value matched has type Int"]);
+    }
+
+    #[test]
+    fn match_arm_mismatch() {
+        assert_eq!(
+            check("match 1 { 0 => 'zero', _ => 1 }"),
+            vec!["match arms have incompatible types, `1` has type Int but earlier arms have type Str"]);
+    }
+
+    #[test]
+    fn guard_must_be_bool() {
+        assert_eq!(
+            check("match 1 { n if n + 1 => n }"),
+            vec!["mismatched types in `n + 1`, expected Bool but found Int"]);
+    }
+
+    #[test]
+    fn unknown_type() {
+        assert_eq!(check("let x: Foo = 1"), vec!["unknown type `Foo`"]);
+    }
+
+    #[test]
+    fn unbound_identifier() {
+        assert_eq!(check("y + 1"), vec!["unbound identifier `y`"]);
+    }
+
+    #[test]
+    fn ill_typed_let_is_still_bound() {
+        assert_eq!(check("let x: Int = 'a'\nx + 1").len(), 1);
+    }
+
+    #[test]
+    fn errors_carry_the_declaration_span() {
+        let parse = Parser::new("let x = 1\nlet y: Str = x").parse();
+        let errors = TypeChecker::default().check_decls(&parse.declarations, &parse.spans);
+        assert_eq!(errors[0].span, 10..24);
+    }
+
+    #[test]
+    fn errors_carry_the_innermost_span() {
+        let span = |input: &str| {
+            let parse = Parser::new(input).parse();
+            TypeChecker::default().check_decls(&parse.declarations, &parse.spans).remove(0).span
+        };
+        let at = |input: &str, inner: &str| {
+            let start = input.find(inner).unwrap();
+            start..start + inner.len()
+        };
+        let input = "fn f(x) {\n  let y = x + 1\n  not(y)\n}";
+        assert_eq!(span(input), at(input, "not(y)"));
+        let input = "let x = {\n  let a: Str = 1\n  a\n}";
+        assert_eq!(span(input), at(input, "let a: Str = 1"));
+        let input = "let f = [fn(s: Str) s + 1]";
+        assert_eq!(span(input), at(input, "fn(s: Str) s + 1"));
+        let input = "let xs = [1, 2]\nlet s = xs + 'a'";
+        assert_eq!(span(input), at(input, "let s = xs + 'a'"));
+    }
+
+    fn type_of(input: &str) -> String {
+        let mut parse = Parser::new(input).parse();
+        let expr = match parse.declarations.pop() {
+            Some(Decl::Stmt(bx_stmt)) => match *bx_stmt {
+                Stmt::Expr(expr) => expr,
+            },
+            decl => panic!("expected an expression, got {:?}", decl),
+        };
+        let mut tc = TypeChecker::default();
+        assert!(tc.check_decls(&parse.declarations, &parse.spans).is_empty());
+        match tc.type_of(&expr) {
+            Ok(ty) => ty.to_string(),
+            Err(msg) => msg,
+        }
+    }
+
+    #[test]
+    fn identity() {
+        assert_eq!(type_of("fn(x) x"), "'a -> 'a");
+    }
+
+    #[test]
+    fn let_polymorphism() {
+        assert!(check("let id = fn(x) x\nlet a: Int = id(1)\nlet b: Str = id('b')").is_empty());
+        assert!(check("fn id(x) { x }\n(id(1), id(true))").is_empty());
+    }
+
+    #[test]
+    fn parameters_are_monomorphic() {
+        assert_eq!(
+            type_of("fn(f) (f(1), f('a'))"),
+            "mismatched types in `'a'`, expected Int but found Str");
+    }
+
+    #[test]
+    fn infer_map() {
+        let input = "fn map(f, xs) { match xs { [] => [], [x, ..rest] => [f(x), ..map(f, rest)] } }\nmap";
+        assert_eq!(type_of(input), "('a -> 'b, List<'a>) -> List<'b>");
+    }
+
+    #[test]
+    fn infer_tuples() {
+        assert_eq!(type_of("fn(p) match p { (a, b) => (b, a) }"), "('a, 'b) -> ('b, 'a)");
+    }
+
+    #[test]
+    fn infer_compose() {
+        assert_eq!(type_of("fn(f, g) fn(x) f(g(x))"), "('a -> 'b, 'c -> 'a) -> 'c -> 'b");
+    }
+
+    #[test]
+    fn list_items_must_agree() {
+        assert_eq!(type_of("[1, 'two']"), "mismatched types in `'two'`, expected Int but found Str");
+    }
+
+    #[test]
+    fn occurs_check() {
+        assert_eq!(type_of("fn(x) x(x)"), "infinite type in `x`, 'a occurs in 'a -> 'b");
+        assert_eq!(type_of("fn(x) [x, [x]]"), "infinite type in `[x]`, 'a occurs in List<'a>");
+    }
+
+    #[test]
+    fn enum_fields_are_inferred() {
+        assert_eq!(
+            check("enum Box { Box(x) }\nlet a = Box(1)\nlet b = Box('one')"),
+            vec!["mismatched types in `'one'`, expected Int but found Str"]);
+    }
+
+    #[test]
+    fn constrained_variables_default_to_int() {
+        assert_eq!(type_of("fn lt(a, b) { a < b }\nlt"), "(Int, Int) -> Bool");
+        assert_eq!(type_of("fn(a: Str, b) a < b"), "(Str, Str) -> Bool");
+        // only once they'd be generalized
+        assert_eq!(type_of("int({ let x = 1\n 2.5 })"), "Int");
+        assert_eq!(type_of("fn(a) { let b = a\n float(a) }"), "Int -> Float");
+    }
+
+    #[test]
+    fn generic_enum() {
+        assert_eq!(
+            type_of("enum Option<T> { Some(T), None }\nfn(x) match x { Some(v) => v, None => 0 }"),
+            "Option<Int> -> Int");
+        assert_eq!(type_of("enum Option<T> { Some(T), None }\nNone"), "Option<'a>");
+    }
+
+    #[test]
+    fn generic_enum_is_polymorphic() {
+        assert!(check("enum Option<T> { Some(T), None }\nlet a: Option<Int> = Some(1)\nlet b: Option<Str> = Some('b')").is_empty());
+    }
+
+    #[test]
+    fn type_argument_mismatch() {
+        assert_eq!(
+            check("enum Option<T> { Some(T), None }\nlet a: Option<Int> = Some('a')"),
+            vec!["mismatched types in `Some('a')`, expected Option<Int> but found Option<Str>"]);
+    }
+
+    #[test]
+    fn recursive_generic_enum() {
+        let input = "enum Tree<T> { Leaf, Node(Tree<T>, T, Tree<T>) }\n\
+                     fn sum(t: Tree<Int>) -> Int { match t { Leaf => 0, Node(l, v, r) => sum(l) + v + sum(r) } }\n\
+                     sum(Node(Leaf, 'a', Leaf))";
+        assert_eq!(check(input), vec!["mismatched types in `Node(Leaf, 'a', Leaf)`, expected Tree<Int> but found Tree<Str>"]);
+    }
+
+    #[test]
+    fn generic_fn() {
+        let input = "fn map<A, B>(xs: List<A>, f: A -> B) -> List<B> {\n\
+                       match xs { [] => [], [x, ..rest] => [f(x), ..map(rest, f)] }\n\
+                     }\nmap";
+        assert_eq!(type_of(input), "(List<'a>, 'a -> 'b) -> List<'b>");
+    }
+
+    #[test]
+    fn type_parameters_are_rigid() {
+        assert_eq!(
+            check("fn f<A>(x: A) -> Int { x }"),
+            vec!["mismatched types in `{ x }`, expected Int but found A"]);
+        assert_eq!(
+            check("fn f<A, B>(x: A, y: B) -> A { y }"),
+            vec!["mismatched types in `{ y }`, expected A but found B"]);
+        assert_eq!(check("fn f<A>(x: A, y: A) -> Bool { x < y }"), vec!["`x < y` can't order values of type A"]);
+    }
+
+    #[test]
+    fn kind_checking() {
+        assert_eq!(
+            check("enum Option<T> { Some(T), None }\nlet x: Option = None"),
+            vec!["type `Option` expects 1 type argument(s) but was given 0"]);
+        assert_eq!(check("let x: List<Int, Str> = []"), vec!["type `List` expects 1 type argument(s) but was given 2"]);
+        assert_eq!(check("let x: Int<Str> = 1"), vec!["type `Int` expects 0 type argument(s) but was given 1"]);
+        assert_eq!(check("fn f<F>(x: F<Int>) { x }"), vec!["type `F` expects 0 type argument(s) but was given 1"]);
+    }
+
+    #[test]
+    fn type_parameters_must_be_declared() {
+        assert_eq!(check("fn f(x: A) -> A { x }"), vec!["unknown type `A`"]);
+        assert_eq!(check("enum Box { Box(T) }"), vec!["unknown type `T`"]);
+        assert_eq!(check("fn f<A, A>(x: A) { x }"), vec!["type parameter `A` is declared twice"]);
+    }
+
+    fn check_gradual(input: &str) -> Vec<String> {
+        let parse = Parser::new(input).parse();
+        let mut tc = TypeChecker::default();
+        tc.set_gradual(true);
+        tc.check_decls(&parse.declarations, &parse.spans)
+            .into_iter()
+            .map(|e| e.msg)
+            .collect()
+    }
+
+    #[test]
+    fn gradual_unannotated_code_is_dyn() {
+        assert!(check_gradual("fn f(a, b) { a + b }\nf('a', true)").is_empty());
+        assert!(check_gradual("fn call(f) { f(1, 2) }\ncall(3)").is_empty());
+        assert_eq!(
+            check_gradual("fn f(a: Int) -> Int { a }\nf('a')"),
+            vec!["mismatched types in `'a'`, expected Int but found Str"]);
+    }
+
+    #[test]
+    fn dyn_is_consistent_with_everything() {
+        assert!(check("let d: Dyn = 1\nlet s: Str = d\nlet f: Int -> Int = d").is_empty());
+        assert_eq!(type_of("let d: Dyn = 1\nfn(x) x * 2 + d"), "Int -> Int");
+    }
+
+    #[test]
+    fn contract_clauses_are_checked() {
+        assert!(check("fn f(x) requires x > 0 ensures result != 'a' { 'b' }").is_empty());
+        assert_eq!(
+            check("fn f(x: Str) requires x > 0 { x }"),
+            vec!["mismatched types in `x > 0`, expected Str but found Int"]);
+        assert_eq!(
+            check("fn f(x) ensures result { x + 1 }"),
+            vec!["mismatched types in `result`, expected Bool but found Int"]);
+    }
+
+    #[test]
+    fn contracts_constrain_types() {
+        let input = "fn pos(x) { x > 0 }\nfn apply(g, x) requires g: pos -> pos { g(x) }\napply";
+        assert_eq!(type_of(input), "(Int -> Int, Int) -> Int");
+        assert_eq!(
+            check("fn f(x: Int) requires x: fn(a, b) true -> fn(a) true { x }"),
+            vec!["contract `fn(a, b) true -> fn(a) true` needs a function of 1 argument(s) but was given Int"]);
+    }
+
+    #[test]
+    fn ints_and_floats_are_distinct() {
+        assert_eq!(type_of("1 + 2"), "Int");
+        assert_eq!(type_of("1.5 * 2.0"), "Float");
+        assert_eq!(type_of("7.0 / 2.0"), "Float");
+        assert_eq!(type_of("7 // 2 % 3"), "Int");
+        assert_eq!(type_of("1 + 1.5"), "mismatched types in `1 + 1.5`, expected Int but found Float");
+        assert_eq!(type_of("1.5 // 2.0"), "mismatched types in `1.5 // 2.0`, expected Int but found Float");
+        assert_eq!(type_of("float(1) + 1.5"), "Float");
+        assert_eq!(type_of("fn(x) -x"), "Int -> Int");
+        assert_eq!(type_of("fn(x) x + 0.5"), "Float -> Float");
+        assert_eq!(type_of("'a' + 'b'"), "`'a' + 'b'` can't do arithmetic on values of type Str");
+    }
+
+    #[test]
+    fn dividing_ints_is_rational() {
+        assert_eq!(type_of("7 / 2"), "Rat");
+        assert_eq!(type_of("1 / 3 + 1 / 6"), "Rat");
+        assert_eq!(type_of("fn(a, b) a / b"), "(Int, Int) -> Rat");
+        assert_eq!(type_of("1 / 3 + 1"), "Rat");
+        assert_eq!(type_of("fn(n: Int) n < 1 / 2"), "Int -> Bool");
+        let sum = "fn sum(xs: List<Rat>) -> Rat { match xs { [] => 0, [x, ..rest] => x + sum(rest) } }";
+        assert!(check(&format!("{}\nlet r: Rat = 1\nlet s: Rat = sum([1 / 2, r])", sum)).is_empty());
+        assert_eq!(type_of("fn(b) match b { true => 1 / 2, false => 1 }"), "Bool -> Rat");
+        assert_eq!(type_of("[1, 1 / 2, 2]"), "List<Rat>");
+        assert_eq!(check("let n: Int = 1 / 2"), vec!["mismatched types in `1 / 2`, expected Int but found Rat"]);
+        assert_eq!(type_of("float(1 / 3)"), "Float");
+        assert!(check("let r: Rat = 1 / 3\nlet n: Int = int(r)").is_empty());
+        assert_eq!(type_of("99999999999999999999 * 2"), "Int");
+    }
+
+    #[test]
+    fn exponent_and_bitwise_types() {
+        assert_eq!(type_of("2 ** 10"), "Int");
+        assert_eq!(type_of("(1 / 2) ** 2"), "Rat");
+        assert_eq!(type_of("fn(x) x ** 0.5"), "Float -> Float");
+        assert_eq!(type_of("2 ** 0.5"), "mismatched types in `2 ** 0.5`, expected Int but found Float");
+        assert_eq!(type_of("fn(a, b) (a & b) << 1 | ~a"), "(Int, Int) -> Int");
+        assert_eq!(type_of("1.0 ^ 2"), "mismatched types in `1.0 ^ 2`, expected Int but found Float");
+    }
+
+    #[test]
+    fn float_builtins() {
+        assert_eq!(type_of("approx_eq"), "(Float, Float, Int) -> Bool");
+        assert_eq!(type_of("approx_eq(0.1 + 0.2, 0.3, 0.0001)"), "Bool");
+        assert_eq!(type_of("is_nan(1)"), "mismatched types in `1`, expected Float but found Int");
+    }
+
+    #[test]
+    fn unit_is_the_empty_tuple() {
+        assert_eq!(type_of("()"), "()");
+        assert_eq!(type_of("fn(x: ()) x"), "() -> ()");
+        assert_eq!(type_of("match () { () => 1 }"), "Int");
+    }
+}
@@ -0,0 +1,549 @@
+// vm - runs the bytecode made by `compile`
+//
+// A stack machine where each call gets a frame whose slots start at its base
+// in the value stack, with the values being worked on above them. Calls
+// between compiled functions don't recurse in Rust, so only the maximum depth
+// limits them, and a tail call replaces the frame making it. A failure
+// unwinds to the innermost handler a `try` pushed, leaving the frames it
+// escapes, and values, operators and errors are the same as in `interp`.
+
+use std::fmt;
+use std::rc::Rc;
+use crate::compile::{self, Op, Proto, Shape};
+use crate::interp::{
+    ascend, at_call, binary, bind_pattern, call as call_value, descend, function_name, question, spread, throw, unary,
+    builtin::Builtin,
+    cast::{cast, Blame, Party},
+    contract::{monitor, Ctc},
+    env::Env,
+    value::Value,
+    ErrorKind, RuntimeError, Unwind,
+};
+use crate::parser::{decl::Decl, expr::Expr};
+
+// A function value, one of a group of functions declared together
+#[derive(Clone)]
+pub(crate) struct Closure {
+    group: Rc<Group>,
+    index: usize,
+}
+
+struct Group {
+    protos: Rc<[Proto]>,
+    captures: Vec<Value>,
+}
+
+impl Closure {
+    pub(crate) fn name(&self) -> &str {
+        &self.proto().name
+    }
+
+    pub(crate) fn arity(&self) -> usize {
+        self.proto().params.len()
+    }
+
+    fn proto(&self) -> &Proto {
+        &self.group.protos[self.index]
+    }
+}
+
+// NOTE closures are only equal if they are the same closure
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.group, &other.group) && self.index == other.index
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Closure({:?})", self.name())
+    }
+}
+
+// runs a program at the top level, declaring what it declares in `env`
+pub(crate) fn run(decls: &[Decl], env: &mut Env) -> Result<Value, RuntimeError> {
+    run_main(compile::compile(decls, env), env)
+}
+
+// runs a compiled top level, e.g. one read back from a `.klc` file
+pub(crate) fn run_main(proto: Proto, env: &mut Env) -> Result<Value, RuntimeError> {
+    let main = Closure { group: Rc::new(Group { protos: Rc::from(vec![proto]), captures: vec![] }), index: 0 };
+    let mut vm = Vm { env: env.clone(), ..Vm::default() };
+    let ret = vm.enter(main, vec![], None).and_then(|()| vm.execute());
+    *env = vm.env;
+    ret
+}
+
+// NOTE for the calls `interp` makes, e.g. a guarded function or a contract's predicate
+pub(crate) fn call(f: &Closure, args: Vec<Value>, site: &Expr) -> Result<Value, RuntimeError> {
+    let mut vm = Vm::default();
+    vm.enter(f.clone(), args, Some(Rc::new(site.clone())))?;
+    vm.execute()
+}
+
+struct Frame {
+    closure: Closure,
+    ip: usize,
+    base: usize,
+    // the call that made it, `None` for the top level
+    site: Option<Rc<Expr>>,
+}
+
+#[derive(Clone, Copy)]
+enum Catcher {
+    Catch,
+    Finally,
+    Returned,
+}
+
+// NOTE pushed by the frame it's in, and popped before the frame is left
+struct Handler {
+    catcher: Catcher,
+    ip: usize,
+    frame: usize,
+    // how much of the stack, and of the pending failures, to keep
+    height: usize,
+    pending: usize,
+}
+
+#[derive(Default)]
+struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    handlers: Vec<Handler>,
+    // the failures finally blocks are running for
+    pending: Vec<RuntimeError>,
+    // the top level declarations
+    env: Env,
+}
+
+impl Vm {
+    // pushes a frame for a call, failures are placed by the caller
+    fn enter(&mut self, f: Closure, args: Vec<Value>, site: Option<Rc<Expr>>) -> Result<(), RuntimeError> {
+        let proto = f.proto();
+        if proto.params.len() != args.len() {
+            return Err(RuntimeError {
+                msg: format!("{} expects {} argument(s) but was given {}", function_name(&proto.name), proto.params.len(), args.len()),
+                kind: ErrorKind::Arity,
+                span: None,
+                unwind: None,
+            });
+        }
+        let base = self.stack.len();
+        for (ty, arg) in proto.params.iter().zip(args) {
+            let arg = match ty {
+                Some(ty) => match cast(arg, ty, &proto.generics, &blame(proto, site.as_deref())) {
+                    Ok(arg) => arg,
+                    Err(e) => {
+                        self.stack.truncate(base);
+                        return Err(e);
+                    }
+                },
+                None => arg,
+            };
+            self.stack.push(arg);
+        }
+        self.stack.resize(base + proto.slots, Value::Unit);
+        if let Err(e) = descend() {
+            self.stack.truncate(base);
+            return Err(e);
+        }
+        self.frames.push(Frame { closure: f, ip: 0, base, site });
+        Ok(())
+    }
+
+    fn leave(&mut self) -> Frame {
+        let frame = self.frames.pop().unwrap();
+        self.stack.truncate(frame.base);
+        // NOTE only a damaged program leaves a handler behind
+        while self.handlers.last().is_some_and(|h| h.frame >= self.frames.len()) {
+            self.handlers.pop();
+        }
+        ascend();
+        frame
+    }
+
+    // gives the caller the result of a frame that's been left, the result of
+    // the first frame is the result of running
+    fn ret(&mut self, frame: &Frame, val: Value) -> Result<Option<Value>, RuntimeError> {
+        let proto = frame.closure.proto();
+        let val = match &proto.ret {
+            Some(ty) => cast(val, ty, &proto.generics, &blame(proto, frame.site.as_deref()).flip())?,
+            None => val,
+        };
+        if self.frames.is_empty() {
+            return Ok(Some(val));
+        }
+        self.stack.push(val);
+        Ok(None)
+    }
+
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            let ret = match self.step() {
+                Ok(val) => return Ok(val),
+                Err(e) => self.unwind(e)?,
+            };
+            if let Some(val) = ret {
+                return Ok(val);
+            }
+        }
+    }
+
+    // runs until the first frame returns or something fails
+    fn step(&mut self) -> Result<Value, RuntimeError> {
+        'frames: loop {
+            let frame = self.frames.last().unwrap();
+            let closure = frame.closure.clone();
+            let site = frame.site.clone();
+            let (base, mut ip) = (frame.base, frame.ip);
+            let proto = closure.proto();
+            loop {
+                let op = proto.code[ip];
+                ip += 1;
+                match op {
+                    Op::Const(i) => self.stack.push(proto.consts[i].clone()),
+                    Op::Unit => self.stack.push(Value::Unit),
+                    Op::Local(slot) => self.stack.push(self.stack[base + slot].clone()),
+                    Op::SetLocal(slot) => self.stack[base + slot] = self.pop()?,
+                    Op::Capture(i) => self.stack.push(closure.group.captures[i].clone()),
+                    Op::Sibling(index) => self.stack.push(Value::Compiled(Closure { group: closure.group.clone(), index })),
+                    Op::Global(i) => {
+                        let name = &proto.names[i];
+                        let val = self.env.lookup(name).map_or_else(|| builtin(name), Ok)?;
+                        self.stack.push(val);
+                    }
+                    Op::Builtin(i) => self.stack.push(builtin(&proto.names[i])?),
+                    Op::Define(i) => {
+                        let val = self.pop()?;
+                        self.env = self.env.extend(proto.names[i].clone(), val);
+                    }
+                    Op::Pop => {
+                        self.pop()?;
+                    }
+                    Op::Unary(prefop) => {
+                        let val = self.pop()?;
+                        self.stack.push(unary(&prefop, val)?);
+                    }
+                    Op::Binary(infop) => {
+                        let vrhs = self.pop()?;
+                        let vlhs = self.pop()?;
+                        self.stack.push(binary(&infop, vlhs, vrhs)?);
+                    }
+                    Op::Jump(target) => ip = target,
+
+                    Op::Call(argc, i) => {
+                        let args = self.popn(argc)?;
+                        let site = &proto.sites[i];
+                        match self.pop()? {
+                            Value::Compiled(f) => {
+                                self.frames.last_mut().unwrap().ip = ip;
+                                self.enter(f, args, Some(site.clone())).map_err(|e| at_call(e, site))?;
+                                continue 'frames;
+                            }
+                            callee => {
+                                let val = call_value(callee, args, site).map_err(|e| at_call(e, site))?;
+                                self.stack.push(val);
+                            }
+                        }
+                    }
+                    Op::TailCall(argc, i) => {
+                        let args = self.popn(argc)?;
+                        let site = &proto.sites[i];
+                        match self.pop()? {
+                            Value::Compiled(f) => {
+                                self.leave();
+                                self.enter(f, args, Some(site.clone())).map_err(|e| at_call(e, site))?;
+                                continue 'frames;
+                            }
+                            callee => {
+                                let val = call_value(callee, args, site).map_err(|e| at_call(e, site))?;
+                                let frame = self.leave();
+                                match self.ret(&frame, val)? {
+                                    Some(val) => return Ok(val),
+                                    None => continue 'frames,
+                                }
+                            }
+                        }
+                    }
+                    Op::Return => {
+                        let val = self.pop()?;
+                        let frame = self.leave();
+                        match self.ret(&frame, val)? {
+                            Some(val) => return Ok(val),
+                            None => continue 'frames,
+                        }
+                    }
+                    Op::Closure(i, n) => {
+                        let captures = self.popn(n)?;
+                        let group = Rc::new(Group { protos: proto.groups[i].clone(), captures });
+                        for index in 0..group.protos.len() {
+                            self.stack.push(Value::Compiled(Closure { group: group.clone(), index }));
+                        }
+                    }
+
+                    Op::Tuple(n) => {
+                        let items = self.popn(n)?;
+                        self.stack.push(Value::Tuple(items));
+                    }
+                    Op::List(n) => {
+                        let items = self.popn(n)?;
+                        self.stack.push(Value::List(items));
+                    }
+                    Op::Spread => {
+                        let rest = self.pop()?;
+                        self.peek()?;
+                        match self.stack.last_mut() {
+                            Some(Value::List(vals)) => spread(vals, rest)?,
+                            _ => return Err(corrupt("a spread isn't into a list")),
+                        }
+                    }
+
+                    Op::Match(i, target) => {
+                        let (pat, slots) = &proto.patterns[i];
+                        let mut vals = Vec::with_capacity(slots.len());
+                        if bind_pattern(pat, self.peek()?, &mut |_, val| vals.push(val)) {
+                            for (slot, val) in slots.iter().zip(vals) {
+                                self.stack[base + slot] = val;
+                            }
+                        } else {
+                            ip = target;
+                        }
+                    }
+                    Op::Guard(target) => match self.pop()? {
+                        Value::Bool(true) => (),
+                        Value::Bool(false) => ip = target,
+                        v => return Err(RuntimeError {
+                            msg: format!("match guard must produce a boolean, got {}", v),
+                            kind: ErrorKind::Type,
+                            span: None,
+                            unwind: None,
+                        }),
+                    },
+                    Op::NoMatch => {
+                        let val = self.pop()?;
+                        return Err(RuntimeError {
+                            msg: format!("no match arm matches the value {}", val),
+                            kind: ErrorKind::Match,
+                            span: None,
+                            unwind: None,
+                        });
+                    }
+
+                    Op::Question => {
+                        let val = self.pop()?;
+                        self.stack.push(question(val)?);
+                    }
+                    Op::Throw => return Err(throw(self.pop()?)),
+                    Op::Catch(target) => self.handle(Catcher::Catch, target),
+                    Op::Finally(target) => self.handle(Catcher::Finally, target),
+                    Op::Returned(target) => self.handle(Catcher::Returned, target),
+                    Op::PopHandler => match self.handlers.last() {
+                        Some(handler) if handler.frame == self.frames.len() - 1 => {
+                            self.handlers.pop();
+                        }
+                        _ => return Err(corrupt("a handler that isn't there is popped")),
+                    },
+                    Op::Reraise => return Err(self.pending.pop().unwrap_or_else(|| corrupt("nothing is reraised"))),
+
+                    Op::Require(i, ensures) => {
+                        let blame = clause_blame(proto, site.as_deref(), ensures);
+                        let text = &proto.texts[i];
+                        match self.pop()? {
+                            Value::Bool(true) => (),
+                            Value::Bool(false) => return Err(blame.positive.blame(format!("contract violation: `{}` does not hold", text))),
+                            v => return Err(RuntimeError {
+                                msg: format!("contract `{}` must produce a boolean, got {}", text, v),
+                                kind: ErrorKind::Type,
+                                span: None,
+                                unwind: None,
+                            }),
+                        }
+                    }
+                    Op::Monitor(i, ensures) => {
+                        let shape = &proto.contracts[i];
+                        let preds = self.popn(shape.flats())?;
+                        let ctc = contract(shape, &mut preds.into_iter());
+                        let val = self.pop()?;
+                        let blame = clause_blame(proto, site.as_deref(), ensures);
+                        let val = monitor(val, &ctc, &blame, site.as_deref().unwrap())?;
+                        self.stack.push(val);
+                    }
+                    Op::Fail(i) => {
+                        let (kind, msg) = &proto.errors[i];
+                        return Err(RuntimeError { msg: msg.clone(), kind: *kind, span: None, unwind: None });
+                    }
+                    Op::Cast(i) => {
+                        let annotation = &proto.casts[i];
+                        let party = Party::binding(&annotation.name, &annotation.span);
+                        let val = self.pop()?;
+                        let val = cast(val, &annotation.ty, &annotation.generics, &Blame { positive: party.clone(), negative: party })?;
+                        self.stack.push(val);
+                    }
+                }
+            }
+        }
+    }
+
+    // NOTE a damaged program could pop its frame's slots or its caller's
+    // values, so going below them fails, see `klc::validate`
+    fn pop(&mut self) -> Result<Value, RuntimeError> {
+        self.peek()?;
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn popn(&mut self, n: usize) -> Result<Vec<Value>, RuntimeError> {
+        match self.stack.len().checked_sub(n) {
+            Some(at) if at >= self.floor() => Ok(self.stack.split_off(at)),
+            _ => Err(corrupt("the stack underflows")),
+        }
+    }
+
+    fn peek(&self) -> Result<&Value, RuntimeError> {
+        match self.stack.len() > self.floor() {
+            true => Ok(&self.stack[self.stack.len() - 1]),
+            false => Err(corrupt("the stack underflows")),
+        }
+    }
+
+    fn floor(&self) -> usize {
+        let frame = self.frames.last().unwrap();
+        frame.base + frame.closure.proto().slots
+    }
+
+    fn handle(&mut self, catcher: Catcher, ip: usize) {
+        self.handlers.push(Handler {
+            catcher,
+            ip,
+            frame: self.frames.len() - 1,
+            height: self.stack.len(),
+            pending: self.pending.len(),
+        });
+    }
+
+    // jumps to the handler for a failure, leaving the frames it escapes
+    // NOTE like `interp`, `?` passes through a catch and returns from the function
+    fn unwind(&mut self, mut e: RuntimeError) -> Result<Option<Value>, RuntimeError> {
+        while let Some(frame) = self.frames.len().checked_sub(1) {
+            while let Some(handler) = self.handlers.pop() {
+                if handler.frame != frame {
+                    self.handlers.push(handler);
+                    break;
+                }
+                let returning = matches!(e.unwind, Some(Unwind::Return(_)));
+                match (handler.catcher, returning) {
+                    (Catcher::Catch, true) | (Catcher::Returned, false) => continue,
+                    _ => (),
+                }
+                self.stack.truncate(handler.height);
+                self.pending.truncate(handler.pending);
+                match handler.catcher {
+                    Catcher::Catch | Catcher::Returned => self.stack.push(e.into_value()),
+                    Catcher::Finally => self.pending.push(e),
+                }
+                self.frames[frame].ip = handler.ip;
+                return Ok(None);
+            }
+            let left = self.leave();
+            e = match (e, &left.site) {
+                (RuntimeError { unwind: Some(Unwind::Return(val)), .. }, Some(_)) => match self.ret(&left, *val) {
+                    Ok(ret) => return Ok(ret),
+                    Err(e) => e,
+                },
+                (e, Some(site)) => at_call(e, site),
+                (e, None) => e,
+            };
+        }
+        Err(e)
+    }
+}
+
+fn corrupt(why: &str) -> RuntimeError {
+    RuntimeError { msg: format!("corrupt program: {}", why), kind: ErrorKind::Stack, span: None, unwind: None }
+}
+
+fn builtin(name: &str) -> Result<Value, RuntimeError> {
+    Builtin::from_name(name).map(Value::Builtin).ok_or_else(|| RuntimeError {
+        msg: format!("unbound identifier {}", name),
+        kind: ErrorKind::Name,
+        span: None,
+        unwind: None,
+    })
+}
+
+// NOTE only functions have a site, and only they have annotations or contracts
+fn blame(proto: &Proto, site: Option<&Expr>) -> Blame {
+    Blame { positive: Party::call(site.unwrap()), negative: Party::function(&proto.name, &proto.span) }
+}
+
+// the caller is blamed for a `requires` clause and the function for an `ensures` one
+fn clause_blame(proto: &Proto, site: Option<&Expr>, ensures: bool) -> Blame {
+    match ensures {
+        false => blame(proto, site),
+        true => blame(proto, site).flip(),
+    }
+}
+
+fn contract(shape: &Shape, preds: &mut impl Iterator<Item = Value>) -> Ctc {
+    match shape {
+        Shape::Flat(label) => Ctc::Flat(preds.next().unwrap(), label.clone()),
+        Shape::Fn(params, ret) => {
+            let params = params.iter().map(|p| contract(p, preds)).collect();
+            Ctc::Fn(params, Box::new(contract(ret, preds)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::{exec_decls, set_max_depth, MAX_DEPTH};
+    use crate::parser::Parser;
+
+    fn run(input: &str) -> Result<String, String> {
+        let decls = Parser::new(input).parse().declarations;
+        super::run(&decls, &mut Env::default()).map(|val| val.to_string()).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn deep_calls_use_no_rust_stack() {
+        let ret = std::thread::Builder::new()
+            .stack_size(1 << 20)
+            .spawn(|| {
+                set_max_depth(1_000_000);
+                run("fn f(n) { match n { 0 => 0, _ => 1 + f(n - 1) } }\nf(100000)")
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(ret, Ok("100000".to_string()));
+    }
+
+    #[test]
+    fn declarations_are_kept_in_the_env() {
+        let mut env = Env::default();
+        let decls = Parser::new("let x = 1\nfn f(y) { x + y }").parse().declarations;
+        assert_eq!(super::run(&decls, &mut env).unwrap(), Value::Unit);
+        // and the tree walker can call what the vm compiled
+        let decls = Parser::new("let x = 2\nf(x)").parse().declarations;
+        assert_eq!(exec_decls(&decls, &mut env).unwrap(), Value::Int(3));
+    }
+
+    #[test]
+    fn declarations_before_a_failure_are_kept() {
+        let mut env = Env::default();
+        let decls = Parser::new("let x = 1\n1 // 0\nlet y = 2").parse().declarations;
+        assert!(super::run(&decls, &mut env).is_err());
+        assert_eq!(env.lookup("x"), Some(Value::Int(1)));
+        assert_eq!(env.lookup("y"), None);
+    }
+
+    #[test]
+    fn finally_runs_when_returning_early() {
+        set_max_depth(MAX_DEPTH);
+        let input = "enum Option<T> { Some(T), None }\n\
+                     fn f(x) ensures result != 1 { try { Some(x?) } finally { throw 'finally' } }\n\
+                     try { f(None) } catch e { e }";
+        assert_eq!(run(input), Ok("finally".to_string()));
+    }
+}
@@ -0,0 +1,11 @@
+// lex - the lexer takes any input without panicking
+//
+//   cargo fuzz run lex
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        klug::fuzzing::lex(input);
+    }
+});
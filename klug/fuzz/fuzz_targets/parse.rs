@@ -0,0 +1,11 @@
+// parse - the parser turns any input into declarations or errors without panicking
+//
+//   cargo fuzz run parse ../crates/klug/corpus
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        klug::fuzzing::parse(input);
+    }
+});
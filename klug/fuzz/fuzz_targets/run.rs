@@ -0,0 +1,30 @@
+// run - any input runs like `klug run` does, to a result or an error, on either engine
+//
+//   cargo fuzz run run ../crates/klug/corpus -- -fork=4 -ignore_timeouts=1
+//
+// Compiled programs are loaded, anything else that's UTF-8 is run as source.
+// The limits are kept low, so a program that loops or recurses deeply fails
+// quickly instead of timing out or running out of stack.
+// NOTE fuel doesn't cover arithmetic, and ints are unbounded, so something
+// like `5 ** 505050` can still time out, which isn't a panic
+#![no_main]
+use klug::{Engine, Limits, Session};
+use libfuzzer_sys::fuzz_target;
+
+const LIMITS: Limits = Limits { nesting: 100, depth: 200, fuel: Some(10_000) };
+
+fn session(engine: Engine) -> Session {
+    let mut session = Session::default();
+    session.set_limits(LIMITS);
+    session.set_engine(engine);
+    session
+}
+
+fuzz_target!(|data: &[u8]| {
+    if klug::is_compiled(data) {
+        let _ = session(Engine::Vm).run_compiled(data);
+    } else if let Ok(input) = std::str::from_utf8(data) {
+        session(Engine::Tree).run(input);
+        session(Engine::Vm).run(input);
+    }
+});